@@ -0,0 +1,952 @@
+//! Browsing zip and tar archives as virtual directories.
+//!
+//! [`ArchiveFileSystem`] wraps a host filesystem. A path that runs through
+//! an archive file, such as `/srv/release.tar.gz/bin/tool`, is served from
+//! the archive; every other path goes to the host. Archives are opened from
+//! the local disk, so the host is expected to be the local filesystem.
+//!
+//! Supported formats, by file extension: `.zip`, `.tar`, `.tar.gz`/`.tgz`
+//! and `.tar.zst`/`.tzst`. Archive contents are read-only; entries can be
+//! copied out with [`FileSystem::extract`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::NaiveDate;
+
+use crate::{
+    errors::FilesError,
+    filesystem::{Chunks, FileSystem, RealFileSystem, chunked, entry_at, normalize},
+    models::{EntryKind, EntryMetadata, FileEntry, LinkInfo},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+}
+
+impl Format {
+    fn of(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+
+        if name.ends_with(".zip") {
+            Some(Format::Zip)
+        } else if name.ends_with(".tar") {
+            Some(Format::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Format::TarGz)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(Format::TarZst)
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns `true` if `path` names a supported archive format.
+pub fn is_archive_name(path: &Path) -> bool {
+    Format::of(path).is_some()
+}
+
+/// Splits `path` into the archive file it runs through and the path inside
+/// that archive (empty for the archive's root).
+///
+/// Returns `None` for paths that do not run through an archive.
+pub fn split_archive_path(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let mut prefix = PathBuf::new();
+    let mut components = path.components();
+
+    while let Some(component) = components.next() {
+        prefix.push(component);
+
+        if is_archive_name(&prefix) && fs::metadata(&prefix).is_ok_and(|m| m.is_file()) {
+            return Some((prefix, components.as_path().to_path_buf()));
+        }
+    }
+
+    None
+}
+
+/// One entry of an archive, with a sanitized relative path.
+#[derive(Debug, Clone)]
+struct Item {
+    path: PathBuf,
+    kind: EntryKind,
+    metadata: EntryMetadata,
+    link_target: Option<PathBuf>,
+}
+
+/// Keeps only plain relative paths; anything with `..`, a root or a prefix
+/// could escape the archive and is rejected.
+fn sanitize(path: &Path) -> Option<PathBuf> {
+    let mut clean = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => clean.push(name),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    if clean.as_os_str().is_empty() {
+        None
+    } else {
+        Some(clean)
+    }
+}
+
+/// Calls `visit` for every entry of the archive, with a reader over its
+/// contents.
+fn for_each_item(
+    archive: &Path,
+    format: Format,
+    mut visit: impl FnMut(&Item, &mut dyn Read) -> Result<(), FilesError>,
+) -> Result<(), FilesError> {
+    let file = BufReader::new(File::open(archive)?);
+
+    match format {
+        Format::Zip => for_each_zip_item(file, &mut visit),
+        Format::Tar => for_each_tar_item(file, &mut visit),
+        Format::TarGz => for_each_tar_item(flate2::read::GzDecoder::new(file), &mut visit),
+        Format::TarZst => for_each_tar_item(zstd::stream::read::Decoder::new(file)?, &mut visit),
+    }
+}
+
+fn for_each_tar_item(
+    reader: impl Read,
+    visit: &mut dyn FnMut(&Item, &mut dyn Read) -> Result<(), FilesError>,
+) -> Result<(), FilesError> {
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let header = entry.header();
+
+        let kind = match header.entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Continuous | tar::EntryType::Link => {
+                EntryKind::File
+            }
+            tar::EntryType::Directory => EntryKind::Directory,
+            tar::EntryType::Symlink => EntryKind::Symlink,
+            tar::EntryType::Fifo => EntryKind::Fifo,
+            tar::EntryType::Char => EntryKind::CharDevice,
+            tar::EntryType::Block => EntryKind::BlockDevice,
+            _ => continue,
+        };
+
+        let Some(path) = sanitize(&entry.path()?) else {
+            continue;
+        };
+
+        let metadata = EntryMetadata {
+            size: header.size().unwrap_or(0),
+            modified: header
+                .mtime()
+                .ok()
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+            mode: header.mode().unwrap_or(0o644) & 0o7777,
+            uid: header.uid().unwrap_or(0) as u32,
+            gid: header.gid().unwrap_or(0) as u32,
+            ..EntryMetadata::default()
+        };
+
+        let link_target = match kind {
+            EntryKind::Symlink => entry.link_name()?.map(|target| target.into_owned()),
+            _ => None,
+        };
+
+        let item = Item {
+            path,
+            kind,
+            metadata,
+            link_target,
+        };
+        visit(&item, &mut entry)?;
+    }
+
+    Ok(())
+}
+
+fn for_each_zip_item(
+    reader: impl Read + io::Seek,
+    visit: &mut dyn FnMut(&Item, &mut dyn Read) -> Result<(), FilesError>,
+) -> Result<(), FilesError> {
+    let mut archive = zip::ZipArchive::new(reader).map_err(zip_error)?;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(zip_error)?;
+
+        let Some(path) = file.enclosed_name().as_deref().and_then(sanitize) else {
+            continue;
+        };
+
+        let kind = if file.is_dir() {
+            EntryKind::Directory
+        } else if file.is_symlink() {
+            EntryKind::Symlink
+        } else {
+            EntryKind::File
+        };
+
+        let default_mode = if kind == EntryKind::Directory {
+            0o755
+        } else {
+            0o644
+        };
+
+        let metadata = EntryMetadata {
+            size: file.size(),
+            modified: file.last_modified().and_then(|time| {
+                let date = NaiveDate::from_ymd_opt(
+                    time.year().into(),
+                    time.month().into(),
+                    time.day().into(),
+                )?;
+                let time = date.and_hms_opt(
+                    time.hour().into(),
+                    time.minute().into(),
+                    time.second().into(),
+                )?;
+                Some(time.and_utc().into())
+            }),
+            mode: file.unix_mode().map(|m| m & 0o7777).unwrap_or(default_mode),
+            ..EntryMetadata::default()
+        };
+
+        // Zip stores a link's target as its contents.
+        let link_target = if kind == EntryKind::Symlink {
+            let mut target = String::new();
+            file.read_to_string(&mut target)?;
+            Some(PathBuf::from(target))
+        } else {
+            None
+        };
+
+        let item = Item {
+            path,
+            kind,
+            metadata,
+            link_target,
+        };
+        visit(&item, &mut file)?;
+    }
+
+    Ok(())
+}
+
+fn zip_error(err: zip::result::ZipError) -> FilesError {
+    match err {
+        zip::result::ZipError::Io(err) => FilesError::Io(err),
+        other => FilesError::Io(io::Error::new(io::ErrorKind::InvalidData, other)),
+    }
+}
+
+/// The directory tree of one archive.
+#[derive(Debug, Default)]
+struct Index {
+    items: BTreeMap<PathBuf, Item>,
+}
+
+impl Index {
+    fn build(archive: &Path, format: Format) -> Result<Self, FilesError> {
+        let mut index = Index::default();
+
+        for_each_item(archive, format, |item, _| {
+            // Directories may be implied by their contents only.
+            for ancestor in item.path.ancestors().skip(1) {
+                if ancestor.as_os_str().is_empty() {
+                    break;
+                }
+                index
+                    .items
+                    .entry(ancestor.to_path_buf())
+                    .or_insert_with(|| Item {
+                        path: ancestor.to_path_buf(),
+                        kind: EntryKind::Directory,
+                        metadata: EntryMetadata {
+                            mode: 0o755,
+                            ..item.metadata.clone()
+                        },
+                        link_target: None,
+                    });
+            }
+
+            index.items.insert(item.path.clone(), item.clone());
+            Ok(())
+        })?;
+
+        Ok(index)
+    }
+
+    fn is_dir(&self, inner: &Path) -> bool {
+        inner.as_os_str().is_empty()
+            || self
+                .items
+                .get(inner)
+                .is_some_and(|item| item.kind == EntryKind::Directory)
+    }
+
+    fn children<'a>(&'a self, inner: &'a Path) -> impl Iterator<Item = &'a Item> + 'a {
+        self.items
+            .values()
+            .filter(move |item| item.path.parent() == Some(inner))
+    }
+
+    /// Resolves a link stored at `inner` to a path inside the archive.
+    fn resolve_link(&self, inner: &Path, target: &Path) -> Option<PathBuf> {
+        if target.is_absolute() {
+            return None;
+        }
+
+        let joined = inner.parent().unwrap_or(Path::new("")).join(target);
+        let mut resolved = PathBuf::new();
+        for component in joined.components() {
+            match component {
+                Component::Normal(name) => resolved.push(name),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !resolved.pop() {
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+        }
+
+        (resolved.as_os_str().is_empty() || self.items.contains_key(&resolved)).then_some(resolved)
+    }
+}
+
+/// Indexes by archive path, with the archive's mtime when it was read.
+type IndexCache = HashMap<PathBuf, (Option<SystemTime>, Arc<Index>)>;
+
+/// A [`FileSystem`] that lets zip and tar archives be browsed like
+/// directories.
+pub struct ArchiveFileSystem<F: FileSystem = RealFileSystem> {
+    host: F,
+    cache: Mutex<IndexCache>,
+}
+
+impl ArchiveFileSystem<RealFileSystem> {
+    pub fn new() -> Self {
+        Self::with_host(RealFileSystem)
+    }
+}
+
+impl Default for ArchiveFileSystem<RealFileSystem> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: FileSystem> ArchiveFileSystem<F> {
+    pub fn with_host(host: F) -> Self {
+        Self {
+            host,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the archive's index, rebuilding it if the file changed.
+    fn index(&self, archive: &Path) -> Result<Arc<Index>, FilesError> {
+        let format = Format::of(archive).ok_or(FilesError::InvalidPath)?;
+        let modified = fs::metadata(archive)?.modified().ok();
+
+        let mut cache = self.cache.lock().unwrap_or_else(|p| p.into_inner());
+        if let Some((cached_at, index)) = cache.get(archive)
+            && *cached_at == modified
+        {
+            return Ok(Arc::clone(index));
+        }
+
+        let index = Arc::new(Index::build(archive, format)?);
+        cache.insert(archive.to_path_buf(), (modified, Arc::clone(&index)));
+        Ok(index)
+    }
+
+    fn entry(&self, index: &Index, archive: &Path, item: &Item) -> FileEntry {
+        let name = item
+            .path
+            .file_name()
+            .map(OsString::from)
+            .unwrap_or_default();
+
+        let mut entry = FileEntry::new(name, archive.join(&item.path), item.kind)
+            .with_metadata(item.metadata.clone());
+
+        if let Some(target) = &item.link_target {
+            let resolved = index.resolve_link(&item.path, target);
+            let target_kind = resolved.as_ref().map(|resolved| {
+                if index.is_dir(resolved) {
+                    EntryKind::Directory
+                } else {
+                    index.items[resolved].kind
+                }
+            });
+
+            entry = entry.with_link(LinkInfo {
+                target: target.clone(),
+                resolved: resolved.map(|resolved| archive.join(resolved)),
+                target_kind,
+            });
+        }
+
+        entry
+    }
+
+    /// Writes the archive subtree at `inner` to `output`.
+    fn extract_from(&self, archive: &Path, inner: &Path, output: &Path) -> Result<(), FilesError> {
+        let format = Format::of(archive).ok_or(FilesError::InvalidPath)?;
+        let index = self.index(archive)?;
+
+        if !index.is_dir(inner) && !index.items.contains_key(inner) {
+            return Err(FilesError::Io(io::Error::from(io::ErrorKind::NotFound)));
+        }
+
+        if index.is_dir(inner) {
+            fs::create_dir(output)?;
+        }
+
+        // Links are created last, so no file is ever written through one.
+        let mut links = Vec::new();
+
+        for_each_item(archive, format, |item, contents| {
+            let Ok(relative) = item.path.strip_prefix(inner) else {
+                return Ok(());
+            };
+            // `inner` itself, when it is a file or link, becomes `output`.
+            let destination = if relative.as_os_str().is_empty() {
+                output.to_path_buf()
+            } else {
+                output.join(relative)
+            };
+
+            match item.kind {
+                EntryKind::Directory => fs::create_dir_all(&destination)?,
+                EntryKind::File => {
+                    if let Some(parent) = destination.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    let mut file = File::options()
+                        .write(true)
+                        .create_new(true)
+                        .open(&destination)?;
+                    io::copy(contents, &mut file)?;
+                    set_mode(&destination, item.metadata.mode)?;
+                }
+                EntryKind::Symlink => {
+                    if let Some(target) = &item.link_target {
+                        links.push((target.clone(), destination));
+                    }
+                }
+                // Special files are not recreated.
+                _ => {}
+            }
+
+            Ok(())
+        })?;
+
+        for (target, link) in links {
+            create_symlink(&target, &link)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn create_symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+impl<F: FileSystem> FileSystem for ArchiveFileSystem<F> {
+    fn read_directory(&self, path: &Path) -> Result<Vec<FileEntry>, FilesError> {
+        let Some((archive, inner)) = split_archive_path(path) else {
+            return self.host.read_directory(path);
+        };
+
+        let inner = normalize(&inner);
+        let index = self.index(&archive)?;
+
+        if !index.is_dir(&inner) {
+            return Err(FilesError::InvalidPath);
+        }
+
+        // Report entries under the path the caller asked for.
+        Ok(index
+            .children(&inner)
+            .map(|item| {
+                let mut entry = self.entry(&index, &archive, item);
+                entry.path = path.join(&entry.name);
+                entry
+            })
+            .collect())
+    }
+
+    fn read_directory_chunks(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<Chunks<'_>, FilesError> {
+        match split_archive_path(path) {
+            Some(_) => Ok(chunked(self.read_directory(path)?, chunk_size)),
+            None => self.host.read_directory_chunks(path, chunk_size),
+        }
+    }
+
+    fn read_entry(&self, path: &Path) -> Result<FileEntry, FilesError> {
+        match split_archive_path(path) {
+            Some(_) => {
+                entry_at(self, path).ok_or_else(|| FilesError::Io(io::ErrorKind::NotFound.into()))
+            }
+            None => self.host.read_entry(path),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        if split_archive_path(from).is_some() || split_archive_path(&normalize(to)).is_some() {
+            return Err(FilesError::ReadOnly);
+        }
+        self.host.rename(from, to)
+    }
+
+    /// Copying out of an archive extracts to the host, and never replaces
+    /// an existing destination.
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        if split_archive_path(&normalize(to)).is_some() {
+            return Err(FilesError::ReadOnly);
+        }
+
+        match split_archive_path(from) {
+            Some((archive, inner)) => {
+                if self.host.canonicalize(to).is_ok() {
+                    return Err(FilesError::Io(io::ErrorKind::AlreadyExists.into()));
+                }
+                self.extract_from(&archive, &normalize(&inner), to)
+            }
+            None => self.host.copy(from, to),
+        }
+    }
+
+    /// Entries copied out of an archive keep what extraction gave them.
+    fn copy_metadata(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        if split_archive_path(&normalize(to)).is_some() {
+            return Err(FilesError::ReadOnly);
+        }
+
+        match split_archive_path(from) {
+            Some(_) => Ok(()),
+            None => self.host.copy_metadata(from, to),
+        }
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> Result<(), FilesError> {
+        match split_archive_path(&normalize(path)) {
+            Some(_) => Err(FilesError::ReadOnly),
+            None => self.host.set_permissions(path, mode),
+        }
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<(), FilesError> {
+        match split_archive_path(&normalize(path)) {
+            Some(_) => Err(FilesError::ReadOnly),
+            None => self.host.set_owner(path, uid, gid),
+        }
+    }
+
+    fn set_times(
+        &self,
+        path: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<(), FilesError> {
+        match split_archive_path(&normalize(path)) {
+            Some(_) => Err(FilesError::ReadOnly),
+            None => self.host.set_times(path, accessed, modified),
+        }
+    }
+
+    fn move_to(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        if split_archive_path(from).is_some() || split_archive_path(&normalize(to)).is_some() {
+            return Err(FilesError::ReadOnly);
+        }
+        self.host.move_to(from, to)
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), FilesError> {
+        match split_archive_path(path) {
+            // Deleting the archive file itself is a host operation.
+            Some((_, inner)) if !inner.as_os_str().is_empty() => Err(FilesError::ReadOnly),
+            _ => self.host.delete(path),
+        }
+    }
+
+    fn create_file(&self, path: &Path) -> Result<(), FilesError> {
+        if split_archive_path(path).is_some() {
+            return Err(FilesError::ReadOnly);
+        }
+        self.host.create_file(path)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), FilesError> {
+        if split_archive_path(path).is_some() {
+            return Err(FilesError::ReadOnly);
+        }
+        self.host.create_dir(path)
+    }
+
+    /// Links can point into an archive, but not be created in one.
+    fn create_symlink(&self, target: &Path, link: &Path) -> Result<(), FilesError> {
+        if split_archive_path(&normalize(link)).is_some() {
+            return Err(FilesError::ReadOnly);
+        }
+        self.host.create_symlink(target, link)
+    }
+
+    fn create_hardlink(&self, original: &Path, link: &Path) -> Result<(), FilesError> {
+        if split_archive_path(&normalize(original)).is_some()
+            || split_archive_path(&normalize(link)).is_some()
+        {
+            return Err(FilesError::ReadOnly);
+        }
+        self.host.create_hardlink(original, link)
+    }
+
+    /// Archive entries carry no attributes of their own.
+    fn list_xattrs(&self, path: &Path) -> Result<Vec<OsString>, FilesError> {
+        match split_archive_path(&normalize(path)) {
+            Some(_) => self.read_entry(path).map(|_| Vec::new()),
+            None => self.host.list_xattrs(path),
+        }
+    }
+
+    fn get_xattr(&self, path: &Path, name: &OsStr) -> Result<Option<Vec<u8>>, FilesError> {
+        match split_archive_path(&normalize(path)) {
+            Some(_) => self.read_entry(path).map(|_| None),
+            None => self.host.get_xattr(path, name),
+        }
+    }
+
+    fn set_xattr(&self, path: &Path, name: &OsStr, value: &[u8]) -> Result<(), FilesError> {
+        match split_archive_path(&normalize(path)) {
+            Some(_) => Err(FilesError::ReadOnly),
+            None => self.host.set_xattr(path, name, value),
+        }
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &OsStr) -> Result<(), FilesError> {
+        match split_archive_path(&normalize(path)) {
+            Some(_) => Err(FilesError::ReadOnly),
+            None => self.host.remove_xattr(path, name),
+        }
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        let Some((archive, inner)) = split_archive_path(path) else {
+            return self.host.canonicalize(path);
+        };
+
+        let inner = normalize(&inner);
+        let index = self.index(&archive)?;
+
+        if !index.is_dir(&inner) && !index.items.contains_key(&inner) {
+            return Err(FilesError::Io(io::Error::from(io::ErrorKind::NotFound)));
+        }
+
+        Ok(self.host.canonicalize(&archive)?.join(inner))
+    }
+
+    fn root(&self) -> Option<&Path> {
+        self.host.root()
+    }
+
+    fn can_enter(&self, entry: &FileEntry) -> bool {
+        if entry.is_dir() {
+            return true;
+        }
+
+        // Archives nested inside archives are not opened.
+        entry.kind == EntryKind::File
+            && is_archive_name(&entry.path)
+            && entry
+                .path
+                .parent()
+                .is_none_or(|parent| split_archive_path(parent).is_none())
+    }
+
+    fn extract(&self, path: &Path, destination: &Path) -> Result<(), FilesError> {
+        let (archive, inner) = split_archive_path(path).ok_or(FilesError::InvalidPath)?;
+        let inner = normalize(&inner);
+
+        let name = if inner.as_os_str().is_empty() {
+            archive_stem(&archive)
+        } else {
+            inner
+                .file_name()
+                .map(OsString::from)
+                .ok_or(FilesError::InvalidPath)?
+        };
+
+        self.extract_from(&archive, &inner, &destination.join(name))
+    }
+
+    fn trash(&self, path: &Path) -> Result<(), FilesError> {
+        match split_archive_path(path) {
+            Some((_, inner)) if !inner.as_os_str().is_empty() => Err(FilesError::ReadOnly),
+            _ => self.host.trash(path),
+        }
+    }
+
+    fn restore(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        self.host.restore(path)
+    }
+
+    fn empty_trash(&self) -> Result<(), FilesError> {
+        self.host.empty_trash()
+    }
+}
+
+/// The archive's file name without its archive extensions.
+fn archive_stem(archive: &Path) -> OsString {
+    let name = archive
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let lower = name.to_ascii_lowercase();
+
+    for extension in [".tar.gz", ".tar.zst", ".tgz", ".tzst", ".tar", ".zip"] {
+        if lower.ends_with(extension) && lower.len() > extension.len() {
+            return OsString::from(&name[..name.len() - extension.len()]);
+        }
+    }
+
+    OsString::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use crate::state::AppState;
+    use crate::state::Command;
+    use crate::state::test_utils::temp_dir;
+
+    fn write_tar(path: &Path) {
+        let file = File::create(path).unwrap();
+        let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o755);
+        header.set_mtime(1_700_000_000);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "release/bin/tool", &b"#!/sh"[..])
+            .unwrap();
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(2);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "release/README", &b"hi"[..])
+            .unwrap();
+
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_cksum();
+        builder
+            .append_link(&mut header, "release/tool", "bin/tool")
+            .unwrap();
+
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn write_zip(path: &Path) {
+        let file = File::create(path).unwrap();
+        let mut writer = zip::ZipWriter::new(file);
+        let options = zip::write::SimpleFileOptions::default();
+
+        writer.start_file("docs/guide.md", options).unwrap();
+        writer.write_all(b"# Guide").unwrap();
+        writer.add_directory("empty/", options).unwrap();
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn lists_tar_gz_as_directories() {
+        let dir = temp_dir("archive-tar");
+        let archive = dir.join("release.tar.gz");
+        write_tar(&archive);
+
+        let fs = ArchiveFileSystem::new();
+
+        let root = fs.read_directory(&archive).unwrap();
+        assert_eq!(root.len(), 1);
+        assert_eq!(root[0].name, "release");
+        assert!(root[0].is_dir());
+
+        let release = fs.read_directory(&archive.join("release")).unwrap();
+        let names: Vec<_> = release.iter().map(|e| e.name.clone()).collect();
+        assert_eq!(names, ["README", "bin", "tool"]);
+
+        let link = release.iter().find(|e| e.name == "tool").unwrap();
+        assert_eq!(
+            link.link.as_ref().unwrap().resolved.as_deref(),
+            Some(archive.join("release/bin/tool").as_path())
+        );
+
+        let bin = fs.read_directory(&archive.join("release/bin")).unwrap();
+        assert_eq!(bin[0].metadata.size, 5);
+        assert_eq!(bin[0].metadata.mode, 0o755);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lists_zip_entries() {
+        let dir = temp_dir("archive-zip");
+        let archive = dir.join("docs.zip");
+        write_zip(&archive);
+
+        let fs = ArchiveFileSystem::new();
+
+        let root = fs.read_directory(&archive).unwrap();
+        let names: Vec<_> = root.iter().map(|e| e.name.clone()).collect();
+        assert_eq!(names, ["docs", "empty"]);
+
+        let docs = fs.read_directory(&archive.join("docs")).unwrap();
+        assert_eq!(docs[0].name, "guide.md");
+        assert_eq!(docs[0].metadata.size, 7);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn archive_contents_are_read_only() {
+        let dir = temp_dir("archive-read-only");
+        let archive = dir.join("docs.zip");
+        write_zip(&archive);
+
+        let fs = ArchiveFileSystem::new();
+
+        assert!(matches!(
+            fs.create_file(&archive.join("new.txt")),
+            Err(FilesError::ReadOnly)
+        ));
+        assert!(matches!(
+            fs.delete(&archive.join("docs")),
+            Err(FilesError::ReadOnly)
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn extracts_subtree_to_host() {
+        let dir = temp_dir("archive-extract");
+        let archive = dir.join("release.tar.gz");
+        write_tar(&archive);
+        let output = dir.join("out");
+        fs::create_dir(&output).unwrap();
+
+        let fs = ArchiveFileSystem::new();
+        fs.extract(&archive.join("release/bin"), &output).unwrap();
+        fs.extract(&archive, &output).unwrap();
+
+        assert_eq!(fs::read(output.join("bin/tool")).unwrap(), b"#!/sh");
+        assert_eq!(
+            fs::read(output.join("release/release/README")).unwrap(),
+            b"hi"
+        );
+        assert!(
+            fs::symlink_metadata(output.join("release/release/tool"))
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn extracts_a_single_file_or_link() {
+        let dir = temp_dir("archive-extract-file");
+        let archive = dir.join("release.tar.gz");
+        write_tar(&archive);
+        let output = dir.join("out");
+        fs::create_dir(&output).unwrap();
+
+        let fs = ArchiveFileSystem::new();
+        fs.extract(&archive.join("release/README"), &output)
+            .unwrap();
+        fs.extract(&archive.join("release/tool"), &output).unwrap();
+
+        assert_eq!(fs::read(output.join("README")).unwrap(), b"hi");
+        assert!(
+            fs::symlink_metadata(output.join("tool"))
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn app_state_enters_archive_and_goes_back_out() {
+        let dir = temp_dir("archive-navigation");
+        let archive = dir.join("docs.zip");
+        write_zip(&archive);
+        fs::write(dir.join("notes.txt"), "n").unwrap();
+
+        let fs = ArchiveFileSystem::new();
+        let entries = fs.read_directory(&dir).unwrap();
+        let mut state = AppState::new(dir.clone(), entries, fs);
+        assert_eq!(state.cursor().unwrap().name, "docs.zip");
+
+        state.handle_command(Command::Enter).unwrap();
+        assert_eq!(state.current_directory(), archive);
+        assert_eq!(state.cursor().unwrap().name, "docs");
+
+        state.handle_command(Command::Enter).unwrap();
+        assert_eq!(state.cursor().unwrap().name, "guide.md");
+
+        state.handle_command(Command::GoUp).unwrap();
+        state.handle_command(Command::GoUp).unwrap();
+        assert_eq!(state.current_directory(), dir);
+        assert_eq!(state.cursor().unwrap().name, "docs.zip");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
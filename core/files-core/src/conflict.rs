@@ -0,0 +1,276 @@
+//! What to do when an operation's destination already exists.
+
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    errors::FilesError,
+    models::{EntryKind, FileEntry},
+};
+
+/// How to resolve a destination that already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// Stop with [`FilesError::AlreadyExists`].
+    #[default]
+    Fail,
+    /// Replace the existing entry. A directory landing on a directory is
+    /// merged into it instead, entry by entry, and a directory and a file
+    /// never replace one another.
+    Overwrite,
+    /// Leave the existing entry alone and skip the new one.
+    Skip,
+    /// Keep both, giving the new entry a free name such as `name (1).txt`.
+    KeepBoth,
+    /// Overwrite only if the new entry was modified more recently, and skip
+    /// it otherwise.
+    OverwriteIfNewer,
+}
+
+/// An entry about to land where another one already exists.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    /// The entry being written: the source of a copy or move, or the entry
+    /// about to be created.
+    pub source: FileEntry,
+    pub existing: FileEntry,
+}
+
+/// What a [`Policy`] decided for a [`Conflict`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// Write to this free path instead.
+    Write(PathBuf),
+    /// Replace the existing entry. Only given when neither is a
+    /// directory, so that writing over it replaces it in one go.
+    Replace,
+    /// Merge the source directory into the existing one.
+    Merge,
+    Skip,
+}
+
+impl Conflict {
+    /// Whether the source was modified after the existing entry. An entry
+    /// without a modification time is never newer.
+    pub fn source_is_newer(&self) -> bool {
+        match (
+            self.source.metadata.modified,
+            self.existing.metadata.modified,
+        ) {
+            (Some(source), Some(existing)) => source > existing,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    /// Applies `policy`. `taken` tells whether a name is in use in the
+    /// destination directory, to pick a free one for [`Policy::KeepBoth`].
+    ///
+    /// Overwriting a directory with a file fails with `IsADirectory`, and a
+    /// file with a directory with `NotADirectory`, as rename(2) does, rather
+    /// than deleting what is in the way.
+    pub fn resolve(
+        &self,
+        policy: Policy,
+        taken: impl Fn(&OsStr) -> bool,
+    ) -> Result<Resolution, FilesError> {
+        let source_is_dir = self.source.kind == EntryKind::Directory;
+        let existing_is_dir = self.existing.kind == EntryKind::Directory;
+        let merge = source_is_dir && existing_is_dir;
+
+        match policy {
+            Policy::Fail => Err(FilesError::AlreadyExists(self.existing.path.clone())),
+            Policy::Skip => Ok(Resolution::Skip),
+            Policy::Overwrite | Policy::OverwriteIfNewer if source_is_dir != existing_is_dir => {
+                let kind = if existing_is_dir {
+                    io::ErrorKind::IsADirectory
+                } else {
+                    io::ErrorKind::NotADirectory
+                };
+                Err(FilesError::Io(kind.into()))
+            }
+            Policy::OverwriteIfNewer if !merge && !self.source_is_newer() => Ok(Resolution::Skip),
+            Policy::Overwrite | Policy::OverwriteIfNewer if merge => Ok(Resolution::Merge),
+            Policy::Overwrite | Policy::OverwriteIfNewer => Ok(Resolution::Replace),
+            Policy::KeepBoth => {
+                let name = keep_both_name(&self.existing.name, taken);
+                Ok(Resolution::Write(self.existing.path.with_file_name(name)))
+            }
+        }
+    }
+}
+
+/// The first of `name (1)`, `name (2)`, ... that is not `taken`.
+///
+/// The number goes before the extension, and before `.tar` for compressed
+/// tarballs: `report (1).txt`, `backup (1).tar.gz`. A name that is already
+/// numbered is counted on rather than numbered again.
+pub fn keep_both_name(name: &OsStr, taken: impl Fn(&OsStr) -> bool) -> OsString {
+    let path = Path::new(name);
+    let (mut stem, mut extension) = match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(extension)) => (stem.to_os_string(), Some(extension.to_os_string())),
+        _ => (name.to_os_string(), None),
+    };
+
+    if let Some(ext) = &extension
+        && Path::new(&stem).extension() == Some(OsStr::new("tar"))
+    {
+        let mut joined = OsString::from("tar.");
+        joined.push(ext);
+        stem = Path::new(&stem)
+            .file_stem()
+            .unwrap_or_default()
+            .to_os_string();
+        extension = Some(joined);
+    }
+
+    let (base, first) = match stem.to_str().and_then(split_number) {
+        Some((base, number)) => (OsString::from(base), number + 1),
+        None => (stem, 1),
+    };
+
+    (first..)
+        .map(|n| {
+            let mut candidate = base.clone();
+            candidate.push(format!(" ({})", n));
+            if let Some(extension) = &extension {
+                candidate.push(".");
+                candidate.push(extension);
+            }
+            candidate
+        })
+        .find(|candidate| !taken(candidate))
+        .expect("some number is free")
+}
+
+/// Splits `name (3)` into `name` and 3.
+fn split_number(stem: &str) -> Option<(&str, u64)> {
+    let (base, rest) = stem.strip_suffix(')')?.rsplit_once(" (")?;
+    let number = rest.parse().ok().filter(|_| !base.is_empty())?;
+    Some((base, number))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::models::EntryMetadata;
+
+    fn entry(path: &str, kind: EntryKind, modified: u64) -> FileEntry {
+        let path = PathBuf::from(path);
+        FileEntry::new(path.file_name().unwrap(), path.clone(), kind).with_metadata(EntryMetadata {
+            modified: Some(UNIX_EPOCH + Duration::from_secs(modified)),
+            ..EntryMetadata::default()
+        })
+    }
+
+    fn none_taken(_: &OsStr) -> bool {
+        false
+    }
+
+    #[test]
+    fn keep_both_numbers_before_the_extension() {
+        let name = |name: &str| keep_both_name(OsStr::new(name), none_taken);
+
+        assert_eq!(name("report.txt"), "report (1).txt");
+        assert_eq!(name("backup.tar.gz"), "backup (1).tar.gz");
+        assert_eq!(name(".bashrc"), ".bashrc (1)");
+        assert_eq!(name("photos"), "photos (1)");
+        assert_eq!(name("report (1).txt"), "report (2).txt");
+    }
+
+    #[test]
+    fn keep_both_skips_taken_names() {
+        let taken = |name: &OsStr| name == "a (1).txt" || name == "a (2).txt";
+
+        assert_eq!(keep_both_name(OsStr::new("a.txt"), taken), "a (3).txt");
+    }
+
+    #[test]
+    fn policies_resolve_conflicts() {
+        let conflict = Conflict {
+            source: entry("/src/a.txt", EntryKind::File, 200),
+            existing: entry("/dst/a.txt", EntryKind::File, 100),
+        };
+        let resolve = |policy| conflict.resolve(policy, none_taken);
+
+        assert!(matches!(
+            resolve(Policy::Fail),
+            Err(FilesError::AlreadyExists(path)) if path == Path::new("/dst/a.txt")
+        ));
+        assert_eq!(resolve(Policy::Overwrite).unwrap(), Resolution::Replace);
+        assert_eq!(resolve(Policy::Skip).unwrap(), Resolution::Skip);
+        assert_eq!(
+            resolve(Policy::KeepBoth).unwrap(),
+            Resolution::Write(PathBuf::from("/dst/a (1).txt"))
+        );
+        assert_eq!(
+            resolve(Policy::OverwriteIfNewer).unwrap(),
+            Resolution::Replace
+        );
+
+        let older = Conflict {
+            source: entry("/src/a.txt", EntryKind::File, 50),
+            ..conflict.clone()
+        };
+        assert_eq!(
+            older.resolve(Policy::OverwriteIfNewer, none_taken).unwrap(),
+            Resolution::Skip
+        );
+    }
+
+    #[test]
+    fn files_and_directories_never_replace_one_another() {
+        let file_over_directory = Conflict {
+            source: entry("/src/photos", EntryKind::File, 200),
+            existing: entry("/dst/photos", EntryKind::Directory, 100),
+        };
+        let directory_over_file = Conflict {
+            source: entry("/src/photos", EntryKind::Directory, 200),
+            existing: entry("/dst/photos", EntryKind::File, 100),
+        };
+        let kind = |conflict: &Conflict, policy| match conflict.resolve(policy, none_taken) {
+            Err(FilesError::Io(err)) => err.kind(),
+            other => panic!("expected an I/O error, got {:?}", other),
+        };
+
+        for policy in [Policy::Overwrite, Policy::OverwriteIfNewer] {
+            assert_eq!(
+                kind(&file_over_directory, policy),
+                io::ErrorKind::IsADirectory
+            );
+            assert_eq!(
+                kind(&directory_over_file, policy),
+                io::ErrorKind::NotADirectory
+            );
+        }
+        assert_eq!(
+            file_over_directory
+                .resolve(Policy::KeepBoth, none_taken)
+                .unwrap(),
+            Resolution::Write(PathBuf::from("/dst/photos (1)"))
+        );
+    }
+
+    #[test]
+    fn directories_merge_on_overwrite() {
+        let conflict = Conflict {
+            source: entry("/src/photos", EntryKind::Directory, 0),
+            existing: entry("/dst/photos", EntryKind::Directory, 0),
+        };
+
+        assert_eq!(
+            conflict.resolve(Policy::Overwrite, none_taken).unwrap(),
+            Resolution::Merge
+        );
+        assert_eq!(
+            conflict
+                .resolve(Policy::OverwriteIfNewer, none_taken)
+                .unwrap(),
+            Resolution::Merge
+        );
+    }
+}
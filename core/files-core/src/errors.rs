@@ -1,10 +1,23 @@
 use std::fmt;
 use std::io;
+use std::path::PathBuf;
 
 #[derive(Debug)]
 pub enum FilesError {
     Io(io::Error),
     InvalidPath,
+    /// A mutation was attempted on a read-only filesystem.
+    ReadOnly,
+    /// A path resolved outside the directory the filesystem is confined to.
+    OutsideRoot,
+    /// The filesystem does not support the requested operation.
+    Unsupported,
+    /// A destination already exists and the conflict policy is to fail.
+    AlreadyExists(PathBuf),
+    /// A permission mode that is neither octal nor symbolic.
+    InvalidMode(String),
+    /// A tag that is empty or contains a comma.
+    InvalidTag(String),
 }
 
 impl fmt::Display for FilesError {
@@ -12,6 +25,12 @@ impl fmt::Display for FilesError {
         match self {
             FilesError::Io(err) => write!(f, "IO error: {}", err),
             FilesError::InvalidPath => write!(f, "Invalid path provided"),
+            FilesError::ReadOnly => write!(f, "Filesystem is read-only"),
+            FilesError::OutsideRoot => write!(f, "Path is outside the permitted root"),
+            FilesError::Unsupported => write!(f, "Operation is not supported"),
+            FilesError::AlreadyExists(path) => write!(f, "{} already exists", path.display()),
+            FilesError::InvalidMode(mode) => write!(f, "Invalid mode: {:?}", mode),
+            FilesError::InvalidTag(tag) => write!(f, "Invalid tag: {:?}", tag),
         }
     }
 }
@@ -0,0 +1,501 @@
+use std::ffi::{OsStr, OsString};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use super::{Chunks, FileSystem, TRASH};
+use crate::{
+    errors::FilesError,
+    models::{FileEntry, escape_name},
+};
+
+/// Writes one JSON line per mutating call to a journal.
+///
+/// Each line records when the call finished, who made it, the operation,
+/// its source and destination paths, and whether it succeeded:
+///
+/// ```text
+/// {"timestamp":"2026-01-02T03:04:05.678Z","user":"alice","user_id":1000,"operation":"rename","source":"/a","destination":"/b","result":"ok"}
+/// {"timestamp":"2026-01-02T03:04:06.001Z","user":"alice","user_id":1000,"operation":"delete","source":"/c","destination":null,"result":"error","error":"IO error: ..."}
+/// ```
+///
+/// The user is the process's real uid and its name in the user database,
+/// never the environment, which any caller can set.
+///
+/// Changes of mode, owner and times add `"mode"` (octal), `"uid"` and
+/// `"gid"`, or `"accessed"` and `"modified"` fields before the result;
+/// attribute changes add the attribute `"name"` and, when set, its
+/// `"value"`.
+/// Links record their target, as stored, as the source and the new link as
+/// the destination. Paths use the lossless `escape_name` form.
+///
+/// Reads are not recorded. If the journal cannot be written, a call that
+/// otherwise succeeded returns that error, since the change happened
+/// without a record of it.
+pub struct Audited<F: FileSystem> {
+    inner: F,
+    journal: Mutex<Box<dyn Write + Send>>,
+    user: String,
+    user_id: Option<u32>,
+}
+
+impl<F: FileSystem> Audited<F> {
+    /// Journals to `writer`, as the user running the process.
+    pub fn new(inner: F, writer: impl Write + Send + 'static) -> Self {
+        let (user, user_id) = current_user();
+        Self {
+            inner,
+            journal: Mutex::new(Box::new(writer)),
+            user,
+            user_id,
+        }
+    }
+
+    /// Appends the journal to the file at `path`, creating it if needed.
+    pub fn open(inner: F, path: &Path) -> Result<Self, FilesError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(inner, file))
+    }
+
+    /// Records `user` and `user_id` instead of the user running the
+    /// process.
+    pub fn with_user(mut self, user: impl Into<String>, user_id: u32) -> Self {
+        self.user = user.into();
+        self.user_id = Some(user_id);
+        self
+    }
+
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    fn record(
+        &self,
+        operation: &str,
+        source: &Path,
+        destination: Option<&Path>,
+        result: Result<(), FilesError>,
+    ) -> Result<(), FilesError> {
+        self.record_with(operation, source, destination, &[], result)
+    }
+
+    /// Like `record`, with extra fields given as already encoded JSON
+    /// values.
+    fn record_with(
+        &self,
+        operation: &str,
+        source: &Path,
+        destination: Option<&Path>,
+        fields: &[(&str, String)],
+        result: Result<(), FilesError>,
+    ) -> Result<(), FilesError> {
+        let mut line = format!(
+            "{{\"timestamp\":{},\"user\":{},\"user_id\":{},\"operation\":{},\"source\":{},\"destination\":{}",
+            json_string(&rfc3339(SystemTime::now())),
+            json_string(&self.user),
+            self.user_id
+                .map_or_else(|| "null".to_string(), |uid| uid.to_string()),
+            json_string(operation),
+            json_path(source),
+            destination.map_or_else(|| "null".to_string(), json_path),
+        );
+        for (name, value) in fields {
+            line.push_str(&format!(",{}:{}", json_string(name), value));
+        }
+
+        match &result {
+            Ok(()) => line.push_str(",\"result\":\"ok\"}\n"),
+            Err(err) => {
+                line.push_str(",\"result\":\"error\",\"error\":");
+                line.push_str(&json_string(&err.to_string()));
+                line.push_str("}\n");
+            }
+        }
+
+        let mut journal = self.journal.lock().unwrap_or_else(|p| p.into_inner());
+        let written = journal
+            .write_all(line.as_bytes())
+            .and_then(|()| journal.flush());
+
+        match (result, written) {
+            (Ok(()), Err(err)) => Err(FilesError::Io(io::Error::new(
+                err.kind(),
+                format!("audit journal: {}", err),
+            ))),
+            (result, _) => result,
+        }
+    }
+}
+
+impl<F: FileSystem> FileSystem for Audited<F> {
+    fn read_directory(&self, path: &Path) -> Result<Vec<FileEntry>, FilesError> {
+        self.inner.read_directory(path)
+    }
+
+    fn read_directory_chunks(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<Chunks<'_>, FilesError> {
+        self.inner.read_directory_chunks(path, chunk_size)
+    }
+
+    fn read_entry(&self, path: &Path) -> Result<FileEntry, FilesError> {
+        self.inner.read_entry(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let result = self.inner.rename(from, to);
+        self.record("rename", from, Some(to), result)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let result = self.inner.copy(from, to);
+        self.record("copy", from, Some(to), result)
+    }
+
+    fn copy_metadata(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let result = self.inner.copy_metadata(from, to);
+        self.record("copy_metadata", from, Some(to), result)
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> Result<(), FilesError> {
+        let result = self.inner.set_permissions(path, mode);
+        let mode = json_string(&format!("{:04o}", mode));
+        self.record_with("set_permissions", path, None, &[("mode", mode)], result)
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<(), FilesError> {
+        let result = self.inner.set_owner(path, uid, gid);
+        let id = |id: Option<u32>| id.map_or_else(|| "null".to_string(), |id| id.to_string());
+        let fields = [("uid", id(uid)), ("gid", id(gid))];
+        self.record_with("set_owner", path, None, &fields, result)
+    }
+
+    fn set_times(
+        &self,
+        path: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<(), FilesError> {
+        let result = self.inner.set_times(path, accessed, modified);
+        let time = |time: Option<SystemTime>| {
+            time.map_or_else(|| "null".to_string(), |time| json_string(&rfc3339(time)))
+        };
+        let fields = [("accessed", time(accessed)), ("modified", time(modified))];
+        self.record_with("set_times", path, None, &fields, result)
+    }
+
+    fn move_to(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let result = self.inner.move_to(from, to);
+        self.record("move", from, Some(to), result)
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), FilesError> {
+        let result = self.inner.delete(path);
+        self.record("delete", path, None, result)
+    }
+
+    fn create_file(&self, path: &Path) -> Result<(), FilesError> {
+        let result = self.inner.create_file(path);
+        self.record("create_file", path, None, result)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), FilesError> {
+        let result = self.inner.create_dir(path);
+        self.record("create_dir", path, None, result)
+    }
+
+    fn create_symlink(&self, target: &Path, link: &Path) -> Result<(), FilesError> {
+        let result = self.inner.create_symlink(target, link);
+        self.record("create_symlink", target, Some(link), result)
+    }
+
+    fn create_hardlink(&self, original: &Path, link: &Path) -> Result<(), FilesError> {
+        let result = self.inner.create_hardlink(original, link);
+        self.record("create_hardlink", original, Some(link), result)
+    }
+
+    fn list_xattrs(&self, path: &Path) -> Result<Vec<OsString>, FilesError> {
+        self.inner.list_xattrs(path)
+    }
+
+    fn get_xattr(&self, path: &Path, name: &OsStr) -> Result<Option<Vec<u8>>, FilesError> {
+        self.inner.get_xattr(path, name)
+    }
+
+    fn set_xattr(&self, path: &Path, name: &OsStr, value: &[u8]) -> Result<(), FilesError> {
+        let result = self.inner.set_xattr(path, name, value);
+        let fields = [
+            ("name", json_string(&escape_name(name))),
+            ("value", json_string(&String::from_utf8_lossy(value))),
+        ];
+        self.record_with("set_xattr", path, None, &fields, result)
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &OsStr) -> Result<(), FilesError> {
+        let result = self.inner.remove_xattr(path, name);
+        let fields = [("name", json_string(&escape_name(name)))];
+        self.record_with("remove_xattr", path, None, &fields, result)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        self.inner.canonicalize(path)
+    }
+
+    fn root(&self) -> Option<&Path> {
+        self.inner.root()
+    }
+
+    fn can_enter(&self, entry: &FileEntry) -> bool {
+        self.inner.can_enter(entry)
+    }
+
+    fn extract(&self, path: &Path, destination: &Path) -> Result<(), FilesError> {
+        let result = self.inner.extract(path, destination);
+        self.record("extract", path, Some(destination), result)
+    }
+
+    fn trash(&self, path: &Path) -> Result<(), FilesError> {
+        let result = self.inner.trash(path);
+        self.record("trash", path, None, result)
+    }
+
+    fn restore(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        let restored = self.inner.restore(path);
+        let original = restored.as_ref().ok().cloned();
+        self.record("restore", path, original.as_deref(), restored.map(|_| ()))?;
+        Ok(original.expect("restore succeeded"))
+    }
+
+    fn empty_trash(&self) -> Result<(), FilesError> {
+        let result = self.inner.empty_trash();
+        self.record("empty_trash", Path::new(TRASH), None, result)
+    }
+}
+
+/// The name and real uid of the user running the process.
+#[cfg(unix)]
+fn current_user() -> (String, Option<u32>) {
+    // SAFETY: getuid(2) takes no arguments and always succeeds.
+    let uid = unsafe { libc::getuid() };
+    let name = user_name(uid).unwrap_or_else(|| "unknown".to_string());
+    (name, Some(uid))
+}
+
+#[cfg(not(unix))]
+fn current_user() -> (String, Option<u32>) {
+    ("unknown".to_string(), None)
+}
+
+/// The login name of `uid` in the user database, if it has one.
+#[cfg(unix)]
+fn user_name(uid: u32) -> Option<String> {
+    let mut buffer = vec![0u8; 1024];
+    loop {
+        // SAFETY: passwd is plain old data; getpwuid_r fills it in.
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut found = std::ptr::null_mut();
+        // SAFETY: entry, buffer and found outlive the call, which writes
+        // no more than buffer.len() bytes into buffer.
+        let code = unsafe {
+            libc::getpwuid_r(
+                uid,
+                &mut entry,
+                buffer.as_mut_ptr().cast(),
+                buffer.len(),
+                &mut found,
+            )
+        };
+        if code == libc::ERANGE && buffer.len() < 1 << 20 {
+            buffer.resize(buffer.len() * 2, 0);
+            continue;
+        }
+        if code != 0 || found.is_null() {
+            return None;
+        }
+
+        // SAFETY: on success pw_name points at a NUL-terminated string in
+        // buffer.
+        let name = unsafe { std::ffi::CStr::from_ptr(entry.pw_name) };
+        return Some(name.to_string_lossy().into_owned());
+    }
+}
+
+/// Formats `time` as UTC, e.g. `2026-01-02T03:04:05.678Z`.
+fn rfc3339(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn json_path(path: &Path) -> String {
+    json_string(&escape_name(path.as_os_str()))
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::memory::MemoryFileSystem;
+
+    /// A journal the test can read back.
+    #[derive(Clone, Default)]
+    struct Journal(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Journal {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Journal {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    fn audited() -> (Audited<MemoryFileSystem>, Journal) {
+        let fs = MemoryFileSystem::new();
+        fs.write_file(Path::new("/work/a.txt"), "a").unwrap();
+
+        let journal = Journal::default();
+        (
+            Audited::new(fs, journal.clone()).with_user("alice", 1000),
+            journal,
+        )
+    }
+
+    #[test]
+    fn records_one_line_per_mutation() {
+        let (fs, journal) = audited();
+
+        fs.rename(Path::new("/work/a.txt"), Path::new("/work/b.txt"))
+            .unwrap();
+        fs.create_dir(Path::new("/work/sub")).unwrap();
+        fs.read_directory(Path::new("/work")).unwrap();
+
+        let lines = journal.lines();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("{\"timestamp\":\""));
+        assert!(lines[0].ends_with(
+            "\"user\":\"alice\",\"user_id\":1000,\"operation\":\"rename\",\"source\":\"/work/a.txt\",\
+             \"destination\":\"/work/b.txt\",\"result\":\"ok\"}"
+        ));
+        assert!(lines[1].contains("\"operation\":\"create_dir\""));
+        assert!(lines[1].contains("\"destination\":null"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn records_the_real_uid_by_default() {
+        let journal = Journal::default();
+        let fs = Audited::new(MemoryFileSystem::new(), journal.clone());
+
+        fs.create_dir(Path::new("/work")).unwrap();
+
+        // SAFETY: getuid(2) takes no arguments and always succeeds.
+        let uid = unsafe { libc::getuid() };
+        let name = user_name(uid).unwrap_or_else(|| "unknown".to_string());
+        assert!(journal.lines()[0].contains(&format!(
+            "\"user\":{},\"user_id\":{},",
+            json_string(&name),
+            uid
+        )));
+    }
+
+    #[test]
+    fn records_failures_with_the_error() {
+        let (fs, journal) = audited();
+
+        assert!(fs.delete(Path::new("/work/missing")).is_err());
+
+        let lines = journal.lines();
+        assert!(lines[0].contains("\"result\":\"error\",\"error\":\"IO error: "));
+    }
+
+    #[test]
+    fn records_modes_and_owners() {
+        let (fs, journal) = audited();
+
+        fs.set_permissions(Path::new("/work/a.txt"), 0o640).unwrap();
+        fs.set_owner(Path::new("/work/a.txt"), None, Some(100))
+            .unwrap();
+
+        let lines = journal.lines();
+        assert!(lines[0].contains("\"destination\":null,\"mode\":\"0640\",\"result\":\"ok\""));
+        assert!(lines[1].contains("\"uid\":null,\"gid\":100,\"result\":\"ok\""));
+    }
+
+    #[test]
+    fn records_attribute_names_and_values() {
+        let (fs, journal) = audited();
+        let name = OsStr::new("user.xdg.tags");
+
+        fs.set_xattr(Path::new("/work/a.txt"), name, b"draft,final")
+            .unwrap();
+        fs.remove_xattr(Path::new("/work/a.txt"), name).unwrap();
+
+        let lines = journal.lines();
+        assert!(lines[0].contains(
+            "\"operation\":\"set_xattr\",\"source\":\"/work/a.txt\",\"destination\":null,\
+             \"name\":\"user.xdg.tags\",\"value\":\"draft,final\",\"result\":\"ok\""
+        ));
+        assert!(lines[1].contains("\"name\":\"user.xdg.tags\",\"result\":\"ok\""));
+    }
+
+    #[test]
+    fn escapes_paths_as_json() {
+        let (fs, journal) = audited();
+
+        fs.create_file(Path::new("/work/say \"hi\".txt")).unwrap();
+
+        assert!(journal.lines()[0].contains("\"source\":\"/work/say \\\"hi\\\".txt\""));
+    }
+
+    #[test]
+    fn formats_utc_timestamps() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_767_323_045_678);
+
+        assert_eq!(rfc3339(time), "2026-01-02T03:04:05.678Z");
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            rfc3339(UNIX_EPOCH - Duration::from_secs(86_400)),
+            "1969-12-31T00:00:00.000Z"
+        );
+    }
+}
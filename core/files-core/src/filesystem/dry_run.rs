@@ -0,0 +1,1327 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use super::{FileSystem, TRASH, join, normalize};
+use crate::{
+    errors::FilesError,
+    models::{EntryKind, EntryMetadata, FileEntry, LinkInfo, escape_name},
+    tags,
+};
+
+/// A mutation recorded by [`DryRun`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    CopyMetadata {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    SetPermissions {
+        path: PathBuf,
+        mode: u32,
+    },
+    SetOwner {
+        path: PathBuf,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    },
+    SetTimes {
+        path: PathBuf,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    },
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Delete(PathBuf),
+    CreateFile(PathBuf),
+    CreateDir(PathBuf),
+    CreateSymlink {
+        target: PathBuf,
+        link: PathBuf,
+    },
+    CreateHardlink {
+        original: PathBuf,
+        link: PathBuf,
+    },
+    SetXattr {
+        path: PathBuf,
+        name: OsString,
+        value: Vec<u8>,
+    },
+    RemoveXattr {
+        path: PathBuf,
+        name: OsString,
+    },
+    Extract {
+        path: PathBuf,
+        destination: PathBuf,
+    },
+    Trash(PathBuf),
+    Restore(PathBuf),
+    EmptyTrash,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Rename { from, to } => {
+                write!(f, "rename {} -> {}", from.display(), to.display())
+            }
+            Operation::Copy { from, to } => {
+                write!(f, "copy {} -> {}", from.display(), to.display())
+            }
+            Operation::CopyMetadata { from, to } => {
+                write!(f, "copy metadata {} -> {}", from.display(), to.display())
+            }
+            Operation::SetPermissions { path, mode } => {
+                write!(f, "set mode {:04o} on {}", mode, path.display())
+            }
+            Operation::SetOwner { path, uid, gid } => {
+                let id = |id: &Option<u32>| id.map_or_else(|| "-".to_string(), |id| id.to_string());
+                write!(f, "set owner {}:{} on {}", id(uid), id(gid), path.display())
+            }
+            Operation::SetTimes {
+                path,
+                accessed,
+                modified,
+            } => {
+                let time = |time: &Option<SystemTime>| {
+                    time.map_or_else(
+                        || "-".to_string(),
+                        |time| {
+                            DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Millis, true)
+                        },
+                    )
+                };
+                write!(
+                    f,
+                    "set times on {} (accessed {}, modified {})",
+                    path.display(),
+                    time(accessed),
+                    time(modified)
+                )
+            }
+            Operation::Move { from, to } => {
+                write!(f, "move {} -> {}", from.display(), to.display())
+            }
+            Operation::Delete(path) => write!(f, "delete {}", path.display()),
+            Operation::CreateFile(path) => write!(f, "create file {}", path.display()),
+            Operation::CreateDir(path) => write!(f, "create dir {}", path.display()),
+            Operation::CreateSymlink { target, link } => {
+                write!(f, "link {} -> {}", link.display(), target.display())
+            }
+            Operation::CreateHardlink { original, link } => {
+                write!(f, "hard link {} -> {}", link.display(), original.display())
+            }
+            Operation::SetXattr { path, name, value } => write!(
+                f,
+                "set attribute {}={:?} on {}",
+                escape_name(name),
+                String::from_utf8_lossy(value),
+                path.display()
+            ),
+            Operation::RemoveXattr { path, name } => {
+                write!(
+                    f,
+                    "remove attribute {} from {}",
+                    escape_name(name),
+                    path.display()
+                )
+            }
+            Operation::Extract { path, destination } => {
+                write!(f, "extract {} -> {}", path.display(), destination.display())
+            }
+            Operation::Trash(path) => write!(f, "trash {}", path.display()),
+            Operation::Restore(path) => write!(f, "restore {}", path.display()),
+            Operation::EmptyTrash => write!(f, "empty trash"),
+        }
+    }
+}
+
+/// An entry that exists only in the overlay.
+#[derive(Debug, Clone)]
+struct Added {
+    entry: FileEntry,
+    /// Where the entry's contents live on the inner filesystem, for entries
+    /// that were moved there by a rename; `None` for newly created ones.
+    source: Option<PathBuf>,
+    /// Extended attributes set, or removed with `None`, over those the
+    /// source has.
+    xattrs: Xattrs,
+}
+
+/// Pending changes to extended attributes, by name.
+type Xattrs = BTreeMap<OsString, Option<Vec<u8>>>;
+
+/// Pending changes on top of the inner filesystem.
+#[derive(Debug, Default)]
+struct Overlay {
+    /// Paths whose inner contents are hidden.
+    removed: BTreeSet<PathBuf>,
+    /// Entries shown in place of, or in addition to, the inner ones.
+    added: BTreeMap<PathBuf, Added>,
+    plan: Vec<Operation>,
+}
+
+/// Where the contents of a path come from.
+enum Backing {
+    Inner(PathBuf),
+    Overlay,
+    Missing,
+}
+
+impl Overlay {
+    fn backing(&self, path: &Path) -> Backing {
+        for ancestor in path.ancestors() {
+            if let Some(added) = self.added.get(ancestor) {
+                return match &added.source {
+                    Some(source) => {
+                        let rest = path.strip_prefix(ancestor).unwrap_or(Path::new(""));
+                        Backing::Inner(join(source, rest))
+                    }
+                    None if ancestor == path => Backing::Overlay,
+                    None => Backing::Missing,
+                };
+            }
+
+            if self.removed.contains(ancestor) {
+                return Backing::Missing;
+            }
+        }
+
+        Backing::Inner(path.to_path_buf())
+    }
+
+    /// The attribute changes pending for the entry at `path`.
+    fn pending(&self, path: &Path) -> Xattrs {
+        self.added
+            .get(path)
+            .map(|added| added.xattrs.clone())
+            .unwrap_or_default()
+    }
+
+    /// Moves overlay state recorded under `from` to `to`.
+    fn rekey(&mut self, from: &Path, to: &Path) {
+        let moved: Vec<_> = self
+            .added
+            .keys()
+            .filter(|path| path.starts_with(from))
+            .cloned()
+            .collect();
+
+        for old in moved {
+            if let Some(mut added) = self.added.remove(&old) {
+                let new = join(to, old.strip_prefix(from).unwrap_or(Path::new("")));
+                added.entry.path = new.clone();
+                if let Some(name) = new.file_name() {
+                    added.entry.name = name.to_os_string();
+                }
+                self.added.insert(new, added);
+            }
+        }
+
+        let hidden: Vec<_> = self
+            .removed
+            .iter()
+            .filter(|path| path.starts_with(from) && *path != from)
+            .cloned()
+            .collect();
+
+        for old in hidden {
+            self.removed.remove(&old);
+            self.removed
+                .insert(join(to, old.strip_prefix(from).unwrap_or(Path::new(""))));
+        }
+    }
+
+    /// Copies overlay state recorded under `from` to `to`.
+    fn duplicate(&mut self, from: &Path, to: &Path) {
+        let copies: Vec<_> = self
+            .added
+            .iter()
+            .filter(|(path, _)| path.starts_with(from))
+            .map(|(old, added)| {
+                let new = join(to, old.strip_prefix(from).unwrap_or(Path::new("")));
+                let mut added = added.clone();
+                added.entry.path = new.clone();
+                if let Some(name) = new.file_name() {
+                    added.entry.name = name.to_os_string();
+                }
+                (new, added)
+            })
+            .collect();
+        self.added.extend(copies);
+
+        let hidden: Vec<_> = self
+            .removed
+            .iter()
+            .filter(|path| path.starts_with(from) && *path != from)
+            .map(|old| join(to, old.strip_prefix(from).unwrap_or(Path::new(""))))
+            .collect();
+        self.removed.extend(hidden);
+    }
+
+    /// Forgets all overlay state at and below `path`, then hides it.
+    fn clear(&mut self, path: &Path) {
+        self.added.retain(|key, _| !key.starts_with(path));
+        self.removed.retain(|key| !key.starts_with(path));
+        self.removed.insert(path.to_path_buf());
+    }
+}
+
+fn io_error(kind: io::ErrorKind) -> FilesError {
+    FilesError::Io(io::Error::from(kind))
+}
+
+/// Records mutations as a plan instead of executing them.
+///
+/// Pending changes are kept in an overlay, so `read_directory` shows the
+/// tree as it will look once the plan runs, and mutations fail with the
+/// same errors the inner filesystem would give. The plan is then either
+/// applied with [`commit`](Self::commit) or dropped with
+/// [`discard`](Self::discard).
+///
+/// Extractions are recorded but do not show up in listings. Trashed items
+/// disappear from their directory but only show up in [`TRASH`] once the
+/// plan is committed; restoring them and emptying the trash only hide
+/// them there.
+#[derive(Debug)]
+pub struct DryRun<F: FileSystem> {
+    inner: F,
+    overlay: Mutex<Overlay>,
+}
+
+impl<F: FileSystem> DryRun<F> {
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            overlay: Mutex::new(Overlay::default()),
+        }
+    }
+
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    /// The recorded operations, oldest first.
+    pub fn plan(&self) -> Vec<Operation> {
+        self.lock().plan.clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().plan.is_empty()
+    }
+
+    /// Runs the plan against the inner filesystem.
+    ///
+    /// Stops at the first failing operation; it and the ones after it stay
+    /// pending.
+    pub fn commit(&self) -> Result<(), FilesError> {
+        let plan = std::mem::take(&mut *self.lock()).plan;
+
+        for (i, operation) in plan.iter().enumerate() {
+            if let Err(err) = self.apply(operation) {
+                for pending in &plan[i..] {
+                    // Replays fail when the inner tree changed under the
+                    // plan; keep the operation listed even then.
+                    if self.record(pending).is_err() {
+                        self.lock().plan.push(pending.clone());
+                    }
+                }
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drops every pending operation.
+    pub fn discard(&self) {
+        *self.lock() = Overlay::default();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Overlay> {
+        self.overlay.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    fn apply(&self, operation: &Operation) -> Result<(), FilesError> {
+        match operation {
+            Operation::Rename { from, to } => self.inner.rename(from, to),
+            Operation::Copy { from, to } => self.inner.copy(from, to),
+            Operation::CopyMetadata { from, to } => self.inner.copy_metadata(from, to),
+            Operation::SetPermissions { path, mode } => self.inner.set_permissions(path, *mode),
+            Operation::SetOwner { path, uid, gid } => self.inner.set_owner(path, *uid, *gid),
+            Operation::SetTimes {
+                path,
+                accessed,
+                modified,
+            } => self.inner.set_times(path, *accessed, *modified),
+            Operation::Move { from, to } => self.inner.move_to(from, to),
+            Operation::Delete(path) => self.inner.delete(path),
+            Operation::CreateFile(path) => self.inner.create_file(path),
+            Operation::CreateDir(path) => self.inner.create_dir(path),
+            Operation::CreateSymlink { target, link } => self.inner.create_symlink(target, link),
+            Operation::CreateHardlink { original, link } => {
+                self.inner.create_hardlink(original, link)
+            }
+            Operation::SetXattr { path, name, value } => self.inner.set_xattr(path, name, value),
+            Operation::RemoveXattr { path, name } => self.inner.remove_xattr(path, name),
+            Operation::Extract { path, destination } => self.inner.extract(path, destination),
+            Operation::Trash(path) => self.inner.trash(path),
+            Operation::Restore(path) => self.inner.restore(path).map(|_| ()),
+            Operation::EmptyTrash => self.inner.empty_trash(),
+        }
+    }
+
+    fn record(&self, operation: &Operation) -> Result<(), FilesError> {
+        match operation {
+            Operation::Rename { from, to } => self.rename(from, to),
+            Operation::Copy { from, to } => self.copy(from, to),
+            Operation::CopyMetadata { from, to } => self.copy_metadata(from, to),
+            Operation::SetPermissions { path, mode } => self.set_permissions(path, *mode),
+            Operation::SetOwner { path, uid, gid } => self.set_owner(path, *uid, *gid),
+            Operation::SetTimes {
+                path,
+                accessed,
+                modified,
+            } => self.set_times(path, *accessed, *modified),
+            Operation::Move { from, to } => self.move_to(from, to),
+            Operation::Delete(path) => self.delete(path),
+            Operation::CreateFile(path) => self.create_file(path),
+            Operation::CreateDir(path) => self.create_dir(path),
+            Operation::CreateSymlink { target, link } => self.create_symlink(target, link),
+            Operation::CreateHardlink { original, link } => self.create_hardlink(original, link),
+            Operation::SetXattr { path, name, value } => self.set_xattr(path, name, value),
+            Operation::RemoveXattr { path, name } => self.remove_xattr(path, name),
+            Operation::Extract { path, destination } => self.extract(path, destination),
+            Operation::Trash(path) => self.trash(path),
+            Operation::Restore(path) => self.restore(path).map(|_| ()),
+            Operation::EmptyTrash => self.empty_trash(),
+        }
+    }
+
+    fn list(&self, overlay: &Overlay, path: &Path) -> Result<Vec<FileEntry>, FilesError> {
+        // Trashed items keep their paths inside the trash.
+        if path == Path::new(TRASH) {
+            let mut entries = self.inner.read_directory(path)?;
+            entries.retain(|entry| !overlay.removed.contains(&entry.path));
+            if overlay.plan.contains(&Operation::EmptyTrash) {
+                entries.clear();
+            }
+            return Ok(entries);
+        }
+
+        let mut entries = match overlay.backing(path) {
+            Backing::Missing => return Err(FilesError::InvalidPath),
+            Backing::Overlay => match overlay.added.get(path) {
+                Some(added) if added.entry.kind == EntryKind::Directory => Vec::new(),
+                _ => return Err(FilesError::InvalidPath),
+            },
+            Backing::Inner(source) => {
+                let mut entries = self.inner.read_directory(&source)?;
+                for entry in &mut entries {
+                    entry.path = path.join(&entry.name);
+                }
+                entries.retain(|entry| {
+                    !overlay.removed.contains(&entry.path)
+                        && !overlay.added.contains_key(&entry.path)
+                });
+                entries
+            }
+        };
+
+        entries.extend(
+            overlay
+                .added
+                .iter()
+                .filter(|(key, _)| key.parent() == Some(path))
+                .map(|(_, added)| added.entry.clone()),
+        );
+
+        Ok(entries)
+    }
+
+    /// Looks up the entry at `path` as the plan would leave it.
+    fn entry(&self, overlay: &Overlay, path: &Path) -> Option<FileEntry> {
+        let parent = path.parent()?;
+        let name = path.file_name()?;
+
+        self.list(overlay, parent)
+            .ok()?
+            .into_iter()
+            .find(|entry| entry.name == name)
+    }
+
+    /// Looks up the entry at `path`, following a final link.
+    fn target(&self, overlay: &Overlay, path: &Path) -> Result<FileEntry, FilesError> {
+        let entry = self
+            .entry(overlay, path)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?;
+
+        match &entry.link {
+            Some(link) => link
+                .resolved
+                .as_ref()
+                .and_then(|resolved| self.entry(overlay, &normalize(resolved)))
+                .ok_or_else(|| io_error(io::ErrorKind::NotFound)),
+            None => Ok(entry),
+        }
+    }
+
+    fn is_dir(&self, overlay: &Overlay, path: &Path) -> bool {
+        self.list(overlay, path).is_ok()
+    }
+
+    /// Checks that `from` can be moved or copied to `to`, the way
+    /// rename(2) checks it, and returns the source entry.
+    fn check_destination(
+        &self,
+        overlay: &Overlay,
+        from: &Path,
+        to: &Path,
+    ) -> Result<FileEntry, FilesError> {
+        let source = self
+            .entry(overlay, from)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?;
+        let source_is_dir = source.kind == EntryKind::Directory;
+
+        if !to
+            .parent()
+            .is_some_and(|parent| self.is_dir(overlay, parent))
+        {
+            return Err(io_error(io::ErrorKind::NotFound));
+        }
+        if from == to {
+            return Ok(source);
+        }
+        if source_is_dir && to.starts_with(from) {
+            return Err(io_error(io::ErrorKind::InvalidInput));
+        }
+
+        if let Some(target) = self.entry(overlay, to) {
+            let target_is_dir = target.kind == EntryKind::Directory;
+
+            if target_is_dir && !source_is_dir {
+                return Err(io_error(io::ErrorKind::IsADirectory));
+            }
+            if !target_is_dir && source_is_dir {
+                return Err(io_error(io::ErrorKind::NotADirectory));
+            }
+            if target_is_dir && !self.list(overlay, to)?.is_empty() {
+                return Err(io_error(io::ErrorKind::DirectoryNotEmpty));
+            }
+        }
+
+        Ok(source)
+    }
+
+    /// Moves `from` to `to` in the overlay and records the move.
+    fn relocate(
+        &self,
+        from: &Path,
+        to: &Path,
+        operation: impl FnOnce(PathBuf, PathBuf) -> Operation,
+    ) -> Result<(), FilesError> {
+        let (from, to) = (normalize(from), normalize(to));
+        let mut overlay = self.lock();
+
+        let source = self.check_destination(&overlay, &from, &to)?;
+        if from == to {
+            return Ok(());
+        }
+
+        let backing = match overlay.backing(&from) {
+            Backing::Inner(source) => Some(source),
+            _ => None,
+        };
+        let was_added = overlay.added.contains_key(&from);
+
+        overlay.clear(&to);
+        overlay.rekey(&from, &to);
+
+        if !was_added {
+            let mut entry = source;
+            entry.path = to.clone();
+            if let Some(name) = to.file_name() {
+                entry.name = name.to_os_string();
+            }
+            overlay.added.insert(
+                to.clone(),
+                Added {
+                    entry,
+                    source: backing,
+                    xattrs: Xattrs::new(),
+                },
+            );
+        }
+
+        overlay.clear(&from);
+        overlay.plan.push(operation(from, to));
+        Ok(())
+    }
+
+    /// Shows `entry` with updated metadata or tags. Entries that only exist
+    /// on the inner filesystem are added in front of it, keeping their
+    /// contents there.
+    fn restat(overlay: &mut Overlay, mut entry: FileEntry, update: impl FnOnce(&mut FileEntry)) {
+        if let Some(added) = overlay.added.get_mut(&entry.path) {
+            update(&mut added.entry);
+        } else if let Backing::Inner(source) = overlay.backing(&entry.path) {
+            update(&mut entry);
+            overlay.added.insert(
+                entry.path.clone(),
+                Added {
+                    entry,
+                    source: Some(source),
+                    xattrs: Xattrs::new(),
+                },
+            );
+        }
+    }
+
+    /// Checks that `path` can be created: its parent is a directory and
+    /// nothing is there yet.
+    fn check_new(&self, overlay: &Overlay, path: &Path) -> Result<(), FilesError> {
+        if !path
+            .parent()
+            .is_some_and(|parent| self.is_dir(overlay, parent))
+        {
+            return Err(io_error(io::ErrorKind::NotFound));
+        }
+        if self.entry(overlay, path).is_some() {
+            return Err(io_error(io::ErrorKind::AlreadyExists));
+        }
+        Ok(())
+    }
+
+    /// The extended attributes of `path` as the plan would leave them.
+    fn xattrs(
+        &self,
+        overlay: &Overlay,
+        path: &Path,
+    ) -> Result<BTreeMap<OsString, Vec<u8>>, FilesError> {
+        if self.entry(overlay, path).is_none() {
+            return Err(io_error(io::ErrorKind::NotFound));
+        }
+
+        let mut xattrs = BTreeMap::new();
+        if let Backing::Inner(source) = overlay.backing(path) {
+            for name in self.inner.list_xattrs(&source)? {
+                if let Some(value) = self.inner.get_xattr(&source, &name)? {
+                    xattrs.insert(name, value);
+                }
+            }
+        }
+
+        for (name, value) in overlay.pending(path) {
+            match value {
+                Some(value) => xattrs.insert(name, value),
+                None => xattrs.remove(&name),
+            };
+        }
+        Ok(xattrs)
+    }
+
+    /// Sets, or with `None` removes, an extended attribute in the overlay,
+    /// and the tags shown for the entry along with it.
+    fn change_xattr(
+        &self,
+        overlay: &mut Overlay,
+        path: &Path,
+        name: &OsStr,
+        value: Option<Vec<u8>>,
+    ) -> Result<(), FilesError> {
+        let entry = self
+            .entry(overlay, path)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?;
+        // Links cannot take user attributes.
+        if entry.is_symlink() {
+            return Err(io_error(io::ErrorKind::PermissionDenied));
+        }
+        // Fail the way the inner filesystem would.
+        if let Backing::Inner(source) = overlay.backing(path) {
+            self.inner.get_xattr(&source, name)?;
+        }
+
+        Self::restat(overlay, entry, |entry| {
+            if name == tags::TAGS {
+                entry.tags = value.as_deref().map(tags::parse).unwrap_or_default();
+            }
+        });
+        if let Some(added) = overlay.added.get_mut(path) {
+            added.xattrs.insert(name.to_os_string(), value);
+        }
+        Ok(())
+    }
+
+    fn add_new(overlay: &mut Overlay, path: &Path, kind: EntryKind) {
+        let mode = match kind {
+            EntryKind::Directory => 0o755,
+            EntryKind::Symlink => 0o777,
+            _ => 0o644,
+        };
+
+        let entry = FileEntry::new(
+            path.file_name().unwrap_or_default().to_os_string(),
+            path.to_path_buf(),
+            kind,
+        )
+        .with_metadata(EntryMetadata {
+            modified: Some(SystemTime::now()),
+            mode,
+            ..EntryMetadata::default()
+        });
+
+        overlay.clear(path);
+        overlay.added.insert(
+            path.to_path_buf(),
+            Added {
+                entry,
+                source: None,
+                xattrs: Xattrs::new(),
+            },
+        );
+    }
+}
+
+impl<F: FileSystem> FileSystem for DryRun<F> {
+    fn read_directory(&self, path: &Path) -> Result<Vec<FileEntry>, FilesError> {
+        let overlay = self.lock();
+        self.list(&overlay, &normalize(path))
+    }
+
+    fn read_entry(&self, path: &Path) -> Result<FileEntry, FilesError> {
+        let overlay = self.lock();
+        self.entry(&overlay, &normalize(path))
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        self.relocate(from, to, |from, to| Operation::Rename { from, to })
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let (from, to) = (normalize(from), normalize(to));
+        let mut overlay = self.lock();
+
+        if from == to {
+            return Err(io_error(io::ErrorKind::InvalidInput));
+        }
+        let source = self.check_destination(&overlay, &from, &to)?;
+
+        let backing = match overlay.backing(&from) {
+            Backing::Inner(source) => Some(source),
+            _ => None,
+        };
+        let xattrs = overlay.pending(&from);
+
+        overlay.clear(&to);
+        overlay.duplicate(&from, &to);
+
+        if backing.is_some() {
+            let mut entry = source;
+            entry.path = to.clone();
+            if let Some(name) = to.file_name() {
+                entry.name = name.to_os_string();
+            }
+            overlay.added.insert(
+                to.clone(),
+                Added {
+                    entry,
+                    source: backing,
+                    xattrs,
+                },
+            );
+        }
+
+        overlay.plan.push(Operation::Copy { from, to });
+        Ok(())
+    }
+
+    fn copy_metadata(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let (from, to) = (normalize(from), normalize(to));
+        let mut overlay = self.lock();
+
+        let source = self
+            .entry(&overlay, &from)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?;
+        let target = self
+            .entry(&overlay, &to)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?;
+        // Backends without attributes copy none.
+        let xattrs = self.xattrs(&overlay, &from).ok();
+        let replaced = self.xattrs(&overlay, &to).ok();
+
+        Self::restat(&mut overlay, target, |entry| {
+            entry.metadata = EntryMetadata {
+                size: entry.metadata.size,
+                ..source.metadata
+            };
+            entry.tags = source.tags;
+        });
+        if let (Some(xattrs), Some(replaced), Some(added)) =
+            (xattrs, replaced, overlay.added.get_mut(&to))
+        {
+            added.xattrs = replaced.into_keys().map(|name| (name, None)).collect();
+            added
+                .xattrs
+                .extend(xattrs.into_iter().map(|(name, value)| (name, Some(value))));
+        }
+
+        overlay.plan.push(Operation::CopyMetadata { from, to });
+        Ok(())
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> Result<(), FilesError> {
+        let path = normalize(path);
+        let mut overlay = self.lock();
+
+        let target = self.target(&overlay, &path)?;
+        Self::restat(&mut overlay, target, |entry| {
+            entry.metadata.mode = mode & 0o7777
+        });
+
+        overlay.plan.push(Operation::SetPermissions { path, mode });
+        Ok(())
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<(), FilesError> {
+        let path = normalize(path);
+        let mut overlay = self.lock();
+
+        let target = self.target(&overlay, &path)?;
+        Self::restat(&mut overlay, target, |entry| {
+            let metadata = &mut entry.metadata;
+            metadata.uid = uid.unwrap_or(metadata.uid);
+            metadata.gid = gid.unwrap_or(metadata.gid);
+        });
+
+        overlay.plan.push(Operation::SetOwner { path, uid, gid });
+        Ok(())
+    }
+
+    fn set_times(
+        &self,
+        path: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<(), FilesError> {
+        let path = normalize(path);
+        let mut overlay = self.lock();
+
+        let target = self.target(&overlay, &path)?;
+        Self::restat(&mut overlay, target, |entry| {
+            let metadata = &mut entry.metadata;
+            metadata.accessed = accessed.or(metadata.accessed);
+            metadata.modified = modified.or(metadata.modified);
+        });
+
+        overlay.plan.push(Operation::SetTimes {
+            path,
+            accessed,
+            modified,
+        });
+        Ok(())
+    }
+
+    /// Shown like a rename; only the replay may copy across devices.
+    fn move_to(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        self.relocate(from, to, |from, to| Operation::Move { from, to })
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), FilesError> {
+        let path = normalize(path);
+        let mut overlay = self.lock();
+
+        if self.entry(&overlay, &path).is_none() {
+            return Err(io_error(io::ErrorKind::NotFound));
+        }
+
+        overlay.clear(&path);
+        overlay.plan.push(Operation::Delete(path));
+        Ok(())
+    }
+
+    fn create_file(&self, path: &Path) -> Result<(), FilesError> {
+        let path = normalize(path);
+        let mut overlay = self.lock();
+
+        if !path
+            .parent()
+            .is_some_and(|parent| self.is_dir(&overlay, parent))
+        {
+            return Err(io_error(io::ErrorKind::NotFound));
+        }
+        match self.entry(&overlay, &path) {
+            Some(entry) if entry.kind == EntryKind::Directory => {
+                return Err(io_error(io::ErrorKind::IsADirectory));
+            }
+            Some(_) => return Err(io_error(io::ErrorKind::AlreadyExists)),
+            None => {}
+        }
+
+        Self::add_new(&mut overlay, &path, EntryKind::File);
+        overlay.plan.push(Operation::CreateFile(path));
+        Ok(())
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), FilesError> {
+        let path = normalize(path);
+        let mut overlay = self.lock();
+
+        self.check_new(&overlay, &path)?;
+
+        Self::add_new(&mut overlay, &path, EntryKind::Directory);
+        overlay.plan.push(Operation::CreateDir(path));
+        Ok(())
+    }
+
+    fn create_symlink(&self, target: &Path, link: &Path) -> Result<(), FilesError> {
+        let link = normalize(link);
+        let mut overlay = self.lock();
+
+        self.check_new(&overlay, &link)?;
+
+        let resolved = link.parent().map(|parent| normalize(&parent.join(target)));
+        let pointee = resolved
+            .as_ref()
+            .and_then(|resolved| self.entry(&overlay, resolved));
+        let info = LinkInfo {
+            target: target.to_path_buf(),
+            target_kind: pointee.as_ref().and_then(|entry| match &entry.link {
+                Some(link) => link.target_kind,
+                None => Some(entry.kind),
+            }),
+            resolved: pointee.and(resolved),
+        };
+
+        Self::add_new(&mut overlay, &link, EntryKind::Symlink);
+        if let Some(added) = overlay.added.get_mut(&link) {
+            added.entry.metadata.size = target.as_os_str().len() as u64;
+            added.entry.link = Some(info);
+        }
+
+        overlay.plan.push(Operation::CreateSymlink {
+            target: target.to_path_buf(),
+            link,
+        });
+        Ok(())
+    }
+
+    fn create_hardlink(&self, original: &Path, link: &Path) -> Result<(), FilesError> {
+        let (original, link) = (normalize(original), normalize(link));
+        let mut overlay = self.lock();
+
+        let mut entry = self
+            .entry(&overlay, &original)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?;
+        if entry.kind == EntryKind::Directory {
+            return Err(io_error(io::ErrorKind::PermissionDenied));
+        }
+        self.check_new(&overlay, &link)?;
+
+        let source = match overlay.backing(&original) {
+            Backing::Inner(source) => Some(source),
+            _ => None,
+        };
+        entry.path = link.clone();
+        entry.name = link.file_name().unwrap_or_default().to_os_string();
+
+        // Both names share the attributes, pending changes included.
+        let xattrs = overlay.pending(&original);
+        overlay.clear(&link);
+        overlay.added.insert(
+            link.clone(),
+            Added {
+                entry,
+                source,
+                xattrs,
+            },
+        );
+
+        overlay
+            .plan
+            .push(Operation::CreateHardlink { original, link });
+        Ok(())
+    }
+
+    fn list_xattrs(&self, path: &Path) -> Result<Vec<OsString>, FilesError> {
+        let overlay = self.lock();
+        Ok(self
+            .xattrs(&overlay, &normalize(path))?
+            .into_keys()
+            .collect())
+    }
+
+    fn get_xattr(&self, path: &Path, name: &OsStr) -> Result<Option<Vec<u8>>, FilesError> {
+        let path = normalize(path);
+        let overlay = self.lock();
+
+        if self.entry(&overlay, &path).is_none() {
+            return Err(io_error(io::ErrorKind::NotFound));
+        }
+        if let Some(value) = overlay.pending(&path).remove(name) {
+            return Ok(value);
+        }
+
+        match overlay.backing(&path) {
+            Backing::Inner(source) => self.inner.get_xattr(&source, name),
+            _ => Ok(None),
+        }
+    }
+
+    fn set_xattr(&self, path: &Path, name: &OsStr, value: &[u8]) -> Result<(), FilesError> {
+        let path = normalize(path);
+        let mut overlay = self.lock();
+
+        self.change_xattr(&mut overlay, &path, name, Some(value.to_vec()))?;
+        overlay.plan.push(Operation::SetXattr {
+            path,
+            name: name.to_os_string(),
+            value: value.to_vec(),
+        });
+        Ok(())
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &OsStr) -> Result<(), FilesError> {
+        let path = normalize(path);
+        let mut overlay = self.lock();
+
+        self.change_xattr(&mut overlay, &path, name, None)?;
+        overlay.plan.push(Operation::RemoveXattr {
+            path,
+            name: name.to_os_string(),
+        });
+        Ok(())
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        let path = normalize(path);
+        let overlay = self.lock();
+
+        match overlay.backing(&path) {
+            Backing::Inner(source) if source == path => self.inner.canonicalize(&path),
+            Backing::Missing => Err(io_error(io::ErrorKind::NotFound)),
+            // Paths the plan creates or moves have no physical form yet.
+            _ => Ok(path),
+        }
+    }
+
+    fn root(&self) -> Option<&Path> {
+        self.inner.root()
+    }
+
+    fn can_enter(&self, entry: &FileEntry) -> bool {
+        self.inner.can_enter(entry)
+    }
+
+    fn extract(&self, path: &Path, destination: &Path) -> Result<(), FilesError> {
+        let (path, destination) = (normalize(path), normalize(destination));
+        let mut overlay = self.lock();
+
+        if self.entry(&overlay, &path).is_none() {
+            return Err(io_error(io::ErrorKind::NotFound));
+        }
+        if !self.is_dir(&overlay, &destination) {
+            return Err(io_error(io::ErrorKind::NotFound));
+        }
+
+        overlay.plan.push(Operation::Extract { path, destination });
+        Ok(())
+    }
+
+    fn trash(&self, path: &Path) -> Result<(), FilesError> {
+        let path = normalize(path);
+        let mut overlay = self.lock();
+
+        if self.entry(&overlay, &path).is_none() {
+            return Err(io_error(io::ErrorKind::NotFound));
+        }
+
+        overlay.clear(&path);
+        overlay.plan.push(Operation::Trash(path));
+        Ok(())
+    }
+
+    /// Returns the trashed path itself: where it goes back to is only
+    /// known once the plan runs.
+    fn restore(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        let path = normalize(path);
+        let mut overlay = self.lock();
+
+        let trashed = self.list(&overlay, Path::new(TRASH))?;
+        if !trashed.iter().any(|entry| entry.path == path) {
+            return Err(io_error(io::ErrorKind::NotFound));
+        }
+
+        overlay.clear(&path);
+        overlay.plan.push(Operation::Restore(path.clone()));
+        Ok(path)
+    }
+
+    fn empty_trash(&self) -> Result<(), FilesError> {
+        self.lock().plan.push(Operation::EmptyTrash);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::memory::MemoryFileSystem;
+    use crate::testing;
+
+    fn dry_run() -> DryRun<MemoryFileSystem> {
+        let fs = MemoryFileSystem::new();
+        fs.write_file(Path::new("/work/a/one.txt"), "1").unwrap();
+        fs.write_file(Path::new("/work/b.txt"), "22").unwrap();
+
+        DryRun::new(fs)
+    }
+
+    fn names(fs: &dyn FileSystem, dir: &str) -> Vec<String> {
+        let mut names: Vec<_> = fs
+            .read_directory(Path::new(dir))
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name.to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn trash_hides_entry_until_commit() {
+        let fs = dry_run();
+
+        fs.trash(Path::new("/work/b.txt")).unwrap();
+        fs.empty_trash().unwrap();
+
+        assert_eq!(names(&fs, "/work"), ["a"]);
+        assert_eq!(names(fs.inner(), "/work"), ["a", "b.txt"]);
+        assert_eq!(
+            fs.plan(),
+            [
+                Operation::Trash(PathBuf::from("/work/b.txt")),
+                Operation::EmptyTrash
+            ]
+        );
+    }
+
+    #[test]
+    fn mutations_are_recorded_not_executed() {
+        let fs = dry_run();
+
+        fs.rename(Path::new("/work/a"), Path::new("/work/c"))
+            .unwrap();
+        fs.delete(Path::new("/work/b.txt")).unwrap();
+        fs.create_file(Path::new("/work/c/two.txt")).unwrap();
+
+        assert_eq!(names(&fs, "/work"), ["c"]);
+        assert_eq!(names(&fs, "/work/c"), ["one.txt", "two.txt"]);
+        assert_eq!(names(fs.inner(), "/work"), ["a", "b.txt"]);
+
+        assert_eq!(
+            fs.plan(),
+            [
+                Operation::Rename {
+                    from: PathBuf::from("/work/a"),
+                    to: PathBuf::from("/work/c"),
+                },
+                Operation::Delete(PathBuf::from("/work/b.txt")),
+                Operation::CreateFile(PathBuf::from("/work/c/two.txt")),
+            ]
+        );
+    }
+
+    #[test]
+    fn commit_applies_the_plan() {
+        let fs = dry_run();
+
+        fs.rename(Path::new("/work/a"), Path::new("/work/c"))
+            .unwrap();
+        fs.create_file(Path::new("/work/c/two.txt")).unwrap();
+        fs.commit().unwrap();
+
+        assert!(fs.is_empty());
+        assert_eq!(names(fs.inner(), "/work/c"), ["one.txt", "two.txt"]);
+        assert_eq!(names(&fs, "/work"), ["b.txt", "c"]);
+    }
+
+    #[test]
+    fn copies_show_up_before_commit() {
+        let fs = dry_run();
+
+        fs.copy(Path::new("/work/a"), Path::new("/work/c")).unwrap();
+        fs.create_file(Path::new("/work/c/two.txt")).unwrap();
+        fs.move_to(Path::new("/work/b.txt"), Path::new("/work/a/b.txt"))
+            .unwrap();
+
+        assert_eq!(names(&fs, "/work"), ["a", "c"]);
+        assert_eq!(names(&fs, "/work/a"), ["b.txt", "one.txt"]);
+        assert_eq!(names(&fs, "/work/c"), ["one.txt", "two.txt"]);
+        assert_eq!(names(fs.inner(), "/work"), ["a", "b.txt"]);
+
+        fs.commit().unwrap();
+
+        assert_eq!(names(fs.inner(), "/work/a"), ["b.txt", "one.txt"]);
+        assert_eq!(names(fs.inner(), "/work/c"), ["one.txt", "two.txt"]);
+    }
+
+    #[test]
+    fn mode_changes_show_up_before_commit() {
+        let fs = dry_run();
+        let mode =
+            |fs: &dyn FileSystem, path: &str| fs.read_entry(Path::new(path)).unwrap().metadata.mode;
+
+        fs.set_permissions(Path::new("/work/a"), 0o700).unwrap();
+        fs.set_permissions(Path::new("/work/a/one.txt"), 0o600)
+            .unwrap();
+
+        assert_eq!(mode(&fs, "/work/a"), 0o700);
+        assert_eq!(mode(&fs, "/work/a/one.txt"), 0o600);
+        assert_eq!(names(&fs, "/work/a"), ["one.txt"]);
+        assert_eq!(mode(fs.inner(), "/work/a"), 0o755);
+        assert_eq!(fs.plan()[0].to_string(), "set mode 0700 on /work/a");
+
+        fs.commit().unwrap();
+
+        assert_eq!(mode(fs.inner(), "/work/a"), 0o700);
+        assert_eq!(mode(fs.inner(), "/work/a/one.txt"), 0o600);
+    }
+
+    #[test]
+    fn links_show_up_before_commit() {
+        let fs = dry_run();
+
+        fs.create_symlink(Path::new("a/one.txt"), Path::new("/work/one"))
+            .unwrap();
+        fs.create_hardlink(Path::new("/work/b.txt"), Path::new("/work/a/b.txt"))
+            .unwrap();
+
+        let link = fs.read_entry(Path::new("/work/one")).unwrap();
+        assert_eq!(
+            link.link.unwrap().resolved.as_deref(),
+            Some(Path::new("/work/a/one.txt"))
+        );
+        assert_eq!(names(&fs, "/work/a"), ["b.txt", "one.txt"]);
+        assert_eq!(fs.plan()[0].to_string(), "link /work/one -> a/one.txt");
+
+        fs.commit().unwrap();
+
+        assert_eq!(fs.inner().read_file(Path::new("/work/one")).unwrap(), b"1");
+        fs.inner()
+            .write_file(Path::new("/work/b.txt"), "changed")
+            .unwrap();
+        assert_eq!(
+            fs.inner().read_file(Path::new("/work/a/b.txt")).unwrap(),
+            b"changed"
+        );
+    }
+
+    #[test]
+    fn attributes_follow_renames_before_commit() {
+        let fs = dry_run();
+        let name = OsStr::new(tags::TAGS);
+        let comment = OsStr::new(tags::COMMENT);
+        fs.inner()
+            .set_xattr(Path::new("/work/b.txt"), comment, b"old")
+            .unwrap();
+
+        fs.set_xattr(Path::new("/work/b.txt"), name, b"draft")
+            .unwrap();
+        fs.remove_xattr(Path::new("/work/b.txt"), comment).unwrap();
+        fs.rename(Path::new("/work/b.txt"), Path::new("/work/c.txt"))
+            .unwrap();
+
+        let entry = fs.read_entry(Path::new("/work/c.txt")).unwrap();
+        assert_eq!(entry.tags, ["draft"]);
+        assert_eq!(
+            fs.list_xattrs(Path::new("/work/c.txt")).unwrap(),
+            [OsString::from(tags::TAGS)]
+        );
+        assert!(
+            fs.inner()
+                .get_xattr(Path::new("/work/b.txt"), name)
+                .unwrap()
+                .is_none()
+        );
+
+        fs.commit().unwrap();
+
+        let inner = fs.inner();
+        assert_eq!(
+            inner.read_entry(Path::new("/work/c.txt")).unwrap().tags,
+            ["draft"]
+        );
+        assert!(
+            inner
+                .get_xattr(Path::new("/work/c.txt"), comment)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn discard_drops_the_plan() {
+        let fs = dry_run();
+
+        fs.delete(Path::new("/work/a")).unwrap();
+        fs.discard();
+
+        assert!(fs.plan().is_empty());
+        assert_eq!(names(&fs, "/work"), ["a", "b.txt"]);
+    }
+
+    #[test]
+    fn failed_commit_keeps_remaining_operations() {
+        let fs = dry_run();
+
+        fs.delete(Path::new("/work/b.txt")).unwrap();
+        fs.create_dir(Path::new("/work/d")).unwrap();
+
+        // Something else takes the name before the plan runs.
+        fs.inner().write_file(Path::new("/work/d"), "").unwrap();
+
+        assert!(fs.commit().is_err());
+        assert!(!fs.inner().exists(Path::new("/work/b.txt")));
+        assert_eq!(fs.plan(), [Operation::CreateDir(PathBuf::from("/work/d"))]);
+    }
+
+    #[test]
+    fn dry_run_conforms_before_and_after_commit() {
+        let fs = DryRun::new(MemoryFileSystem::new());
+        fs.inner()
+            .create_dir_all(Path::new("/conformance"))
+            .unwrap();
+
+        testing::run(&fs, Path::new("/conformance"));
+
+        let planned = names(&fs, "/conformance/rename_moves_directory_with_contents/b");
+        fs.commit().unwrap();
+        assert_eq!(
+            names(
+                fs.inner(),
+                "/conformance/rename_moves_directory_with_contents/b"
+            ),
+            planned
+        );
+    }
+}
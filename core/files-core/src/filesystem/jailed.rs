@@ -0,0 +1,390 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::{Chunks, FileSystem, normalize};
+use crate::{errors::FilesError, models::FileEntry};
+
+/// Confines every operation to a root directory.
+///
+/// Each path is canonicalized through the inner filesystem before use, so
+/// `..`, absolute paths and symbolic links that lead outside the root are
+/// refused with `FilesError::OutsideRoot`. Relative paths are taken
+/// relative to the root.
+///
+/// A final symbolic link is only followed where the operation itself
+/// follows it (`read_directory`, `create_file`), so a link pointing out of
+/// the root can still be renamed or deleted.
+///
+/// Checks happen before the operation, so a concurrent process swapping a
+/// directory for a link in between is not guarded against.
+///
+/// There is no trash: it lives outside the root, so trashing would move
+/// entries out of the jail for good. `trash`, `restore` and `empty_trash`
+/// fail with `FilesError::Unsupported`, leaving permanent deletion.
+#[derive(Debug, Clone)]
+pub struct Jailed<F: FileSystem> {
+    root: PathBuf,
+    inner: F,
+}
+
+impl<F: FileSystem> Jailed<F> {
+    /// Confines `inner` to `root`, which must be an existing directory.
+    pub fn new(root: &Path, inner: F) -> Result<Self, FilesError> {
+        let root = inner.canonicalize(root)?;
+
+        Ok(Self { root, inner })
+    }
+
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    /// Resolves a directory to list, reporting anything but an escape as
+    /// `InvalidPath` like `read_directory` does.
+    fn confine_directory(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        match self.confine(path, true) {
+            Ok(physical) => Ok(physical),
+            Err(FilesError::OutsideRoot) => Err(FilesError::OutsideRoot),
+            Err(_) => Err(FilesError::InvalidPath),
+        }
+    }
+
+    /// Resolves `path` to a physical path inside the root.
+    fn confine(&self, path: &Path, follow_last: bool) -> Result<PathBuf, FilesError> {
+        let path = self.root.join(path);
+
+        // Refuse obvious escapes without probing paths outside the root.
+        if !normalize(&path).starts_with(&self.root) {
+            return Err(FilesError::OutsideRoot);
+        }
+
+        let physical = match (follow_last, path.parent(), path.file_name()) {
+            (false, Some(parent), Some(name)) => self.inner.canonicalize(parent)?.join(name),
+            _ => match self.inner.canonicalize(&path) {
+                Ok(physical) => physical,
+                // Not created yet: only the parent has to exist.
+                Err(_) => match (path.parent(), path.file_name()) {
+                    (Some(parent), Some(name)) => self.inner.canonicalize(parent)?.join(name),
+                    _ => return Err(FilesError::InvalidPath),
+                },
+            },
+        };
+
+        if physical.starts_with(&self.root) {
+            Ok(physical)
+        } else {
+            Err(FilesError::OutsideRoot)
+        }
+    }
+}
+
+impl<F: FileSystem> FileSystem for Jailed<F> {
+    fn read_directory(&self, path: &Path) -> Result<Vec<FileEntry>, FilesError> {
+        let physical = self.confine_directory(path)?;
+        let mut entries = self.inner.read_directory(&physical)?;
+
+        // Report entries under the path the caller asked for.
+        let requested = self.root.join(path);
+        for entry in &mut entries {
+            entry.path = requested.join(&entry.name);
+        }
+
+        Ok(entries)
+    }
+
+    fn read_directory_chunks(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<Chunks<'_>, FilesError> {
+        let physical = self.confine_directory(path)?;
+        let chunks = self.inner.read_directory_chunks(&physical, chunk_size)?;
+
+        let requested = self.root.join(path);
+        Ok(Box::new(chunks.map(move |chunk| {
+            chunk.map(|mut entries| {
+                for entry in &mut entries {
+                    entry.path = requested.join(&entry.name);
+                }
+                entries
+            })
+        })))
+    }
+
+    fn read_entry(&self, path: &Path) -> Result<FileEntry, FilesError> {
+        let physical = self.confine(path, false)?;
+        let mut entry = self.inner.read_entry(&physical)?;
+        entry.path = self.root.join(path);
+        Ok(entry)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let from = self.confine(from, false)?;
+        let to = self.confine(to, false)?;
+        self.inner.rename(&from, &to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let from = self.confine(from, false)?;
+        let to = self.confine(to, false)?;
+        self.inner.copy(&from, &to)
+    }
+
+    fn copy_metadata(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let from = self.confine(from, false)?;
+        let to = self.confine(to, false)?;
+        self.inner.copy_metadata(&from, &to)
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> Result<(), FilesError> {
+        let path = self.confine(path, true)?;
+        self.inner.set_permissions(&path, mode)
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<(), FilesError> {
+        let path = self.confine(path, true)?;
+        self.inner.set_owner(&path, uid, gid)
+    }
+
+    fn set_times(
+        &self,
+        path: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<(), FilesError> {
+        let path = self.confine(path, true)?;
+        self.inner.set_times(&path, accessed, modified)
+    }
+
+    fn move_to(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let from = self.confine(from, false)?;
+        let to = self.confine(to, false)?;
+        self.inner.move_to(&from, &to)
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), FilesError> {
+        let path = self.confine(path, false)?;
+
+        // Deleting the root itself would leave nothing to be confined to.
+        if path == self.root {
+            return Err(FilesError::OutsideRoot);
+        }
+
+        self.inner.delete(&path)
+    }
+
+    fn create_file(&self, path: &Path) -> Result<(), FilesError> {
+        let path = self.confine(path, true)?;
+        self.inner.create_file(&path)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), FilesError> {
+        let path = self.confine(path, false)?;
+        self.inner.create_dir(&path)
+    }
+
+    fn create_symlink(&self, target: &Path, link: &Path) -> Result<(), FilesError> {
+        let link = self.confine(link, false)?;
+
+        // A link that would lead out of the root is refused, dangling or not.
+        let parent = link.parent().ok_or(FilesError::InvalidPath)?;
+        self.confine(&parent.join(target), true)?;
+
+        self.inner.create_symlink(target, &link)
+    }
+
+    fn create_hardlink(&self, original: &Path, link: &Path) -> Result<(), FilesError> {
+        let original = self.confine(original, false)?;
+        let link = self.confine(link, false)?;
+        self.inner.create_hardlink(&original, &link)
+    }
+
+    fn list_xattrs(&self, path: &Path) -> Result<Vec<OsString>, FilesError> {
+        self.inner.list_xattrs(&self.confine(path, false)?)
+    }
+
+    fn get_xattr(&self, path: &Path, name: &OsStr) -> Result<Option<Vec<u8>>, FilesError> {
+        self.inner.get_xattr(&self.confine(path, false)?, name)
+    }
+
+    fn set_xattr(&self, path: &Path, name: &OsStr, value: &[u8]) -> Result<(), FilesError> {
+        self.inner
+            .set_xattr(&self.confine(path, false)?, name, value)
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &OsStr) -> Result<(), FilesError> {
+        self.inner.remove_xattr(&self.confine(path, false)?, name)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        let physical = self.inner.canonicalize(&self.root.join(path))?;
+
+        if physical.starts_with(&self.root) {
+            Ok(physical)
+        } else {
+            Err(FilesError::OutsideRoot)
+        }
+    }
+
+    fn root(&self) -> Option<&Path> {
+        Some(&self.root)
+    }
+
+    fn can_enter(&self, entry: &FileEntry) -> bool {
+        self.inner.can_enter(entry)
+    }
+
+    fn extract(&self, path: &Path, destination: &Path) -> Result<(), FilesError> {
+        let path = self.confine(path, false)?;
+        let destination = self.confine(destination, true)?;
+        self.inner.extract(&path, &destination)
+    }
+
+    fn trash(&self, _path: &Path) -> Result<(), FilesError> {
+        Err(FilesError::Unsupported)
+    }
+
+    fn restore(&self, _path: &Path) -> Result<PathBuf, FilesError> {
+        Err(FilesError::Unsupported)
+    }
+
+    fn empty_trash(&self) -> Result<(), FilesError> {
+        Err(FilesError::Unsupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::memory::MemoryFileSystem;
+    use crate::testing::{self, TempRoot};
+
+    fn jail() -> Jailed<MemoryFileSystem> {
+        let fs = MemoryFileSystem::new();
+        fs.write_file(Path::new("/project/src/main.rs"), "fn main() {}")
+            .unwrap();
+        fs.write_file(Path::new("/secret/key"), "hunter2").unwrap();
+        fs.symlink("/secret", Path::new("/project/escape")).unwrap();
+        fs.symlink("src", Path::new("/project/inside")).unwrap();
+
+        Jailed::new(Path::new("/project"), fs).unwrap()
+    }
+
+    #[test]
+    fn paths_inside_root_are_allowed() {
+        let fs = jail();
+
+        let entries = fs.read_directory(Path::new("/project/inside")).unwrap();
+        assert_eq!(entries[0].path, Path::new("/project/inside/main.rs"));
+
+        fs.create_file(Path::new("/project/src/lib.rs")).unwrap();
+        fs.rename(
+            Path::new("/project/src/lib.rs"),
+            Path::new("/project/lib.rs"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn parent_components_cannot_escape() {
+        let fs = jail();
+
+        assert!(matches!(
+            fs.read_directory(Path::new("/project/../secret")),
+            Err(FilesError::OutsideRoot)
+        ));
+        assert!(matches!(
+            fs.delete(Path::new("/project/src/../../secret/key")),
+            Err(FilesError::OutsideRoot)
+        ));
+    }
+
+    #[test]
+    fn absolute_paths_outside_root_are_refused() {
+        let fs = jail();
+
+        assert!(matches!(
+            fs.create_file(Path::new("/secret/new")),
+            Err(FilesError::OutsideRoot)
+        ));
+        assert!(matches!(
+            fs.rename(Path::new("/project/src/main.rs"), Path::new("/tmp/main.rs")),
+            Err(FilesError::OutsideRoot)
+        ));
+    }
+
+    #[test]
+    fn nothing_leaves_the_root_through_the_trash() {
+        let fs = jail();
+
+        assert!(matches!(
+            fs.trash(Path::new("/project/src/main.rs")),
+            Err(FilesError::Unsupported)
+        ));
+        assert!(fs.inner().exists(Path::new("/project/src/main.rs")));
+        assert!(matches!(fs.empty_trash(), Err(FilesError::Unsupported)));
+    }
+
+    #[test]
+    fn symlinks_cannot_escape() {
+        let fs = jail();
+
+        assert!(matches!(
+            fs.read_directory(Path::new("/project/escape")),
+            Err(FilesError::OutsideRoot)
+        ));
+        assert!(matches!(
+            fs.delete(Path::new("/project/escape/key")),
+            Err(FilesError::OutsideRoot)
+        ));
+        assert!(fs.inner().exists(Path::new("/secret/key")));
+
+        // The link itself lives inside the root and may be removed.
+        fs.delete(Path::new("/project/escape")).unwrap();
+        assert!(fs.inner().exists(Path::new("/secret/key")));
+    }
+
+    #[test]
+    fn links_cannot_be_made_to_escape() {
+        let fs = jail();
+
+        assert!(matches!(
+            fs.create_symlink(Path::new("../secret"), Path::new("/project/out")),
+            Err(FilesError::OutsideRoot)
+        ));
+        assert!(matches!(
+            fs.create_symlink(Path::new("escape/key"), Path::new("/project/key")),
+            Err(FilesError::OutsideRoot)
+        ));
+        assert!(matches!(
+            fs.create_hardlink(Path::new("/secret/key"), Path::new("/project/key")),
+            Err(FilesError::OutsideRoot)
+        ));
+
+        fs.create_symlink(Path::new("src/main.rs"), Path::new("/project/main"))
+            .unwrap();
+        fs.create_hardlink(Path::new("/project/main"), Path::new("/project/hard"))
+            .unwrap();
+    }
+
+    #[test]
+    fn relative_paths_are_taken_from_root() {
+        let fs = jail();
+
+        fs.create_dir(Path::new("docs")).unwrap();
+
+        assert!(fs.inner().exists(Path::new("/project/docs")));
+    }
+
+    #[test]
+    fn jailed_real_filesystem_conforms() {
+        use crate::filesystem::RealFileSystem;
+
+        let root = TempRoot::new("jailed");
+        let fs = Jailed::new(root.path(), RealFileSystem).unwrap();
+
+        testing::run(&fs, &fs.canonicalize(root.path()).unwrap());
+    }
+}
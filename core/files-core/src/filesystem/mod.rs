@@ -0,0 +1,1094 @@
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::{errors::FilesError, models::FileEntry};
+
+pub use audited::Audited;
+pub use dry_run::{DryRun, Operation};
+pub use jailed::Jailed;
+pub use read_only::ReadOnly;
+pub use sidecar::Sidecar;
+
+mod audited;
+mod dry_run;
+mod jailed;
+mod read_only;
+mod sidecar;
+
+/// The virtual directory listing trashed items, for backends with a trash.
+pub const TRASH: &str = "trash:";
+
+/// A directory listing delivered in chunks, in no particular order.
+pub type Chunks<'a> = Box<dyn Iterator<Item = Result<Vec<FileEntry>, FilesError>> + 'a>;
+
+pub trait FileSystem {
+    fn read_directory(&self, path: &Path) -> Result<Vec<FileEntry>, FilesError>;
+
+    /// Lists a directory in chunks of at most `chunk_size` entries, so huge
+    /// directories can be shown before they are fully read.
+    ///
+    /// Entries come in no particular order. The default splits the result
+    /// of `read_directory`.
+    fn read_directory_chunks(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<Chunks<'_>, FilesError> {
+        Ok(chunked(self.read_directory(path)?, chunk_size))
+    }
+
+    /// Reads the single entry at `path`, without following a final link.
+    ///
+    /// Fails with `NotFound` if there is none. The default looks it up in
+    /// its parent's listing.
+    fn read_entry(&self, path: &Path) -> Result<FileEntry, FilesError> {
+        entry_at(self, path).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound).into())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), FilesError>;
+
+    /// Copies `from` to `to`, with everything under it for directories.
+    ///
+    /// Links are copied as links. The destination is treated like
+    /// `rename` treats it: an existing file is replaced by a file, an
+    /// empty directory by a directory, and anything else fails with the
+    /// same errors. Copying a path onto itself or a directory into itself
+    /// fails with `InvalidInput`.
+    fn copy(&self, _from: &Path, _to: &Path) -> Result<(), FilesError> {
+        Err(FilesError::Unsupported)
+    }
+
+    /// Gives `to` the mode, times and other metadata of `from`, as far as
+    /// the backend keeps them, without following links.
+    ///
+    /// `copy` already does this. It is for entries copied piece by piece,
+    /// such as the directories a job creates before filling them. The
+    /// default does nothing.
+    fn copy_metadata(&self, _from: &Path, _to: &Path) -> Result<(), FilesError> {
+        Ok(())
+    }
+
+    /// Sets the permission bits of `path`, following a final link.
+    ///
+    /// `mode` holds the low twelve bits of a Unix mode, as in
+    /// `EntryMetadata::mode`.
+    fn set_permissions(&self, _path: &Path, _mode: u32) -> Result<(), FilesError> {
+        Err(FilesError::Unsupported)
+    }
+
+    /// Sets the owning user and group of `path`, following a final link.
+    /// `None` leaves that one unchanged.
+    fn set_owner(
+        &self,
+        _path: &Path,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+    ) -> Result<(), FilesError> {
+        Err(FilesError::Unsupported)
+    }
+
+    /// Sets the access and modification times of `path`, following a final
+    /// link. `None` leaves that time as it is.
+    fn set_times(
+        &self,
+        _path: &Path,
+        _accessed: Option<SystemTime>,
+        _modified: Option<SystemTime>,
+    ) -> Result<(), FilesError> {
+        Err(FilesError::Unsupported)
+    }
+
+    /// Moves `from` to `to`, even across devices.
+    ///
+    /// The default is [`rename_or_copy`].
+    fn move_to(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        rename_or_copy(self, from, to)
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), FilesError>;
+
+    /// Creates an empty file.
+    ///
+    /// An existing file is never truncated: creating one fails with
+    /// `AlreadyExists`, or `IsADirectory` for a directory.
+    fn create_file(&self, path: &Path) -> Result<(), FilesError>;
+
+    fn create_dir(&self, path: &Path) -> Result<(), FilesError>;
+
+    /// Creates a symbolic link at `link` pointing to `target`.
+    ///
+    /// The target is stored as given, absolute or relative to the link's
+    /// directory, and need not exist. An existing `link` is never replaced.
+    fn create_symlink(&self, _target: &Path, _link: &Path) -> Result<(), FilesError> {
+        Err(FilesError::Unsupported)
+    }
+
+    /// Creates `link` as another name for the file `original`, without
+    /// following a final link in either.
+    fn create_hardlink(&self, _original: &Path, _link: &Path) -> Result<(), FilesError> {
+        Err(FilesError::Unsupported)
+    }
+
+    /// Lists the names of the extended attributes of `path`, without
+    /// following a final link.
+    ///
+    /// Backends without extended attributes fail with `Unsupported` here
+    /// and in the methods below; [`Sidecar`] keeps them for such backends.
+    fn list_xattrs(&self, _path: &Path) -> Result<Vec<OsString>, FilesError> {
+        Err(FilesError::Unsupported)
+    }
+
+    /// Reads the extended attribute `name` of `path`, without following a
+    /// final link; `None` if it is not set.
+    fn get_xattr(&self, _path: &Path, _name: &OsStr) -> Result<Option<Vec<u8>>, FilesError> {
+        Err(FilesError::Unsupported)
+    }
+
+    /// Sets the extended attribute `name` of `path` to `value`, without
+    /// following a final link.
+    fn set_xattr(&self, _path: &Path, _name: &OsStr, _value: &[u8]) -> Result<(), FilesError> {
+        Err(FilesError::Unsupported)
+    }
+
+    /// Removes the extended attribute `name` of `path`, without following
+    /// a final link. Removing one that is not set succeeds.
+    fn remove_xattr(&self, _path: &Path, _name: &OsStr) -> Result<(), FilesError> {
+        Err(FilesError::Unsupported)
+    }
+
+    /// Returns the absolute, physical form of an existing path.
+    ///
+    /// The default normalizes `.` and `..` lexically, which is only correct
+    /// for backends without symbolic links.
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        if path.is_absolute() {
+            Ok(normalize(path))
+        } else {
+            Err(FilesError::InvalidPath)
+        }
+    }
+
+    /// The top-most directory this filesystem exposes, if it is confined.
+    ///
+    /// Navigation never moves above it.
+    fn root(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Whether navigation can open `entry` like a directory.
+    fn can_enter(&self, entry: &FileEntry) -> bool {
+        entry.is_dir()
+    }
+
+    /// Copies `path` out to the local directory `destination`, keeping its
+    /// name.
+    ///
+    /// Only backends whose contents do not already live on the local
+    /// filesystem, such as archives, support this.
+    fn extract(&self, _path: &Path, _destination: &Path) -> Result<(), FilesError> {
+        Err(FilesError::Unsupported)
+    }
+
+    /// Moves `path` to the trash, where it can be restored from.
+    ///
+    /// Trashed items are listed by reading [`TRASH`]. Backends without a
+    /// trash only support [`delete`](Self::delete).
+    fn trash(&self, _path: &Path) -> Result<(), FilesError> {
+        Err(FilesError::Unsupported)
+    }
+
+    /// Moves a trashed item, as listed under [`TRASH`], back to where it was
+    /// trashed from, and returns that path.
+    fn restore(&self, _path: &Path) -> Result<PathBuf, FilesError> {
+        Err(FilesError::Unsupported)
+    }
+
+    /// Permanently deletes everything in the trash.
+    fn empty_trash(&self) -> Result<(), FilesError> {
+        Err(FilesError::Unsupported)
+    }
+}
+
+/// Renames `from` to `to`, falling back to `copy` then `delete` when the
+/// rename fails with `CrossesDevices`.
+///
+/// The source is only deleted once the copy is complete.
+pub fn rename_or_copy<F: FileSystem + ?Sized>(
+    fs: &F,
+    from: &Path,
+    to: &Path,
+) -> Result<(), FilesError> {
+    match fs.rename(from, to) {
+        Err(FilesError::Io(err)) if err.kind() == io::ErrorKind::CrossesDevices => {
+            fs.copy(from, to)?;
+            fs.delete(from)
+        }
+        result => result,
+    }
+}
+
+/// Looks `path` up in its parent's listing, so that a link is returned as
+/// the link rather than its target. Returns `None` if it does not exist or
+/// the parent cannot be read.
+pub fn entry_at<F: FileSystem + ?Sized>(fs: &F, path: &Path) -> Option<FileEntry> {
+    fs.read_directory(path.parent()?)
+        .ok()?
+        .into_iter()
+        .find(|entry| entry.path == path)
+}
+
+/// Splits a complete listing into [`Chunks`].
+pub fn chunked(entries: Vec<FileEntry>, chunk_size: usize) -> Chunks<'static> {
+    let chunk_size = chunk_size.max(1);
+    let mut entries = entries.into_iter();
+
+    Box::new(std::iter::from_fn(move || {
+        let chunk: Vec<_> = entries.by_ref().take(chunk_size).collect();
+        (!chunk.is_empty()).then_some(Ok(chunk))
+    }))
+}
+
+/// Resolves `.` and `..` components without touching the filesystem.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+
+    normalized
+}
+
+/// `base` with `rest` appended, or `base` itself for an empty `rest`,
+/// without the trailing separator `join` would add.
+fn join(base: &Path, rest: &Path) -> PathBuf {
+    if rest.as_os_str().is_empty() {
+        base.to_path_buf()
+    } else {
+        base.join(rest)
+    }
+}
+
+/// The relative path that leads from the directory `base` to `path`, both
+/// absolute, as a link in `base` would store it.
+///
+/// Both are compared lexically, so they should already be canonical.
+pub fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let (path, base) = (normalize(path), normalize(base));
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::new();
+    for _ in base.components().skip(common) {
+        relative.push(Component::ParentDir);
+    }
+    for component in path.components().skip(common) {
+        relative.push(component);
+    }
+
+    if relative.as_os_str().is_empty() {
+        relative.push(Component::CurDir);
+    }
+    relative
+}
+
+impl<F: FileSystem + ?Sized> FileSystem for Box<F> {
+    fn read_directory(&self, path: &Path) -> Result<Vec<FileEntry>, FilesError> {
+        (**self).read_directory(path)
+    }
+
+    fn read_directory_chunks(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<Chunks<'_>, FilesError> {
+        (**self).read_directory_chunks(path, chunk_size)
+    }
+
+    fn read_entry(&self, path: &Path) -> Result<FileEntry, FilesError> {
+        (**self).read_entry(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        (**self).rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        (**self).copy(from, to)
+    }
+
+    fn copy_metadata(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        (**self).copy_metadata(from, to)
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> Result<(), FilesError> {
+        (**self).set_permissions(path, mode)
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<(), FilesError> {
+        (**self).set_owner(path, uid, gid)
+    }
+
+    fn set_times(
+        &self,
+        path: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<(), FilesError> {
+        (**self).set_times(path, accessed, modified)
+    }
+
+    fn move_to(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        (**self).move_to(from, to)
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), FilesError> {
+        (**self).delete(path)
+    }
+
+    fn create_file(&self, path: &Path) -> Result<(), FilesError> {
+        (**self).create_file(path)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), FilesError> {
+        (**self).create_dir(path)
+    }
+
+    fn create_symlink(&self, target: &Path, link: &Path) -> Result<(), FilesError> {
+        (**self).create_symlink(target, link)
+    }
+
+    fn create_hardlink(&self, original: &Path, link: &Path) -> Result<(), FilesError> {
+        (**self).create_hardlink(original, link)
+    }
+
+    fn list_xattrs(&self, path: &Path) -> Result<Vec<OsString>, FilesError> {
+        (**self).list_xattrs(path)
+    }
+
+    fn get_xattr(&self, path: &Path, name: &OsStr) -> Result<Option<Vec<u8>>, FilesError> {
+        (**self).get_xattr(path, name)
+    }
+
+    fn set_xattr(&self, path: &Path, name: &OsStr, value: &[u8]) -> Result<(), FilesError> {
+        (**self).set_xattr(path, name, value)
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &OsStr) -> Result<(), FilesError> {
+        (**self).remove_xattr(path, name)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        (**self).canonicalize(path)
+    }
+
+    fn root(&self) -> Option<&Path> {
+        (**self).root()
+    }
+
+    fn can_enter(&self, entry: &FileEntry) -> bool {
+        (**self).can_enter(entry)
+    }
+
+    fn extract(&self, path: &Path, destination: &Path) -> Result<(), FilesError> {
+        (**self).extract(path, destination)
+    }
+
+    fn trash(&self, path: &Path) -> Result<(), FilesError> {
+        (**self).trash(path)
+    }
+
+    fn restore(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        (**self).restore(path)
+    }
+
+    fn empty_trash(&self) -> Result<(), FilesError> {
+        (**self).empty_trash()
+    }
+}
+
+impl<F: FileSystem + ?Sized> FileSystem for Rc<F> {
+    fn read_directory(&self, path: &Path) -> Result<Vec<FileEntry>, FilesError> {
+        (**self).read_directory(path)
+    }
+
+    fn read_directory_chunks(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<Chunks<'_>, FilesError> {
+        (**self).read_directory_chunks(path, chunk_size)
+    }
+
+    fn read_entry(&self, path: &Path) -> Result<FileEntry, FilesError> {
+        (**self).read_entry(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        (**self).rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        (**self).copy(from, to)
+    }
+
+    fn copy_metadata(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        (**self).copy_metadata(from, to)
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> Result<(), FilesError> {
+        (**self).set_permissions(path, mode)
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<(), FilesError> {
+        (**self).set_owner(path, uid, gid)
+    }
+
+    fn set_times(
+        &self,
+        path: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<(), FilesError> {
+        (**self).set_times(path, accessed, modified)
+    }
+
+    fn move_to(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        (**self).move_to(from, to)
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), FilesError> {
+        (**self).delete(path)
+    }
+
+    fn create_file(&self, path: &Path) -> Result<(), FilesError> {
+        (**self).create_file(path)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), FilesError> {
+        (**self).create_dir(path)
+    }
+
+    fn create_symlink(&self, target: &Path, link: &Path) -> Result<(), FilesError> {
+        (**self).create_symlink(target, link)
+    }
+
+    fn create_hardlink(&self, original: &Path, link: &Path) -> Result<(), FilesError> {
+        (**self).create_hardlink(original, link)
+    }
+
+    fn list_xattrs(&self, path: &Path) -> Result<Vec<OsString>, FilesError> {
+        (**self).list_xattrs(path)
+    }
+
+    fn get_xattr(&self, path: &Path, name: &OsStr) -> Result<Option<Vec<u8>>, FilesError> {
+        (**self).get_xattr(path, name)
+    }
+
+    fn set_xattr(&self, path: &Path, name: &OsStr, value: &[u8]) -> Result<(), FilesError> {
+        (**self).set_xattr(path, name, value)
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &OsStr) -> Result<(), FilesError> {
+        (**self).remove_xattr(path, name)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        (**self).canonicalize(path)
+    }
+
+    fn root(&self) -> Option<&Path> {
+        (**self).root()
+    }
+
+    fn can_enter(&self, entry: &FileEntry) -> bool {
+        (**self).can_enter(entry)
+    }
+
+    fn extract(&self, path: &Path, destination: &Path) -> Result<(), FilesError> {
+        (**self).extract(path, destination)
+    }
+
+    fn trash(&self, path: &Path) -> Result<(), FilesError> {
+        (**self).trash(path)
+    }
+
+    fn restore(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        (**self).restore(path)
+    }
+
+    fn empty_trash(&self) -> Result<(), FilesError> {
+        (**self).empty_trash()
+    }
+}
+
+impl<F: FileSystem + ?Sized> FileSystem for Arc<F> {
+    fn read_directory(&self, path: &Path) -> Result<Vec<FileEntry>, FilesError> {
+        (**self).read_directory(path)
+    }
+
+    fn read_directory_chunks(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<Chunks<'_>, FilesError> {
+        (**self).read_directory_chunks(path, chunk_size)
+    }
+
+    fn read_entry(&self, path: &Path) -> Result<FileEntry, FilesError> {
+        (**self).read_entry(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        (**self).rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        (**self).copy(from, to)
+    }
+
+    fn copy_metadata(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        (**self).copy_metadata(from, to)
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> Result<(), FilesError> {
+        (**self).set_permissions(path, mode)
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<(), FilesError> {
+        (**self).set_owner(path, uid, gid)
+    }
+
+    fn set_times(
+        &self,
+        path: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<(), FilesError> {
+        (**self).set_times(path, accessed, modified)
+    }
+
+    fn move_to(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        (**self).move_to(from, to)
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), FilesError> {
+        (**self).delete(path)
+    }
+
+    fn create_file(&self, path: &Path) -> Result<(), FilesError> {
+        (**self).create_file(path)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), FilesError> {
+        (**self).create_dir(path)
+    }
+
+    fn create_symlink(&self, target: &Path, link: &Path) -> Result<(), FilesError> {
+        (**self).create_symlink(target, link)
+    }
+
+    fn create_hardlink(&self, original: &Path, link: &Path) -> Result<(), FilesError> {
+        (**self).create_hardlink(original, link)
+    }
+
+    fn list_xattrs(&self, path: &Path) -> Result<Vec<OsString>, FilesError> {
+        (**self).list_xattrs(path)
+    }
+
+    fn get_xattr(&self, path: &Path, name: &OsStr) -> Result<Option<Vec<u8>>, FilesError> {
+        (**self).get_xattr(path, name)
+    }
+
+    fn set_xattr(&self, path: &Path, name: &OsStr, value: &[u8]) -> Result<(), FilesError> {
+        (**self).set_xattr(path, name, value)
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &OsStr) -> Result<(), FilesError> {
+        (**self).remove_xattr(path, name)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        (**self).canonicalize(path)
+    }
+
+    fn root(&self) -> Option<&Path> {
+        (**self).root()
+    }
+
+    fn can_enter(&self, entry: &FileEntry) -> bool {
+        (**self).can_enter(entry)
+    }
+
+    fn extract(&self, path: &Path, destination: &Path) -> Result<(), FilesError> {
+        (**self).extract(path, destination)
+    }
+
+    fn trash(&self, path: &Path) -> Result<(), FilesError> {
+        (**self).trash(path)
+    }
+
+    fn restore(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        (**self).restore(path)
+    }
+
+    fn empty_trash(&self) -> Result<(), FilesError> {
+        (**self).empty_trash()
+    }
+}
+
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn read_directory(&self, path: &Path) -> Result<Vec<FileEntry>, FilesError> {
+        #[cfg(unix)]
+        if path == Path::new(TRASH) {
+            return crate::trash::Trash::new()?.list();
+        }
+
+        crate::fs::read_directory(path)
+    }
+
+    fn read_directory_chunks(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<Chunks<'_>, FilesError> {
+        #[cfg(unix)]
+        if path == Path::new(TRASH) {
+            return Ok(chunked(crate::trash::Trash::new()?.list()?, chunk_size));
+        }
+
+        Ok(Box::new(crate::fs::read_directory_chunks(
+            path, chunk_size,
+        )?))
+    }
+
+    fn read_entry(&self, path: &Path) -> Result<FileEntry, FilesError> {
+        crate::fs::read_entry(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        fs::rename(from, to).map_err(FilesError::from)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let source = fs::symlink_metadata(from)?;
+        let (from, to) = (std::path::absolute(from)?, std::path::absolute(to)?);
+
+        if normalize(&to) == normalize(&from)
+            || (source.is_dir() && normalize(&to).starts_with(normalize(&from)))
+        {
+            return Err(FilesError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot copy a path onto or into itself",
+            )));
+        }
+
+        // Replace the destination the way rename(2) would, by copying under
+        // a temporary name and renaming that over it, so the destination is
+        // kept should the copy fail.
+        match fs::symlink_metadata(&to) {
+            Ok(existing) => match (source.is_dir(), existing.is_dir()) {
+                (false, true) => return Err(io::Error::from(io::ErrorKind::IsADirectory).into()),
+                (true, false) => return Err(io::Error::from(io::ErrorKind::NotADirectory).into()),
+                (true, true) if fs::read_dir(&to)?.next().is_some() => {
+                    return Err(io::Error::from(io::ErrorKind::DirectoryNotEmpty).into());
+                }
+                _ => {}
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                crate::fs::operations::copy(&from, &to)?;
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        let name = to.file_name().ok_or(FilesError::InvalidPath)?;
+        let temporary = (0..)
+            .map(|n| {
+                let mut candidate = OsString::from(".");
+                candidate.push(name);
+                candidate.push(format!(".{}.tmp", n));
+                to.with_file_name(candidate)
+            })
+            .find(|candidate| fs::symlink_metadata(candidate).is_err())
+            .expect("some name is free");
+
+        crate::fs::operations::copy(&from, &temporary)
+            .and_then(|()| fs::rename(&temporary, &to))
+            .map_err(|err| {
+                let _ = if source.is_dir() {
+                    fs::remove_dir_all(&temporary)
+                } else {
+                    fs::remove_file(&temporary)
+                };
+                err.into()
+            })
+    }
+
+    fn copy_metadata(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        crate::fs::operations::copy_metadata(from, to)?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn set_permissions(&self, path: &Path, mode: u32) -> Result<(), FilesError> {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777))?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<(), FilesError> {
+        std::os::unix::fs::chown(path, uid, gid)?;
+        Ok(())
+    }
+
+    fn set_times(
+        &self,
+        path: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<(), FilesError> {
+        crate::fs::operations::set_times(path, accessed, modified)?;
+        Ok(())
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), FilesError> {
+        // Never recurse through a link: remove the link itself.
+        if fs::symlink_metadata(path)?.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+
+        // Deleting a trashed item for good also drops its record. The
+        // deletion has happened by now, so a trash that cannot be updated
+        // keeps a stale record, which listings skip, rather than failing it.
+        // Only paths laid out like a trash get as far as looking for one.
+        #[cfg(unix)]
+        if crate::trash::may_be_trashed(path) {
+            let _ = crate::trash::Trash::new().and_then(|trash| trash.forget(path));
+        }
+
+        Ok(())
+    }
+
+    fn create_file(&self, path: &Path) -> Result<(), FilesError> {
+        if fs::metadata(path).is_ok_and(|meta| meta.is_dir()) {
+            return Err(io::Error::from(io::ErrorKind::IsADirectory).into());
+        }
+
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        Ok(())
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), FilesError> {
+        fs::create_dir(path)?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn create_symlink(&self, target: &Path, link: &Path) -> Result<(), FilesError> {
+        std::os::unix::fs::symlink(target, link)?;
+        Ok(())
+    }
+
+    fn create_hardlink(&self, original: &Path, link: &Path) -> Result<(), FilesError> {
+        fs::hard_link(original, link)?;
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn list_xattrs(&self, path: &Path) -> Result<Vec<OsString>, FilesError> {
+        Ok(crate::fs::operations::list_xattrs(path)?)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn get_xattr(&self, path: &Path, name: &OsStr) -> Result<Option<Vec<u8>>, FilesError> {
+        Ok(crate::fs::operations::get_xattr(path, name)?)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_xattr(&self, path: &Path, name: &OsStr, value: &[u8]) -> Result<(), FilesError> {
+        crate::fs::operations::set_xattr(path, name, value)?;
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn remove_xattr(&self, path: &Path, name: &OsStr) -> Result<(), FilesError> {
+        crate::fs::operations::remove_xattr(path, name)?;
+        Ok(())
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        Ok(fs::canonicalize(path)?)
+    }
+
+    #[cfg(unix)]
+    fn trash(&self, path: &Path) -> Result<(), FilesError> {
+        crate::trash::Trash::new()?.trash(path)
+    }
+
+    #[cfg(unix)]
+    fn restore(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        crate::trash::Trash::new()?.restore(path)
+    }
+
+    #[cfg(unix)]
+    fn empty_trash(&self) -> Result<(), FilesError> {
+        crate::trash::Trash::new()?.empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::state::test_utils::temp_dir;
+
+    #[test]
+    fn normalize_resolves_dots_lexically() {
+        assert_eq!(normalize(Path::new("/a/./b/../c")), Path::new("/a/c"));
+        assert_eq!(normalize(Path::new("/../a")), Path::new("/a"));
+    }
+
+    #[test]
+    fn relative_path_climbs_to_the_common_ancestor() {
+        let relative = |path, base| relative_path(Path::new(path), Path::new(base));
+
+        assert_eq!(relative("/a/b/c.txt", "/a/d"), Path::new("../b/c.txt"));
+        assert_eq!(relative("/a/b/c.txt", "/a/b"), Path::new("c.txt"));
+        assert_eq!(relative("/a/b", "/a/b/c/d"), Path::new("../.."));
+        assert_eq!(relative("/x/y", "/a/./b/.."), Path::new("../x/y"));
+        assert_eq!(relative("/a", "/a"), Path::new("."));
+    }
+
+    #[test]
+    fn move_falls_back_to_copy_across_devices() {
+        use crate::memory::MemoryFileSystem;
+        use crate::testing::{Call, Fault, Faulty};
+
+        let memory = MemoryFileSystem::new();
+        memory
+            .write_file(Path::new("/a/file.txt"), "contents")
+            .unwrap();
+        memory.create_dir_all(Path::new("/b")).unwrap();
+        let fs = Faulty::new(memory)
+            .with_fault(Fault::new(io::ErrorKind::CrossesDevices).on(Call::Rename));
+
+        fs.move_to(Path::new("/a"), Path::new("/b/a")).unwrap();
+
+        assert!(!fs.inner().exists(Path::new("/a")));
+        assert_eq!(
+            fs.inner().read_file(Path::new("/b/a/file.txt")).unwrap(),
+            b"contents"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_keeps_links_as_links() {
+        let dir = temp_dir("copy-links");
+        fs::create_dir(dir.join("src")).unwrap();
+        fs::write(dir.join("src/data.txt"), "data").unwrap();
+        std::os::unix::fs::symlink("data.txt", dir.join("src/link")).unwrap();
+
+        RealFileSystem
+            .copy(&dir.join("src"), &dir.join("dst"))
+            .unwrap();
+
+        assert_eq!(
+            fs::read_link(dir.join("dst/link")).unwrap(),
+            Path::new("data.txt")
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("dst/data.txt")).unwrap(),
+            "data"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_copy_keeps_the_destination() {
+        let dir = temp_dir("copy-failed");
+        fs::write(dir.join("old.txt"), "old").unwrap();
+        // Sockets cannot be opened, so copying one fails even for root.
+        let _socket = std::os::unix::net::UnixListener::bind(dir.join("socket")).unwrap();
+
+        assert!(
+            RealFileSystem
+                .copy(&dir.join("socket"), &dir.join("old.txt"))
+                .is_err()
+        );
+
+        assert_eq!(fs::read_to_string(dir.join("old.txt")).unwrap(), "old");
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["old.txt", "socket"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_keeps_modes_and_times() {
+        use std::fs::{File, FileTimes, Permissions};
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let dir = temp_dir("copy-metadata");
+        let old = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::create_dir(dir.join("src")).unwrap();
+        fs::write(dir.join("src/data.txt"), "data").unwrap();
+        File::options()
+            .write(true)
+            .open(dir.join("src/data.txt"))
+            .unwrap()
+            .set_times(FileTimes::new().set_modified(old).set_accessed(old))
+            .unwrap();
+        fs::set_permissions(dir.join("src/data.txt"), Permissions::from_mode(0o640)).unwrap();
+        File::open(dir.join("src"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        fs::set_permissions(dir.join("src"), Permissions::from_mode(0o750)).unwrap();
+
+        RealFileSystem
+            .copy(&dir.join("src"), &dir.join("dst"))
+            .unwrap();
+
+        let file = fs::metadata(dir.join("dst/data.txt")).unwrap();
+        assert_eq!(file.permissions().mode() & 0o7777, 0o640);
+        assert_eq!(file.modified().unwrap(), old);
+        assert_eq!(file.accessed().unwrap(), old);
+        let directory = fs::metadata(dir.join("dst")).unwrap();
+        assert_eq!(directory.permissions().mode() & 0o7777, 0o750);
+        assert_eq!(directory.modified().unwrap(), old);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn set_times_keeps_the_time_not_given() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let dir = temp_dir("set-times");
+        let path = dir.join("file.txt");
+        fs::write(&path, "").unwrap();
+        let accessed = fs::metadata(&path).unwrap().accessed().unwrap();
+        let old = UNIX_EPOCH + Duration::from_nanos(1_000_000_000_123_456_789);
+        let before_epoch = UNIX_EPOCH - Duration::from_millis(1500);
+
+        RealFileSystem.set_times(&path, None, Some(old)).unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.modified().unwrap(), old);
+        assert_eq!(metadata.accessed().unwrap(), accessed);
+
+        RealFileSystem
+            .set_times(&path, Some(before_epoch), None)
+            .unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.accessed().unwrap(), before_epoch);
+        assert_eq!(metadata.modified().unwrap(), old);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn copy_keeps_holes_and_extended_attributes() {
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::fs::{FileExt, MetadataExt};
+
+        let dir = temp_dir("copy-sparse");
+        let len = 64 * 1024 * 1024;
+        let file = fs::File::create(dir.join("sparse")).unwrap();
+        file.write_all_at(b"start", 0).unwrap();
+        file.write_all_at(b"middle", len / 2).unwrap();
+        file.set_len(len).unwrap();
+        let path = std::ffi::CString::new(dir.join("sparse").as_os_str().as_bytes()).unwrap();
+        // SAFETY: the path and name are NUL-terminated and the value is 3 bytes.
+        let tagged = unsafe {
+            libc::setxattr(
+                path.as_ptr(),
+                c"user.tag".as_ptr(),
+                b"red".as_ptr().cast(),
+                3,
+                0,
+            )
+        } == 0;
+
+        RealFileSystem
+            .copy(&dir.join("sparse"), &dir.join("copy"))
+            .unwrap();
+
+        let copy = fs::File::open(dir.join("copy")).unwrap();
+        let metadata = copy.metadata().unwrap();
+        assert_eq!(metadata.len(), len);
+        assert!(metadata.blocks() * 512 < len / 4, "holes were filled in");
+        let mut buffer = [0; 6];
+        copy.read_exact_at(&mut buffer, len / 2).unwrap();
+        assert_eq!(&buffer, b"middle");
+
+        // Filesystems without user attributes have none to keep.
+        if tagged {
+            let path = std::ffi::CString::new(dir.join("copy").as_os_str().as_bytes()).unwrap();
+            let mut value = [0u8; 8];
+            // SAFETY: as above, with an 8 byte buffer.
+            let read = unsafe {
+                libc::getxattr(
+                    path.as_ptr(),
+                    c"user.tag".as_ptr(),
+                    value.as_mut_ptr().cast(),
+                    value.len(),
+                )
+            };
+            assert_eq!(&value[..read.max(0) as usize], b"red");
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn delete_removes_link_not_target_directory() {
+        let dir = temp_dir("delete-link");
+        fs::create_dir(dir.join("target")).unwrap();
+        fs::write(dir.join("target/keep.txt"), "keep").unwrap();
+        std::os::unix::fs::symlink(dir.join("target"), dir.join("link")).unwrap();
+
+        RealFileSystem.delete(&dir.join("link")).unwrap();
+
+        assert!(fs::symlink_metadata(dir.join("link")).is_err());
+        assert!(dir.join("target/keep.txt").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
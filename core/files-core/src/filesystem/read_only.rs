@@ -0,0 +1,198 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::{Chunks, FileSystem};
+use crate::{errors::FilesError, models::FileEntry};
+
+/// Wraps a filesystem so that reads pass through and every mutation fails
+/// with `FilesError::ReadOnly` without reaching the inner filesystem.
+#[derive(Debug, Clone)]
+pub struct ReadOnly<F: FileSystem> {
+    inner: F,
+}
+
+impl<F: FileSystem> ReadOnly<F> {
+    pub fn new(inner: F) -> Self {
+        Self { inner }
+    }
+
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+impl<F: FileSystem> FileSystem for ReadOnly<F> {
+    fn read_directory(&self, path: &Path) -> Result<Vec<FileEntry>, FilesError> {
+        self.inner.read_directory(path)
+    }
+
+    fn read_directory_chunks(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<Chunks<'_>, FilesError> {
+        self.inner.read_directory_chunks(path, chunk_size)
+    }
+
+    fn read_entry(&self, path: &Path) -> Result<FileEntry, FilesError> {
+        self.inner.read_entry(path)
+    }
+
+    fn rename(&self, _from: &Path, _to: &Path) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn copy(&self, _from: &Path, _to: &Path) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn copy_metadata(&self, _from: &Path, _to: &Path) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn set_permissions(&self, _path: &Path, _mode: u32) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn set_owner(
+        &self,
+        _path: &Path,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+    ) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn set_times(
+        &self,
+        _path: &Path,
+        _accessed: Option<SystemTime>,
+        _modified: Option<SystemTime>,
+    ) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn move_to(&self, _from: &Path, _to: &Path) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn delete(&self, _path: &Path) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn create_file(&self, _path: &Path) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn create_dir(&self, _path: &Path) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn create_symlink(&self, _target: &Path, _link: &Path) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn create_hardlink(&self, _original: &Path, _link: &Path) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn list_xattrs(&self, path: &Path) -> Result<Vec<OsString>, FilesError> {
+        self.inner.list_xattrs(path)
+    }
+
+    fn get_xattr(&self, path: &Path, name: &OsStr) -> Result<Option<Vec<u8>>, FilesError> {
+        self.inner.get_xattr(path, name)
+    }
+
+    fn set_xattr(&self, _path: &Path, _name: &OsStr, _value: &[u8]) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn remove_xattr(&self, _path: &Path, _name: &OsStr) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        self.inner.canonicalize(path)
+    }
+
+    fn root(&self) -> Option<&Path> {
+        self.inner.root()
+    }
+
+    fn can_enter(&self, entry: &FileEntry) -> bool {
+        self.inner.can_enter(entry)
+    }
+
+    fn extract(&self, _path: &Path, _destination: &Path) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn trash(&self, _path: &Path) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn restore(&self, _path: &Path) -> Result<PathBuf, FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+
+    fn empty_trash(&self) -> Result<(), FilesError> {
+        Err(FilesError::ReadOnly)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::memory::MemoryFileSystem;
+
+    fn read_only() -> ReadOnly<MemoryFileSystem> {
+        let fs = MemoryFileSystem::new();
+        fs.write_file(Path::new("/data/report.txt"), "q3").unwrap();
+        ReadOnly::new(fs)
+    }
+
+    #[test]
+    fn reads_pass_through() {
+        let fs = read_only();
+
+        let entries = fs.read_directory(Path::new("/data")).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "report.txt");
+    }
+
+    #[test]
+    fn mutations_are_rejected_and_leave_inner_untouched() {
+        let fs = read_only();
+        let file = Path::new("/data/report.txt");
+
+        assert!(matches!(
+            fs.rename(file, Path::new("/data/renamed.txt")),
+            Err(FilesError::ReadOnly)
+        ));
+        assert!(matches!(fs.delete(file), Err(FilesError::ReadOnly)));
+        assert!(matches!(
+            fs.create_file(Path::new("/data/new.txt")),
+            Err(FilesError::ReadOnly)
+        ));
+        assert!(matches!(
+            fs.create_dir(Path::new("/data/sub")),
+            Err(FilesError::ReadOnly)
+        ));
+
+        let names: Vec<_> = fs
+            .read_directory(Path::new("/data"))
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(names, ["report.txt"]);
+    }
+}
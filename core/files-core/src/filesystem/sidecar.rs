@@ -0,0 +1,654 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use super::{Chunks, FileSystem, join, normalize};
+use crate::{errors::FilesError, models::FileEntry, tags};
+
+/// Attributes kept for each path, by name.
+type Records = BTreeMap<PathBuf, BTreeMap<OsString, Vec<u8>>>;
+
+/// Keeps extended attributes in a database for backends without them.
+///
+/// Attribute calls go to the inner filesystem first. Where it fails with
+/// `Unsupported`, or an I/O error of that kind on a mount without
+/// attributes, they are kept in the sidecar database instead, by path,
+/// and listings take their entries' tags from there.
+///
+/// The database follows renames, moves, copies and deletions made through
+/// this wrapper, and forgets a path when something new is created there.
+/// Trashed entries keep theirs, to have them back once restored. Changes
+/// made around the wrapper are not seen.
+///
+/// A database loaded from a file is saved back after every change, one
+/// attribute per line: its path, name and value, percent-encoded and
+/// separated by spaces. The file's directory is created on the first save.
+/// If the database cannot be saved, a call that otherwise succeeded returns
+/// that error, as with [`Audited`](super::Audited): the change to the entry
+/// happened, and its attributes are kept in memory until a later save.
+#[derive(Debug)]
+pub struct Sidecar<F: FileSystem> {
+    inner: F,
+    records: Mutex<Records>,
+    file: Option<PathBuf>,
+}
+
+impl<F: FileSystem> Sidecar<F> {
+    /// Keeps the database in memory only.
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            records: Mutex::new(Records::new()),
+            file: None,
+        }
+    }
+
+    /// Loads the database from the file at `path`, if there is one yet, and
+    /// saves it there.
+    pub fn open(inner: F, path: &Path) -> Result<Self, FilesError> {
+        let mut sidecar = Self::new(inner);
+        sidecar.load(path)?;
+        Ok(sidecar)
+    }
+
+    /// Replaces the database with the one in the file at `path`, if there
+    /// is one yet, and saves it there from now on. On failure, the
+    /// database stays as it was.
+    pub fn load(&mut self, path: &Path) -> Result<(), FilesError> {
+        let records = match fs::read_to_string(path) {
+            Ok(text) => parse(&text).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is not an attribute database", path.display()),
+                )
+            })?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Records::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        *self.lock() = records;
+        self.file = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Records> {
+        self.records.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    /// Applies `change` to the database and saves it if `change` says it
+    /// changed anything, so calls on untagged paths cost no writes.
+    fn update(&self, change: impl FnOnce(&mut Records) -> bool) -> Result<(), FilesError> {
+        let mut records = self.lock();
+        let changed = change(&mut records);
+
+        match &self.file {
+            Some(file) if changed => {
+                // Next to the file, under a name no other database shares.
+                let mut temporary = file.as_os_str().to_os_string();
+                temporary.push(".tmp");
+                let temporary = PathBuf::from(temporary);
+
+                if let Some(parent) = file.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&temporary, format(&records))?;
+                fs::rename(&temporary, file)?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// `entry` with the tags kept for it, if any.
+    fn tagged(&self, mut entry: FileEntry) -> FileEntry {
+        let records = self.lock();
+        if let Some(value) = records
+            .get(&normalize(&entry.path))
+            .and_then(|attributes| attributes.get(OsStr::new(tags::TAGS)))
+        {
+            entry.tags = tags::parse(value);
+        }
+        entry
+    }
+
+    /// Runs `call` on the inner filesystem, or `fallback` on the database
+    /// if the inner one keeps no attributes at `path`.
+    fn attribute<T>(
+        &self,
+        path: &Path,
+        call: impl FnOnce(&F) -> Result<T, FilesError>,
+        fallback: impl FnOnce(&Self, PathBuf) -> Result<T, FilesError>,
+    ) -> Result<T, FilesError> {
+        match call(&self.inner) {
+            Err(err) if unsupported(&err) => {
+                // Only existing entries have attributes.
+                self.inner.read_entry(path)?;
+                fallback(self, normalize(path))
+            }
+            result => result,
+        }
+    }
+}
+
+impl<F: FileSystem> FileSystem for Sidecar<F> {
+    fn read_directory(&self, path: &Path) -> Result<Vec<FileEntry>, FilesError> {
+        let entries = self.inner.read_directory(path)?;
+        Ok(entries
+            .into_iter()
+            .map(|entry| self.tagged(entry))
+            .collect())
+    }
+
+    fn read_directory_chunks(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<Chunks<'_>, FilesError> {
+        let chunks = self.inner.read_directory_chunks(path, chunk_size)?;
+        Ok(Box::new(chunks.map(|chunk| {
+            chunk.map(|entries| entries.into_iter().map(|e| self.tagged(e)).collect())
+        })))
+    }
+
+    fn read_entry(&self, path: &Path) -> Result<FileEntry, FilesError> {
+        Ok(self.tagged(self.inner.read_entry(path)?))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        self.inner.rename(from, to)?;
+        self.update(|records| rekey(records, from, to))
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        self.inner.copy(from, to)?;
+        self.update(|records| {
+            let copies = moved(under(records, from), from, to);
+            let changed = !take(records, to).is_empty() || !copies.is_empty();
+            records.extend(copies);
+            changed
+        })
+    }
+
+    fn copy_metadata(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        self.inner.copy_metadata(from, to)?;
+        self.update(|records| match records.get(&normalize(from)).cloned() {
+            Some(attributes) => {
+                records.insert(normalize(to), attributes.clone()) != Some(attributes)
+            }
+            None => records.remove(&normalize(to)).is_some(),
+        })
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> Result<(), FilesError> {
+        self.inner.set_permissions(path, mode)
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<(), FilesError> {
+        self.inner.set_owner(path, uid, gid)
+    }
+
+    fn set_times(
+        &self,
+        path: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<(), FilesError> {
+        self.inner.set_times(path, accessed, modified)
+    }
+
+    fn move_to(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        self.inner.move_to(from, to)?;
+        self.update(|records| rekey(records, from, to))
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), FilesError> {
+        self.inner.delete(path)?;
+        self.update(|records| !take(records, path).is_empty())
+    }
+
+    fn create_file(&self, path: &Path) -> Result<(), FilesError> {
+        self.inner.create_file(path)?;
+        self.update(|records| !take(records, path).is_empty())
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), FilesError> {
+        self.inner.create_dir(path)?;
+        self.update(|records| !take(records, path).is_empty())
+    }
+
+    fn create_symlink(&self, target: &Path, link: &Path) -> Result<(), FilesError> {
+        self.inner.create_symlink(target, link)?;
+        self.update(|records| !take(records, link).is_empty())
+    }
+
+    /// The new name starts out with the attributes kept for the original,
+    /// but they are not shared from then on.
+    fn create_hardlink(&self, original: &Path, link: &Path) -> Result<(), FilesError> {
+        self.inner.create_hardlink(original, link)?;
+        self.update(|records| {
+            let replaced = !take(records, link).is_empty();
+            match records.get(&normalize(original)).cloned() {
+                Some(attributes) => {
+                    records.insert(normalize(link), attributes);
+                    true
+                }
+                None => replaced,
+            }
+        })
+    }
+
+    fn list_xattrs(&self, path: &Path) -> Result<Vec<OsString>, FilesError> {
+        self.attribute(
+            path,
+            |inner| inner.list_xattrs(path),
+            |sidecar, key| {
+                let records = sidecar.lock();
+                Ok(records
+                    .get(&key)
+                    .map(|attributes| attributes.keys().cloned().collect())
+                    .unwrap_or_default())
+            },
+        )
+    }
+
+    fn get_xattr(&self, path: &Path, name: &OsStr) -> Result<Option<Vec<u8>>, FilesError> {
+        self.attribute(
+            path,
+            |inner| inner.get_xattr(path, name),
+            |sidecar, key| {
+                let records = sidecar.lock();
+                Ok(records
+                    .get(&key)
+                    .and_then(|attributes| attributes.get(name))
+                    .cloned())
+            },
+        )
+    }
+
+    fn set_xattr(&self, path: &Path, name: &OsStr, value: &[u8]) -> Result<(), FilesError> {
+        self.attribute(
+            path,
+            |inner| inner.set_xattr(path, name, value),
+            |sidecar, key| {
+                sidecar.update(|records| {
+                    records
+                        .entry(key)
+                        .or_default()
+                        .insert(name.to_os_string(), value.to_vec())
+                        .as_deref()
+                        != Some(value)
+                })
+            },
+        )
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &OsStr) -> Result<(), FilesError> {
+        self.attribute(
+            path,
+            |inner| inner.remove_xattr(path, name),
+            |sidecar, key| {
+                sidecar.update(|records| {
+                    let Some(attributes) = records.get_mut(&key) else {
+                        return false;
+                    };
+                    let removed = attributes.remove(name).is_some();
+                    if attributes.is_empty() {
+                        records.remove(&key);
+                    }
+                    removed
+                })
+            },
+        )
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        self.inner.canonicalize(path)
+    }
+
+    fn root(&self) -> Option<&Path> {
+        self.inner.root()
+    }
+
+    fn can_enter(&self, entry: &FileEntry) -> bool {
+        self.inner.can_enter(entry)
+    }
+
+    fn extract(&self, path: &Path, destination: &Path) -> Result<(), FilesError> {
+        self.inner.extract(path, destination)
+    }
+
+    fn trash(&self, path: &Path) -> Result<(), FilesError> {
+        self.inner.trash(path)
+    }
+
+    fn restore(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        self.inner.restore(path)
+    }
+
+    fn empty_trash(&self) -> Result<(), FilesError> {
+        self.inner.empty_trash()
+    }
+}
+
+/// Whether `err` says the backend keeps no attributes.
+fn unsupported(err: &FilesError) -> bool {
+    match err {
+        FilesError::Unsupported => true,
+        FilesError::Io(err) => err.kind() == io::ErrorKind::Unsupported,
+        _ => false,
+    }
+}
+
+/// Removes and returns the records at and below `path`. Paths order
+/// component by component, so these directly follow `path` itself.
+fn take(records: &mut Records, path: &Path) -> Records {
+    let path = normalize(path);
+    let keys: Vec<_> = records
+        .range(path.clone()..)
+        .take_while(|(key, _)| key.starts_with(&path))
+        .map(|(key, _)| key.clone())
+        .collect();
+    keys.into_iter()
+        .filter_map(|key| records.remove_entry(&key))
+        .collect()
+}
+
+/// A copy of the records at and below `path`.
+fn under(records: &Records, path: &Path) -> Records {
+    let path = normalize(path);
+    records
+        .range(path.clone()..)
+        .take_while(|(key, _)| key.starts_with(&path))
+        .map(|(key, attributes)| (key.clone(), attributes.clone()))
+        .collect()
+}
+
+/// `taken` with every path below `from` moved below `to` instead.
+fn moved(taken: Records, from: &Path, to: &Path) -> Records {
+    let (from, to) = (normalize(from), normalize(to));
+    taken
+        .into_iter()
+        .map(|(key, attributes)| {
+            let rest = key.strip_prefix(&from).unwrap_or(Path::new(""));
+            (join(&to, rest), attributes)
+        })
+        .collect()
+}
+
+/// Moves the records at and below `from` to `to`, replacing those there,
+/// and tells whether there were any.
+fn rekey(records: &mut Records, from: &Path, to: &Path) -> bool {
+    let taken = take(records, from);
+    let changed = !take(records, to).is_empty() || !taken.is_empty();
+    records.extend(moved(taken, from, to));
+    changed
+}
+
+fn format(records: &Records) -> String {
+    let mut text = String::new();
+
+    for (path, attributes) in records {
+        for (name, value) in attributes {
+            text.push_str(&format!(
+                "{} {} {}\n",
+                encode(&bytes(path.as_os_str())),
+                encode(&bytes(name)),
+                encode(value)
+            ));
+        }
+    }
+
+    text
+}
+
+fn parse(text: &str) -> Option<Records> {
+    let mut records = Records::new();
+
+    for line in text.lines().filter(|line| !line.is_empty()) {
+        let mut fields = line.split(' ');
+        let (path, name, value) = (fields.next()?, fields.next()?, fields.next()?);
+        if fields.next().is_some() {
+            return None;
+        }
+
+        records
+            .entry(PathBuf::from(os_string(decode(path)?)))
+            .or_default()
+            .insert(os_string(decode(name)?), decode(value)?);
+    }
+
+    Some(records)
+}
+
+/// Percent-encodes everything but unreserved characters and `/`.
+fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+
+    for &byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
+fn decode(text: &str) -> Option<Vec<u8>> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = text.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    Some(decoded)
+}
+
+#[cfg(unix)]
+fn bytes(name: &OsStr) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Cow::Borrowed(name.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn bytes(name: &OsStr) -> Cow<'_, [u8]> {
+    match name.to_string_lossy() {
+        Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+        Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+    }
+}
+
+#[cfg(unix)]
+fn os_string(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+fn os_string(bytes: Vec<u8>) -> OsString {
+    String::from_utf8_lossy(&bytes).into_owned().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::memory::MemoryFileSystem;
+    use crate::state::test_utils::temp_dir;
+    use crate::testing::{Call, Fault, Faulty};
+
+    /// A tree whose backend fails every attribute call as unsupported.
+    fn without_attributes() -> Faulty<MemoryFileSystem> {
+        let fs = MemoryFileSystem::new();
+        fs.write_file(Path::new("/work/a.txt"), "a").unwrap();
+        fs.write_file(Path::new("/work/dir/b.txt"), "b").unwrap();
+
+        let mut faulty = Faulty::new(fs);
+        for call in [
+            Call::ListXattrs,
+            Call::GetXattr,
+            Call::SetXattr,
+            Call::RemoveXattr,
+        ] {
+            faulty = faulty.with_fault(Fault::new(io::ErrorKind::Unsupported).on(call));
+        }
+        faulty
+    }
+
+    fn tags_of(fs: &impl FileSystem, path: &str) -> Vec<String> {
+        fs.read_entry(Path::new(path)).unwrap().tags
+    }
+
+    #[test]
+    fn keeps_attributes_the_backend_cannot() {
+        let fs = Sidecar::new(without_attributes());
+        let name = OsStr::new(tags::TAGS);
+
+        fs.set_xattr(Path::new("/work/a.txt"), name, b"draft,final")
+            .unwrap();
+
+        assert_eq!(tags_of(&fs, "/work/a.txt"), ["draft", "final"]);
+        let listing = fs.read_directory(Path::new("/work")).unwrap();
+        let entry = listing.iter().find(|e| e.name == "a.txt").unwrap();
+        assert_eq!(entry.tags, ["draft", "final"]);
+        assert_eq!(
+            fs.list_xattrs(Path::new("/work/a.txt")).unwrap(),
+            [OsString::from(tags::TAGS)]
+        );
+        assert!(
+            fs.set_xattr(Path::new("/work/missing"), name, b"x")
+                .is_err()
+        );
+
+        fs.remove_xattr(Path::new("/work/a.txt"), name).unwrap();
+        assert_eq!(fs.get_xattr(Path::new("/work/a.txt"), name).unwrap(), None);
+    }
+
+    #[test]
+    fn prefers_the_backend_where_it_keeps_attributes() {
+        let memory = MemoryFileSystem::new();
+        memory.write_file(Path::new("/a.txt"), "").unwrap();
+        let fs = Sidecar::new(memory);
+
+        fs.set_xattr(Path::new("/a.txt"), OsStr::new(tags::TAGS), b"x")
+            .unwrap();
+
+        assert!(fs.lock().is_empty());
+        assert_eq!(tags_of(fs.inner(), "/a.txt"), ["x"]);
+    }
+
+    #[test]
+    fn attributes_follow_their_entries() {
+        let fs = Sidecar::new(without_attributes());
+        let name = OsStr::new(tags::TAGS);
+        fs.set_xattr(Path::new("/work/dir/b.txt"), name, b"b")
+            .unwrap();
+        fs.set_xattr(Path::new("/work/a.txt"), name, b"a").unwrap();
+
+        fs.rename(Path::new("/work/dir"), Path::new("/work/moved"))
+            .unwrap();
+        fs.copy(Path::new("/work/moved"), Path::new("/work/copy"))
+            .unwrap();
+        fs.delete(Path::new("/work/a.txt")).unwrap();
+        fs.create_file(Path::new("/work/a.txt")).unwrap();
+
+        assert_eq!(tags_of(&fs, "/work/moved/b.txt"), ["b"]);
+        assert_eq!(tags_of(&fs, "/work/copy/b.txt"), ["b"]);
+        assert!(tags_of(&fs, "/work/a.txt").is_empty());
+        assert_eq!(fs.lock().len(), 2);
+    }
+
+    #[test]
+    fn conforms_over_a_backend_without_attributes() {
+        let fs = Sidecar::new(without_attributes());
+        fs.create_dir(Path::new("/conformance")).unwrap();
+
+        crate::testing::run(&fs, Path::new("/conformance"));
+    }
+
+    #[test]
+    fn saves_to_and_loads_from_a_file() {
+        let dir = temp_dir("sidecar");
+        // Neither the directory nor a shared `.tmp` name may get in the way.
+        let file = dir.join("attributes/s3-my.bucket");
+        let name = OsStr::new("user.odd name\n");
+
+        let fs = Sidecar::open(without_attributes(), &file).unwrap();
+        fs.set_xattr(Path::new("/work/a.txt"), name, b"50% off")
+            .unwrap();
+        let saved: Vec<_> = fs::read_dir(dir.join("attributes"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        let inner = fs.into_inner();
+
+        let mut fs = Sidecar::open(inner, &file).unwrap();
+        let value = fs.get_xattr(Path::new("/work/a.txt"), name).unwrap();
+
+        let garbage = dir.join("garbage");
+        fs::write(&garbage, "garbage").unwrap();
+        let failed = fs.load(&garbage);
+        let kept = fs.get_xattr(Path::new("/work/a.txt"), name).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(saved, ["s3-my.bucket"]);
+        assert_eq!(value.as_deref(), Some(&b"50% off"[..]));
+        assert!(failed.is_err());
+        assert_eq!(kept, value);
+    }
+
+    #[test]
+    fn saves_only_changes() {
+        let dir = temp_dir("sidecar-changes");
+        let file = dir.join("attributes/local");
+        let name = OsStr::new(tags::TAGS);
+
+        let fs = Sidecar::open(without_attributes(), &file).unwrap();
+        fs.rename(Path::new("/work/a.txt"), Path::new("/work/c.txt"))
+            .unwrap();
+        fs.delete(Path::new("/work/dir")).unwrap();
+        let untouched = !dir.join("attributes").exists();
+
+        fs.set_xattr(Path::new("/work/c.txt"), name, b"draft")
+            .unwrap();
+        let saved = file.exists();
+        fs::remove_file(&file).unwrap();
+        fs.set_xattr(Path::new("/work/c.txt"), name, b"draft")
+            .unwrap();
+        fs.remove_xattr(Path::new("/work/c.txt"), OsStr::new("user.other"))
+            .unwrap();
+        fs.create_file(Path::new("/work/d.txt")).unwrap();
+        let unsaved = !file.exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(untouched);
+        assert!(saved);
+        assert!(unsaved);
+    }
+}
@@ -1,8 +1,11 @@
+use std::ffi::OsStr;
 use std::fs;
 use std::path::Path;
 
 use crate::errors::FilesError;
-use crate::models::FileEntry;
+use crate::models::{EntryKind, EntryMetadata, FileEntry, LinkInfo};
+#[cfg(target_os = "linux")]
+use crate::tags;
 
 pub mod operations;
 
@@ -11,6 +14,8 @@ pub mod operations;
 /// # Behavior
 /// - Returns `FilesError::InvalidPath` if the path is not a directory.
 /// - Propagates IO errors using `FilesError`.
+/// - Captures each entry's metadata (size, timestamps, mode, owner, kind).
+/// - Does not follow symbolic links; broken links are listed, not errors.
 /// - Sorts entries with directories first, then files,
 ///   both in case-insensitive alphabetical order.
 pub fn read_directory(path: &Path) -> Result<Vec<FileEntry>, FilesError> {
@@ -23,33 +28,189 @@ pub fn read_directory(path: &Path) -> Result<Vec<FileEntry>, FilesError> {
 
     for entry in fs::read_dir(path)? {
         let entry = entry?;
-        let metadata = entry.metadata()?;
-
-        entries.push(FileEntry {
-            name: entry.file_name().to_string_lossy().into_owned(),
-            path: entry.path(),
-            is_dir: metadata.is_dir(),
-        });
+        entries.push(read_entry(&entry.path())?);
     }
 
     // Directories first, then alphabetical (case-insensitive)
     entries.sort_by(|a, b| {
-        b.is_dir
-            .cmp(&a.is_dir)
-            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+        b.is_dir().cmp(&a.is_dir()).then_with(|| {
+            a.name
+                .to_string_lossy()
+                .to_lowercase()
+                .cmp(&b.name.to_string_lossy().to_lowercase())
+        })
     });
 
     Ok(entries)
 }
 
+/// Reads a directory in chunks of at most `chunk_size` entries, unsorted.
+///
+/// Fails up front like [`read_directory`] if the path is not a directory;
+/// errors while reading later entries are yielded in place of a chunk.
+pub fn read_directory_chunks(
+    path: &Path,
+    chunk_size: usize,
+) -> Result<impl Iterator<Item = Result<Vec<FileEntry>, FilesError>> + use<>, FilesError> {
+    if !path.is_dir() {
+        return Err(FilesError::InvalidPath);
+    }
+
+    let mut dir = fs::read_dir(path)?;
+    let chunk_size = chunk_size.max(1);
+
+    Ok(std::iter::from_fn(move || {
+        let mut chunk = Vec::with_capacity(chunk_size);
+
+        for entry in dir.by_ref() {
+            match entry
+                .map_err(FilesError::from)
+                .and_then(|e| read_entry(&e.path()))
+            {
+                Ok(entry) => chunk.push(entry),
+                Err(err) => return Some(Err(err)),
+            }
+            if chunk.len() == chunk_size {
+                break;
+            }
+        }
+
+        (!chunk.is_empty()).then_some(Ok(chunk))
+    }))
+}
+
+/// Reads a single entry without following symbolic links.
+///
+/// For links, the raw target is recorded along with where it resolves to,
+/// if anywhere. Tags are read from the entry's extended attributes.
+pub fn read_entry(path: &Path) -> Result<FileEntry, FilesError> {
+    let metadata = fs::symlink_metadata(path)?;
+    let kind = EntryKind::from(metadata.file_type());
+
+    let name = path
+        .file_name()
+        .map(OsStr::to_os_string)
+        .unwrap_or_default();
+
+    let mut entry = FileEntry::new(name, path, kind).with_metadata(EntryMetadata::from(&metadata));
+
+    if kind == EntryKind::Symlink {
+        let target = fs::read_link(path)?;
+        let resolved = fs::canonicalize(path).ok();
+        let target_kind = resolved
+            .as_ref()
+            .and_then(|resolved| fs::metadata(resolved).ok())
+            .map(|metadata| EntryKind::from(metadata.file_type()));
+
+        entry = entry.with_link(LinkInfo {
+            target,
+            resolved,
+            target_kind,
+        });
+    }
+
+    // Filesystems without user attributes leave entries untagged.
+    #[cfg(target_os = "linux")]
+    if let Ok(Some(value)) = operations::get_xattr(path, OsStr::new(tags::TAGS)) {
+        entry = entry.with_tags(tags::parse(&value));
+    }
+
+    Ok(entry)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::path::Path;
 
+    use crate::state::test_utils::temp_dir;
+
     #[test]
     fn invalid_path_returns_error() {
         let result = read_directory(Path::new("non_existent_path"));
         assert!(matches!(result, Err(FilesError::InvalidPath)));
     }
+
+    #[test]
+    fn chunks_cover_every_entry() {
+        let dir = temp_dir("chunks");
+        for i in 0..7 {
+            fs::write(dir.join(format!("{}.txt", i)), "").unwrap();
+        }
+
+        let chunks: Vec<_> = read_directory_chunks(&dir, 3)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let sizes: Vec<_> = chunks.iter().map(Vec::len).collect();
+        assert_eq!(sizes, [3, 3, 1]);
+    }
+
+    #[test]
+    fn entries_carry_metadata() {
+        let dir = temp_dir("metadata");
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("data.bin"), [0u8; 42]).unwrap();
+
+        let entries = read_directory(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let sub = entries.iter().find(|e| e.name == "sub").unwrap();
+        assert_eq!(sub.kind, EntryKind::Directory);
+
+        let file = entries.iter().find(|e| e.name == "data.bin").unwrap();
+        assert_eq!(file.kind, EntryKind::File);
+        assert_eq!(file.metadata.size, 42);
+        assert!(file.metadata.modified.is_some());
+        assert_ne!(file.metadata.mode, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_listed_without_following() {
+        use std::os::unix::fs::symlink;
+
+        let dir = temp_dir("symlinks");
+        fs::create_dir(dir.join("target")).unwrap();
+        symlink("target", dir.join("to_dir")).unwrap();
+        symlink("missing", dir.join("broken")).unwrap();
+
+        let entries = read_directory(&dir).unwrap();
+
+        let to_dir = entries.iter().find(|e| e.name == "to_dir").unwrap();
+        assert_eq!(to_dir.kind, EntryKind::Symlink);
+        assert!(to_dir.is_dir());
+        let link = to_dir.link.as_ref().unwrap();
+        assert_eq!(link.target, Path::new("target"));
+        assert_eq!(
+            link.resolved.as_deref(),
+            Some(fs::canonicalize(dir.join("target")).unwrap().as_path())
+        );
+        assert_eq!(link.target_kind, Some(EntryKind::Directory));
+
+        let broken = entries.iter().find(|e| e.name == "broken").unwrap();
+        assert!(broken.is_broken_link());
+        assert!(!broken.is_dir());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names_are_preserved() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = temp_dir("non-utf8");
+        let name = OsStr::from_bytes(b"report-\xff.txt");
+        fs::write(dir.join(name), "x").unwrap();
+
+        let entries = read_directory(&dir).unwrap();
+
+        assert_eq!(entries[0].name, name);
+        assert_eq!(entries[0].display_name(), "report-\\xff.txt");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
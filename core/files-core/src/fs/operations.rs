@@ -1,17 +1,29 @@
-use std::fs;
+#[cfg(unix)]
+use std::ffi::CString;
+#[cfg(target_os = "linux")]
+use std::ffi::{OsStr, OsString};
+#[cfg(target_os = "linux")]
+use std::fs::File;
+use std::fs::{self, Metadata};
 use std::io;
 use std::path::Path;
+use std::time::SystemTime;
 
 pub fn delete(path: &Path) -> io::Result<()> {
-    if path.is_dir() {
+    // Never recurse through a link: remove the link itself.
+    if fs::symlink_metadata(path)?.is_dir() {
         fs::remove_dir_all(path)
     } else {
         fs::remove_file(path)
     }
 }
 
+/// Creates an empty file, failing if `path` already exists.
 pub fn create_file(path: &Path) -> io::Result<()> {
-    fs::File::create(path)?;
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
     Ok(())
 }
 
@@ -24,18 +36,480 @@ pub fn rename(from: &Path, to: &Path) -> io::Result<()> {
     fs::rename(from, to)
 }
 
+/// Copies `from` to the new path `to`, recursively for directories.
+///
+/// Links are recreated as links, never followed. Every entry keeps its
+/// mode, times, extended attributes and, where permitted, owner (see
+/// [`copy_metadata`]). Files are cloned on filesystems with reflinks;
+/// otherwise only their data is copied, in the kernel where possible, so
+/// the holes of sparse files stay holes.
 pub fn copy(from: &Path, to: &Path) -> io::Result<()> {
-    if from.is_dir() {
-        fs::create_dir_all(to)?;
+    let metadata = fs::symlink_metadata(from)?;
+    let file_type = metadata.file_type();
+
+    if file_type.is_symlink() {
+        symlink(&fs::read_link(from)?, to)?;
+    } else if file_type.is_dir() {
+        fs::create_dir(to)?;
         for entry in fs::read_dir(from)? {
             let entry = entry?;
-            let src = entry.path();
-            let dst = to.join(entry.file_name());
-            copy(&src, &dst)?;
+            copy(&entry.path(), &to.join(entry.file_name()))?;
         }
-        Ok(())
     } else {
-        fs::copy(from, to)?;
-        Ok(())
+        copy_file(from, to)?;
+    }
+
+    // Last, so the directory times are not bumped by its contents.
+    apply_metadata(&metadata, from, to)
+}
+
+/// Gives `to` the mode, access and modification times, extended attributes
+/// and owner of `from`, without following links.
+///
+/// Changing the owner is skipped where it is not permitted, and so are
+/// extended attributes the destination does not support.
+pub fn copy_metadata(from: &Path, to: &Path) -> io::Result<()> {
+    apply_metadata(&fs::symlink_metadata(from)?, from, to)
+}
+
+#[cfg(unix)]
+fn apply_metadata(metadata: &Metadata, from: &Path, to: &Path) -> io::Result<()> {
+    use std::os::unix::fs::{MetadataExt, lchown};
+
+    #[cfg(target_os = "linux")]
+    copy_xattrs(from, to)?;
+    #[cfg(not(target_os = "linux"))]
+    let _ = from;
+
+    // Before the mode: a change of owner clears the setuid and setgid bits.
+    match lchown(to, Some(metadata.uid()), Some(metadata.gid())) {
+        // Only root can give files away, but the group may still be ours.
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            permitted(lchown(to, None, Some(metadata.gid())))?
+        }
+        result => result?,
+    }
+
+    // Links have no mode of their own.
+    if !metadata.file_type().is_symlink() {
+        fs::set_permissions(to, metadata.permissions())?;
+    }
+
+    let times = [
+        timespec(metadata.atime(), metadata.atime_nsec()),
+        timespec(metadata.mtime(), metadata.mtime_nsec()),
+    ];
+    let to = c_path(to)?;
+    // SAFETY: `to` is NUL-terminated and `times` holds the two timestamps
+    // utimensat(2) reads.
+    let set = unsafe {
+        libc::utimensat(
+            libc::AT_FDCWD,
+            to.as_ptr(),
+            times.as_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    };
+    if set != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn apply_metadata(metadata: &Metadata, _from: &Path, to: &Path) -> io::Result<()> {
+    if !metadata.file_type().is_symlink() {
+        fs::set_permissions(to, metadata.permissions())?;
+    }
+    Ok(())
+}
+
+/// Sets the access and modification times of `path`, following links.
+/// `None` leaves that time as it is.
+#[cfg(unix)]
+pub fn set_times(
+    path: &Path,
+    accessed: Option<SystemTime>,
+    modified: Option<SystemTime>,
+) -> io::Result<()> {
+    let time = |time: Option<SystemTime>| match time {
+        Some(time) => {
+            let (seconds, nanoseconds) = since_epoch(time);
+            timespec(seconds, nanoseconds)
+        }
+        None => timespec(0, libc::UTIME_OMIT),
+    };
+
+    let times = [time(accessed), time(modified)];
+    let path = c_path(path)?;
+    // SAFETY: `path` is NUL-terminated and `times` holds the two timestamps
+    // utimensat(2) reads.
+    if unsafe { libc::utimensat(libc::AT_FDCWD, path.as_ptr(), times.as_ptr(), 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn set_times(
+    path: &Path,
+    accessed: Option<SystemTime>,
+    modified: Option<SystemTime>,
+) -> io::Result<()> {
+    let mut times = fs::FileTimes::new();
+    if let Some(accessed) = accessed {
+        times = times.set_accessed(accessed);
+    }
+    if let Some(modified) = modified {
+        times = times.set_modified(modified);
+    }
+    fs::File::options().write(true).open(path)?.set_times(times)
+}
+
+/// Seconds and nanoseconds since the epoch, negative before it, with the
+/// nanoseconds always counting forward.
+#[cfg(unix)]
+fn since_epoch(time: SystemTime) -> (i64, i64) {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(after) => (after.as_secs() as i64, i64::from(after.subsec_nanos())),
+        Err(err) => {
+            let before = err.duration();
+            match before.subsec_nanos() {
+                0 => (-(before.as_secs() as i64), 0),
+                nanos => (
+                    -(before.as_secs() as i64) - 1,
+                    1_000_000_000 - i64::from(nanos),
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn timespec(seconds: i64, nanoseconds: i64) -> libc::timespec {
+    // SAFETY: timespec is plain data, for which all zeroes is valid.
+    let mut time: libc::timespec = unsafe { std::mem::zeroed() };
+    time.tv_sec = seconds as libc::time_t;
+    time.tv_nsec = nanoseconds as _;
+    time
+}
+
+#[cfg(unix)]
+fn c_path(path: &Path) -> io::Result<CString> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+/// Treats a lack of permission as success, for best-effort steps.
+#[cfg(unix)]
+fn permitted(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => Ok(()),
+        result => result,
+    }
+}
+
+/// Copies every extended attribute the destination accepts. Namespaces
+/// that need privileges, such as `trusted.`, are skipped when refused.
+#[cfg(target_os = "linux")]
+fn copy_xattrs(from: &Path, to: &Path) -> io::Result<()> {
+    let (from, to) = (c_path(from)?, c_path(to)?);
+
+    // SAFETY: the path is NUL-terminated, and `read_xattr` passes a buffer
+    // of at least `size` bytes.
+    let names = match read_xattr(|buffer, size| unsafe {
+        libc::llistxattr(from.as_ptr(), buffer.cast(), size)
+    }) {
+        Err(err) if unsupported(&err) => return Ok(()),
+        names => names?,
+    };
+
+    for name in names
+        .split(|&byte| byte == 0)
+        .filter(|name| !name.is_empty())
+    {
+        let name = CString::new(name)?;
+        // SAFETY: as above, with a NUL-terminated name.
+        let value = match read_xattr(|buffer, size| unsafe {
+            libc::lgetxattr(from.as_ptr(), name.as_ptr(), buffer.cast(), size)
+        }) {
+            Err(err) if unsupported(&err) => continue,
+            value => value?,
+        };
+
+        // SAFETY: the path and name are NUL-terminated and `value` holds
+        // `value.len()` bytes.
+        let set = unsafe {
+            libc::lsetxattr(
+                to.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            )
+        };
+        if set != 0 {
+            let err = io::Error::last_os_error();
+            if !unsupported(&err) {
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs an xattr call that reports the size it needs when given no
+/// buffer, retrying if the value grows in between.
+#[cfg(target_os = "linux")]
+fn read_xattr(call: impl Fn(*mut u8, usize) -> isize) -> io::Result<Vec<u8>> {
+    loop {
+        let size = call(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut buffer = vec![0; size as usize];
+        if buffer.is_empty() {
+            return Ok(buffer);
+        }
+
+        let read = call(buffer.as_mut_ptr(), buffer.len());
+        if read >= 0 {
+            buffer.truncate(read as usize);
+            return Ok(buffer);
+        }
+
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn unsupported(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::EOPNOTSUPP | libc::EPERM | libc::EACCES)
+    )
+}
+
+/// Lists the names of the extended attributes of `path`, without
+/// following links.
+#[cfg(target_os = "linux")]
+pub fn list_xattrs(path: &Path) -> io::Result<Vec<OsString>> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = c_path(path)?;
+    // SAFETY: the path is NUL-terminated, and `read_xattr` passes a buffer
+    // of at least `size` bytes.
+    let names =
+        read_xattr(|buffer, size| unsafe { libc::llistxattr(path.as_ptr(), buffer.cast(), size) })?;
+
+    Ok(names
+        .split(|&byte| byte == 0)
+        .filter(|name| !name.is_empty())
+        .map(|name| OsStr::from_bytes(name).to_os_string())
+        .collect())
+}
+
+/// Reads the extended attribute `name` of `path`, without following links;
+/// `None` if it is not set.
+#[cfg(target_os = "linux")]
+pub fn get_xattr(path: &Path, name: &OsStr) -> io::Result<Option<Vec<u8>>> {
+    let (path, name) = (c_path(path)?, c_name(name)?);
+    // SAFETY: as in `list_xattrs`, with a NUL-terminated name.
+    let value = read_xattr(|buffer, size| unsafe {
+        libc::lgetxattr(path.as_ptr(), name.as_ptr(), buffer.cast(), size)
+    });
+
+    match value {
+        Err(err) if err.raw_os_error() == Some(libc::ENODATA) => Ok(None),
+        value => value.map(Some),
+    }
+}
+
+/// Sets the extended attribute `name` of `path`, without following links.
+#[cfg(target_os = "linux")]
+pub fn set_xattr(path: &Path, name: &OsStr, value: &[u8]) -> io::Result<()> {
+    let (path, name) = (c_path(path)?, c_name(name)?);
+    // SAFETY: the path and name are NUL-terminated and `value` holds
+    // `value.len()` bytes.
+    let set = unsafe {
+        libc::lsetxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    };
+    if set != 0 {
+        return Err(io::Error::last_os_error());
     }
+    Ok(())
+}
+
+/// Removes the extended attribute `name` of `path`, without following
+/// links. Removing one that is not set succeeds.
+#[cfg(target_os = "linux")]
+pub fn remove_xattr(path: &Path, name: &OsStr) -> io::Result<()> {
+    let (path, name) = (c_path(path)?, c_name(name)?);
+    // SAFETY: the path and name are NUL-terminated.
+    if unsafe { libc::lremovexattr(path.as_ptr(), name.as_ptr()) } != 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ENODATA) {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn c_name(name: &OsStr) -> io::Result<CString> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Ok(CString::new(name.as_bytes())?)
+}
+
+/// Copies a file's contents: a reflink where the filesystem supports one,
+/// and otherwise each data region with copy_file_range(2), falling back to
+/// reads and writes across filesystems that do not support it.
+#[cfg(target_os = "linux")]
+fn copy_file(from: &Path, to: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let source = File::open(from)?;
+    let destination = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(to)?;
+
+    // SAFETY: both descriptors stay open for the duration of the call.
+    if unsafe { libc::ioctl(destination.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } == 0 {
+        return Ok(());
+    }
+
+    let len = source.metadata()?.len();
+    let mut in_kernel = true;
+    let mut offset = 0;
+    while let Some((start, end)) = next_data(&source, offset, len)? {
+        copy_range(&source, &destination, start, end, &mut in_kernel)?;
+        offset = end;
+    }
+
+    // Holes at the end leave no data to write.
+    destination.set_len(len)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_file(from: &Path, to: &Path) -> io::Result<()> {
+    fs::copy(from, to)?;
+    Ok(())
+}
+
+/// The next region of data in `file` at or after `offset`, as a start and
+/// end offset, or `None` once only holes are left.
+///
+/// Filesystems without SEEK_DATA report the rest of the file as data.
+#[cfg(target_os = "linux")]
+fn next_data(file: &File, offset: u64, len: u64) -> io::Result<Option<(u64, u64)>> {
+    use std::os::fd::AsRawFd;
+
+    if offset >= len {
+        return Ok(None);
+    }
+
+    let seek = |offset: u64, whence| {
+        // SAFETY: lseek(2) only repositions the open descriptor.
+        match unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, whence) } {
+            -1 => Err(io::Error::last_os_error()),
+            position => Ok(position as u64),
+        }
+    };
+
+    let start = match seek(offset, libc::SEEK_DATA) {
+        Ok(start) => start,
+        Err(err) if err.raw_os_error() == Some(libc::ENXIO) => return Ok(None),
+        Err(err) if err.raw_os_error() == Some(libc::EINVAL) => return Ok(Some((offset, len))),
+        Err(err) => return Err(err),
+    };
+    let end = seek(start, libc::SEEK_HOLE)?;
+
+    Ok((start < len).then(|| (start, end.min(len))))
+}
+
+/// Copies bytes `start..end` of `source` to the same offsets in
+/// `destination`. Clears `in_kernel` when copy_file_range(2) cannot be
+/// used between the two, so later regions go straight to the fallback.
+#[cfg(target_os = "linux")]
+fn copy_range(
+    source: &File,
+    destination: &File,
+    start: u64,
+    end: u64,
+    in_kernel: &mut bool,
+) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::FileExt;
+
+    let mut position = start;
+
+    while *in_kernel && position < end {
+        let (mut from, mut to) = (position as libc::loff_t, position as libc::loff_t);
+        let chunk = (end - position).min(1 << 30) as usize;
+        // SAFETY: both descriptors are open and the offsets are owned here.
+        let copied = unsafe {
+            libc::copy_file_range(
+                source.as_raw_fd(),
+                &mut from,
+                destination.as_raw_fd(),
+                &mut to,
+                chunk,
+                0,
+            )
+        };
+
+        match copied {
+            // The source shrank under us.
+            0 => return Ok(()),
+            -1 => {
+                let err = io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(libc::EXDEV | libc::ENOSYS | libc::EOPNOTSUPP | libc::EINVAL) => {
+                        *in_kernel = false
+                    }
+                    _ => return Err(err),
+                }
+            }
+            copied => position += copied as u64,
+        }
+    }
+
+    let mut buffer = vec![0; 64 * 1024];
+    while position < end {
+        let chunk = (end - position).min(buffer.len() as u64) as usize;
+        let read = source.read_at(&mut buffer[..chunk], position)?;
+        if read == 0 {
+            break;
+        }
+        destination.write_all_at(&buffer[..read], position)?;
+        position += read as u64;
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
 }
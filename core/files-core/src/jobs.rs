@@ -0,0 +1,952 @@
+//! Long-running copies, moves and deletions with progress and cancellation.
+//!
+//! A [`Task`] is broken down into one filesystem call per entry, so progress
+//! is reported and cancellation is checked between entries. Every call is a
+//! complete operation: whenever a job stops, each entry is either fully
+//! handled or untouched, and the [`Progress`] says how far it got.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{
+    conflict::{Conflict, Policy, Resolution},
+    errors::FilesError,
+    filesystem::{FileSystem, entry_at, normalize},
+    models::{EntryKind, FileEntry},
+};
+
+/// Work for a [`Job`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Task {
+    /// Copies `from` to `to`, as [`FileSystem::copy`] would.
+    Copy { from: PathBuf, to: PathBuf },
+    /// Moves `from` to `to`, as [`FileSystem::move_to`] would.
+    Move { from: PathBuf, to: PathBuf },
+    /// Deletes `path` and everything under it.
+    Delete(PathBuf),
+}
+
+impl Task {
+    /// The path the task works on.
+    pub fn source(&self) -> &Path {
+        match self {
+            Task::Copy { from, .. } | Task::Move { from, .. } => from,
+            Task::Delete(path) => path,
+        }
+    }
+}
+
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Task::Copy { from, to } => write!(f, "copy {} -> {}", from.display(), to.display()),
+            Task::Move { from, to } => write!(f, "move {} -> {}", from.display(), to.display()),
+            Task::Delete(path) => write!(f, "delete {}", path.display()),
+        }
+    }
+}
+
+/// How far a job has got.
+///
+/// Directories are not counted as files. Bytes are counted once a file has
+/// been handled in full.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    pub files_done: u64,
+    pub files_total: u64,
+    /// Files left alone because of a conflict; counted as done too.
+    pub files_skipped: u64,
+    /// The entry being worked on.
+    pub current: Option<PathBuf>,
+    /// Where the task's source is going, once known. It differs from the
+    /// task's destination when both entries are kept.
+    pub destination: Option<PathBuf>,
+    /// Set while the totals are still being counted.
+    pub scanning: bool,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// The share of the work done, from 0 to 1: by bytes, or by files when
+    /// there are no bytes to count.
+    pub fn fraction(&self) -> f64 {
+        if self.bytes_total > 0 {
+            self.bytes_done as f64 / self.bytes_total as f64
+        } else if self.files_total > 0 {
+            self.files_done as f64 / self.files_total as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Bytes handled per second so far.
+    pub fn rate(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.bytes_done as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// The estimated time left, once there is enough to go on.
+    pub fn eta(&self) -> Option<Duration> {
+        let fraction = self.fraction();
+        if self.scanning || fraction <= 0.0 {
+            return None;
+        }
+
+        let seconds = self.elapsed.as_secs_f64() * (1.0 - fraction) / fraction;
+        Some(Duration::from_secs_f64(seconds.max(0.0)))
+    }
+}
+
+/// Asks a running job to stop at the next entry.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// How a job that did not fail ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Finished,
+    /// Stopped by its [`CancelToken`] between two entries.
+    Cancelled,
+}
+
+/// Runs `task` on the calling thread, calling `report` as it progresses.
+///
+/// When a destination already exists, `resolve` picks the [`Policy`] for
+/// it. Directories are merged entry by entry, so a policy applies to each
+/// colliding entry rather than the whole tree.
+///
+/// A move is first tried as a single rename; only one that crosses devices
+/// or merges into an existing directory is moved entry by entry.
+/// Cancelling a copy leaves the entries copied so far in place, and
+/// cancelling a move leaves every entry either at its source or at its
+/// destination.
+pub fn run<F: FileSystem + ?Sized>(
+    fs: &F,
+    task: &Task,
+    cancel: &CancelToken,
+    resolve: impl FnMut(&Conflict) -> Policy,
+    report: impl FnMut(&Progress),
+) -> Result<Outcome, FilesError> {
+    let mut runner = Runner {
+        fs,
+        cancel,
+        resolve,
+        report,
+        progress: Progress::default(),
+        started: Instant::now(),
+    };
+
+    let outcome = runner.run(task);
+    runner.progress.current = None;
+    runner.progress.scanning = false;
+    runner.report();
+    outcome
+}
+
+/// A [`Task`] running on a background thread.
+///
+/// Conflicts are resolved with the policy the job was started with or, if
+/// it has none, put to the owner through [`conflict`](Self::conflict) while
+/// the job waits for [`resolve`](Self::resolve). Dropping the handle
+/// cancels the job.
+pub struct Job {
+    task: Task,
+    cancel: CancelToken,
+    progress: Arc<Mutex<Progress>>,
+    result: Receiver<Result<Outcome, FilesError>>,
+    question: Arc<Mutex<Option<Conflict>>>,
+    policy: Arc<Mutex<Option<Policy>>>,
+    answers: Sender<Policy>,
+}
+
+impl Job {
+    pub fn spawn<F>(fs: Arc<F>, task: Task, policy: Option<Policy>) -> Self
+    where
+        F: FileSystem + Send + Sync + ?Sized + 'static,
+    {
+        let cancel = CancelToken::new();
+        let progress = Arc::new(Mutex::new(Progress::default()));
+        let question = Arc::new(Mutex::new(None));
+        let policy = Arc::new(Mutex::new(policy));
+        let (outgoing, result) = mpsc::channel();
+        let (answers, incoming) = mpsc::channel();
+
+        let worker = (
+            task.clone(),
+            cancel.clone(),
+            Arc::clone(&progress),
+            Arc::clone(&question),
+            Arc::clone(&policy),
+        );
+        thread::spawn(move || {
+            let (task, cancel, progress, question, policy) = worker;
+            let ask = |conflict: &Conflict| {
+                if let Some(policy) = *lock(&policy) {
+                    return policy;
+                }
+
+                *lock(&question) = Some(conflict.clone());
+                let answer = loop {
+                    match incoming.recv_timeout(Duration::from_millis(100)) {
+                        Ok(answer) => break answer,
+                        Err(RecvTimeoutError::Timeout) if !cancel.is_cancelled() => {}
+                        // Cancelled or abandoned: the job stops next.
+                        Err(_) => break Policy::Skip,
+                    }
+                };
+                *lock(&question) = None;
+                answer
+            };
+
+            let outcome = run(&*fs, &task, &cancel, ask, |update| {
+                *lock(&progress) = update.clone();
+            });
+            let _ = outgoing.send(outcome);
+        });
+
+        Self {
+            task,
+            cancel,
+            progress,
+            result,
+            question,
+            policy,
+            answers,
+        }
+    }
+
+    pub fn task(&self) -> &Task {
+        &self.task
+    }
+
+    /// A snapshot of the job's progress.
+    pub fn progress(&self) -> Progress {
+        lock(&self.progress).clone()
+    }
+
+    /// The conflict the job is waiting on an answer for, if any.
+    pub fn conflict(&self) -> Option<Conflict> {
+        lock(&self.question).clone()
+    }
+
+    /// Answers the pending [`conflict`](Self::conflict), and with
+    /// `for_all`, every later one too.
+    pub fn resolve(&self, policy: Policy, for_all: bool) {
+        if for_all {
+            *lock(&self.policy) = Some(policy);
+        }
+        if lock(&self.question).take().is_some() {
+            // The worker only stops once it has an answer or `self` is gone.
+            let _ = self.answers.send(policy);
+        }
+    }
+
+    /// Asks the job to stop; it ends with [`Outcome::Cancelled`] once the
+    /// entry in progress is done.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// Returns how the job ended, if it has, without blocking.
+    pub fn try_finish(&self) -> Option<Result<Outcome, FilesError>> {
+        self.result.try_recv().ok()
+    }
+
+    /// Waits up to `timeout` for the job to end.
+    pub fn wait(&self, timeout: Duration) -> Option<Result<Outcome, FilesError>> {
+        self.result.recv_timeout(timeout).ok()
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// An entry found by the scan, relative to the task's source.
+struct Item {
+    entry: FileEntry,
+    relative: PathBuf,
+}
+
+impl Item {
+    fn is_dir(&self) -> bool {
+        self.entry.kind == EntryKind::Directory
+    }
+}
+
+/// The entries of a destination directory that existed before the job, by
+/// name.
+type Existing = HashMap<OsString, FileEntry>;
+
+/// Where a directory's contents go.
+struct Target {
+    path: PathBuf,
+    /// What was already there, for a directory being merged into; `None`
+    /// for one the job created.
+    existing: Option<Existing>,
+}
+
+/// Where an entry goes, once any conflict is resolved.
+enum Placement {
+    /// A free path, or one holding a file to replace.
+    At(PathBuf),
+    /// The path of a directory to merge into.
+    Merge(PathBuf),
+    Skip,
+}
+
+struct Runner<'a, F: ?Sized, C, R> {
+    fs: &'a F,
+    cancel: &'a CancelToken,
+    resolve: C,
+    report: R,
+    progress: Progress,
+    started: Instant,
+}
+
+impl<F, C, R> Runner<'_, F, C, R>
+where
+    F: FileSystem + ?Sized,
+    C: FnMut(&Conflict) -> Policy,
+    R: FnMut(&Progress),
+{
+    fn run(&mut self, task: &Task) -> Result<Outcome, FilesError> {
+        match task {
+            Task::Copy { from, to } => self.transfer(from, to, false),
+            Task::Move { from, to } => self.transfer(from, to, true),
+            Task::Delete(path) => self.delete(path),
+        }
+    }
+
+    fn report(&mut self) {
+        self.progress.elapsed = self.started.elapsed();
+        (self.report)(&self.progress);
+    }
+
+    /// Reports `path` as the entry in progress, unless the job was
+    /// cancelled.
+    fn step(&mut self, path: &Path) -> bool {
+        if self.cancel.is_cancelled() {
+            return false;
+        }
+
+        self.progress.current = Some(path.to_path_buf());
+        self.report();
+        true
+    }
+
+    fn done(&mut self, item: &Item) {
+        if !item.is_dir() {
+            self.progress.files_done += 1;
+            self.progress.bytes_done += item.entry.metadata.size;
+        }
+        self.report();
+    }
+
+    fn skip(&mut self, item: &Item) {
+        if !item.is_dir() {
+            self.progress.files_skipped += 1;
+        }
+        self.done(item);
+    }
+
+    /// Copies or moves `from` to `to`, resolving conflicts entry by entry.
+    fn transfer(&mut self, from: &Path, to: &Path, remove: bool) -> Result<Outcome, FilesError> {
+        if to != from && normalize(to).starts_with(normalize(from)) {
+            return Err(FilesError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot move or copy a directory into itself",
+            )));
+        }
+
+        let source = self.source_entry(from);
+        let existing = to.parent().map(|parent| self.existing(parent));
+        let placement = self.place(&source, to.to_path_buf(), existing.as_ref())?;
+        if self.cancel.is_cancelled() {
+            return Ok(Outcome::Cancelled);
+        }
+
+        let (target, merge) = match placement {
+            Placement::Skip => {
+                self.progress.files_skipped += 1;
+                return Ok(Outcome::Finished);
+            }
+            Placement::At(path) => (path, false),
+            Placement::Merge(path) => (path, true),
+        };
+        self.progress.destination = Some(target.clone());
+
+        if remove && !merge {
+            match self.fs.rename(from, &target) {
+                Err(FilesError::Io(err)) if err.kind() == io::ErrorKind::CrossesDevices => {}
+                result => {
+                    result?;
+                    self.progress.files_total = 1;
+                    self.progress.files_done = 1;
+                    return Ok(Outcome::Finished);
+                }
+            }
+        }
+
+        let Some(items) = self.scan(source)? else {
+            return Ok(Outcome::Cancelled);
+        };
+        let (top, rest) = items.split_first().expect("scan yields the source");
+
+        if !self.step(from) {
+            return Ok(Outcome::Cancelled);
+        }
+        if !top.is_dir() {
+            self.put(top, &target, remove)?;
+            self.done(top);
+            return Ok(Outcome::Finished);
+        }
+
+        // Directories this job created, to finish once they are filled.
+        let mut created = Vec::new();
+        let existing = if merge {
+            Some(self.existing(&target))
+        } else {
+            self.fs.create_dir(&target)?;
+            created.push((from.to_path_buf(), target.clone()));
+            None
+        };
+        let mut targets = HashMap::from([(
+            PathBuf::new(),
+            Target {
+                path: target,
+                existing,
+            },
+        )]);
+
+        for item in rest {
+            let parent = item.relative.parent().unwrap_or(Path::new(""));
+            let Some(directory) = targets.get(parent) else {
+                // Inside a skipped directory.
+                self.skip(item);
+                continue;
+            };
+            if !self.step(&item.entry.path) {
+                return Ok(Outcome::Cancelled);
+            }
+
+            let destination = directory.path.join(&item.entry.name);
+            let placement = self.place(&item.entry, destination, directory.existing.as_ref())?;
+            if self.cancel.is_cancelled() {
+                return Ok(Outcome::Cancelled);
+            }
+
+            let path = match placement {
+                Placement::Skip => {
+                    self.skip(item);
+                    continue;
+                }
+                Placement::Merge(path) => {
+                    let existing = self.existing(&path);
+                    targets.insert(
+                        item.relative.clone(),
+                        Target {
+                            path,
+                            existing: Some(existing),
+                        },
+                    );
+                    self.done(item);
+                    continue;
+                }
+                Placement::At(path) => path,
+            };
+
+            self.put(item, &path, remove)?;
+            if let Some(Target {
+                existing: Some(existing),
+                ..
+            }) = targets.get_mut(parent)
+            {
+                // Later entries must not take a name kept alongside.
+                existing.insert(
+                    path.file_name().unwrap_or_default().into(),
+                    item.entry.clone(),
+                );
+            }
+            if item.is_dir() {
+                created.push((item.entry.path.clone(), path.clone()));
+                targets.insert(
+                    item.relative.clone(),
+                    Target {
+                        path,
+                        existing: None,
+                    },
+                );
+            }
+            self.done(item);
+        }
+
+        // Deepest first: filling a directory changes its times.
+        for (source, target) in created.iter().rev() {
+            self.fs.copy_metadata(source, target)?;
+        }
+
+        if remove {
+            // Skipped entries keep their directories.
+            for item in items.iter().rev().filter(|item| item.is_dir()) {
+                if self
+                    .fs
+                    .read_directory(&item.entry.path)
+                    .is_ok_and(|entries| entries.is_empty())
+                {
+                    self.fs.delete(&item.entry.path)?;
+                }
+            }
+        }
+
+        Ok(Outcome::Finished)
+    }
+
+    /// Decides where `source` goes, asking `resolve` if `destination` is
+    /// among the `existing` entries of its directory.
+    fn place(
+        &mut self,
+        source: &FileEntry,
+        destination: PathBuf,
+        existing: Option<&Existing>,
+    ) -> Result<Placement, FilesError> {
+        let name = destination.file_name().unwrap_or_default();
+        let Some(entry) = existing.and_then(|existing| existing.get(name)) else {
+            return Ok(Placement::At(destination));
+        };
+
+        let conflict = Conflict {
+            source: source.clone(),
+            existing: entry.clone(),
+        };
+        let policy = (self.resolve)(&conflict);
+        let resolution = conflict.resolve(policy, |name| {
+            existing.is_some_and(|existing| existing.contains_key(name))
+        })?;
+
+        if matches!(resolution, Resolution::Replace | Resolution::Merge)
+            && source.path == destination
+        {
+            return Err(FilesError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot move or copy a path onto itself",
+            )));
+        }
+
+        Ok(match resolution {
+            Resolution::Write(path) => Placement::At(path),
+            // Both are files, which the copy or rename replaces in one go.
+            Resolution::Replace => Placement::At(destination),
+            Resolution::Merge => Placement::Merge(destination),
+            Resolution::Skip => Placement::Skip,
+        })
+    }
+
+    /// Copies or moves a single entry; directories are only created.
+    fn put(&self, item: &Item, to: &Path, remove: bool) -> Result<(), FilesError> {
+        if item.is_dir() {
+            self.fs.create_dir(to)
+        } else if remove {
+            self.fs.move_to(&item.entry.path, to)
+        } else {
+            self.fs.copy(&item.entry.path, to)
+        }
+    }
+
+    /// The entries already in `directory`, or none if it cannot be read.
+    fn existing(&self, directory: &Path) -> Existing {
+        self.fs
+            .read_directory(directory)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| (entry.name.clone(), entry))
+            .collect()
+    }
+
+    /// Deletes files first, then directories from the deepest up.
+    fn delete(&mut self, path: &Path) -> Result<Outcome, FilesError> {
+        let source = self.source_entry(path);
+        let Some(items) = self.scan(source)? else {
+            return Ok(Outcome::Cancelled);
+        };
+
+        let (files, directories): (Vec<_>, Vec<_>) = items.iter().partition(|item| !item.is_dir());
+        for item in files.into_iter().chain(directories.into_iter().rev()) {
+            if !self.step(&item.entry.path) {
+                return Ok(Outcome::Cancelled);
+            }
+            self.fs.delete(&item.entry.path)?;
+            self.done(item);
+        }
+
+        Ok(Outcome::Finished)
+    }
+
+    /// Lists `top` and everything under it, parents before their contents,
+    /// counting the totals as it goes. Links are not followed. Returns
+    /// `None` if the job was cancelled meanwhile.
+    fn scan(&mut self, top: FileEntry) -> Result<Option<Vec<Item>>, FilesError> {
+        self.progress.scanning = true;
+
+        let mut items = vec![Item {
+            entry: top,
+            relative: PathBuf::new(),
+        }];
+        self.count(&items[0]);
+
+        // Indices of directories still to be listed.
+        let mut pending = if items[0].is_dir() {
+            vec![0]
+        } else {
+            Vec::new()
+        };
+
+        while let Some(index) = pending.pop() {
+            if self.cancel.is_cancelled() {
+                return Ok(None);
+            }
+
+            let directory = items[index].entry.path.clone();
+            let relative = items[index].relative.clone();
+            self.progress.current = Some(directory.clone());
+            self.report();
+
+            for entry in self.fs.read_directory(&directory)? {
+                let item = Item {
+                    relative: relative.join(&entry.name),
+                    entry,
+                };
+                self.count(&item);
+                if item.is_dir() {
+                    pending.push(items.len());
+                }
+                items.push(item);
+            }
+        }
+
+        self.progress.scanning = false;
+        Ok(Some(items))
+    }
+
+    fn count(&mut self, item: &Item) {
+        if !item.is_dir() {
+            self.progress.files_total += 1;
+            self.progress.bytes_total += item.entry.metadata.size;
+        }
+    }
+
+    /// The entry at `path`, looked up in its parent so that a link is not
+    /// mistaken for its target.
+    fn source_entry(&self, path: &Path) -> FileEntry {
+        entry_at(self.fs, path).unwrap_or_else(|| {
+            // Some listings, like the trash's, are not the parent's.
+            let kind = if self.fs.read_directory(path).is_ok() {
+                EntryKind::Directory
+            } else {
+                EntryKind::File
+            };
+            FileEntry::new(path.file_name().unwrap_or_default(), path, kind)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::UNIX_EPOCH;
+
+    use crate::memory::MemoryFileSystem;
+    use crate::testing::{Call, Fault, Faulty};
+
+    /// `/src` holding four files of 10 bytes, two of them in `/src/sub`.
+    fn memory() -> MemoryFileSystem {
+        let fs = MemoryFileSystem::new();
+        for path in ["/src/a", "/src/b", "/src/sub/c", "/src/sub/d"] {
+            fs.write_file(Path::new(path), "0123456789").unwrap();
+        }
+        fs
+    }
+
+    fn fail(_: &Conflict) -> Policy {
+        Policy::Fail
+    }
+
+    fn copy() -> Task {
+        Task::Copy {
+            from: PathBuf::from("/src"),
+            to: PathBuf::from("/dst"),
+        }
+    }
+
+    #[test]
+    fn copies_a_tree_reporting_totals() {
+        let fs = memory();
+        let mut updates = Vec::new();
+
+        let outcome = run(&fs, &copy(), &CancelToken::new(), fail, |p| {
+            updates.push(p.clone())
+        });
+
+        assert_eq!(outcome.unwrap(), Outcome::Finished);
+        assert_eq!(
+            fs.read_file(Path::new("/dst/sub/d")).unwrap(),
+            b"0123456789"
+        );
+        assert!(updates.iter().any(|p| p.scanning));
+
+        let last = updates.last().unwrap();
+        assert_eq!((last.files_done, last.files_total), (4, 4));
+        assert_eq!((last.bytes_done, last.bytes_total), (40, 40));
+        assert_eq!(last.fraction(), 1.0);
+        assert_eq!(last.current, None);
+    }
+
+    #[test]
+    fn copied_directories_keep_their_metadata() {
+        let fs = memory();
+        let old = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs.set_mode(Path::new("/src/sub"), 0o700).unwrap();
+        fs.set_modified(Path::new("/src/sub"), old).unwrap();
+
+        run(&fs, &copy(), &CancelToken::new(), fail, |_| {}).unwrap();
+
+        let sub = fs.entry(Path::new("/dst/sub")).unwrap();
+        assert_eq!(sub.metadata.mode, 0o700);
+        assert_eq!(sub.metadata.modified, Some(old));
+    }
+
+    #[test]
+    fn cancelled_copy_stops_between_files() {
+        let fs = memory();
+        let cancel = CancelToken::new();
+        let mut last = Progress::default();
+
+        let outcome = run(&fs, &copy(), &cancel, fail, |p| {
+            if p.files_done == 2 {
+                cancel.cancel();
+            }
+            last = p.clone();
+        });
+
+        assert_eq!(outcome.unwrap(), Outcome::Cancelled);
+        assert_eq!((last.files_done, last.files_total), (2, 4));
+        let copied = ["/dst/a", "/dst/b", "/dst/sub/c", "/dst/sub/d"]
+            .iter()
+            .filter(|path| fs.exists(Path::new(path)))
+            .count();
+        assert_eq!(copied, 2);
+        assert_eq!(fs.read_directory(Path::new("/src/sub")).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn cancelled_move_across_devices_keeps_every_file_once() {
+        let fs = Faulty::new(memory())
+            .with_fault(Fault::new(io::ErrorKind::CrossesDevices).on(Call::Rename));
+        let task = Task::Move {
+            from: PathBuf::from("/src"),
+            to: PathBuf::from("/dst"),
+        };
+        let cancel = CancelToken::new();
+
+        let outcome = run(&fs, &task, &cancel, fail, |p| {
+            if p.files_done == 3 {
+                cancel.cancel();
+            }
+        });
+
+        assert_eq!(outcome.unwrap(), Outcome::Cancelled);
+        for name in ["a", "b", "sub/c", "sub/d"] {
+            let at_source = fs.inner().exists(&Path::new("/src").join(name));
+            let at_destination = fs.inner().exists(&Path::new("/dst").join(name));
+            assert!(
+                at_source != at_destination,
+                "{} is in both or neither",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn move_across_devices_removes_the_source() {
+        let fs = Faulty::new(memory())
+            .with_fault(Fault::new(io::ErrorKind::CrossesDevices).on(Call::Rename));
+        let task = Task::Move {
+            from: PathBuf::from("/src"),
+            to: PathBuf::from("/dst"),
+        };
+
+        let outcome = run(&fs, &task, &CancelToken::new(), fail, |_| {});
+        assert_eq!(outcome.unwrap(), Outcome::Finished);
+        assert!(!fs.inner().exists(Path::new("/src")));
+        assert_eq!(
+            fs.inner()
+                .read_directory(Path::new("/dst/sub"))
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn copy_onto_an_existing_directory_fails_by_default() {
+        let fs = memory();
+        fs.write_file(Path::new("/dst/x"), "").unwrap();
+
+        let err = run(&fs, &copy(), &CancelToken::new(), fail, |_| {}).unwrap_err();
+
+        assert!(matches!(err, FilesError::AlreadyExists(ref path) if path == Path::new("/dst")));
+        assert!(!fs.exists(Path::new("/dst/a")));
+    }
+
+    #[test]
+    fn merges_directories_keeping_both_files() {
+        let fs = memory();
+        fs.write_file(Path::new("/dst/sub/c"), "old").unwrap();
+        let policy = |conflict: &Conflict| match conflict.existing.kind {
+            EntryKind::Directory => Policy::Overwrite,
+            _ => Policy::KeepBoth,
+        };
+
+        let outcome = run(&fs, &copy(), &CancelToken::new(), policy, |_| {});
+
+        assert_eq!(outcome.unwrap(), Outcome::Finished);
+        assert_eq!(fs.read_file(Path::new("/dst/sub/c")).unwrap(), b"old");
+        assert_eq!(
+            fs.read_file(Path::new("/dst/sub/c (1)")).unwrap(),
+            b"0123456789"
+        );
+        assert!(fs.exists(Path::new("/dst/a")));
+    }
+
+    #[test]
+    fn overwrite_leaves_a_directory_in_the_way_of_a_file() {
+        let fs = memory();
+        fs.write_file(Path::new("/dst/a/keep"), "kept").unwrap();
+        fs.write_file(Path::new("/dst/sub"), "a file").unwrap();
+        let overwrite = |_: &Conflict| Policy::Overwrite;
+        let kind = |err: FilesError| match err {
+            FilesError::Io(err) => err.kind(),
+            other => panic!("expected an I/O error, got {:?}", other),
+        };
+
+        let file = Task::Copy {
+            from: PathBuf::from("/src/a"),
+            to: PathBuf::from("/dst/a"),
+        };
+        let err = run(&fs, &file, &CancelToken::new(), overwrite, |_| {}).unwrap_err();
+        assert_eq!(kind(err), io::ErrorKind::IsADirectory);
+        assert_eq!(fs.read_file(Path::new("/dst/a/keep")).unwrap(), b"kept");
+
+        fs.delete(Path::new("/dst/a")).unwrap();
+        let err = run(&fs, &copy(), &CancelToken::new(), overwrite, |_| {}).unwrap_err();
+        assert_eq!(kind(err), io::ErrorKind::NotADirectory);
+        assert_eq!(fs.read_file(Path::new("/dst/sub")).unwrap(), b"a file");
+    }
+
+    #[test]
+    fn skipped_entries_stay_at_the_source_of_a_move() {
+        let fs = memory();
+        fs.write_file(Path::new("/dst/sub/c"), "old").unwrap();
+        let task = Task::Move {
+            from: PathBuf::from("/src"),
+            to: PathBuf::from("/dst"),
+        };
+        let policy = |conflict: &Conflict| match conflict.existing.kind {
+            EntryKind::Directory => Policy::Overwrite,
+            _ => Policy::Skip,
+        };
+        let mut last = Progress::default();
+
+        let outcome = run(&fs, &task, &CancelToken::new(), policy, |p| {
+            last = p.clone()
+        });
+
+        assert_eq!(outcome.unwrap(), Outcome::Finished);
+        assert_eq!((last.files_done, last.files_skipped), (4, 1));
+        assert_eq!(fs.read_file(Path::new("/dst/sub/c")).unwrap(), b"old");
+        assert!(fs.exists(Path::new("/src/sub/c")));
+        assert!(!fs.exists(Path::new("/src/a")));
+        assert!(fs.exists(Path::new("/dst/sub/d")));
+    }
+
+    #[test]
+    fn job_asks_about_conflicts_until_told_to_apply_to_all() {
+        let fs = Arc::new(memory());
+        fs.write_file(Path::new("/dst/a"), "old").unwrap();
+        fs.write_file(Path::new("/dst/b"), "old").unwrap();
+        let job = Job::spawn(Arc::clone(&fs), copy(), None);
+
+        let question = || {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            loop {
+                if let Some(conflict) = job.conflict() {
+                    return conflict;
+                }
+                assert!(Instant::now() < deadline, "the job never asked");
+                thread::sleep(Duration::from_millis(5));
+            }
+        };
+
+        assert_eq!(question().existing.path, Path::new("/dst"));
+        job.resolve(Policy::Overwrite, false);
+        assert_eq!(question().existing.kind, EntryKind::File);
+        job.resolve(Policy::Skip, true);
+
+        let outcome = job.wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(outcome.unwrap(), Outcome::Finished);
+        assert_eq!(job.progress().files_skipped, 2);
+        assert_eq!(fs.read_file(Path::new("/dst/a")).unwrap(), b"old");
+        assert!(fs.exists(Path::new("/dst/sub/d")));
+    }
+
+    #[test]
+    fn deletes_a_tree_in_the_background() {
+        let fs = Arc::new(memory());
+        let job = Job::spawn(Arc::clone(&fs), Task::Delete(PathBuf::from("/src")), None);
+
+        let outcome = job.wait(Duration::from_secs(5)).unwrap();
+
+        assert_eq!(outcome.unwrap(), Outcome::Finished);
+        assert!(!fs.exists(Path::new("/src")));
+        assert_eq!(job.progress().files_done, 4);
+    }
+}
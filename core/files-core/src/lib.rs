@@ -1,5 +1,23 @@
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod conflict;
 pub mod errors;
 pub mod filesystem;
 pub mod fs;
+pub mod jobs;
+#[cfg(any(test, feature = "memory"))]
+pub mod memory;
 pub mod models;
+pub mod permissions;
+#[cfg(feature = "s3")]
+pub mod s3;
+#[cfg(feature = "sftp")]
+pub mod sftp;
 pub mod state;
+pub mod tags;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(unix)]
+pub mod trash;
+#[cfg(any(test, feature = "watch"))]
+pub mod watch;
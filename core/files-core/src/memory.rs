@@ -0,0 +1,1075 @@
+//! An in-memory [`FileSystem`] that models a real directory tree.
+//!
+//! `MemoryFileSystem` keeps files (with contents), directories, symbolic
+//! and hard links, with their extended attributes, in a tree behind a
+//! mutex, and mirrors the behavior and error kinds of
+//! [`RealFileSystem`](crate::filesystem::RealFileSystem) on Linux. It is
+//! meant for state-machine tests, demos and sandboxed sessions.
+//!
+//! All paths must be absolute. `..` and symbolic links are resolved the
+//! way the kernel resolves them.
+
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use crate::{
+    errors::FilesError,
+    filesystem::FileSystem,
+    models::{EntryKind, EntryMetadata, FileEntry, LinkInfo},
+    tags,
+};
+
+const MAX_LINK_HOPS: usize = 40;
+
+const DEFAULT_UID: u32 = 1000;
+const DEFAULT_GID: u32 = 1000;
+
+#[derive(Debug, Clone)]
+struct Attributes {
+    modified: SystemTime,
+    accessed: SystemTime,
+    changed: SystemTime,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    xattrs: BTreeMap<OsString, Vec<u8>>,
+}
+
+impl Attributes {
+    fn new(mode: u32) -> Self {
+        let now = SystemTime::now();
+
+        Self {
+            modified: now,
+            accessed: now,
+            changed: now,
+            mode,
+            uid: DEFAULT_UID,
+            gid: DEFAULT_GID,
+            xattrs: BTreeMap::new(),
+        }
+    }
+
+    fn touch(&mut self) {
+        let now = SystemTime::now();
+        self.modified = now;
+        self.changed = now;
+    }
+}
+
+/// State that every hard link to a node sees: cloning the handle makes
+/// another link, not a copy.
+#[derive(Debug, Default)]
+struct Shared<T>(Arc<Mutex<T>>);
+
+impl<T> Shared<T> {
+    fn new(value: T) -> Self {
+        Self(Arc::new(Mutex::new(value)))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, T> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+#[derive(Debug)]
+enum NodeKind {
+    File(Shared<Vec<u8>>),
+    Dir(BTreeMap<OsString, Node>),
+    Symlink(PathBuf),
+}
+
+#[derive(Debug)]
+struct Node {
+    kind: NodeKind,
+    attributes: Shared<Attributes>,
+}
+
+impl Node {
+    fn file(contents: Vec<u8>) -> Self {
+        Self {
+            kind: NodeKind::File(Shared::new(contents)),
+            attributes: Shared::new(Attributes::new(0o644)),
+        }
+    }
+
+    fn dir() -> Self {
+        Self {
+            kind: NodeKind::Dir(BTreeMap::new()),
+            attributes: Shared::new(Attributes::new(0o755)),
+        }
+    }
+
+    fn symlink(target: PathBuf) -> Self {
+        Self {
+            kind: NodeKind::Symlink(target),
+            attributes: Shared::new(Attributes::new(0o777)),
+        }
+    }
+
+    /// Another name for the same file or link; directories cannot have
+    /// one.
+    fn hard_link(&self) -> Option<Node> {
+        let kind = match &self.kind {
+            NodeKind::File(contents) => NodeKind::File(contents.clone()),
+            NodeKind::Dir(_) => return None,
+            NodeKind::Symlink(target) => NodeKind::Symlink(target.clone()),
+        };
+
+        Some(Self {
+            kind,
+            attributes: self.attributes.clone(),
+        })
+    }
+
+    /// A copy of the subtree that shares nothing with it, not even with
+    /// its hard links.
+    fn detached(&self) -> Node {
+        let kind = match &self.kind {
+            NodeKind::File(contents) => NodeKind::File(Shared::new(contents.lock().clone())),
+            NodeKind::Dir(children) => NodeKind::Dir(
+                children
+                    .iter()
+                    .map(|(name, child)| (name.clone(), child.detached()))
+                    .collect(),
+            ),
+            NodeKind::Symlink(target) => NodeKind::Symlink(target.clone()),
+        };
+
+        Self {
+            kind,
+            attributes: Shared::new(self.attributes.lock().clone()),
+        }
+    }
+
+    fn entry_kind(&self) -> EntryKind {
+        match self.kind {
+            NodeKind::File(_) => EntryKind::File,
+            NodeKind::Dir(_) => EntryKind::Directory,
+            NodeKind::Symlink(_) => EntryKind::Symlink,
+        }
+    }
+
+    fn size(&self) -> u64 {
+        match &self.kind {
+            NodeKind::File(contents) => contents.lock().len() as u64,
+            NodeKind::Dir(_) => 4096,
+            NodeKind::Symlink(target) => target.as_os_str().len() as u64,
+        }
+    }
+
+    fn metadata(&self) -> EntryMetadata {
+        let attributes = self.attributes.lock();
+
+        EntryMetadata {
+            size: self.size(),
+            modified: Some(attributes.modified),
+            accessed: Some(attributes.accessed),
+            changed: Some(attributes.changed),
+            mode: attributes.mode,
+            uid: attributes.uid,
+            gid: attributes.gid,
+        }
+    }
+
+    fn children(&self) -> Option<&BTreeMap<OsString, Node>> {
+        match &self.kind {
+            NodeKind::Dir(children) => Some(children),
+            _ => None,
+        }
+    }
+
+    fn children_mut(&mut self) -> Option<&mut BTreeMap<OsString, Node>> {
+        match &mut self.kind {
+            NodeKind::Dir(children) => Some(children),
+            _ => None,
+        }
+    }
+
+    /// A copy of the subtree with the same contents and metadata, only
+    /// changed now.
+    fn copied(&self) -> Node {
+        let mut copy = self.detached();
+        let now = SystemTime::now();
+        copy.for_each_mut(&mut |node| node.attributes.lock().changed = now);
+        copy
+    }
+
+    fn for_each_mut(&mut self, visit: &mut impl FnMut(&mut Node)) {
+        visit(self);
+        if let Some(children) = self.children_mut() {
+            for child in children.values_mut() {
+                child.for_each_mut(visit);
+            }
+        }
+    }
+}
+
+/// A path resolved to its physical location in the tree.
+///
+/// `names` are the components below the root. The last component may not
+/// exist yet, but every component before it is an existing directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Resolved {
+    names: Vec<OsString>,
+}
+
+impl Resolved {
+    fn to_path(&self) -> PathBuf {
+        let mut path = PathBuf::from("/");
+        path.extend(&self.names);
+        path
+    }
+
+    fn split_last(&self) -> Option<(&OsString, &[OsString])> {
+        self.names.split_last()
+    }
+}
+
+fn io_error(kind: io::ErrorKind) -> FilesError {
+    FilesError::Io(io::Error::from(kind))
+}
+
+fn io_error_msg(kind: io::ErrorKind, message: &str) -> FilesError {
+    FilesError::Io(io::Error::new(kind, message.to_string()))
+}
+
+#[derive(Debug)]
+struct Tree {
+    root: Node,
+}
+
+impl Tree {
+    fn node(&self, names: &[OsString]) -> Option<&Node> {
+        let mut node = &self.root;
+        for name in names {
+            node = node.children()?.get(name)?;
+        }
+        Some(node)
+    }
+
+    fn node_mut(&mut self, names: &[OsString]) -> Option<&mut Node> {
+        let mut node = &mut self.root;
+        for name in names {
+            node = node.children_mut()?.get_mut(name)?;
+        }
+        Some(node)
+    }
+
+    /// Resolves `path` physically, following symbolic links in every
+    /// component except the last unless `follow_last` is set.
+    fn resolve(&self, path: &Path, follow_last: bool) -> Result<Resolved, FilesError> {
+        if !path.is_absolute() {
+            return Err(FilesError::InvalidPath);
+        }
+
+        // Components still to walk, in reverse so the next one is popped.
+        let mut pending: Vec<Part> = parts(path).rev().collect();
+        let mut names: Vec<OsString> = Vec::new();
+        let mut hops = 0;
+
+        while let Some(part) = pending.pop() {
+            let name = match part {
+                Part::Root => {
+                    names.clear();
+                    continue;
+                }
+                Part::Parent => {
+                    names.pop();
+                    continue;
+                }
+                Part::Name(name) => name,
+            };
+
+            let is_last = pending.is_empty();
+
+            let parent = self
+                .node(&names)
+                .ok_or_else(|| io_error(io::ErrorKind::NotFound))?;
+            let children = parent
+                .children()
+                .ok_or_else(|| io_error(io::ErrorKind::NotADirectory))?;
+
+            match children.get(&name).map(|node| &node.kind) {
+                None if is_last => names.push(name),
+                None => return Err(io_error(io::ErrorKind::NotFound)),
+                Some(NodeKind::Symlink(target)) if !is_last || follow_last => {
+                    hops += 1;
+                    if hops > MAX_LINK_HOPS {
+                        return Err(io_error_msg(
+                            io::ErrorKind::Other,
+                            "too many levels of symbolic links",
+                        ));
+                    }
+
+                    pending.extend(parts(target).rev());
+                }
+                Some(_) => names.push(name),
+            }
+        }
+
+        Ok(Resolved { names })
+    }
+
+    /// Returns the children of the existing parent directory of `resolved`.
+    fn parent_children_mut(
+        &mut self,
+        resolved: &Resolved,
+    ) -> Result<&mut BTreeMap<OsString, Node>, FilesError> {
+        let (_, parent) = resolved.split_last().ok_or(FilesError::InvalidPath)?;
+
+        self.node_mut(parent)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?
+            .children_mut()
+            .ok_or_else(|| io_error(io::ErrorKind::NotADirectory))
+    }
+
+    fn touch_parent(&mut self, resolved: &Resolved) {
+        if let Some((_, parent)) = resolved.split_last()
+            && let Some(node) = self.node_mut(parent)
+        {
+            node.attributes.lock().touch();
+        }
+    }
+
+    fn entry(&self, path: PathBuf, name: &OsStr, node: &Node) -> FileEntry {
+        let tags = node
+            .attributes
+            .lock()
+            .xattrs
+            .get(OsStr::new(tags::TAGS))
+            .map(|value| tags::parse(value))
+            .unwrap_or_default();
+        let mut entry = FileEntry::new(name, path.clone(), node.entry_kind())
+            .with_metadata(node.metadata())
+            .with_tags(tags);
+
+        if let NodeKind::Symlink(target) = &node.kind {
+            let resolved = self
+                .resolve(&path, true)
+                .ok()
+                .and_then(|r| self.node(&r.names).map(|node| (r.to_path(), node)));
+
+            entry = entry.with_link(LinkInfo {
+                target: target.clone(),
+                target_kind: resolved.as_ref().map(|(_, node)| node.entry_kind()),
+                resolved: resolved.map(|(path, _)| path),
+            });
+        }
+
+        entry
+    }
+}
+
+/// An owned path component, so link targets can be spliced into a walk.
+enum Part {
+    Root,
+    Parent,
+    Name(OsString),
+}
+
+fn parts(path: &Path) -> impl DoubleEndedIterator<Item = Part> + '_ {
+    path.components().filter_map(|component| match component {
+        Component::RootDir | Component::Prefix(_) => Some(Part::Root),
+        Component::CurDir => None,
+        Component::ParentDir => Some(Part::Parent),
+        Component::Normal(name) => Some(Part::Name(name.to_os_string())),
+    })
+}
+
+/// An in-memory directory tree implementing [`FileSystem`].
+#[derive(Debug)]
+pub struct MemoryFileSystem {
+    tree: Mutex<Tree>,
+}
+
+impl Default for MemoryFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for MemoryFileSystem {
+    fn clone(&self) -> Self {
+        Self {
+            tree: Mutex::new(Tree {
+                root: self.tree().root.detached(),
+            }),
+        }
+    }
+}
+
+impl MemoryFileSystem {
+    /// Creates a filesystem containing only the root directory `/`.
+    pub fn new() -> Self {
+        Self {
+            tree: Mutex::new(Tree { root: Node::dir() }),
+        }
+    }
+
+    fn tree(&self) -> MutexGuard<'_, Tree> {
+        self.tree
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Creates a directory and all of its missing parents.
+    pub fn create_dir_all(&self, path: &Path) -> Result<(), FilesError> {
+        let mut tree = self.tree();
+        let mut current = PathBuf::new();
+
+        for component in path.components() {
+            current.push(component);
+
+            let resolved = tree.resolve(&current, true)?;
+            match tree.node(&resolved.names) {
+                Some(node) if node.children().is_some() => continue,
+                Some(_) => return Err(io_error(io::ErrorKind::AlreadyExists)),
+                None => {
+                    let (name, _) = resolved.split_last().ok_or(FilesError::InvalidPath)?;
+                    let name = name.clone();
+                    tree.parent_children_mut(&resolved)?
+                        .insert(name, Node::dir());
+                    tree.touch_parent(&resolved);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes a file, replacing its contents if it exists and creating
+    /// missing parent directories.
+    pub fn write_file(&self, path: &Path, contents: impl Into<Vec<u8>>) -> Result<(), FilesError> {
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent)?;
+        }
+
+        let mut tree = self.tree();
+        let resolved = tree.resolve(path, true)?;
+        let (name, _) = resolved.split_last().ok_or(FilesError::InvalidPath)?;
+        let name = name.clone();
+        let contents = contents.into();
+
+        match tree.node_mut(&resolved.names) {
+            Some(Node {
+                kind: NodeKind::File(existing),
+                attributes,
+            }) => {
+                *existing.lock() = contents;
+                attributes.lock().touch();
+            }
+            Some(_) => return Err(io_error(io::ErrorKind::IsADirectory)),
+            None => {
+                tree.parent_children_mut(&resolved)?
+                    .insert(name, Node::file(contents));
+                tree.touch_parent(&resolved);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the contents of a file, following symbolic links.
+    pub fn read_file(&self, path: &Path) -> Result<Vec<u8>, FilesError> {
+        let tree = self.tree();
+        let resolved = tree.resolve(path, true)?;
+
+        match tree.node(&resolved.names).map(|node| &node.kind) {
+            Some(NodeKind::File(contents)) => Ok(contents.lock().clone()),
+            Some(_) => Err(io_error(io::ErrorKind::IsADirectory)),
+            None => Err(io_error(io::ErrorKind::NotFound)),
+        }
+    }
+
+    /// Creates a symbolic link at `link` pointing to `target`.
+    ///
+    /// The target is stored verbatim and does not need to exist.
+    pub fn symlink(&self, target: impl Into<PathBuf>, link: &Path) -> Result<(), FilesError> {
+        let mut tree = self.tree();
+        let resolved = tree.resolve(link, false)?;
+
+        if tree.node(&resolved.names).is_some() {
+            return Err(io_error(io::ErrorKind::AlreadyExists));
+        }
+
+        let (name, _) = resolved.split_last().ok_or(FilesError::InvalidPath)?;
+        let name = name.clone();
+        tree.parent_children_mut(&resolved)?
+            .insert(name, Node::symlink(target.into()));
+        tree.touch_parent(&resolved);
+
+        Ok(())
+    }
+
+    /// Returns `true` if `path` exists, without following a final link.
+    pub fn exists(&self, path: &Path) -> bool {
+        let tree = self.tree();
+
+        tree.resolve(path, false)
+            .ok()
+            .is_some_and(|resolved| tree.node(&resolved.names).is_some())
+    }
+
+    /// Returns the entry for `path`, without following a final link.
+    pub fn entry(&self, path: &Path) -> Result<FileEntry, FilesError> {
+        let tree = self.tree();
+        let resolved = tree.resolve(path, false)?;
+        let node = tree
+            .node(&resolved.names)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?;
+
+        let name = path.file_name().unwrap_or_default();
+        Ok(tree.entry(path.to_path_buf(), name, node))
+    }
+
+    /// Sets the permission bits of an entry, following symbolic links.
+    pub fn set_mode(&self, path: &Path, mode: u32) -> Result<(), FilesError> {
+        self.with_attributes(path, |attributes| attributes.mode = mode & 0o7777)
+    }
+
+    /// Sets the modification time of an entry, following symbolic links.
+    pub fn set_modified(&self, path: &Path, modified: SystemTime) -> Result<(), FilesError> {
+        self.with_attributes(path, |attributes| attributes.modified = modified)
+    }
+
+    fn with_attributes(
+        &self,
+        path: &Path,
+        apply: impl FnOnce(&mut Attributes),
+    ) -> Result<(), FilesError> {
+        let tree = self.tree();
+        let resolved = tree.resolve(path, true)?;
+        let node = tree
+            .node(&resolved.names)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?;
+
+        let mut attributes = node.attributes.lock();
+        apply(&mut attributes);
+        attributes.changed = SystemTime::now();
+        Ok(())
+    }
+
+    /// Runs `apply` on the node at `path`, without following a final link.
+    fn with_node<T>(
+        &self,
+        path: &Path,
+        apply: impl FnOnce(&Node) -> Result<T, FilesError>,
+    ) -> Result<T, FilesError> {
+        let tree = self.tree();
+        let resolved = tree.resolve(path, false)?;
+        let node = tree
+            .node(&resolved.names)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?;
+
+        apply(node)
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn read_directory(&self, path: &Path) -> Result<Vec<FileEntry>, FilesError> {
+        let tree = self.tree();
+
+        let children = tree
+            .resolve(path, true)
+            .ok()
+            .and_then(|resolved| tree.node(&resolved.names))
+            .and_then(Node::children)
+            .ok_or(FilesError::InvalidPath)?;
+
+        Ok(children
+            .iter()
+            .map(|(name, node)| tree.entry(path.join(name), name, node))
+            .collect())
+    }
+
+    fn read_entry(&self, path: &Path) -> Result<FileEntry, FilesError> {
+        self.entry(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let mut tree = self.tree();
+        let source = tree.resolve(from, false)?;
+        let destination = tree.resolve(to, false)?;
+
+        let source_is_dir = match tree.node(&source.names) {
+            Some(node) => node.children().is_some(),
+            None => return Err(io_error(io::ErrorKind::NotFound)),
+        };
+
+        if source == destination {
+            return Ok(());
+        }
+
+        if source.names.is_empty() || destination.names.is_empty() {
+            return Err(io_error(io::ErrorKind::ResourceBusy));
+        }
+
+        if destination.names.starts_with(&source.names) {
+            return Err(io_error(io::ErrorKind::InvalidInput));
+        }
+
+        if let Some(existing) = tree.node(&destination.names) {
+            match (source_is_dir, existing.children()) {
+                (false, Some(_)) => return Err(io_error(io::ErrorKind::IsADirectory)),
+                (true, None) => return Err(io_error(io::ErrorKind::NotADirectory)),
+                (true, Some(children)) if !children.is_empty() => {
+                    return Err(io_error(io::ErrorKind::DirectoryNotEmpty));
+                }
+                _ => {}
+            }
+        }
+
+        // Validate the destination parent before detaching the source.
+        tree.parent_children_mut(&destination)?;
+
+        let (source_name, _) = source.split_last().ok_or(FilesError::InvalidPath)?;
+        let node = tree
+            .parent_children_mut(&source)?
+            .remove(source_name)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?;
+        node.attributes.lock().changed = SystemTime::now();
+        tree.touch_parent(&source);
+
+        let (destination_name, _) = destination.split_last().ok_or(FilesError::InvalidPath)?;
+        let destination_name = destination_name.clone();
+        tree.parent_children_mut(&destination)?
+            .insert(destination_name, node);
+        tree.touch_parent(&destination);
+
+        Ok(())
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let mut tree = self.tree();
+        let source = tree.resolve(from, false)?;
+        let destination = tree.resolve(to, false)?;
+
+        let node = tree
+            .node(&source.names)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?
+            .copied();
+        let source_is_dir = node.children().is_some();
+
+        if destination.names.starts_with(&source.names) {
+            return Err(io_error_msg(
+                io::ErrorKind::InvalidInput,
+                "cannot copy a path onto or into itself",
+            ));
+        }
+
+        if let Some(existing) = tree.node(&destination.names) {
+            match (source_is_dir, existing.children()) {
+                (false, Some(_)) => return Err(io_error(io::ErrorKind::IsADirectory)),
+                (true, None) => return Err(io_error(io::ErrorKind::NotADirectory)),
+                (true, Some(children)) if !children.is_empty() => {
+                    return Err(io_error(io::ErrorKind::DirectoryNotEmpty));
+                }
+                _ => {}
+            }
+        }
+
+        let (destination_name, _) = destination.split_last().ok_or(FilesError::InvalidPath)?;
+        let destination_name = destination_name.clone();
+        tree.parent_children_mut(&destination)?
+            .insert(destination_name, node);
+        tree.touch_parent(&destination);
+
+        Ok(())
+    }
+
+    fn copy_metadata(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let mut tree = self.tree();
+        let source = tree.resolve(from, false)?;
+        let destination = tree.resolve(to, false)?;
+
+        let attributes = tree
+            .node(&source.names)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?
+            .attributes
+            .lock()
+            .clone();
+        let node = tree
+            .node_mut(&destination.names)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?;
+        *node.attributes.lock() = Attributes {
+            changed: SystemTime::now(),
+            ..attributes
+        };
+
+        Ok(())
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> Result<(), FilesError> {
+        self.set_mode(path, mode)
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<(), FilesError> {
+        self.with_attributes(path, |attributes| {
+            attributes.uid = uid.unwrap_or(attributes.uid);
+            attributes.gid = gid.unwrap_or(attributes.gid);
+        })
+    }
+
+    fn set_times(
+        &self,
+        path: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<(), FilesError> {
+        self.with_attributes(path, |attributes| {
+            attributes.accessed = accessed.unwrap_or(attributes.accessed);
+            attributes.modified = modified.unwrap_or(attributes.modified);
+        })
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), FilesError> {
+        let mut tree = self.tree();
+        let resolved = tree.resolve(path, false)?;
+
+        let (name, _) = resolved
+            .split_last()
+            .ok_or_else(|| io_error(io::ErrorKind::ResourceBusy))?;
+
+        tree.parent_children_mut(&resolved)?
+            .remove(name)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?;
+        tree.touch_parent(&resolved);
+
+        Ok(())
+    }
+
+    fn create_file(&self, path: &Path) -> Result<(), FilesError> {
+        let mut tree = self.tree();
+        let resolved = tree.resolve(path, true)?;
+
+        match tree.node_mut(&resolved.names) {
+            Some(Node {
+                kind: NodeKind::Dir(_),
+                ..
+            }) => Err(io_error(io::ErrorKind::IsADirectory)),
+            Some(_) => Err(io_error(io::ErrorKind::AlreadyExists)),
+            None => {
+                let (name, _) = resolved.split_last().ok_or(FilesError::InvalidPath)?;
+                let name = name.clone();
+                tree.parent_children_mut(&resolved)?
+                    .insert(name, Node::file(Vec::new()));
+                tree.touch_parent(&resolved);
+                Ok(())
+            }
+        }
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), FilesError> {
+        let mut tree = self.tree();
+        let resolved = tree.resolve(path, false)?;
+
+        if tree.node(&resolved.names).is_some() {
+            return Err(io_error(io::ErrorKind::AlreadyExists));
+        }
+
+        let (name, _) = resolved.split_last().ok_or(FilesError::InvalidPath)?;
+        let name = name.clone();
+        tree.parent_children_mut(&resolved)?
+            .insert(name, Node::dir());
+        tree.touch_parent(&resolved);
+
+        Ok(())
+    }
+
+    fn create_symlink(&self, target: &Path, link: &Path) -> Result<(), FilesError> {
+        self.symlink(target, link)
+    }
+
+    fn list_xattrs(&self, path: &Path) -> Result<Vec<OsString>, FilesError> {
+        self.with_node(path, |node| {
+            Ok(node.attributes.lock().xattrs.keys().cloned().collect())
+        })
+    }
+
+    fn get_xattr(&self, path: &Path, name: &OsStr) -> Result<Option<Vec<u8>>, FilesError> {
+        self.with_node(path, |node| {
+            Ok(node.attributes.lock().xattrs.get(name).cloned())
+        })
+    }
+
+    fn set_xattr(&self, path: &Path, name: &OsStr, value: &[u8]) -> Result<(), FilesError> {
+        self.with_node(path, |node| {
+            // Linux keeps no user attributes on links.
+            if let NodeKind::Symlink(_) = node.kind {
+                return Err(io_error(io::ErrorKind::PermissionDenied));
+            }
+
+            let mut attributes = node.attributes.lock();
+            attributes
+                .xattrs
+                .insert(name.to_os_string(), value.to_vec());
+            attributes.changed = SystemTime::now();
+            Ok(())
+        })
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &OsStr) -> Result<(), FilesError> {
+        self.with_node(path, |node| {
+            let mut attributes = node.attributes.lock();
+            if attributes.xattrs.remove(name).is_some() {
+                attributes.changed = SystemTime::now();
+            }
+            Ok(())
+        })
+    }
+
+    fn create_hardlink(&self, original: &Path, link: &Path) -> Result<(), FilesError> {
+        let mut tree = self.tree();
+        let source = tree.resolve(original, false)?;
+        let destination = tree.resolve(link, false)?;
+
+        let node = tree
+            .node(&source.names)
+            .ok_or_else(|| io_error(io::ErrorKind::NotFound))?
+            .hard_link()
+            .ok_or_else(|| io_error(io::ErrorKind::PermissionDenied))?;
+        if tree.node(&destination.names).is_some() {
+            return Err(io_error(io::ErrorKind::AlreadyExists));
+        }
+
+        let (name, _) = destination.split_last().ok_or(FilesError::InvalidPath)?;
+        let name = name.clone();
+        tree.parent_children_mut(&destination)?.insert(name, node);
+        tree.touch_parent(&destination);
+
+        // Every name sees the new link count change.
+        if let Some(node) = tree.node(&destination.names) {
+            node.attributes.lock().changed = SystemTime::now();
+        }
+
+        Ok(())
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        let tree = self.tree();
+        let resolved = tree.resolve(path, true)?;
+
+        match tree.node(&resolved.names) {
+            Some(_) => Ok(resolved.to_path()),
+            None => Err(io_error(io::ErrorKind::NotFound)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    fn sample() -> MemoryFileSystem {
+        let fs = MemoryFileSystem::new();
+        fs.write_file(Path::new("/home/docs/readme.md"), "hello")
+            .unwrap();
+        fs.create_dir_all(Path::new("/home/empty")).unwrap();
+        fs
+    }
+
+    fn kind(err: FilesError) -> io::ErrorKind {
+        match err {
+            FilesError::Io(err) => err.kind(),
+            other => panic!("expected an IO error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lists_nested_entries_with_metadata() {
+        let fs = sample();
+
+        let entries = fs.read_directory(Path::new("/home/docs")).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "readme.md");
+        assert_eq!(entries[0].path, Path::new("/home/docs/readme.md"));
+        assert_eq!(entries[0].kind, EntryKind::File);
+        assert_eq!(entries[0].metadata.size, 5);
+    }
+
+    #[test]
+    fn read_directory_on_file_is_invalid_path() {
+        let fs = sample();
+
+        let result = fs.read_directory(Path::new("/home/docs/readme.md"));
+
+        assert!(matches!(result, Err(FilesError::InvalidPath)));
+    }
+
+    #[test]
+    fn rename_moves_contents() {
+        let fs = sample();
+
+        fs.rename(
+            Path::new("/home/docs/readme.md"),
+            Path::new("/home/empty/moved.md"),
+        )
+        .unwrap();
+
+        assert!(!fs.exists(Path::new("/home/docs/readme.md")));
+        assert_eq!(
+            fs.read_file(Path::new("/home/empty/moved.md")).unwrap(),
+            b"hello"
+        );
+    }
+
+    #[test]
+    fn copy_duplicates_contents() {
+        let fs = sample();
+        fs.symlink("readme.md", Path::new("/home/docs/link"))
+            .unwrap();
+
+        fs.copy(Path::new("/home/docs"), Path::new("/home/empty"))
+            .unwrap();
+        fs.write_file(Path::new("/home/docs/readme.md"), "changed")
+            .unwrap();
+
+        assert_eq!(
+            fs.read_file(Path::new("/home/empty/readme.md")).unwrap(),
+            b"hello"
+        );
+        let link = fs.entry(Path::new("/home/empty/link")).unwrap();
+        assert_eq!(link.kind, EntryKind::Symlink);
+        assert_eq!(
+            link.link.unwrap().resolved.as_deref(),
+            Some(Path::new("/home/empty/readme.md"))
+        );
+    }
+
+    #[test]
+    fn copy_keeps_modes_and_times() {
+        let fs = sample();
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs.set_mode(Path::new("/home/docs/readme.md"), 0o600)
+            .unwrap();
+        fs.set_modified(Path::new("/home/docs/readme.md"), old)
+            .unwrap();
+
+        fs.copy(Path::new("/home/docs"), Path::new("/home/empty"))
+            .unwrap();
+
+        let copy = fs.entry(Path::new("/home/empty/readme.md")).unwrap();
+        assert_eq!(copy.metadata.mode, 0o600);
+        assert_eq!(copy.metadata.modified, Some(old));
+    }
+
+    #[test]
+    fn rename_directory_into_itself_fails() {
+        let fs = sample();
+
+        let err = fs
+            .rename(Path::new("/home/docs"), Path::new("/home/docs/inner"))
+            .unwrap_err();
+
+        assert_eq!(kind(err), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn rename_file_onto_directory_fails() {
+        let fs = sample();
+
+        let err = fs
+            .rename(Path::new("/home/docs/readme.md"), Path::new("/home/empty"))
+            .unwrap_err();
+
+        assert_eq!(kind(err), io::ErrorKind::IsADirectory);
+    }
+
+    #[test]
+    fn delete_removes_tree() {
+        let fs = sample();
+
+        fs.delete(Path::new("/home/docs")).unwrap();
+
+        assert!(!fs.exists(Path::new("/home/docs")));
+        assert_eq!(
+            kind(fs.delete(Path::new("/home/docs")).unwrap_err()),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn create_dir_on_existing_path_fails() {
+        let fs = sample();
+
+        let err = fs.create_dir(Path::new("/home/docs")).unwrap_err();
+
+        assert_eq!(kind(err), io::ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn create_file_in_missing_directory_fails() {
+        let fs = sample();
+
+        let err = fs.create_file(Path::new("/nope/new.txt")).unwrap_err();
+
+        assert_eq!(kind(err), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn symlinks_resolve_and_delete_without_following() {
+        let fs = sample();
+        fs.symlink("docs", Path::new("/home/link")).unwrap();
+        fs.symlink("/missing", Path::new("/home/broken")).unwrap();
+
+        let entries = fs.read_directory(Path::new("/home")).unwrap();
+
+        let link = entries.iter().find(|e| e.name == "link").unwrap();
+        assert!(link.is_dir());
+        assert_eq!(
+            link.link.as_ref().unwrap().resolved.as_deref(),
+            Some(Path::new("/home/docs"))
+        );
+
+        let broken = entries.iter().find(|e| e.name == "broken").unwrap();
+        assert!(broken.is_broken_link());
+
+        assert_eq!(
+            fs.read_directory(Path::new("/home/link/../empty"))
+                .unwrap()
+                .len(),
+            0
+        );
+
+        fs.delete(Path::new("/home/link")).unwrap();
+        assert!(fs.exists(Path::new("/home/docs/readme.md")));
+    }
+
+    #[test]
+    fn hard_links_share_contents_until_copied() {
+        let fs = sample();
+        let (original, link) = (Path::new("/home/docs/readme.md"), Path::new("/home/readme"));
+        fs.create_hardlink(original, link).unwrap();
+
+        fs.write_file(link, "changed").unwrap();
+        fs.set_mode(original, 0o600).unwrap();
+        assert_eq!(fs.read_file(original).unwrap(), b"changed");
+        assert_eq!(fs.entry(link).unwrap().metadata.mode, 0o600);
+
+        fs.copy(link, Path::new("/home/copy")).unwrap();
+        fs.write_file(Path::new("/home/copy"), "copy").unwrap();
+        fs.delete(original).unwrap();
+        assert_eq!(fs.read_file(link).unwrap(), b"changed");
+
+        let err = fs
+            .create_hardlink(Path::new("/home/empty"), Path::new("/home/dir"))
+            .unwrap_err();
+        assert_eq!(kind(err), io::ErrorKind::PermissionDenied);
+    }
+}
@@ -1,8 +1,294 @@
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::fs;
 use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// The type of a directory entry, as reported by the filesystem
+/// without following symbolic links.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
+}
+
+impl From<fs::FileType> for EntryKind {
+    fn from(file_type: fs::FileType) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+
+            if file_type.is_fifo() {
+                return EntryKind::Fifo;
+            }
+            if file_type.is_socket() {
+                return EntryKind::Socket;
+            }
+            if file_type.is_block_device() {
+                return EntryKind::BlockDevice;
+            }
+            if file_type.is_char_device() {
+                return EntryKind::CharDevice;
+            }
+        }
+
+        if file_type.is_symlink() {
+            EntryKind::Symlink
+        } else if file_type.is_dir() {
+            EntryKind::Directory
+        } else {
+            EntryKind::File
+        }
+    }
+}
+
+/// Metadata captured when an entry is listed, so frontends can display
+/// and sort on it without touching the filesystem again.
+///
+/// Fields a backend cannot provide are left at their default value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryMetadata {
+    /// Size in bytes.
+    pub size: u64,
+    /// Last content modification (mtime).
+    pub modified: Option<SystemTime>,
+    /// Last access (atime).
+    pub accessed: Option<SystemTime>,
+    /// Last status change (ctime).
+    pub changed: Option<SystemTime>,
+    /// Permission bits (`mode & 0o7777`), without the file type.
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl From<&fs::Metadata> for EntryMetadata {
+    fn from(metadata: &fs::Metadata) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            use std::time::{Duration, UNIX_EPOCH};
+
+            let changed = if metadata.ctime() >= 0 {
+                UNIX_EPOCH.checked_add(Duration::new(
+                    metadata.ctime() as u64,
+                    metadata.ctime_nsec() as u32,
+                ))
+            } else {
+                None
+            };
+
+            Self {
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+                accessed: metadata.accessed().ok(),
+                changed,
+                mode: metadata.mode() & 0o7777,
+                uid: metadata.uid(),
+                gid: metadata.gid(),
+            }
+        }
+
+        #[cfg(not(unix))]
+        {
+            Self {
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+                accessed: metadata.accessed().ok(),
+                changed: None,
+                mode: if metadata.permissions().readonly() {
+                    0o444
+                } else {
+                    0o644
+                },
+                uid: 0,
+                gid: 0,
+            }
+        }
+    }
+}
+
+/// Where a symbolic link points.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkInfo {
+    /// The target exactly as stored in the link; may be relative.
+    pub target: PathBuf,
+    /// The canonical target path, or `None` if the link is broken.
+    pub resolved: Option<PathBuf>,
+    /// The kind of the resolved target, or `None` if the link is broken.
+    pub target_kind: Option<EntryKind>,
+}
 
 #[derive(Debug, Clone)]
 pub struct FileEntry {
-    pub name: String,
+    /// The exact on-disk name, which need not be valid UTF-8.
+    pub name: OsString,
     pub path: PathBuf,
-    pub is_dir: bool,
+    /// Kind of the entry itself; symbolic links are not followed.
+    pub kind: EntryKind,
+    /// Metadata of the entry itself; symbolic links are not followed.
+    pub metadata: EntryMetadata,
+    /// Set when `kind` is `EntryKind::Symlink`.
+    pub link: Option<LinkInfo>,
+    /// User tags, from the [`TAGS`](crate::tags::TAGS) extended attribute
+    /// of the entry itself. Empty where the backend cannot read them.
+    pub tags: Vec<String>,
+}
+
+impl FileEntry {
+    /// Creates an entry with empty metadata.
+    pub fn new(name: impl Into<OsString>, path: impl Into<PathBuf>, kind: EntryKind) -> Self {
+        Self {
+            name: name.into(),
+            path: path.into(),
+            kind,
+            metadata: EntryMetadata::default(),
+            link: None,
+            tags: Vec::new(),
+        }
+    }
+
+    /// The name in printable form; see [`escape_name`].
+    pub fn display_name(&self) -> Cow<'_, str> {
+        escape_name(&self.name)
+    }
+
+    pub fn with_metadata(mut self, metadata: EntryMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn with_link(mut self, link: LinkInfo) -> Self {
+        self.link = Some(link);
+        self
+    }
+
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Returns `true` for directories and for links that resolve to one.
+    pub fn is_dir(&self) -> bool {
+        self.kind == EntryKind::Directory
+            || self
+                .link
+                .as_ref()
+                .is_some_and(|link| link.target_kind == Some(EntryKind::Directory))
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.kind == EntryKind::Symlink
+    }
+
+    /// Returns `true` for links whose target does not resolve.
+    pub fn is_broken_link(&self) -> bool {
+        self.link
+            .as_ref()
+            .is_some_and(|link| link.resolved.is_none())
+    }
+}
+
+/// Converts a name into printable text without losing information.
+///
+/// Valid UTF-8 is returned unchanged. Otherwise every byte that is not
+/// part of a valid UTF-8 sequence is written as `\xNN` and backslashes
+/// are doubled, so [`unescape_name`] can restore the exact bytes.
+pub fn escape_name(name: &OsStr) -> Cow<'_, str> {
+    #[cfg(unix)]
+    {
+        use std::fmt::Write;
+        use std::os::unix::ffi::OsStrExt;
+
+        if let Some(text) = name.to_str() {
+            return Cow::Borrowed(text);
+        }
+
+        let mut out = String::new();
+        for chunk in name.as_bytes().utf8_chunks() {
+            out.push_str(&chunk.valid().replace('\\', "\\\\"));
+            for byte in chunk.invalid() {
+                let _ = write!(out, "\\x{:02x}", byte);
+            }
+        }
+        Cow::Owned(out)
+    }
+
+    #[cfg(not(unix))]
+    {
+        name.to_string_lossy()
+    }
+}
+
+/// Reverses [`escape_name`]: `\\` becomes `\` and `\xNN` becomes the
+/// raw byte `NN`. Any other text is taken literally.
+pub fn unescape_name(text: &str) -> OsString {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+
+        let bytes = text.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+
+        while i < bytes.len() {
+            if bytes[i] == b'\\' {
+                if bytes.get(i + 1) == Some(&b'\\') {
+                    out.push(b'\\');
+                    i += 2;
+                    continue;
+                }
+
+                let hex = bytes
+                    .get(i + 2..i + 4)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+                if bytes.get(i + 1) == Some(&b'x')
+                    && let Some(byte) = hex
+                {
+                    out.push(byte);
+                    i += 4;
+                    continue;
+                }
+            }
+
+            out.push(bytes[i]);
+            i += 1;
+        }
+
+        OsString::from_vec(out)
+    }
+
+    #[cfg(not(unix))]
+    {
+        OsString::from(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf8_names_display_unchanged() {
+        assert_eq!(escape_name(OsStr::new("a\\b.txt")), "a\\b.txt");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names_round_trip() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = OsStr::from_bytes(b"caf\xe9 \\ \xff.txt");
+        let escaped = escape_name(name);
+
+        assert_eq!(escaped, "caf\\xe9 \\\\ \\xff.txt");
+        assert_eq!(unescape_name(&escaped), name);
+    }
 }
@@ -0,0 +1,264 @@
+//! Permission bits, and changes to them written the way chmod(1) takes
+//! them: octal (`755`) or symbolic (`u+x,go-w`).
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::errors::FilesError;
+
+const SETUID: u32 = 0o4000;
+const SETGID: u32 = 0o2000;
+const STICKY: u32 = 0o1000;
+
+/// The classes a symbolic clause applies to, as masks of the bits each
+/// one owns: `u`, `g` and `o`.
+const CLASSES: [(char, u32); 3] = [('u', 0o4700), ('g', 0o2070), ('o', 0o1007)];
+
+/// A change to permission bits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModeChange {
+    /// Replaces the bits with an absolute mode, as in `chmod 640`.
+    Set(u32),
+    /// Adjusts the bits, as in `chmod u+x,go-w`.
+    Symbolic(Vec<Clause>),
+}
+
+/// One comma-separated part of a symbolic mode, such as `go-w`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clause {
+    /// The `u`, `g` and `o` masks combined; all of them when no class is
+    /// given, as for `a`.
+    who: u32,
+    actions: Vec<Action>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Action {
+    op: char,
+    /// Letters from `rwxXst`, or a single class to copy from, as in `g=u`.
+    perms: String,
+}
+
+impl ModeChange {
+    /// The mode that results from applying this change to `mode`. `X` sets
+    /// execute for directories and for files executable by someone.
+    pub fn apply(&self, mode: u32, is_dir: bool) -> u32 {
+        let mode = mode & 0o7777;
+
+        match self {
+            ModeChange::Set(bits) => bits & 0o7777,
+            ModeChange::Symbolic(clauses) => clauses.iter().fold(mode, |mode, clause| {
+                clause.actions.iter().fold(mode, |mode, action| {
+                    let bits = action.bits(mode, is_dir) & clause.who;
+                    match action.op {
+                        '+' => mode | bits,
+                        '-' => mode & !bits,
+                        _ => (mode & !clause.who) | bits,
+                    }
+                })
+            }),
+        }
+    }
+
+    /// The smallest symbolic change that turns `from` into `to`, so that
+    /// applying it elsewhere only touches the bits that differ.
+    pub fn between(from: u32, to: u32) -> Self {
+        let (from, to) = (from & 0o7777, to & 0o7777);
+        let mut clauses = Vec::new();
+
+        for (op, bits) in [('+', to & !from), ('-', from & !to)] {
+            for (_, mask) in CLASSES {
+                let bits = bits & mask;
+                let perms: String = [
+                    ('r', 0o444),
+                    ('w', 0o222),
+                    ('x', 0o111),
+                    ('s', SETUID | SETGID),
+                    ('t', STICKY),
+                ]
+                .into_iter()
+                .filter(|(_, bit)| bits & bit != 0)
+                .map(|(letter, _)| letter)
+                .collect();
+
+                if !perms.is_empty() {
+                    clauses.push(Clause {
+                        who: mask,
+                        actions: vec![Action { op, perms }],
+                    });
+                }
+            }
+        }
+
+        ModeChange::Symbolic(clauses)
+    }
+
+    /// Whether applying this changes nothing.
+    pub fn is_empty(&self) -> bool {
+        matches!(self, ModeChange::Symbolic(clauses) if clauses.is_empty())
+    }
+}
+
+impl Action {
+    fn bits(&self, mode: u32, is_dir: bool) -> u32 {
+        if let Some((_, mask)) = CLASSES
+            .iter()
+            .find(|(class, _)| self.perms == class.to_string())
+        {
+            // Copy another class's rwx bits to every class.
+            let shift = mask.trailing_zeros() - mask.trailing_zeros() % 3;
+            return ((mode >> shift) & 0o7) * 0o111;
+        }
+
+        self.perms.chars().fold(0, |bits, letter| {
+            bits | match letter {
+                'r' => 0o444,
+                'w' => 0o222,
+                'x' => 0o111,
+                'X' if is_dir || mode & 0o111 != 0 => 0o111,
+                's' => SETUID | SETGID,
+                't' => STICKY,
+                _ => 0,
+            }
+        })
+    }
+}
+
+impl FromStr for ModeChange {
+    type Err = FilesError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || FilesError::InvalidMode(text.to_string());
+        let text = text.trim();
+
+        if !text.is_empty() && text.chars().all(|c| c.is_digit(8)) {
+            return match u32::from_str_radix(text, 8) {
+                Ok(mode) if mode <= 0o7777 => Ok(ModeChange::Set(mode)),
+                _ => Err(invalid()),
+            };
+        }
+
+        text.split(',')
+            .map(|clause| parse_clause(clause).ok_or_else(invalid))
+            .collect::<Result<_, _>>()
+            .map(ModeChange::Symbolic)
+    }
+}
+
+/// Parses `[ugoa]*([+-=]([rwxXst]*|[ugo]))+`.
+fn parse_clause(text: &str) -> Option<Clause> {
+    let split = text.find(['+', '-', '='])?;
+    let (classes, mut rest) = text.split_at(split);
+
+    let mut who = 0;
+    for class in classes.chars() {
+        who |= match class {
+            'a' => 0o7777,
+            _ => CLASSES.iter().find(|(c, _)| *c == class)?.1,
+        };
+    }
+    if who == 0 {
+        who = 0o7777;
+    }
+
+    let mut actions = Vec::new();
+    while let Some(op) = rest.chars().next() {
+        rest = &rest[1..];
+        let end = rest.find(['+', '-', '=']).unwrap_or(rest.len());
+        let (perms, next) = rest.split_at(end);
+
+        let copies = perms.len() == 1 && "ugo".contains(perms);
+        if !copies && !perms.chars().all(|c| "rwxXst".contains(c)) {
+            return None;
+        }
+        actions.push(Action {
+            op,
+            perms: perms.to_string(),
+        });
+        rest = next;
+    }
+
+    Some(Clause { who, actions })
+}
+
+impl fmt::Display for ModeChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModeChange::Set(mode) => write!(f, "{:04o}", mode),
+            ModeChange::Symbolic(clauses) => {
+                for (i, clause) in clauses.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    if clause.who == 0o7777 {
+                        write!(f, "a")?;
+                    } else {
+                        for (class, mask) in CLASSES {
+                            if clause.who & mask == mask {
+                                write!(f, "{}", class)?;
+                            }
+                        }
+                    }
+                    for action in &clause.actions {
+                        write!(f, "{}{}", action.op, action.perms)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(change: &str, mode: u32) -> u32 {
+        change.parse::<ModeChange>().unwrap().apply(mode, false)
+    }
+
+    #[test]
+    fn octal_modes_replace_the_bits() {
+        assert_eq!(apply("640", 0o777), 0o640);
+        assert_eq!(apply("4755", 0o644), 0o4755);
+        assert!("8".parse::<ModeChange>().is_err());
+        assert!("77777".parse::<ModeChange>().is_err());
+    }
+
+    #[test]
+    fn symbolic_modes_adjust_the_bits() {
+        assert_eq!(apply("u+x", 0o644), 0o744);
+        assert_eq!(apply("go-w", 0o666), 0o644);
+        assert_eq!(apply("u+x,go-w", 0o666), 0o744);
+        assert_eq!(apply("a=r", 0o755), 0o444);
+        assert_eq!(apply("+x", 0o644), 0o755);
+        assert_eq!(apply("o=", 0o777), 0o770);
+        assert_eq!(apply("u=rwx,g=rx,o=", 0o0), 0o750);
+        assert_eq!(apply("g=u", 0o740), 0o770);
+        assert_eq!(apply("u-w+x", 0o644), 0o544);
+        assert_eq!(apply("u+s,+t", 0o755), 0o5755);
+
+        for invalid in ["", "u", "q+x", "u+k", "u+x,", "g=uo"] {
+            assert!(invalid.parse::<ModeChange>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn capital_x_only_executes_directories_and_executables() {
+        let change: ModeChange = "go+X".parse().unwrap();
+
+        assert_eq!(change.apply(0o600, false), 0o600);
+        assert_eq!(change.apply(0o700, false), 0o711);
+        assert_eq!(change.apply(0o700, true), 0o711);
+    }
+
+    #[test]
+    fn between_changes_only_the_bits_that_differ() {
+        let change = ModeChange::between(0o644, 0o750);
+
+        assert_eq!(change.to_string(), "u+x,g+x,o-r");
+        assert_eq!(change.apply(0o644, false), 0o750);
+        assert_eq!(change.apply(0o600, false), 0o710);
+        assert!(ModeChange::between(0o644, 0o644).is_empty());
+    }
+}
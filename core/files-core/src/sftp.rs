@@ -0,0 +1,662 @@
+//! Browsing remote hosts over SFTP.
+//!
+//! [`SftpFileSystem`] runs every operation over one SSH session, using the
+//! `ssh2` bindings to libssh2. The server is authenticated against
+//! `~/.ssh/known_hosts`, and the user through `ssh-agent` or, failing that,
+//! the default keys in `~/.ssh`. `~/.ssh/config` is not read.
+//!
+//! SFTP (version 3, as spoken by OpenSSH) reports most failures as a bare
+//! "failure", so operations check the paths involved first and return the
+//! error kinds [`RealFileSystem`](crate::filesystem::RealFileSystem) would
+//! on Linux. These checks and the operation are separate requests, so they
+//! can race with other clients changing the same paths.
+
+use std::ffi::OsString;
+use std::io;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ssh2::{
+    CheckResult, ErrorCode, FileStat, FileType, KnownHostFileKind, OpenFlags, OpenType, Session,
+    Sftp,
+};
+
+use crate::{
+    errors::FilesError,
+    filesystem::FileSystem,
+    models::{EntryKind, EntryMetadata, FileEntry, LinkInfo},
+};
+
+/// Gives up on a request the server has not answered in this long.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Private keys tried, in order, when the agent has none that work.
+const DEFAULT_KEYS: &[&str] = &["id_ed25519", "id_ecdsa", "id_rsa"];
+
+// SFTP status codes (draft-ietf-secsh-filexfer); libssh2 passes them
+// through as `ErrorCode::SFTP`.
+const FX_NO_SUCH_FILE: i32 = 2;
+const FX_PERMISSION_DENIED: i32 = 3;
+const FX_NO_SUCH_PATH: i32 = 10;
+const FX_FILE_ALREADY_EXISTS: i32 = 11;
+const FX_WRITE_PROTECT: i32 = 12;
+const FX_NO_SPACE_ON_FILESYSTEM: i32 = 14;
+const FX_QUOTA_EXCEEDED: i32 = 15;
+const FX_DIR_NOT_EMPTY: i32 = 18;
+const FX_NOT_A_DIRECTORY: i32 = 19;
+const FX_FILE_IS_A_DIRECTORY: i32 = 24;
+
+// libssh2's own code for a request that timed out.
+const ERROR_TIMEOUT: i32 = -9;
+
+/// A parsed `sftp://[user@]host[:port][/path]` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SftpUrl {
+    /// The login name; the local user's if `None`.
+    pub user: Option<String>,
+    pub host: String,
+    pub port: u16,
+    /// The directory to start in; the remote home directory if `None`.
+    pub path: Option<PathBuf>,
+}
+
+impl SftpUrl {
+    /// Parses `url`, decoding `%XX` escapes in the user and path.
+    ///
+    /// Returns `None` if `url` is not a well-formed `sftp://` URL. IPv6
+    /// hosts are written in brackets, as in `sftp://[::1]:2222/srv`.
+    pub fn parse(url: &str) -> Option<Self> {
+        let rest = url.strip_prefix("sftp://")?;
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host_port)) => (Some(percent_decode(user)?), host_port),
+            None => (None, authority),
+        };
+
+        let (host, port) = match host_port.strip_prefix('[') {
+            Some(bracketed) => {
+                let (host, after) = bracketed.split_once(']')?;
+                let port = match after {
+                    "" => None,
+                    after => Some(after.strip_prefix(':')?),
+                };
+                (host, port)
+            }
+            None => match host_port.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (host_port, None),
+            },
+        };
+
+        if host.is_empty() || user.as_deref() == Some("") {
+            return None;
+        }
+
+        let port = match port {
+            Some(port) => port.parse().ok()?,
+            None => 22,
+        };
+
+        let path = match path {
+            "" => None,
+            path => Some(PathBuf::from(percent_decode(path)?)),
+        };
+
+        Some(Self {
+            user,
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = text.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+/// A remote directory tree, reached over SFTP.
+pub struct SftpFileSystem {
+    // Owns the connection `sftp` runs on.
+    _session: Session,
+    sftp: Sftp,
+    home: PathBuf,
+}
+
+impl SftpFileSystem {
+    /// Connects and authenticates to the host named by `url`.
+    pub fn connect(url: &SftpUrl) -> Result<Self, FilesError> {
+        let user = match &url.user {
+            Some(user) => user.clone(),
+            None => local_user().ok_or_else(|| {
+                connection_error(io::ErrorKind::InvalidInput, "no user given in the URL")
+            })?,
+        };
+
+        let tcp = TcpStream::connect((url.host.as_str(), url.port))?;
+        let mut session = Session::new().map_err(io_error)?;
+        session.set_tcp_stream(tcp);
+        session.set_timeout(TIMEOUT.as_millis() as u32);
+        session.handshake().map_err(io_error)?;
+
+        verify_host_key(&session, &url.host, url.port)?;
+        authenticate(&session, &user)?;
+
+        let sftp = session.sftp().map_err(io_error)?;
+        let home = sftp.realpath(Path::new(".")).map_err(io_error)?;
+
+        Ok(Self {
+            _session: session,
+            sftp,
+            home,
+        })
+    }
+
+    /// The remote user's home directory.
+    pub fn home(&self) -> &Path {
+        &self.home
+    }
+
+    fn entry(&self, path: PathBuf, stat: &FileStat) -> Result<FileEntry, FilesError> {
+        let kind = entry_kind(stat.file_type());
+        let name = path.file_name().map(OsString::from).unwrap_or_default();
+        let mut entry = FileEntry::new(name, &path, kind).with_metadata(metadata(stat));
+
+        if kind == EntryKind::Symlink {
+            let target = self.sftp.readlink(&path).map_err(io_error)?;
+            let target_kind = self
+                .sftp
+                .stat(&path)
+                .ok()
+                .map(|stat| entry_kind(stat.file_type()));
+            let resolved = target_kind.and_then(|_| self.sftp.realpath(&path).ok());
+
+            entry = entry.with_link(LinkInfo {
+                target,
+                resolved,
+                target_kind,
+            });
+        }
+
+        Ok(entry)
+    }
+
+    /// Removes `path`, and everything under it if it is a directory.
+    fn remove(&self, path: &Path, stat: &FileStat) -> Result<(), FilesError> {
+        if stat.file_type() == FileType::Directory {
+            for (child, stat) in self.list(path)? {
+                self.remove(&child, &stat)?;
+            }
+            self.sftp.rmdir(path).map_err(io_error)
+        } else {
+            self.sftp.unlink(path).map_err(io_error)
+        }
+    }
+
+    /// Copies `from` to the new path `to`, recursively for directories.
+    ///
+    /// Contents pass through this client; SFTP v3 has no server-side copy.
+    /// Modes and times are kept, owners are not.
+    fn copy_tree(&self, from: &Path, to: &Path, stat: &FileStat) -> Result<(), FilesError> {
+        let mode = stat.perm.map_or(0o777, |perm| (perm & 0o7777) as i32);
+
+        match stat.file_type() {
+            FileType::Directory => {
+                self.sftp.mkdir(to, mode).map_err(io_error)?;
+                for (child, stat) in self.list(from)? {
+                    let name = child.file_name().ok_or(FilesError::InvalidPath)?;
+                    self.copy_tree(&child, &to.join(name), &stat)?;
+                }
+            }
+            FileType::Symlink => {
+                let target = self.sftp.readlink(from).map_err(io_error)?;
+                self.sftp.symlink(&target, to).map_err(io_error)?;
+            }
+            _ => {
+                let mut source = self.sftp.open(from).map_err(io_error)?;
+                let mut destination = self
+                    .sftp
+                    .open_mode(
+                        to,
+                        OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::EXCLUSIVE,
+                        mode,
+                        OpenType::File,
+                    )
+                    .map_err(io_error)?;
+                io::copy(&mut source, &mut destination)?;
+            }
+        }
+
+        self.set_attributes(to, stat)
+    }
+
+    /// Gives `path` the mode and times in `stat`. Links are left alone:
+    /// SFTP v3 can only change what they point to.
+    fn set_attributes(&self, path: &Path, stat: &FileStat) -> Result<(), FilesError> {
+        if stat.file_type() == FileType::Symlink {
+            return Ok(());
+        }
+
+        let attributes = FileStat {
+            size: None,
+            uid: None,
+            gid: None,
+            perm: stat.perm.map(|perm| perm & 0o7777),
+            atime: stat.atime,
+            mtime: stat.mtime,
+        };
+        self.sftp.setstat(path, attributes).map_err(io_error)
+    }
+
+    /// Makes way for `from` at another path `to` the way rename(2) would,
+    /// replacing a file with a file or a directory with an empty directory.
+    fn clear_destination(
+        &self,
+        from: &Path,
+        to: &Path,
+        source: &FileStat,
+    ) -> Result<(), FilesError> {
+        let source_is_dir = source.file_type() == FileType::Directory;
+
+        if source_is_dir && to.starts_with(from) {
+            return Err(FilesError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot move or copy a directory into itself",
+            )));
+        }
+
+        // SFTP v3 refuses to replace an existing destination.
+        if let Some(existing) = self.lstat(to)? {
+            match (source_is_dir, existing.file_type() == FileType::Directory) {
+                (false, true) => {
+                    return Err(FilesError::Io(io::ErrorKind::IsADirectory.into()));
+                }
+                (true, false) => {
+                    return Err(FilesError::Io(io::ErrorKind::NotADirectory.into()));
+                }
+                (true, true) => {
+                    if !self.list(to)?.is_empty() {
+                        return Err(FilesError::Io(io::ErrorKind::DirectoryNotEmpty.into()));
+                    }
+                    self.sftp.rmdir(to).map_err(io_error)?;
+                }
+                (false, false) => self.sftp.unlink(to).map_err(io_error)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The entries of `path` with their attributes, links not followed.
+    fn list(&self, path: &Path) -> Result<Vec<(PathBuf, FileStat)>, FilesError> {
+        self.sftp.readdir(path).map_err(io_error)
+    }
+
+    fn lstat(&self, path: &Path) -> Result<Option<FileStat>, FilesError> {
+        match self.sftp.lstat(path) {
+            Ok(stat) => Ok(Some(stat)),
+            Err(err) => match io_error(err) {
+                FilesError::Io(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+                err => Err(err),
+            },
+        }
+    }
+}
+
+impl FileSystem for SftpFileSystem {
+    fn read_directory(&self, path: &Path) -> Result<Vec<FileEntry>, FilesError> {
+        match self.sftp.stat(path) {
+            Ok(stat) if stat.is_dir() => {}
+            _ => return Err(FilesError::InvalidPath),
+        }
+
+        self.list(path)?
+            .into_iter()
+            .map(|(path, stat)| self.entry(path, &stat))
+            .collect()
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let source = self.lstat(from)?.ok_or_else(|| not_found(from))?;
+        if to == from {
+            return Ok(());
+        }
+        self.clear_destination(from, to, &source)?;
+
+        self.sftp.rename(from, to, None).map_err(io_error)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let source = self.lstat(from)?.ok_or_else(|| not_found(from))?;
+
+        if to == from {
+            return Err(FilesError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot copy a path onto itself",
+            )));
+        }
+        self.clear_destination(from, to, &source)?;
+
+        self.copy_tree(from, to, &source)
+    }
+
+    fn copy_metadata(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        let source = self.lstat(from)?.ok_or_else(|| not_found(from))?;
+        self.set_attributes(to, &source)
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> Result<(), FilesError> {
+        let attributes = FileStat {
+            size: None,
+            uid: None,
+            gid: None,
+            perm: Some(mode & 0o7777),
+            atime: None,
+            mtime: None,
+        };
+        self.sftp.setstat(path, attributes).map_err(io_error)
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<(), FilesError> {
+        // The protocol sets both ids at once, so keep the one not given.
+        let current = self.sftp.stat(path).map_err(io_error)?;
+        let attributes = FileStat {
+            size: None,
+            uid: uid.or(current.uid),
+            gid: gid.or(current.gid),
+            perm: None,
+            atime: None,
+            mtime: None,
+        };
+        self.sftp.setstat(path, attributes).map_err(io_error)
+    }
+
+    /// Times are kept to the second.
+    fn set_times(
+        &self,
+        path: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<(), FilesError> {
+        let seconds = |time: SystemTime| {
+            time.duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs())
+        };
+
+        // Like the owner, both times are set at once.
+        let current = self.sftp.stat(path).map_err(io_error)?;
+        let attributes = FileStat {
+            size: None,
+            uid: None,
+            gid: None,
+            perm: None,
+            atime: accessed.map(seconds).or(current.atime),
+            mtime: modified.map(seconds).or(current.mtime),
+        };
+        self.sftp.setstat(path, attributes).map_err(io_error)
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), FilesError> {
+        let stat = self.lstat(path)?.ok_or_else(|| not_found(path))?;
+        self.remove(path, &stat)
+    }
+
+    fn create_file(&self, path: &Path) -> Result<(), FilesError> {
+        if self.sftp.stat(path).is_ok_and(|stat| stat.is_dir()) {
+            return Err(FilesError::Io(io::ErrorKind::IsADirectory.into()));
+        }
+        if self.lstat(path)?.is_some() {
+            return Err(FilesError::Io(io::ErrorKind::AlreadyExists.into()));
+        }
+
+        let flags = OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::EXCLUSIVE;
+        self.sftp
+            .open_mode(path, flags, 0o666, OpenType::File)
+            .map_err(io_error)?;
+        Ok(())
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), FilesError> {
+        if self.lstat(path)?.is_some() {
+            return Err(FilesError::Io(io::ErrorKind::AlreadyExists.into()));
+        }
+
+        self.sftp.mkdir(path, 0o777).map_err(io_error)
+    }
+
+    /// Hard links would need the `hardlink@openssh.com` extension, which
+    /// ssh2 does not expose, so only symbolic links can be created.
+    fn create_symlink(&self, target: &Path, link: &Path) -> Result<(), FilesError> {
+        if self.lstat(link)?.is_some() {
+            return Err(FilesError::Io(io::ErrorKind::AlreadyExists.into()));
+        }
+
+        self.sftp.symlink(target, link).map_err(io_error)
+    }
+}
+
+fn verify_host_key(session: &Session, host: &str, port: u16) -> Result<(), FilesError> {
+    let (key, _) = session
+        .host_key()
+        .ok_or_else(|| connection_error(io::ErrorKind::InvalidData, "server sent no host key"))?;
+
+    let mut known_hosts = session.known_hosts().map_err(io_error)?;
+    if let Some(home) = std::env::var_os("HOME") {
+        let file = Path::new(&home).join(".ssh/known_hosts");
+        // A missing file just means no host is known yet.
+        let _ = known_hosts.read_file(&file, KnownHostFileKind::OpenSSH);
+    }
+
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::NotFound => Err(connection_error(
+            io::ErrorKind::PermissionDenied,
+            &format!(
+                "host key for {} is not in ~/.ssh/known_hosts; connect once with ssh to verify it",
+                host
+            ),
+        )),
+        CheckResult::Mismatch => Err(connection_error(
+            io::ErrorKind::PermissionDenied,
+            &format!(
+                "host key for {} does not match ~/.ssh/known_hosts; refusing to connect",
+                host
+            ),
+        )),
+        CheckResult::Failure => Err(connection_error(
+            io::ErrorKind::Other,
+            "could not check the host key",
+        )),
+    }
+}
+
+fn authenticate(session: &Session, user: &str) -> Result<(), FilesError> {
+    if session.userauth_agent(user).is_ok() && session.authenticated() {
+        return Ok(());
+    }
+
+    if let Some(home) = std::env::var_os("HOME") {
+        let ssh = Path::new(&home).join(".ssh");
+
+        for key in DEFAULT_KEYS {
+            let key = ssh.join(key);
+            if key.is_file()
+                && session.userauth_pubkey_file(user, None, &key, None).is_ok()
+                && session.authenticated()
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    Err(connection_error(
+        io::ErrorKind::PermissionDenied,
+        &format!(
+            "authentication failed for {} (tried ssh-agent and ~/.ssh keys)",
+            user
+        ),
+    ))
+}
+
+fn local_user() -> Option<String> {
+    ["USER", "LOGNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|user| !user.is_empty()))
+}
+
+fn entry_kind(file_type: FileType) -> EntryKind {
+    match file_type {
+        FileType::Directory => EntryKind::Directory,
+        FileType::Symlink => EntryKind::Symlink,
+        FileType::NamedPipe => EntryKind::Fifo,
+        FileType::Socket => EntryKind::Socket,
+        FileType::BlockDevice => EntryKind::BlockDevice,
+        FileType::CharDevice => EntryKind::CharDevice,
+        FileType::RegularFile | FileType::Other(_) => EntryKind::File,
+    }
+}
+
+fn metadata(stat: &FileStat) -> EntryMetadata {
+    let time =
+        |secs: Option<u64>| secs.and_then(|s| UNIX_EPOCH.checked_add(Duration::from_secs(s)));
+
+    EntryMetadata {
+        size: stat.size.unwrap_or_default(),
+        modified: time(stat.mtime),
+        accessed: time(stat.atime),
+        // SFTP v3 has no status-change time.
+        changed: None,
+        mode: stat.perm.unwrap_or_default() & 0o7777,
+        uid: stat.uid.unwrap_or_default(),
+        gid: stat.gid.unwrap_or_default(),
+    }
+}
+
+/// Maps an SFTP status to the `io::ErrorKind` Linux would report.
+fn io_error(err: ssh2::Error) -> FilesError {
+    let kind = match err.code() {
+        ErrorCode::SFTP(FX_NO_SUCH_FILE | FX_NO_SUCH_PATH) => io::ErrorKind::NotFound,
+        ErrorCode::SFTP(FX_PERMISSION_DENIED) => io::ErrorKind::PermissionDenied,
+        ErrorCode::SFTP(FX_FILE_ALREADY_EXISTS) => io::ErrorKind::AlreadyExists,
+        ErrorCode::SFTP(FX_WRITE_PROTECT) => io::ErrorKind::ReadOnlyFilesystem,
+        ErrorCode::SFTP(FX_NO_SPACE_ON_FILESYSTEM) => io::ErrorKind::StorageFull,
+        ErrorCode::SFTP(FX_QUOTA_EXCEEDED) => io::ErrorKind::QuotaExceeded,
+        ErrorCode::SFTP(FX_DIR_NOT_EMPTY) => io::ErrorKind::DirectoryNotEmpty,
+        ErrorCode::SFTP(FX_NOT_A_DIRECTORY) => io::ErrorKind::NotADirectory,
+        ErrorCode::SFTP(FX_FILE_IS_A_DIRECTORY) => io::ErrorKind::IsADirectory,
+        ErrorCode::Session(ERROR_TIMEOUT) => io::ErrorKind::TimedOut,
+        _ => io::ErrorKind::Other,
+    };
+
+    FilesError::Io(io::Error::new(kind, err.message().to_string()))
+}
+
+fn not_found(path: &Path) -> FilesError {
+    FilesError::Io(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{}: no such file or directory", path.display()),
+    ))
+}
+
+fn connection_error(kind: io::ErrorKind, message: &str) -> FilesError {
+    FilesError::Io(io::Error::new(kind, format!("sftp: {}", message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_urls() {
+        assert_eq!(
+            SftpUrl::parse("sftp://alice@build.example:2222/srv/my%20dir"),
+            Some(SftpUrl {
+                user: Some("alice".to_string()),
+                host: "build.example".to_string(),
+                port: 2222,
+                path: Some(PathBuf::from("/srv/my dir")),
+            })
+        );
+        assert_eq!(
+            SftpUrl::parse("sftp://[::1]"),
+            Some(SftpUrl {
+                user: None,
+                host: "::1".to_string(),
+                port: 22,
+                path: None,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_urls() {
+        for url in [
+            "ssh://host/path",
+            "sftp://",
+            "sftp://@host",
+            "sftp://host:port",
+            "sftp://[::1/path",
+            "sftp://host/bad%2",
+        ] {
+            assert_eq!(SftpUrl::parse(url), None, "{}", url);
+        }
+    }
+
+    #[test]
+    fn maps_sftp_statuses_to_error_kinds() {
+        let kind = |code| match io_error(ssh2::Error::new(code, "")) {
+            FilesError::Io(err) => err.kind(),
+            other => panic!("unexpected {:?}", other),
+        };
+
+        assert_eq!(
+            kind(ErrorCode::SFTP(FX_NO_SUCH_FILE)),
+            io::ErrorKind::NotFound
+        );
+        assert_eq!(
+            kind(ErrorCode::SFTP(FX_PERMISSION_DENIED)),
+            io::ErrorKind::PermissionDenied
+        );
+        assert_eq!(kind(ErrorCode::SFTP(4)), io::ErrorKind::Other);
+    }
+
+    /// Runs the conformance suite against a real server, for example a
+    /// local OpenSSH with `FILES_SFTP_TEST_URL=sftp://$USER@localhost/tmp`.
+    #[test]
+    #[ignore = "needs an SSH server; set FILES_SFTP_TEST_URL"]
+    fn sftp_filesystem_conforms() {
+        let url = std::env::var("FILES_SFTP_TEST_URL").expect("FILES_SFTP_TEST_URL is set");
+        let url = SftpUrl::parse(&url).expect("FILES_SFTP_TEST_URL is an sftp:// URL");
+        let fs = SftpFileSystem::connect(&url).unwrap();
+
+        let base = url.path.clone().unwrap_or_else(|| fs.home().to_path_buf());
+        let root = base.join(format!("files-conformance-sftp-{}", std::process::id()));
+        fs.create_dir(&root).unwrap();
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            crate::testing::run(&fs, &root)
+        }));
+        fs.delete(&root).unwrap();
+
+        if let Err(panic) = result {
+            std::panic::resume_unwind(panic);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::io;
+
+use super::*;
+
+use super::command::{TouchTime, TouchTimes};
+use crate::permissions::ModeChange;
+
+/// The order in which a recursive change visits a directory and its
+/// contents.
+#[derive(Clone, Copy)]
+enum Order {
+    /// So that a change granting access lets the walk into a directory.
+    DirectoryFirst,
+    /// So that listing a directory does not undo its change.
+    ContentsFirst,
+}
+
+impl<F: FileSystem> AppState<F> {
+    /// The selected entry or, for a link, the entry it resolves to, whose
+    /// mode and owner are the ones that apply.
+    pub fn selected_target(&self) -> Result<Option<FileEntry>, FilesError> {
+        self.cursor()
+            .map(|selected| self.follow(selected.clone()))
+            .transpose()
+    }
+
+    /// The entry a link resolves to, or `entry` itself if it is no link.
+    fn follow(&self, entry: FileEntry) -> Result<FileEntry, FilesError> {
+        match &entry.link {
+            Some(link) => {
+                let resolved = link
+                    .resolved
+                    .as_ref()
+                    .ok_or_else(|| FilesError::Io(io::ErrorKind::NotFound.into()))?;
+                self.fs.read_entry(resolved)
+            }
+            None => Ok(entry),
+        }
+    }
+
+    /// Changes the permission bits of the selected entry, and of
+    /// everything under it if `recursive`.
+    ///
+    /// A selected link changes its target, while links met further down
+    /// are skipped. Entries already in the resulting mode are left alone.
+    pub fn set_permissions_selected(
+        &mut self,
+        change: &ModeChange,
+        recursive: bool,
+    ) -> Result<(), FilesError> {
+        self.change_selected(recursive, Order::DirectoryFirst, |fs, entry| {
+            let mode = entry.metadata.mode & 0o7777;
+            let new = change.apply(mode, entry.is_dir());
+            if new != mode {
+                fs.set_permissions(&entry.path, new)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Changes the owning user and group of the selected entry, and of
+    /// everything under it if `recursive`; `None` keeps the current one.
+    ///
+    /// Links are treated as by
+    /// [`set_permissions_selected`](Self::set_permissions_selected).
+    pub fn set_owner_selected(
+        &mut self,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        recursive: bool,
+    ) -> Result<(), FilesError> {
+        self.change_selected(recursive, Order::DirectoryFirst, |fs, entry| {
+            let metadata = &entry.metadata;
+            if uid.is_some_and(|uid| uid != metadata.uid)
+                || gid.is_some_and(|gid| gid != metadata.gid)
+            {
+                fs.set_owner(&entry.path, uid, gid)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Sets the access and modification times of the selected entry, and
+    /// of everything under it if `recursive`.
+    ///
+    /// Links are treated as by
+    /// [`set_permissions_selected`](Self::set_permissions_selected), and so
+    /// is a link given as the reference.
+    pub fn touch_selected(
+        &mut self,
+        time: &TouchTime,
+        times: TouchTimes,
+        recursive: bool,
+    ) -> Result<(), FilesError> {
+        let (accessed, modified) = match time {
+            TouchTime::Now => {
+                let now = SystemTime::now();
+                (Some(now), Some(now))
+            }
+            TouchTime::At(time) => (Some(*time), Some(*time)),
+            TouchTime::Reference(path) => {
+                let path = self.current_directory.join(path);
+                let reference = self.follow(self.fs.read_entry(&path)?)?;
+                (reference.metadata.accessed, reference.metadata.modified)
+            }
+        };
+        let (accessed, modified) = match times {
+            TouchTimes::Both => (accessed, modified),
+            TouchTimes::AccessOnly => (accessed, None),
+            TouchTimes::ModificationOnly => (None, modified),
+        };
+
+        self.change_selected(recursive, Order::ContentsFirst, |fs, entry| {
+            fs.set_times(&entry.path, accessed, modified)
+        })
+    }
+
+    /// Runs `change` on the [selected target](Self::selected_target) and
+    /// on what lies under it, and reloads even if that fails part way.
+    fn change_selected(
+        &mut self,
+        recursive: bool,
+        order: Order,
+        change: impl Fn(&F, &FileEntry) -> Result<(), FilesError>,
+    ) -> Result<(), FilesError> {
+        let Some(target) = self.selected_target()? else {
+            return Ok(());
+        };
+        let selected = self.cursor().map(|entry| entry.path.clone());
+
+        let outcome = self.change_tree(&target, recursive, order, &change);
+        let reloaded = self.reload_after_change(selected, |_| {});
+        outcome.and(reloaded)
+    }
+
+    fn change_tree(
+        &self,
+        entry: &FileEntry,
+        recursive: bool,
+        order: Order,
+        change: &impl Fn(&F, &FileEntry) -> Result<(), FilesError>,
+    ) -> Result<(), FilesError> {
+        if let Order::DirectoryFirst = order {
+            change(&self.fs, entry)?;
+        }
+
+        if recursive && entry.kind == EntryKind::Directory {
+            for child in self.fs.read_directory(&entry.path)? {
+                if !child.is_symlink() {
+                    self.change_tree(&child, true, order, change)?;
+                }
+            }
+        }
+
+        if let Order::ContentsFirst = order {
+            change(&self.fs, entry)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::memory::MemoryFileSystem;
+
+    fn state() -> AppState<MemoryFileSystem> {
+        let fs = MemoryFileSystem::new();
+        fs.write_file(Path::new("/work/dir/a.txt"), "a").unwrap();
+        fs.write_file(Path::new("/work/dir/sub/b.sh"), "b").unwrap();
+        fs.write_file(Path::new("/outside.txt"), "").unwrap();
+        fs.symlink(Path::new("/outside.txt"), Path::new("/work/dir/link"))
+            .unwrap();
+        fs.symlink(Path::new("/work/dir/a.txt"), Path::new("/work/link"))
+            .unwrap();
+        fs.set_mode(Path::new("/work/dir/sub/b.sh"), 0o755).unwrap();
+
+        let entries = fs.read_directory(Path::new("/work")).unwrap();
+        AppState::new(PathBuf::from("/work"), entries, fs)
+    }
+
+    fn mode(state: &AppState<MemoryFileSystem>, path: &str) -> u32 {
+        state.fs.read_entry(Path::new(path)).unwrap().metadata.mode
+    }
+
+    #[test]
+    fn permissions_apply_recursively_without_following_links() {
+        let mut state = state();
+        let change: ModeChange = "go-rx,u+X".parse().unwrap();
+
+        state.set_permissions_selected(&change, true).unwrap();
+
+        assert_eq!(mode(&state, "/work/dir"), 0o700);
+        assert_eq!(mode(&state, "/work/dir/a.txt"), 0o600);
+        assert_eq!(mode(&state, "/work/dir/sub"), 0o700);
+        assert_eq!(mode(&state, "/work/dir/sub/b.sh"), 0o700);
+        assert_eq!(mode(&state, "/outside.txt"), 0o644);
+        assert_eq!(state.cursor().unwrap().metadata.mode, 0o700);
+    }
+
+    #[test]
+    fn permissions_of_a_selected_link_change_its_target() {
+        let mut state = state();
+        state.select_next();
+        assert_eq!(state.cursor().unwrap().name, "link");
+
+        state
+            .set_permissions_selected(&ModeChange::Set(0o600), false)
+            .unwrap();
+
+        assert_eq!(mode(&state, "/work/dir/a.txt"), 0o600);
+        assert_eq!(mode(&state, "/work/dir"), 0o755);
+    }
+
+    #[test]
+    fn owner_changes_only_the_given_ids() {
+        let mut state = state();
+
+        state.set_owner_selected(Some(2000), None, true).unwrap();
+
+        let entry = state
+            .fs
+            .read_entry(Path::new("/work/dir/sub/b.sh"))
+            .unwrap();
+        assert_eq!(entry.metadata.uid, 2000);
+        assert_eq!(entry.metadata.gid, 1000);
+        let outside = state.fs.read_entry(Path::new("/outside.txt")).unwrap();
+        assert_eq!(outside.metadata.uid, 1000);
+    }
+
+    #[test]
+    fn touch_sets_times_recursively() {
+        let mut state = state();
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+
+        state
+            .touch_selected(&TouchTime::At(time), TouchTimes::Both, true)
+            .unwrap();
+
+        for path in ["/work/dir", "/work/dir/a.txt", "/work/dir/sub/b.sh"] {
+            let metadata = state.fs.read_entry(Path::new(path)).unwrap().metadata;
+            assert_eq!(metadata.modified, Some(time), "{}", path);
+            assert_eq!(metadata.accessed, Some(time), "{}", path);
+        }
+        let outside = state.fs.read_entry(Path::new("/outside.txt")).unwrap();
+        assert_ne!(outside.metadata.modified, Some(time));
+    }
+
+    #[test]
+    fn touch_copies_one_time_from_a_reference() {
+        let mut state = state();
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        state
+            .fs
+            .set_times(Path::new("/outside.txt"), Some(time), Some(time))
+            .unwrap();
+        let before = state.fs.read_entry(Path::new("/work/dir")).unwrap();
+
+        state
+            .touch_selected(
+                &TouchTime::Reference(PathBuf::from("dir/link")),
+                TouchTimes::ModificationOnly,
+                false,
+            )
+            .unwrap();
+
+        let after = state.fs.read_entry(Path::new("/work/dir")).unwrap();
+        assert_eq!(after.metadata.modified, Some(time));
+        assert_eq!(after.metadata.accessed, before.metadata.accessed);
+    }
+}
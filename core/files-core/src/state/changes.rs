@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+
+use super::*;
+
+/// Changes seen in a directory since it was listed, for
+/// [`AppState::apply_changes`].
+#[derive(Debug, Default)]
+pub struct Changes {
+    pub directory: PathBuf,
+    /// Entries that were created or changed.
+    pub updated: Vec<FileEntry>,
+    /// Names that no longer exist.
+    pub removed: Vec<OsString>,
+    /// Whether `updated` is the whole listing, after the watcher lost
+    /// track of individual changes.
+    pub complete: bool,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.updated.is_empty() && self.removed.is_empty() && !self.complete
+    }
+}
+
+impl<F: FileSystem> AppState<F> {
+    /// Patches the listing with changes made behind the state's back,
+    /// keeping the cursor on the same entry. If that entry is gone, the
+    /// cursor stays on the same row.
+    ///
+    /// Changes to another directory, or arriving while a load is in
+    /// flight, are dropped, since the load reads the latest contents.
+    /// Returns whether the listing was patched.
+    pub fn apply_changes(&mut self, changes: Changes) -> bool {
+        if changes.directory != self.current_directory || self.pending.is_some() {
+            return false;
+        }
+
+        let selected = self.cursor().map(|e| e.name.clone());
+        let row = self.cursor_index;
+
+        let mut updated = changes.updated;
+        sorting::sort_entries(&mut updated);
+
+        if changes.complete {
+            self.retain_tagged(&mut updated);
+            self.entries = updated;
+        } else {
+            // Entries that lost the filtered tag are stale as well.
+            let stale: HashSet<OsString> = changes
+                .removed
+                .into_iter()
+                .chain(updated.iter().map(|e| e.name.clone()))
+                .collect();
+            self.retain_tagged(&mut updated);
+            self.entries.retain(|e| !stale.contains(&e.name));
+            sorting::merge_sorted(&mut self.entries, updated, None);
+        }
+
+        let last = self.entries.len().checked_sub(1);
+        self.cursor_index = selected
+            .and_then(|name| self.entries.iter().position(|e| e.name == name))
+            .or_else(|| row.zip(last).map(|(row, last)| row.min(last)));
+        self.select_first_if_unset();
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::memory::MemoryFileSystem;
+
+    fn state() -> AppState<MemoryFileSystem> {
+        let fs = MemoryFileSystem::new();
+        for name in ["a.txt", "c.txt", "e.txt"] {
+            fs.write_file(&Path::new("/work").join(name), "").unwrap();
+        }
+        let entries = fs.read_directory(Path::new("/work")).unwrap();
+        AppState::new(PathBuf::from("/work"), entries, fs)
+    }
+
+    fn names<F: FileSystem>(state: &AppState<F>) -> Vec<String> {
+        state
+            .entries()
+            .iter()
+            .map(|e| e.name.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn changes_are_merged_keeping_the_cursor() {
+        let mut state = state();
+        state.select_next();
+        let fs = MemoryFileSystem::new();
+        fs.write_file(Path::new("/work/b.txt"), "new").unwrap();
+        fs.create_dir_all(Path::new("/work/d")).unwrap();
+
+        let applied = state.apply_changes(Changes {
+            directory: PathBuf::from("/work"),
+            updated: vec![
+                fs.read_entry(Path::new("/work/b.txt")).unwrap(),
+                fs.read_entry(Path::new("/work/d")).unwrap(),
+            ],
+            removed: vec!["e.txt".into()],
+            complete: false,
+        });
+
+        assert!(applied);
+        assert_eq!(names(&state), ["d", "a.txt", "b.txt", "c.txt"]);
+        assert_eq!(state.cursor().unwrap().name, "c.txt");
+    }
+
+    #[test]
+    fn removing_the_selected_entry_keeps_the_row() {
+        let mut state = state();
+        state.select_next();
+
+        state.apply_changes(Changes {
+            directory: PathBuf::from("/work"),
+            removed: vec!["c.txt".into()],
+            ..Changes::default()
+        });
+
+        assert_eq!(names(&state), ["a.txt", "e.txt"]);
+        assert_eq!(state.cursor().unwrap().name, "e.txt");
+    }
+
+    #[test]
+    fn changes_elsewhere_or_during_a_load_are_dropped() {
+        let mut state = state();
+        let removed = || Changes {
+            directory: PathBuf::from("/work"),
+            removed: vec!["a.txt".into()],
+            ..Changes::default()
+        };
+
+        assert!(!state.apply_changes(Changes {
+            directory: PathBuf::from("/elsewhere"),
+            ..removed()
+        }));
+        state.request_load(&Command::Refresh);
+        assert!(!state.apply_changes(removed()));
+        assert_eq!(names(&state), ["a.txt", "c.txt", "e.txt"]);
+    }
+}
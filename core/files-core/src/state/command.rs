@@ -1,5 +1,11 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
 use super::AppState;
-use crate::{errors::FilesError, filesystem::FileSystem};
+use crate::{
+    conflict::Policy, errors::FilesError, filesystem::FileSystem, permissions::ModeChange,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Command {
@@ -7,15 +13,100 @@ pub enum Command {
     MoveCursorUp,
     Enter,
     GoUp,
+    FollowLink,
     Refresh,
-    Rename(String),
+    /// Opens the trash, listing trashed items.
+    OpenTrash,
+    Rename(OsString),
+    /// Moves the selected entry to the trash.
+    Trash,
+    /// Deletes the selected entry permanently.
     Delete,
-    CreateFile(String),
-    CreateDirectory(String),
+    /// Moves the selected trashed item back to where it came from.
+    Restore,
+    /// Permanently deletes everything in the trash.
+    EmptyTrash,
+    CreateFile(OsString),
+    CreateDirectory(OsString),
+    /// Copies the selected entry out into the given local directory.
+    Extract(PathBuf),
+    /// Copies the selected entry to the given path, or into it if it is a
+    /// directory. Relative paths are taken from the current directory.
+    Copy(PathBuf),
+    /// Moves the selected entry like `Copy` copies it.
+    Move(PathBuf),
+    /// Creates a symbolic link to the selected entry, placed like `Copy`
+    /// places a copy. The link stores the entry's absolute path or, if
+    /// `relative`, the path to it from the link's directory.
+    CreateSymlink {
+        destination: PathBuf,
+        relative: bool,
+    },
+    /// Creates a hard link to the selected file, placed like `Copy` places
+    /// a copy.
+    CreateHardlink(PathBuf),
+    /// Changes the permission bits of the selected entry, and of everything
+    /// under it if `recursive`.
+    SetPermissions {
+        mode: ModeChange,
+        recursive: bool,
+    },
+    /// Changes the owning user and group of the selected entry; `None`
+    /// keeps the current one.
+    SetOwner {
+        uid: Option<u32>,
+        gid: Option<u32>,
+        recursive: bool,
+    },
+    /// Sets the access and modification times of the selected entry, and
+    /// of everything under it if `recursive`, like touch(1) on an existing
+    /// file.
+    Touch {
+        time: TouchTime,
+        times: TouchTimes,
+        recursive: bool,
+    },
+    /// Replaces the tags of the selected entry, or of the entry a selected
+    /// link resolves to. No tags removes them.
+    SetTags(Vec<String>),
+    /// Narrows the listing to entries with the given tag, or lists
+    /// everything again for `None`.
+    FilterTag(Option<String>),
+}
+
+/// Where [`Command::Touch`] takes its times from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TouchTime {
+    Now,
+    At(SystemTime),
+    /// The times of another entry, following links. Relative paths are
+    /// taken from the current directory.
+    Reference(PathBuf),
+}
+
+/// Which times [`Command::Touch`] sets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TouchTimes {
+    #[default]
+    Both,
+    AccessOnly,
+    ModificationOnly,
 }
 
 impl<F: FileSystem> AppState<F> {
+    /// Runs `command`, failing with [`FilesError::AlreadyExists`] rather
+    /// than replacing anything in the way.
     pub fn handle_command(&mut self, command: Command) -> Result<(), FilesError> {
+        self.handle_command_with(command, Policy::Fail)
+    }
+
+    /// Runs `command`, resolving destinations that already exist with
+    /// `policy`.
+    pub fn handle_command_with(
+        &mut self,
+        command: Command,
+        policy: Policy,
+    ) -> Result<(), FilesError> {
         match command {
             Command::MoveCursorDown => {
                 self.select_next();
@@ -27,14 +118,45 @@ impl<F: FileSystem> AppState<F> {
             }
             Command::Enter => self.enter_selected_directory(),
             Command::GoUp => self.go_up(),
+            Command::FollowLink => self.follow_selected_link(),
+            Command::OpenTrash => self.open_trash(),
             Command::Refresh => self.refresh(),
-            Command::Rename(new_name) => self.rename_selected(new_name),
+            Command::Rename(new_name) => self.rename_selected(new_name, policy),
+            Command::Trash => self.trash_selected(),
             Command::Delete => {
                 self.delete_selected()?;
                 Ok(())
             }
-            Command::CreateFile(name) => self.create_file(name),
-            Command::CreateDirectory(name) => self.create_directory(name),
+            Command::Restore => {
+                self.restore_selected()?;
+                Ok(())
+            }
+            Command::EmptyTrash => self.empty_trash(),
+            Command::CreateFile(name) => self.create_file(name, policy),
+            Command::CreateDirectory(name) => self.create_directory(name, policy),
+            Command::Extract(destination) => self.extract_selected(&destination),
+            Command::Copy(destination) => self.copy_selected(&destination, policy),
+            Command::Move(destination) => self.move_selected(&destination, policy),
+            Command::CreateSymlink {
+                destination,
+                relative,
+            } => self.symlink_selected(&destination, relative, policy),
+            Command::CreateHardlink(destination) => self.hardlink_selected(&destination, policy),
+            Command::SetPermissions { mode, recursive } => {
+                self.set_permissions_selected(&mode, recursive)
+            }
+            Command::SetOwner {
+                uid,
+                gid,
+                recursive,
+            } => self.set_owner_selected(uid, gid, recursive),
+            Command::Touch {
+                time,
+                times,
+                recursive,
+            } => self.touch_selected(&time, times, recursive),
+            Command::SetTags(tags) => self.set_tags_selected(&tags),
+            Command::FilterTag(tag) => self.filter_by_tag(tag),
         }
     }
 }
@@ -42,7 +164,6 @@ impl<F: FileSystem> AppState<F> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
 
     use crate::state::test_utils::{MockFileSystem, mock_entries};
 
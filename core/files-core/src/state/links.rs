@@ -0,0 +1,261 @@
+use std::io;
+
+use super::*;
+
+use crate::{filesystem::relative_path, models::LinkInfo};
+
+impl<F: FileSystem> AppState<F> {
+    /// Creates a symbolic link to the selected entry at `destination`, or
+    /// in it if it is an existing directory, the way `copy_selected` places
+    /// a copy.
+    ///
+    /// The link stores the selected entry's absolute path or, if
+    /// `relative`, the path to it from the link's directory. That one is
+    /// worked out between canonical directories, so it still leads to the
+    /// entry when either directory was reached through a link.
+    pub fn symlink_selected(
+        &mut self,
+        destination: &Path,
+        relative: bool,
+        policy: Policy,
+    ) -> Result<(), FilesError> {
+        let Some(selected) = self.cursor().cloned() else {
+            return Ok(());
+        };
+        let path = self.transfer_target(&selected, destination);
+
+        let target = if relative {
+            let link = self.physical(&path)?;
+            let parent = link.parent().ok_or(FilesError::InvalidPath)?;
+            relative_path(&self.physical(&selected.path)?, parent)
+        } else {
+            selected.path.clone()
+        };
+
+        let name = path.file_name().unwrap_or_default().to_os_string();
+        let entry = FileEntry::new(name, path, EntryKind::Symlink)
+            .with_metadata(EntryMetadata {
+                modified: Some(SystemTime::now()),
+                mode: 0o777,
+                ..EntryMetadata::default()
+            })
+            .with_link(LinkInfo {
+                target: target.clone(),
+                resolved: Some(selected.path.clone()),
+                target_kind: Some(selected.kind),
+            });
+
+        self.link_selected(&selected, entry, policy, |fs, path| {
+            fs.create_symlink(&target, path)
+        })
+    }
+
+    /// Creates a hard link to the selected file, placed like
+    /// [`symlink_selected`](Self::symlink_selected) places a symbolic one.
+    pub fn hardlink_selected(
+        &mut self,
+        destination: &Path,
+        policy: Policy,
+    ) -> Result<(), FilesError> {
+        let Some(selected) = self.cursor().cloned() else {
+            return Ok(());
+        };
+        let path = self.transfer_target(&selected, destination);
+
+        let mut entry = selected.clone();
+        entry.name = path.file_name().unwrap_or_default().to_os_string();
+        entry.path = path;
+
+        self.link_selected(&selected, entry, policy, |fs, path| {
+            fs.create_hardlink(&selected.path, path)
+        })
+    }
+
+    /// `path` with its directory canonicalized; the entry itself, which
+    /// need not exist, is not followed.
+    fn physical(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => Ok(self.fs.canonicalize(parent)?.join(name)),
+            _ => self.fs.canonicalize(path),
+        }
+    }
+
+    /// Creates the link described by `entry` with `link`, once its place
+    /// is claimed under `policy`, and reloads, selecting the link if it
+    /// was made in the directory on screen. A file it replaces is kept
+    /// until the link is made.
+    fn link_selected(
+        &mut self,
+        selected: &FileEntry,
+        mut entry: FileEntry,
+        policy: Policy,
+        link: impl FnOnce(&F, &Path) -> Result<(), FilesError>,
+    ) -> Result<(), FilesError> {
+        // Replacing the entry with a link to itself would lose it.
+        if matches!(policy, Policy::Overwrite | Policy::OverwriteIfNewer)
+            && self.physical(&entry.path)? == self.physical(&selected.path)?
+        {
+            return Err(FilesError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot link a path onto itself",
+            )));
+        }
+
+        let Some((path, replace)) = self.claim(&entry, entry.path.clone(), policy)? else {
+            return self.refresh();
+        };
+        self.put_in_place(&path, replace, link)?;
+
+        let here = path.parent() == Some(self.current_directory.as_path());
+        let select = if here { &path } else { &selected.path }.clone();
+        entry.name = path.file_name().unwrap_or_default().to_os_string();
+        entry.path = path;
+
+        self.reload_after_change(Some(select), |entries| {
+            if here {
+                entries.retain(|e| e.path != entry.path);
+                entries.push(entry);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::memory::MemoryFileSystem;
+    use crate::testing::{Call, Fault, Faulty};
+
+    fn state() -> AppState<MemoryFileSystem> {
+        let fs = MemoryFileSystem::new();
+        fs.write_file(Path::new("/work/b.txt"), "b").unwrap();
+        fs.write_file(Path::new("/deep/dir/c.txt"), "c").unwrap();
+        fs.create_dir_all(Path::new("/work/a")).unwrap();
+        fs.symlink(Path::new("/deep/dir"), Path::new("/work/via"))
+            .unwrap();
+
+        let entries = fs.read_directory(Path::new("/work")).unwrap();
+        let mut state = AppState::new(PathBuf::from("/work"), entries, fs);
+        state.select_next();
+        state.select_next();
+        assert_eq!(state.cursor().unwrap().name, "b.txt");
+        state
+    }
+
+    fn target(state: &AppState<MemoryFileSystem>, path: &str) -> PathBuf {
+        let entry = state.fs.read_entry(Path::new(path)).unwrap();
+        entry.link.unwrap().target
+    }
+
+    #[test]
+    fn symlinks_store_relative_or_absolute_targets() {
+        let mut state = state();
+
+        state
+            .symlink_selected(Path::new("a"), true, Policy::Fail)
+            .unwrap();
+        state
+            .symlink_selected(Path::new("a/absolute"), false, Policy::Fail)
+            .unwrap();
+
+        assert_eq!(target(&state, "/work/a/b.txt"), Path::new("../b.txt"));
+        assert_eq!(target(&state, "/work/a/absolute"), Path::new("/work/b.txt"));
+        assert_eq!(state.cursor().unwrap().name, "b.txt");
+    }
+
+    #[test]
+    fn relative_targets_are_taken_between_physical_directories() {
+        let mut state = state();
+
+        state
+            .symlink_selected(Path::new("via"), true, Policy::Fail)
+            .unwrap();
+
+        assert_eq!(
+            target(&state, "/deep/dir/b.txt"),
+            Path::new("../../work/b.txt")
+        );
+        let contents = state.fs.read_file(Path::new("/work/via/b.txt")).unwrap();
+        assert_eq!(contents, b"b");
+    }
+
+    #[test]
+    fn links_in_the_current_directory_get_selected_and_keep_both() {
+        let mut state = state();
+
+        let onto_itself = state.hardlink_selected(Path::new("b.txt"), Policy::Overwrite);
+        assert!(onto_itself.is_err());
+        state
+            .hardlink_selected(Path::new("a"), Policy::Fail)
+            .unwrap();
+        state
+            .symlink_selected(Path::new("a/b.txt"), true, Policy::KeepBoth)
+            .unwrap();
+        state
+            .symlink_selected(Path::new("link"), true, Policy::Fail)
+            .unwrap();
+
+        assert_eq!(state.cursor().unwrap().name, "link");
+        assert_eq!(target(&state, "/work/a/b (1).txt"), Path::new("../b.txt"));
+        state
+            .fs
+            .write_file(Path::new("/work/b.txt"), "new")
+            .unwrap();
+        assert_eq!(
+            state.fs.read_file(Path::new("/work/a/b.txt")).unwrap(),
+            b"new"
+        );
+    }
+
+    #[test]
+    fn overwriting_links_keep_what_is_in_the_way_until_they_are_made() {
+        let mut state = state();
+        state.fs.create_dir_all(Path::new("/work/a/b.txt")).unwrap();
+
+        let onto_directory = state.symlink_selected(Path::new("a"), true, Policy::Overwrite);
+        assert!(matches!(
+            onto_directory,
+            Err(FilesError::Io(err)) if err.kind() == io::ErrorKind::IsADirectory
+        ));
+        assert!(
+            state
+                .fs
+                .read_entry(Path::new("/work/a/b.txt"))
+                .unwrap()
+                .is_dir()
+        );
+
+        let fs = MemoryFileSystem::new();
+        fs.write_file(Path::new("/work/b.txt"), "b").unwrap();
+        fs.write_file(Path::new("/work/a/b.txt"), "old").unwrap();
+        let fs = Faulty::new(fs)
+            .with_fault(Fault::new(io::ErrorKind::PermissionDenied).on(Call::CreateSymlink));
+        let entries = fs.read_directory(Path::new("/work")).unwrap();
+        let mut state = AppState::new(PathBuf::from("/work"), entries, fs);
+        state.select_next();
+
+        assert!(
+            state
+                .symlink_selected(Path::new("a"), true, Policy::Overwrite)
+                .is_err()
+        );
+        let kept = state.fs.inner().read_file(Path::new("/work/a/b.txt"));
+        assert_eq!(kept.unwrap(), b"old");
+
+        state
+            .hardlink_selected(Path::new("a"), Policy::Overwrite)
+            .unwrap();
+
+        let listing = state.fs.read_directory(Path::new("/work/a")).unwrap();
+        assert_eq!(listing.len(), 1);
+        assert_eq!(
+            state
+                .fs
+                .inner()
+                .read_file(Path::new("/work/a/b.txt"))
+                .unwrap(),
+            b"b"
+        );
+    }
+}
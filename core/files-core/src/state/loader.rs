@@ -0,0 +1,273 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use super::sorting;
+use crate::{errors::FilesError, filesystem::FileSystem, models::FileEntry};
+
+/// A directory read for [`Loader`], from [`AppState::request_load`].
+///
+/// [`AppState::request_load`]: super::AppState::request_load
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadRequest {
+    pub generation: u64,
+    pub directory: PathBuf,
+}
+
+/// The outcome of a [`LoadRequest`], for [`AppState::apply_load`].
+///
+/// [`AppState::apply_load`]: super::AppState::apply_load
+#[derive(Debug)]
+pub struct LoadResult {
+    pub generation: u64,
+    pub directory: PathBuf,
+    pub entries: Result<Vec<FileEntry>, FilesError>,
+    /// Whether this is the last part of the listing.
+    pub complete: bool,
+}
+
+/// Reads directories on a background thread.
+///
+/// Listings are sent in parts as they are read, so a huge directory shows
+/// up before it has been read in full. Parts grow geometrically, which
+/// keeps the cost of merging them into the listing low. A new request
+/// abandons the read in progress, and requests queued while a read is
+/// running are collapsed to the newest. The thread exits when the `Loader`
+/// is dropped.
+pub struct Loader {
+    requests: Sender<LoadRequest>,
+    results: Receiver<LoadResult>,
+}
+
+impl Loader {
+    pub fn spawn<F>(fs: Arc<F>) -> Self
+    where
+        F: FileSystem + Send + Sync + ?Sized + 'static,
+    {
+        let (requests, incoming) = mpsc::channel::<LoadRequest>();
+        let (outgoing, results) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut next = incoming.recv().ok();
+
+            while let Some(mut request) = next.take() {
+                while let Ok(newer) = incoming.try_recv() {
+                    request = newer;
+                }
+
+                match stream(&*fs, request, &incoming, &outgoing) {
+                    Stream::Done => next = incoming.recv().ok(),
+                    Stream::Superseded(newer) => next = Some(newer),
+                    Stream::Disconnected => break,
+                }
+            }
+        });
+
+        Self { requests, results }
+    }
+
+    pub fn request(&self, request: LoadRequest) {
+        // The worker only stops once `self` is gone, so this cannot fail.
+        let _ = self.requests.send(request);
+    }
+
+    /// Returns a finished result, if there is one, without blocking.
+    pub fn try_result(&self) -> Option<LoadResult> {
+        self.results.try_recv().ok()
+    }
+
+    /// Waits up to `timeout` for a finished result.
+    pub fn wait(&self, timeout: Duration) -> Option<LoadResult> {
+        self.results.recv_timeout(timeout).ok()
+    }
+}
+
+/// Entries asked of the filesystem at a time.
+const CHUNK_SIZE: usize = 256;
+
+enum Stream {
+    Done,
+    Superseded(LoadRequest),
+    Disconnected,
+}
+
+/// Reads `request`'s directory, sending each part once it is at least as
+/// large as everything sent before it.
+fn stream<F: FileSystem + ?Sized>(
+    fs: &F,
+    request: LoadRequest,
+    incoming: &Receiver<LoadRequest>,
+    outgoing: &Sender<LoadResult>,
+) -> Stream {
+    let send = |entries: Result<Vec<FileEntry>, FilesError>, complete| {
+        let result = LoadResult {
+            generation: request.generation,
+            directory: request.directory.clone(),
+            entries: entries.map(|mut entries| {
+                // Sorting here keeps the merge on the caller's thread cheap.
+                sorting::sort_entries(&mut entries);
+                entries
+            }),
+            complete,
+        };
+        outgoing.send(result).is_ok()
+    };
+    let done = |sent: bool| {
+        if sent {
+            Stream::Done
+        } else {
+            Stream::Disconnected
+        }
+    };
+
+    let mut chunks = match fs.read_directory_chunks(&request.directory, CHUNK_SIZE) {
+        Ok(chunks) => chunks.peekable(),
+        Err(err) => return done(send(Err(err), true)),
+    };
+
+    let mut batch = Vec::new();
+    let mut sent = 0;
+
+    while let Some(chunk) = chunks.next() {
+        if let Ok(newer) = incoming.try_recv() {
+            return Stream::Superseded(newer);
+        }
+
+        match chunk {
+            Ok(entries) => batch.extend(entries),
+            Err(err) => return done(send(Err(err), true)),
+        }
+
+        if chunks.peek().is_none() {
+            break;
+        }
+
+        if !batch.is_empty() && batch.len() >= sent {
+            sent += batch.len();
+            if !send(Ok(std::mem::take(&mut batch)), false) {
+                return Stream::Disconnected;
+            }
+        }
+    }
+
+    done(send(Ok(batch), true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use crate::memory::MemoryFileSystem;
+    use crate::state::{AppState, Command};
+
+    fn memory() -> Arc<MemoryFileSystem> {
+        let fs = MemoryFileSystem::new();
+        fs.write_file(Path::new("/work/a/inner.txt"), "").unwrap();
+        fs.write_file(Path::new("/work/b.txt"), "").unwrap();
+        Arc::new(fs)
+    }
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn loads_in_the_background() {
+        let fs = memory();
+        let entries = fs.read_directory(Path::new("/work")).unwrap();
+        let mut state = AppState::new(PathBuf::from("/work"), entries, Arc::clone(&fs));
+        let loader = Loader::spawn(fs);
+
+        let request = state.request_load(&Command::Enter).unwrap();
+        assert_eq!(state.loading(), Some(Path::new("/work/a")));
+        assert_eq!(state.current_directory(), Path::new("/work"));
+
+        loader.request(request);
+        let result = loader.wait(TIMEOUT).unwrap();
+
+        assert!(state.apply_load(result).unwrap());
+        assert_eq!(state.loading(), None);
+        assert_eq!(state.current_directory(), Path::new("/work/a"));
+        assert_eq!(state.cursor().unwrap().name, "inner.txt");
+    }
+
+    #[test]
+    fn superseded_results_are_dropped() {
+        let fs = memory();
+        let entries = fs.read_directory(Path::new("/work")).unwrap();
+        let mut state = AppState::new(PathBuf::from("/work"), entries, Arc::clone(&fs));
+        let loader = Loader::spawn(fs);
+
+        let enter = state.request_load(&Command::Enter).unwrap();
+        let up = state.request_load(&Command::GoUp).unwrap();
+
+        let stale = LoadResult {
+            generation: enter.generation,
+            directory: enter.directory.clone(),
+            entries: Ok(Vec::new()),
+            complete: true,
+        };
+        assert!(!state.apply_load(stale).unwrap());
+        assert_eq!(state.loading(), Some(Path::new("/")));
+
+        loader.request(enter);
+        loader.request(up);
+        let mut applied = false;
+        while let Some(result) = loader.wait(TIMEOUT) {
+            if state.apply_load(result).unwrap() {
+                applied = true;
+                break;
+            }
+        }
+
+        assert!(applied);
+        assert_eq!(state.current_directory(), Path::new("/"));
+        assert_eq!(state.cursor().unwrap().name, "work");
+    }
+
+    #[test]
+    fn cancelled_load_is_ignored() {
+        let fs = memory();
+        let mut state = AppState::new(PathBuf::from("/work"), Vec::new(), Arc::clone(&fs));
+        let loader = Loader::spawn(fs);
+
+        loader.request(state.request_load(&Command::GoUp).unwrap());
+        state.cancel_load();
+
+        assert!(!state.apply_load(loader.wait(TIMEOUT).unwrap()).unwrap());
+        assert_eq!(state.current_directory(), Path::new("/work"));
+    }
+
+    #[test]
+    fn streams_large_directories_in_parts() {
+        let fs = MemoryFileSystem::new();
+        for i in 0..1000 {
+            fs.write_file(&PathBuf::from(format!("/big/{:04}.txt", i)), "")
+                .unwrap();
+        }
+        let fs = Arc::new(fs);
+        let mut state = AppState::new(PathBuf::from("/"), Vec::new(), Arc::clone(&fs));
+        let loader = Loader::spawn(fs);
+
+        state.refresh().unwrap();
+        loader.request(state.request_load(&Command::Enter).unwrap());
+
+        let mut parts = 0;
+        while state.loading().is_some() {
+            assert!(state.apply_load(loader.wait(TIMEOUT).unwrap()).unwrap());
+            parts += 1;
+        }
+
+        assert!(parts > 1);
+        assert_eq!(state.entries().len(), 1000);
+        assert!(
+            state
+                .entries()
+                .windows(2)
+                .all(|pair| pair[0].name < pair[1].name)
+        );
+        assert_eq!(state.cursor().unwrap().name, "0000.txt");
+    }
+}
@@ -1,13 +1,28 @@
+use std::ffi::OsString;
 use std::path::Path;
 use std::path::PathBuf;
-
-use crate::{errors::FilesError, filesystem::FileSystem, models::FileEntry};
-
-pub use command::Command;
-
+use std::time::SystemTime;
+
+use crate::{
+    conflict::{Conflict, Policy, Resolution},
+    errors::FilesError,
+    filesystem::FileSystem,
+    jobs::{self, CancelToken, Progress, Task},
+    models::{EntryKind, EntryMetadata, FileEntry},
+};
+
+pub use changes::Changes;
+pub use command::{Command, TouchTime, TouchTimes};
+pub use loader::{LoadRequest, LoadResult, Loader};
+
+mod attributes;
+mod changes;
+mod links;
+mod loader;
 mod navigation;
 mod selection;
 mod sorting;
+mod tagging;
 
 pub mod command;
 
@@ -17,11 +32,19 @@ pub struct AppState<F: FileSystem> {
     entries: Vec<FileEntry>,
     cursor_index: Option<usize>,
     fs: F,
+    /// Numbers loads so that superseded results can be told apart.
+    generation: u64,
+    pending: Option<navigation::PendingLoad>,
+    /// The tag the listing is narrowed to.
+    tag_filter: Option<String>,
 }
 
 impl<F: FileSystem> AppState<F> {
     /// Creates a new AppState for a given directory and its entries.
-    pub fn new(current_directory: PathBuf, entries: Vec<FileEntry>, fs: F) -> Self {
+    ///
+    /// Entries are sorted the same way `refresh` sorts them.
+    pub fn new(current_directory: PathBuf, mut entries: Vec<FileEntry>, fs: F) -> Self {
+        sorting::sort_entries(&mut entries);
         let cursor_index = if entries.is_empty() { None } else { Some(0) };
 
         Self {
@@ -29,6 +52,9 @@ impl<F: FileSystem> AppState<F> {
             entries,
             fs,
             cursor_index,
+            generation: 0,
+            pending: None,
+            tag_filter: None,
         }
     }
 
@@ -52,52 +78,116 @@ impl<F: FileSystem> AppState<F> {
     pub(crate) fn refresh(&mut self) -> Result<(), FilesError> {
         let previous_selection = self.cursor().map(|e| e.name.clone());
 
-        let mut entries = self.fs.read_directory(&self.current_directory)?;
-        sorting::sort_entries(&mut entries);
+        let entries = self.read_sorted(&self.current_directory)?;
         self.entries = entries;
 
-        // Try to preserve selection if possible
-        if let Some(name) = previous_selection {
-            self.cursor_index = self.entries.iter().position(|e| e.name == name);
-        }
+        // Try to preserve selection if possible (by exact on-disk name)
+        self.cursor_index =
+            previous_selection.and_then(|name| self.entries.iter().position(|e| e.name == name));
+        self.select_first_if_unset();
+
+        Ok(())
+    }
 
-        // If nothing selected and entries exist, select first
+    /// Reads and sorts the entries of `directory` without touching the
+    /// state, so a failed read leaves the current listing in place.
+    /// Entries the tag filter hides are left out.
+    fn read_sorted(&self, directory: &Path) -> Result<Vec<FileEntry>, FilesError> {
+        let mut entries = self.fs.read_directory(directory)?;
+        self.retain_tagged(&mut entries);
+        sorting::sort_entries(&mut entries);
+        Ok(entries)
+    }
+
+    fn select_first_if_unset(&mut self) {
         if self.cursor_index.is_none() && !self.entries.is_empty() {
             self.cursor_index = Some(0);
         }
+    }
 
-        Ok(())
+    /// Reloads the listing after a successful change.
+    ///
+    /// If the reload fails, `patch` applies the change to the listing in
+    /// memory instead, so it still reflects what happened on disk; the
+    /// error is returned either way.
+    fn reload_after_change(
+        &mut self,
+        select: Option<PathBuf>,
+        patch: impl FnOnce(&mut Vec<FileEntry>),
+    ) -> Result<(), FilesError> {
+        let previous_selection = self.cursor().map(|e| e.name.clone());
+
+        let (entries, outcome) = match self.read_sorted(&self.current_directory) {
+            Ok(entries) => (entries, Ok(())),
+            Err(err) => {
+                let mut entries = std::mem::take(&mut self.entries);
+                patch(&mut entries);
+                self.retain_tagged(&mut entries);
+                sorting::sort_entries(&mut entries);
+                (entries, Err(err))
+            }
+        };
+
+        self.entries = entries;
+
+        self.cursor_index = match select {
+            Some(path) => self.entries.iter().position(|e| e.path == path),
+            None => {
+                previous_selection.and_then(|name| self.entries.iter().position(|e| e.name == name))
+            }
+        };
+        self.select_first_if_unset();
+
+        outcome
     }
 
-    fn rename_selected(&mut self, new_name: String) -> Result<(), FilesError> {
+    fn rename_selected(&mut self, new_name: OsString, policy: Policy) -> Result<(), FilesError> {
         let selected = match self.cursor() {
             Some(entry) => entry.clone(),
             None => return Ok(()),
         };
 
-        if new_name.trim().is_empty() {
+        if new_name.to_string_lossy().trim().is_empty() {
             return Ok(());
         }
 
         let mut new_path = selected.path.clone();
         new_path.set_file_name(&new_name);
+        if new_path == selected.path {
+            return Ok(());
+        }
 
-        self.fs.rename(&selected.path, &new_path)?;
-
-        // Read fresh entries
-        let mut entries = self.fs.read_directory(&self.current_directory)?;
-        sorting::sort_entries(&mut entries);
-        self.entries = entries;
+        let task = Task::Move {
+            from: selected.path.clone(),
+            to: new_path,
+        };
+        let Some(new_path) = self.run_task(&task, policy)? else {
+            return Ok(());
+        };
 
         // 🔥 Explicitly reselect renamed file
-        self.cursor_index = self.entries.iter().position(|e| e.path == new_path);
-
-        // Fallback if somehow not found
-        if self.cursor_index.is_none() && !self.entries.is_empty() {
-            self.cursor_index = Some(0);
-        }
+        self.reload_after_change(Some(new_path.clone()), |entries| {
+            entries.retain(|e| e.path != new_path);
+            if let Some(entry) = entries.iter_mut().find(|e| e.path == selected.path) {
+                entry.name = new_path.file_name().unwrap_or_default().to_os_string();
+                entry.path = new_path.clone();
+            }
+        })
+    }
 
-        Ok(())
+    /// Runs `task` to the end on this thread, resolving conflicts with
+    /// `policy`, and returns where the source ended up, unless it was
+    /// skipped.
+    fn run_task(&self, task: &Task, policy: Policy) -> Result<Option<PathBuf>, FilesError> {
+        let mut destination = None;
+        jobs::run(
+            &self.fs,
+            task,
+            &CancelToken::new(),
+            |_| policy,
+            |progress| destination = progress.destination.clone(),
+        )?;
+        Ok(destination)
     }
 
     pub fn delete_selected(&mut self) -> Result<(), FilesError> {
@@ -108,37 +198,285 @@ impl<F: FileSystem> AppState<F> {
 
         self.fs.delete(&selected.path)?;
 
-        self.refresh()?;
+        self.reload_after_change(None, |entries| {
+            entries.retain(|e| e.path != selected.path);
+        })
+    }
 
-        if self.entries.is_empty() {
-            self.cursor_index = None;
-        } else if let Some(i) = self.cursor_index
-            && i >= self.entries.len()
-        {
-            self.cursor_index = Some(self.entries.len() - 1);
-        }
+    /// Moves the selected entry to the trash.
+    pub fn trash_selected(&mut self) -> Result<(), FilesError> {
+        let selected = match self.cursor() {
+            Some(entry) => entry.clone(),
+            None => return Ok(()),
+        };
 
-        Ok(())
+        self.fs.trash(&selected.path)?;
+
+        self.reload_after_change(None, |entries| {
+            entries.retain(|e| e.path != selected.path);
+        })
     }
 
-    pub fn create_file(&mut self, name: String) -> Result<(), FilesError> {
-        let mut path = self.current_directory.clone();
-        path.push(&name);
+    /// Restores the selected trashed item and returns where it went.
+    pub fn restore_selected(&mut self) -> Result<Option<PathBuf>, FilesError> {
+        let selected = match self.cursor() {
+            Some(entry) => entry.clone(),
+            None => return Ok(None),
+        };
 
-        self.fs.create_file(&path)?;
+        let original = self.fs.restore(&selected.path)?;
 
-        self.refresh()?;
-        Ok(())
+        self.reload_after_change(None, |entries| {
+            entries.retain(|e| e.path != selected.path);
+        })?;
+
+        Ok(Some(original))
+    }
+
+    pub fn empty_trash(&mut self) -> Result<(), FilesError> {
+        self.fs.empty_trash()?;
+        self.refresh()
+    }
+
+    pub fn create_file(&mut self, name: OsString, policy: Policy) -> Result<(), FilesError> {
+        self.create(name, EntryKind::File, policy)
     }
 
-    pub fn create_directory(&mut self, name: String) -> Result<(), FilesError> {
+    pub fn create_directory(&mut self, name: OsString, policy: Policy) -> Result<(), FilesError> {
+        self.create(name, EntryKind::Directory, policy)
+    }
+
+    fn create(
+        &mut self,
+        name: OsString,
+        kind: EntryKind,
+        policy: Policy,
+    ) -> Result<(), FilesError> {
         let mut path = self.current_directory.clone();
         path.push(&name);
 
-        self.fs.create_dir(&path)?;
+        let source = FileEntry::new(name, path.clone(), kind).with_metadata(EntryMetadata {
+            modified: Some(SystemTime::now()),
+            ..EntryMetadata::default()
+        });
+        let Some((path, replace)) = self.claim(&source, path, policy)? else {
+            return self.refresh();
+        };
 
-        self.refresh()?;
-        Ok(())
+        self.put_in_place(&path, replace, |fs, path| match kind {
+            EntryKind::Directory => fs.create_dir(path),
+            _ => fs.create_file(path),
+        })?;
+
+        self.reload_after_change(None, |entries| {
+            entries.retain(|e| e.path != path);
+            let name = path.file_name().unwrap_or_default().to_os_string();
+            entries.push(FileEntry::new(name, path.clone(), kind));
+        })
+    }
+
+    /// Decides where `source` is written, given `path` under `policy`:
+    /// returns the path and whether a file there is to be replaced, or
+    /// `None` if nothing should be written.
+    fn claim(
+        &self,
+        source: &FileEntry,
+        path: PathBuf,
+        policy: Policy,
+    ) -> Result<Option<(PathBuf, bool)>, FilesError> {
+        // Without a listing, the filesystem itself refuses to overwrite.
+        let listing = path
+            .parent()
+            .and_then(|parent| self.fs.read_directory(parent).ok())
+            .unwrap_or_default();
+        let Some(existing) = listing.iter().find(|e| e.path == path) else {
+            return Ok(Some((path, false)));
+        };
+
+        let conflict = Conflict {
+            source: source.clone(),
+            existing: existing.clone(),
+        };
+        match conflict.resolve(policy, |name| listing.iter().any(|e| e.name == name))? {
+            Resolution::Write(path) => Ok(Some((path, false))),
+            Resolution::Replace => Ok(Some((path, true))),
+            // The directory asked for is already there.
+            Resolution::Merge | Resolution::Skip => Ok(None),
+        }
+    }
+
+    /// Writes an entry at `path` with `make`. If it is to `replace` a file
+    /// there, the entry is made under a temporary name and renamed over
+    /// it, so the file is kept should `make` fail.
+    fn put_in_place(
+        &self,
+        path: &Path,
+        replace: bool,
+        make: impl FnOnce(&F, &Path) -> Result<(), FilesError>,
+    ) -> Result<(), FilesError> {
+        if !replace {
+            return make(&self.fs, path);
+        }
+
+        let name = path.file_name().ok_or(FilesError::InvalidPath)?;
+        let temporary = (0..)
+            .map(|n| {
+                let mut candidate = OsString::from(".");
+                candidate.push(name);
+                candidate.push(format!(".{}.tmp", n));
+                path.with_file_name(candidate)
+            })
+            .find(|candidate| self.fs.read_entry(candidate).is_err())
+            .expect("some name is free");
+
+        make(&self.fs, &temporary)?;
+        self.fs.rename(&temporary, path).inspect_err(|_| {
+            let _ = self.fs.delete(&temporary);
+        })
+    }
+
+    /// Copies the selected entry to `destination`, or into it if it is an
+    /// existing directory.
+    pub fn copy_selected(&mut self, destination: &Path, policy: Policy) -> Result<(), FilesError> {
+        self.transfer_selected(destination, false, policy)
+    }
+
+    /// Moves the selected entry to `destination`, or into it if it is an
+    /// existing directory, copying it across devices if need be.
+    pub fn move_selected(&mut self, destination: &Path, policy: Policy) -> Result<(), FilesError> {
+        self.transfer_selected(destination, true, policy)
+    }
+
+    fn transfer_selected(
+        &mut self,
+        destination: &Path,
+        remove: bool,
+        policy: Policy,
+    ) -> Result<(), FilesError> {
+        let selected = match self.cursor() {
+            Some(entry) => entry.clone(),
+            None => return Ok(()),
+        };
+
+        let to = self.transfer_target(&selected, destination);
+        let task = if remove {
+            Task::Move {
+                from: selected.path.clone(),
+                to,
+            }
+        } else {
+            Task::Copy {
+                from: selected.path.clone(),
+                to,
+            }
+        };
+        let target = self.run_task(&task, policy)?;
+
+        self.reload_after_transfer(&selected.path, target.as_deref(), remove, true)
+    }
+
+    /// Where `entry` goes when copied or moved to `destination`: into it if
+    /// it is an existing directory.
+    fn transfer_target(&self, entry: &FileEntry, destination: &Path) -> PathBuf {
+        let mut target = self.current_directory.join(destination);
+        if self.fs.read_directory(&target).is_ok() {
+            target.push(&entry.name);
+        }
+        target
+    }
+
+    /// Reloads after `from` was copied or moved to `to`, following the entry
+    /// if it landed in the directory on screen. The listing is only patched
+    /// if the reload fails and the transfer is `complete`.
+    fn reload_after_transfer(
+        &mut self,
+        from: &Path,
+        to: Option<&Path>,
+        remove: bool,
+        complete: bool,
+    ) -> Result<(), FilesError> {
+        let here = to.filter(|to| to.parent() == Some(self.current_directory.as_path()));
+        let select = match here {
+            Some(to) => Some(to.to_path_buf()),
+            None if remove => None,
+            None => Some(from.to_path_buf()),
+        };
+        let source = self.entries.iter().find(|e| e.path == from).cloned();
+
+        self.reload_after_change(select, |entries| {
+            if !complete || to.is_none() {
+                return;
+            }
+            if remove {
+                entries.retain(|e| e.path != from);
+            }
+            if let (Some(mut entry), Some(to)) = (source, here)
+                && !entries.iter().any(|e| e.path == to)
+            {
+                entry.name = to.file_name().unwrap_or_default().to_os_string();
+                entry.path = to.to_path_buf();
+                entries.push(entry);
+            }
+        })
+    }
+
+    /// Describes a `Copy`, `Move` or `Delete` of the selected entry as a
+    /// [`Task`] to run as a job, without performing it.
+    ///
+    /// Once the job has ended, [`apply_job`](Self::apply_job) updates the
+    /// listing. Returns `None` for other commands or when nothing is
+    /// selected.
+    pub fn job_for(&self, command: &Command) -> Option<Task> {
+        let selected = self.cursor()?;
+        let from = selected.path.clone();
+
+        match command {
+            Command::Copy(destination) => Some(Task::Copy {
+                from,
+                to: self.transfer_target(selected, destination),
+            }),
+            Command::Move(destination) => Some(Task::Move {
+                from,
+                to: self.transfer_target(selected, destination),
+            }),
+            Command::Delete => Some(Task::Delete(from)),
+            _ => None,
+        }
+    }
+
+    /// Updates the listing after a job from [`job_for`](Self::job_for) has
+    /// ended, `finished` or not, with its last `progress`.
+    pub fn apply_job(
+        &mut self,
+        task: &Task,
+        progress: &Progress,
+        finished: bool,
+    ) -> Result<(), FilesError> {
+        let to = progress.destination.as_deref();
+
+        match task {
+            Task::Copy { from, .. } => self.reload_after_transfer(from, to, false, finished),
+            Task::Move { from, .. } => self.reload_after_transfer(from, to, true, finished),
+            Task::Delete(path) => self.reload_after_change(None, |entries| {
+                if finished {
+                    entries.retain(|e| e.path != *path);
+                }
+            }),
+        }
+    }
+
+    /// Copies the selected entry into `destination`, which may live on a
+    /// different filesystem than the one being browsed.
+    pub fn extract_selected(&mut self, destination: &Path) -> Result<(), FilesError> {
+        let selected = match self.cursor() {
+            Some(entry) => entry.clone(),
+            None => return Ok(()),
+        };
+
+        self.fs.extract(&selected.path, destination)?;
+
+        // The destination may be the directory on screen.
+        self.refresh()
     }
 }
 
@@ -150,7 +488,11 @@ mod tests {
     use super::*;
     use std::path::PathBuf;
 
+    use std::io;
+
+    use crate::memory::MemoryFileSystem;
     use crate::state::test_utils::{MockFileSystem, mock_entries};
+    use crate::testing::{Call, Fault, Faulty};
 
     #[test]
     fn initializes_with_selection() {
@@ -225,4 +567,318 @@ mod tests {
         // After refresh, selection should still exist
         assert!(state.cursor().is_some());
     }
+
+    fn work_fs() -> MemoryFileSystem {
+        let fs = MemoryFileSystem::new();
+        fs.write_file(Path::new("/work/a.txt"), "a").unwrap();
+        fs.write_file(Path::new("/work/b.txt"), "b").unwrap();
+        fs
+    }
+
+    fn memory_state() -> AppState<MemoryFileSystem> {
+        let fs = work_fs();
+        let entries = fs.read_directory(Path::new("/work")).unwrap();
+        AppState::new(PathBuf::from("/work"), entries, fs)
+    }
+
+    fn faulty_state(fault: Fault) -> AppState<Faulty<MemoryFileSystem>> {
+        let fs = work_fs();
+        let entries = fs.read_directory(Path::new("/work")).unwrap();
+        AppState::new(
+            PathBuf::from("/work"),
+            entries,
+            Faulty::new(fs).with_fault(fault),
+        )
+    }
+
+    fn names<F: FileSystem>(state: &AppState<F>) -> Vec<OsString> {
+        state.entries().iter().map(|e| e.name.clone()).collect()
+    }
+
+    /// The cursor is in range and set exactly when there are entries, and
+    /// the listing is the sorted contents of the current directory.
+    fn assert_consistent<F: FileSystem>(state: &AppState<F>) {
+        match state.cursor_index() {
+            Some(i) => assert!(i < state.entries().len()),
+            None => assert!(state.entries().is_empty()),
+        }
+
+        for entry in state.entries() {
+            assert_eq!(entry.path.parent(), Some(state.current_directory()));
+        }
+
+        let mut sorted = state.entries().to_vec();
+        sorting::sort_entries(&mut sorted);
+        assert_eq!(
+            sorted.iter().map(|e| &e.name).collect::<Vec<_>>(),
+            state.entries().iter().map(|e| &e.name).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn rename_changes_entries_on_backing_filesystem() {
+        let mut state = memory_state();
+
+        state
+            .handle_command(Command::Rename("c.txt".into()))
+            .unwrap();
+
+        let names: Vec<_> = state.entries().iter().map(|e| e.name.clone()).collect();
+        assert_eq!(names, ["b.txt", "c.txt"]);
+        assert_eq!(state.cursor().unwrap().name, "c.txt");
+    }
+
+    #[test]
+    fn delete_removes_entry_from_backing_filesystem() {
+        let mut state = memory_state();
+
+        state.handle_command(Command::Delete).unwrap();
+
+        assert_eq!(state.entries().len(), 1);
+        assert_eq!(state.cursor().unwrap().name, "b.txt");
+    }
+
+    #[test]
+    fn copy_selects_the_copy_in_the_same_directory() {
+        let mut state = memory_state();
+
+        state.handle_command(Command::Copy("c.txt".into())).unwrap();
+
+        assert_eq!(names(&state), ["a.txt", "b.txt", "c.txt"]);
+        assert_eq!(state.cursor().unwrap().name, "c.txt");
+    }
+
+    #[test]
+    fn move_into_directory_keeps_the_name() {
+        let mut state = memory_state();
+        state
+            .handle_command(Command::CreateDirectory("sub".into()))
+            .unwrap();
+        assert_eq!(state.cursor().unwrap().name, "a.txt");
+
+        state
+            .handle_command(Command::Move("/work/sub".into()))
+            .unwrap();
+
+        assert_eq!(names(&state), ["sub", "b.txt"]);
+        assert_eq!(
+            state.fs.read_directory(Path::new("/work/sub")).unwrap()[0].name,
+            "a.txt"
+        );
+    }
+
+    #[test]
+    fn copy_job_resolves_the_target_and_selects_the_copy() {
+        use crate::jobs::Outcome;
+
+        let mut state = memory_state();
+        let task = state.job_for(&Command::Copy("c.txt".into())).unwrap();
+        assert_eq!(
+            task,
+            Task::Copy {
+                from: PathBuf::from("/work/a.txt"),
+                to: PathBuf::from("/work/c.txt"),
+            }
+        );
+
+        let mut last = Progress::default();
+        let outcome = jobs::run(
+            &state.fs,
+            &task,
+            &CancelToken::new(),
+            |_| Policy::Fail,
+            |p| last = p.clone(),
+        );
+        assert_eq!(outcome.unwrap(), Outcome::Finished);
+        state.apply_job(&task, &last, true).unwrap();
+
+        assert_eq!(names(&state), ["a.txt", "b.txt", "c.txt"]);
+        assert_eq!(state.cursor().unwrap().name, "c.txt");
+    }
+
+    #[test]
+    fn create_file_keeps_an_existing_file_by_default() {
+        let mut state = memory_state();
+
+        let err = state
+            .handle_command(Command::CreateFile("b.txt".into()))
+            .unwrap_err();
+
+        assert!(matches!(err, FilesError::AlreadyExists(path) if path == Path::new("/work/b.txt")));
+        assert_eq!(state.fs.read_file(Path::new("/work/b.txt")).unwrap(), b"b");
+    }
+
+    #[test]
+    fn overwriting_never_replaces_a_directory_with_a_file() {
+        let mut state = memory_state();
+        state
+            .fs
+            .write_file(Path::new("/work/sub/keep"), "kept")
+            .unwrap();
+        state.handle_command(Command::Refresh).unwrap();
+
+        let err = state
+            .handle_command_with(Command::CreateFile("sub".into()), Policy::Overwrite)
+            .unwrap_err();
+
+        assert!(matches!(err, FilesError::Io(err) if err.kind() == io::ErrorKind::IsADirectory));
+        assert_eq!(
+            state.fs.read_file(Path::new("/work/sub/keep")).unwrap(),
+            b"kept"
+        );
+
+        state
+            .handle_command_with(Command::CreateFile("b.txt".into()), Policy::Overwrite)
+            .unwrap();
+        assert_eq!(state.fs.read_file(Path::new("/work/b.txt")).unwrap(), b"");
+        assert_eq!(names(&state), ["sub", "a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn rename_onto_an_existing_name_can_keep_both() {
+        let mut state = memory_state();
+
+        state
+            .handle_command_with(Command::Rename("b.txt".into()), Policy::KeepBoth)
+            .unwrap();
+
+        assert_eq!(names(&state), ["b (1).txt", "b.txt"]);
+        assert_eq!(state.cursor().unwrap().name, "b (1).txt");
+        assert_eq!(state.fs.read_file(Path::new("/work/b.txt")).unwrap(), b"b");
+    }
+
+    #[test]
+    fn overwrite_if_newer_compares_modification_times() {
+        let mut state = memory_state();
+        // Written after a.txt, so newer.
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        state
+            .fs
+            .write_file(Path::new("/work/b.txt"), "new")
+            .unwrap();
+
+        state
+            .handle_command_with(Command::Copy("b.txt".into()), Policy::OverwriteIfNewer)
+            .unwrap();
+
+        assert_eq!(
+            state.fs.read_file(Path::new("/work/b.txt")).unwrap(),
+            b"new"
+        );
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        state
+            .fs
+            .write_file(Path::new("/work/a.txt"), "newest")
+            .unwrap();
+        state.handle_command(Command::Refresh).unwrap();
+        state
+            .handle_command_with(Command::Copy("b.txt".into()), Policy::OverwriteIfNewer)
+            .unwrap();
+
+        assert_eq!(
+            state.fs.read_file(Path::new("/work/b.txt")).unwrap(),
+            b"newest"
+        );
+    }
+
+    #[test]
+    fn create_directory_adds_entry() {
+        let mut state = memory_state();
+
+        state
+            .handle_command(Command::CreateDirectory("sub".into()))
+            .unwrap();
+
+        assert_eq!(state.entries()[0].name, "sub");
+        assert!(state.entries()[0].is_dir());
+    }
+
+    #[test]
+    fn failed_rename_leaves_listing_unchanged() {
+        let mut state = faulty_state(Fault::new(io::ErrorKind::PermissionDenied).on(Call::Rename));
+
+        assert!(
+            state
+                .handle_command(Command::Rename("c.txt".into()))
+                .is_err()
+        );
+
+        assert_eq!(names(&state), ["a.txt", "b.txt"]);
+        assert_eq!(state.cursor().unwrap().name, "a.txt");
+    }
+
+    #[test]
+    fn failed_reload_after_rename_still_shows_new_name() {
+        let mut state = faulty_state(Fault::new(io::ErrorKind::TimedOut).on(Call::ReadDirectory));
+
+        assert!(
+            state
+                .handle_command(Command::Rename("c.txt".into()))
+                .is_err()
+        );
+
+        assert_eq!(names(&state), ["b.txt", "c.txt"]);
+        assert_eq!(state.cursor().unwrap().path, Path::new("/work/c.txt"));
+        assert_consistent(&state);
+    }
+
+    #[test]
+    fn failed_reload_after_delete_drops_entry() {
+        let mut state = faulty_state(Fault::new(io::ErrorKind::TimedOut).on(Call::ReadDirectory));
+
+        assert!(state.handle_command(Command::Delete).is_err());
+
+        assert_eq!(names(&state), ["b.txt"]);
+        assert_consistent(&state);
+    }
+
+    #[test]
+    fn failed_reload_after_create_lists_new_entry() {
+        let mut state = faulty_state(Fault::new(io::ErrorKind::TimedOut).on(Call::ReadDirectory));
+
+        assert!(
+            state
+                .handle_command(Command::CreateDirectory("sub".into()))
+                .is_err()
+        );
+
+        assert_eq!(names(&state), ["sub", "a.txt", "b.txt"]);
+        assert_eq!(state.cursor().unwrap().name, "a.txt");
+        assert_consistent(&state);
+    }
+
+    #[test]
+    fn failed_refresh_keeps_listing_and_selection() {
+        let mut state = faulty_state(Fault::new(io::ErrorKind::TimedOut).on(Call::ReadDirectory));
+        state.handle_command(Command::MoveCursorDown).unwrap();
+
+        assert!(state.handle_command(Command::Refresh).is_err());
+
+        assert_eq!(names(&state), ["a.txt", "b.txt"]);
+        assert_eq!(state.cursor().unwrap().name, "b.txt");
+    }
+
+    #[test]
+    fn any_single_fault_keeps_state_consistent() {
+        let script = [
+            Command::CreateDirectory("sub".into()),
+            Command::Enter,
+            Command::CreateFile("inner.txt".into()),
+            Command::GoUp,
+            Command::MoveCursorDown,
+            Command::Rename("c.txt".into()),
+            Command::Delete,
+            Command::Refresh,
+        ];
+
+        for n in 1..=16 {
+            let mut state = faulty_state(Fault::new(io::ErrorKind::Other).nth(n));
+
+            for command in script.clone() {
+                let _ = state.handle_command(command);
+                assert_consistent(&state);
+            }
+        }
+    }
 }
@@ -1,41 +1,226 @@
 use super::*;
+use crate::filesystem::TRASH;
+use crate::models::{EntryKind, LinkInfo};
+
+/// What to select once a directory has loaded.
+#[derive(Debug, Clone)]
+pub(crate) enum Selection {
+    First,
+    Path(PathBuf),
+    Name(OsString),
+}
+
+impl Selection {
+    fn find(&self, entries: &[FileEntry]) -> Option<usize> {
+        match self {
+            Selection::First => None,
+            Selection::Path(path) => entries.iter().position(|e| &e.path == path),
+            Selection::Name(name) => entries.iter().position(|e| &e.name == name),
+        }
+    }
+}
+
+/// A directory read `AppState` is waiting for.
+#[derive(Debug)]
+pub(crate) struct PendingLoad {
+    generation: u64,
+    directory: PathBuf,
+    selection: Selection,
+    /// Set once the first part of the listing is on screen.
+    started: bool,
+    /// The entry the load itself put the cursor on, to tell whether the
+    /// user has moved it since.
+    auto_selected: Option<PathBuf>,
+}
 
 impl<F: FileSystem> AppState<F> {
-    /// Enters the currently selected directory, if it is a directory.
+    /// Enters the currently selected entry, if the filesystem can open it
+    /// like a directory.
     pub(crate) fn enter_selected_directory(&mut self) -> Result<(), FilesError> {
-        let selected = match self.cursor() {
-            Some(entry) if entry.is_dir => entry,
-            _ => return Ok(()), // Not a directory or nothing selected
-        };
+        self.navigate(&Command::Enter)
+    }
 
-        self.current_directory = selected.path.clone();
-        self.refresh()
+    /// Jumps to where the selected symbolic link resolves.
+    ///
+    /// Links to directories open the target directory; links to anything
+    /// else open the target's parent with the target selected. Broken
+    /// links and non-links are ignored.
+    pub(crate) fn follow_selected_link(&mut self) -> Result<(), FilesError> {
+        self.navigate(&Command::FollowLink)
     }
 
     /// Moves to the parent directory, if it exists.
+    ///
+    /// Stops at the filesystem's root when it is confined to one.
     pub fn go_up(&mut self) -> Result<(), FilesError> {
-        let parent = match self.current_directory.parent() {
-            Some(p) => p.to_path_buf(),
-            None => return Ok(()),
-        };
+        self.navigate(&Command::GoUp)
+    }
+
+    /// Opens the list of trashed items, for filesystems with a trash.
+    pub(crate) fn open_trash(&mut self) -> Result<(), FilesError> {
+        self.navigate(&Command::OpenTrash)
+    }
 
-        let previous_dir = self.current_directory.clone();
+    /// Starts the directory read a navigation command (`Enter`, `GoUp`,
+    /// `FollowLink`, `OpenTrash` or `Refresh`) needs, without performing it.
+    ///
+    /// The state keeps showing the current listing until the matching
+    /// result is passed to [`apply_load`](Self::apply_load). Starting a new
+    /// load supersedes the previous one. Returns `None` for commands that
+    /// do not navigate anywhere.
+    pub fn request_load(&mut self, command: &Command) -> Option<LoadRequest> {
+        let (directory, selection) = self.target(command)?;
 
-        self.current_directory = parent;
+        self.generation += 1;
+        self.pending = Some(PendingLoad {
+            generation: self.generation,
+            directory: directory.clone(),
+            selection,
+            started: false,
+            auto_selected: None,
+        });
 
-        let mut entries = self.fs.read_directory(&self.current_directory)?;
-        sorting::sort_entries(&mut entries);
-        self.entries = entries;
+        Some(LoadRequest {
+            generation: self.generation,
+            directory,
+        })
+    }
+
+    /// The directory being loaded, if a load is in flight.
+    pub fn loading(&self) -> Option<&Path> {
+        self.pending.as_ref().map(|p| p.directory.as_path())
+    }
+
+    /// Forgets the load in flight; its result will be dropped.
+    pub fn cancel_load(&mut self) {
+        self.pending = None;
+    }
+
+    /// Shows the result of a load started with
+    /// [`request_load`](Self::request_load).
+    ///
+    /// A load may arrive in several parts: the first replaces the listing,
+    /// later ones are merged into it in sorted order, keeping the cursor on
+    /// the same entry. The load ends with the part marked `complete`.
+    ///
+    /// Results of superseded or cancelled loads are dropped and `Ok(false)`
+    /// is returned. A failed read ends the load and leaves the listing as
+    /// it was.
+    pub fn apply_load(&mut self, result: LoadResult) -> Result<bool, FilesError> {
+        let mut pending = match self.pending.take() {
+            Some(pending) if pending.generation == result.generation => pending,
+            other => {
+                self.pending = other;
+                return Ok(false);
+            }
+        };
 
-        // 🔥 Select the directory we just came from
-        self.cursor_index = self.entries.iter().position(|e| e.path == previous_dir);
+        let mut chunk = result.entries?;
+        self.retain_tagged(&mut chunk);
+        sorting::sort_entries(&mut chunk);
 
-        // Fallback if not found
-        if self.cursor_index.is_none() && !self.entries.is_empty() {
-            self.cursor_index = Some(0);
+        if pending.started {
+            self.cursor_index = sorting::merge_sorted(&mut self.entries, chunk, self.cursor_index);
+        } else {
+            self.current_directory = pending.directory.clone();
+            self.entries = chunk;
+            self.cursor_index = None;
+            pending.started = true;
         }
 
-        Ok(())
+        // The entry to select may only show up in a later part; move to
+        // it then, unless the user has moved the cursor in the meantime.
+        let moved = self.cursor().map(|e| &e.path) != pending.auto_selected.as_ref();
+        if !moved {
+            if let Some(i) = pending.selection.find(&self.entries) {
+                self.cursor_index = Some(i);
+                pending.selection = Selection::First;
+            }
+            self.select_first_if_unset();
+            pending.auto_selected = self.cursor().map(|e| e.path.clone());
+        }
+
+        if !result.complete {
+            self.pending = Some(pending);
+        }
+
+        Ok(true)
+    }
+
+    /// Runs a navigation command to completion on the calling thread.
+    fn navigate(&mut self, command: &Command) -> Result<(), FilesError> {
+        let Some(request) = self.request_load(command) else {
+            return Ok(());
+        };
+
+        let entries = self.fs.read_directory(&request.directory);
+        self.apply_load(LoadResult {
+            generation: request.generation,
+            directory: request.directory,
+            entries,
+            complete: true,
+        })
+        .map(|_| ())
+    }
+
+    /// Where a navigation command leads, and what to select there.
+    fn target(&self, command: &Command) -> Option<(PathBuf, Selection)> {
+        match command {
+            Command::Enter => {
+                let selected = self.cursor().filter(|entry| self.fs.can_enter(entry))?;
+                Some((selected.path.clone(), Selection::First))
+            }
+
+            Command::GoUp => {
+                // Virtual locations such as the trash have no parent.
+                let parent = self
+                    .current_directory
+                    .parent()
+                    .filter(|parent| !parent.as_os_str().is_empty())?;
+
+                if let Some(root) = self.fs.root()
+                    && !parent.starts_with(root)
+                {
+                    return None;
+                }
+
+                // 🔥 Select the directory we just came from
+                Some((
+                    parent.to_path_buf(),
+                    Selection::Path(self.current_directory.clone()),
+                ))
+            }
+
+            Command::FollowLink => {
+                let (resolved, target_is_dir) = match self.cursor().and_then(|e| e.link.as_ref()) {
+                    Some(LinkInfo {
+                        resolved: Some(resolved),
+                        target_kind,
+                        ..
+                    }) => (resolved, *target_kind == Some(EntryKind::Directory)),
+                    _ => return None,
+                };
+
+                if target_is_dir {
+                    Some((resolved.clone(), Selection::First))
+                } else {
+                    let parent = resolved.parent()?;
+                    Some((parent.to_path_buf(), Selection::Path(resolved.clone())))
+                }
+            }
+
+            Command::OpenTrash => Some((PathBuf::from(TRASH), Selection::First)),
+
+            Command::Refresh => {
+                let selection = match self.cursor() {
+                    Some(entry) => Selection::Name(entry.name.clone()),
+                    None => Selection::First,
+                };
+                Some((self.current_directory.clone(), selection))
+            }
+
+            _ => None,
+        }
     }
 }
 
@@ -61,11 +246,7 @@ mod tests {
 
     #[test]
     fn enter_selected_directory_changes_path() {
-        let entries = vec![FileEntry {
-            name: "dir1".into(),
-            path: PathBuf::from("/tmp/dir1"),
-            is_dir: true,
-        }];
+        let entries = vec![FileEntry::new("dir1", "/tmp/dir1", EntryKind::Directory)];
 
         let fs = MockFileSystem { entries: vec![] };
 
@@ -75,4 +256,178 @@ mod tests {
 
         assert_eq!(state.current_directory(), Path::new("/tmp/dir1"));
     }
+
+    fn link_entry(name: &str, resolved: &str, target_kind: EntryKind) -> FileEntry {
+        FileEntry::new(name, format!("/tmp/{}", name), EntryKind::Symlink).with_link(LinkInfo {
+            target: PathBuf::from(resolved),
+            resolved: Some(PathBuf::from(resolved)),
+            target_kind: Some(target_kind),
+        })
+    }
+
+    #[test]
+    fn follow_link_to_directory_opens_target() {
+        let entries = vec![link_entry("docs", "/srv/docs", EntryKind::Directory)];
+        let fs = MockFileSystem { entries: vec![] };
+
+        let mut state = AppState::new(PathBuf::from("/tmp"), entries, fs);
+
+        state.handle_command(Command::FollowLink).unwrap();
+
+        assert_eq!(state.current_directory(), Path::new("/srv/docs"));
+    }
+
+    #[test]
+    fn follow_link_to_file_selects_target_in_parent() {
+        let entries = vec![link_entry("notes", "/srv/notes.txt", EntryKind::File)];
+        let fs = MockFileSystem {
+            entries: vec![
+                FileEntry::new("a.txt", "/srv/a.txt", EntryKind::File),
+                FileEntry::new("notes.txt", "/srv/notes.txt", EntryKind::File),
+            ],
+        };
+
+        let mut state = AppState::new(PathBuf::from("/tmp"), entries, fs);
+
+        state.handle_command(Command::FollowLink).unwrap();
+
+        assert_eq!(state.current_directory(), Path::new("/srv"));
+        assert_eq!(state.cursor().unwrap().name, "notes.txt");
+    }
+
+    #[test]
+    fn follow_broken_link_is_ignored() {
+        let entries = vec![
+            FileEntry::new("gone", "/tmp/gone", EntryKind::Symlink).with_link(LinkInfo {
+                target: PathBuf::from("missing"),
+                resolved: None,
+                target_kind: None,
+            }),
+        ];
+        let fs = MockFileSystem { entries: vec![] };
+
+        let mut state = AppState::new(PathBuf::from("/tmp"), entries, fs);
+
+        state.handle_command(Command::FollowLink).unwrap();
+
+        assert_eq!(state.current_directory(), Path::new("/tmp"));
+    }
+
+    #[test]
+    fn go_up_from_trash_stays_there() {
+        let fs = MockFileSystem { entries: vec![] };
+
+        let mut state = AppState::new(PathBuf::from("/tmp"), vec![], fs);
+
+        state.handle_command(Command::OpenTrash).unwrap();
+        assert_eq!(state.current_directory(), Path::new(TRASH));
+
+        state.go_up().unwrap();
+        assert_eq!(state.current_directory(), Path::new(TRASH));
+    }
+
+    #[test]
+    fn go_up_stops_at_jail_root() {
+        use crate::filesystem::Jailed;
+        use crate::memory::MemoryFileSystem;
+
+        let memory = MemoryFileSystem::new();
+        memory.create_dir_all(Path::new("/jail/sub")).unwrap();
+        let fs = Jailed::new(Path::new("/jail"), memory).unwrap();
+
+        let mut state = AppState::new(PathBuf::from("/jail/sub"), vec![], fs);
+
+        state.go_up().unwrap();
+        assert_eq!(state.current_directory(), Path::new("/jail"));
+
+        state.go_up().unwrap();
+        assert_eq!(state.current_directory(), Path::new("/jail"));
+    }
+
+    #[test]
+    fn failed_enter_keeps_current_directory() {
+        use crate::memory::MemoryFileSystem;
+        use crate::testing::{Call, Fault, Faulty};
+
+        let memory = MemoryFileSystem::new();
+        memory.create_dir_all(Path::new("/work/sub")).unwrap();
+        let entries = memory.read_directory(Path::new("/work")).unwrap();
+        let fs = Faulty::new(memory)
+            .with_fault(Fault::new(std::io::ErrorKind::PermissionDenied).on(Call::ReadDirectory));
+
+        let mut state = AppState::new(PathBuf::from("/work"), entries, fs);
+
+        assert!(state.handle_command(Command::Enter).is_err());
+        assert!(state.go_up().is_err());
+
+        assert_eq!(state.current_directory(), Path::new("/work"));
+        assert_eq!(state.cursor().unwrap().name, "sub");
+    }
+
+    fn part(request: &LoadRequest, names: &[&str], complete: bool) -> LoadResult {
+        let entries = names
+            .iter()
+            .map(|name| {
+                let path = request.directory.join(name);
+                FileEntry::new(*name, path, EntryKind::File)
+            })
+            .collect();
+
+        LoadResult {
+            generation: request.generation,
+            directory: request.directory.clone(),
+            entries: Ok(entries),
+            complete,
+        }
+    }
+
+    #[test]
+    fn partial_loads_merge_around_the_cursor() {
+        let entries = vec![FileEntry::new("dir1", "/tmp/dir1", EntryKind::Directory)];
+        let fs = MockFileSystem { entries: vec![] };
+        let mut state = AppState::new(PathBuf::from("/tmp"), entries, fs);
+
+        let request = state.request_load(&Command::Enter).unwrap();
+
+        assert!(
+            state
+                .apply_load(part(&request, &["d", "b"], false))
+                .unwrap()
+        );
+        assert_eq!(state.current_directory(), Path::new("/tmp/dir1"));
+        assert_eq!(state.loading(), Some(Path::new("/tmp/dir1")));
+        state.handle_command(Command::MoveCursorDown).unwrap();
+        assert_eq!(state.cursor().unwrap().name, "d");
+
+        assert!(
+            state
+                .apply_load(part(&request, &["e", "a", "c"], true))
+                .unwrap()
+        );
+        assert_eq!(state.loading(), None);
+
+        let names: Vec<_> = state
+            .entries()
+            .iter()
+            .map(|e| e.name.to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["a", "b", "c", "d", "e"]);
+        assert_eq!(state.cursor().unwrap().name, "d");
+    }
+
+    #[test]
+    fn selection_found_in_a_later_part() {
+        let fs = MockFileSystem { entries: vec![] };
+        let mut state = AppState::new(PathBuf::from("/tmp/m"), vec![], fs);
+
+        let request = state.request_load(&Command::GoUp).unwrap();
+
+        state
+            .apply_load(part(&request, &["a", "z"], false))
+            .unwrap();
+        assert_eq!(state.cursor().unwrap().name, "a");
+
+        state.apply_load(part(&request, &["m"], true)).unwrap();
+        assert_eq!(state.cursor().unwrap().name, "m");
+    }
 }
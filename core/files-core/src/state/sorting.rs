@@ -1,51 +1,101 @@
+use std::cmp::Ordering;
+
 use crate::models::FileEntry;
 
 pub(crate) fn sort_entries(entries: &mut [FileEntry]) {
-    entries.sort_by(|a, b| match (a.is_dir, b.is_dir) {
-        (true, false) => std::cmp::Ordering::Less,
-        (false, true) => std::cmp::Ordering::Greater,
-        _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-    });
+    entries.sort_by(compare);
+}
+
+fn compare(a: &FileEntry, b: &FileEntry) -> Ordering {
+    match (a.is_dir(), b.is_dir()) {
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        _ => a
+            .name
+            .to_string_lossy()
+            .to_lowercase()
+            .cmp(&b.name.to_string_lossy().to_lowercase())
+            .then_with(|| a.name.cmp(&b.name)),
+    }
+}
+
+/// Merges sorted `chunk` into sorted `entries`.
+///
+/// Returns where the entry at index `keep` of `entries` ended up.
+pub(crate) fn merge_sorted(
+    entries: &mut Vec<FileEntry>,
+    chunk: Vec<FileEntry>,
+    keep: Option<usize>,
+) -> Option<usize> {
+    let old = std::mem::replace(entries, Vec::with_capacity(entries.len() + chunk.len()));
+    let mut old = old.into_iter().enumerate().peekable();
+    let mut chunk = chunk.into_iter().peekable();
+    let mut kept = None;
+
+    loop {
+        let take_old = match (old.peek(), chunk.peek()) {
+            (Some((_, a)), Some(b)) => compare(a, b) != Ordering::Greater,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => break,
+        };
+
+        if take_old {
+            if let Some((i, entry)) = old.next() {
+                if Some(i) == keep {
+                    kept = Some(entries.len());
+                }
+                entries.push(entry);
+            }
+        } else if let Some(entry) = chunk.next() {
+            entries.push(entry);
+        }
+    }
+
+    kept
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
+    use crate::models::EntryKind;
 
     #[test]
     fn directories_come_before_files() {
         let mut entries = vec![
-            FileEntry {
-                name: "b.txt".into(),
-                path: PathBuf::from("b.txt"),
-                is_dir: false,
-            },
-            FileEntry {
-                name: "a_dir".into(),
-                path: PathBuf::from("a_dir"),
-                is_dir: true,
-            },
+            FileEntry::new("b.txt", "b.txt", EntryKind::File),
+            FileEntry::new("a_dir", "a_dir", EntryKind::Directory),
         ];
 
         sort_entries(&mut entries);
 
-        assert!(entries[0].is_dir);
+        assert!(entries[0].is_dir());
+    }
+
+    #[test]
+    fn merge_keeps_order_and_tracks_entry() {
+        let mut entries = vec![
+            FileEntry::new("dir", "dir", EntryKind::Directory),
+            FileEntry::new("b.txt", "b.txt", EntryKind::File),
+            FileEntry::new("d.txt", "d.txt", EntryKind::File),
+        ];
+        let chunk = vec![
+            FileEntry::new("a.txt", "a.txt", EntryKind::File),
+            FileEntry::new("c.txt", "c.txt", EntryKind::File),
+        ];
+
+        let kept = merge_sorted(&mut entries, chunk, Some(2));
+
+        let names: Vec<_> = entries.iter().map(|e| e.name.clone()).collect();
+        assert_eq!(names, ["dir", "a.txt", "b.txt", "c.txt", "d.txt"]);
+        assert_eq!(kept, Some(4));
     }
 
     #[test]
     fn case_insensitive_sorting() {
         let mut entries = vec![
-            FileEntry {
-                name: "b.txt".into(),
-                path: PathBuf::from("b.txt"),
-                is_dir: false,
-            },
-            FileEntry {
-                name: "A.txt".into(),
-                path: PathBuf::from("A.txt"),
-                is_dir: false,
-            },
+            FileEntry::new("b.txt", "b.txt", EntryKind::File),
+            FileEntry::new("A.txt", "A.txt", EntryKind::File),
         ];
 
         sort_entries(&mut entries);
@@ -0,0 +1,179 @@
+use super::*;
+
+use crate::tags;
+
+impl<F: FileSystem> AppState<F> {
+    /// The tag the listing is narrowed to, if any.
+    pub fn tag_filter(&self) -> Option<&str> {
+        self.tag_filter.as_deref()
+    }
+
+    /// Replaces the tags of the selected entry or, for a link, of the
+    /// entry it resolves to, since links keep no attributes of their own.
+    /// No tags removes them all.
+    pub fn set_tags_selected(&mut self, new: &[String]) -> Result<(), FilesError> {
+        let mut checked: Vec<String> = Vec::new();
+        for tag in new {
+            let tag = tags::validate(tag)?;
+            if !checked.iter().any(|t| t == tag) {
+                checked.push(tag.to_string());
+            }
+        }
+
+        let Some(target) = self.selected_target()? else {
+            return Ok(());
+        };
+        if tags::read(&self.fs, &target.path)? == checked {
+            return Ok(());
+        }
+        tags::write(&self.fs, &target.path, &checked)?;
+
+        self.reload_after_change(None, |entries| {
+            if let Some(entry) = entries.iter_mut().find(|e| e.path == target.path) {
+                entry.tags = checked;
+            }
+        })
+    }
+
+    /// Narrows the listing to entries tagged `tag`, or lists everything
+    /// again for `None`. Directories stay listed so that the filter can
+    /// be carried into them.
+    ///
+    /// The filter holds until changed. If the listing cannot be reread,
+    /// the previous filter is kept.
+    pub fn filter_by_tag(&mut self, tag: Option<String>) -> Result<(), FilesError> {
+        let tag = tag
+            .map(|tag| tags::validate(&tag).map(str::to_string))
+            .transpose()?;
+
+        let previous = std::mem::replace(&mut self.tag_filter, tag);
+        self.refresh().inspect_err(|_| self.tag_filter = previous)
+    }
+
+    /// Drops the entries the tag filter hides.
+    pub(super) fn retain_tagged(&self, entries: &mut Vec<FileEntry>) {
+        if let Some(tag) = &self.tag_filter {
+            entries.retain(|e| e.is_dir() || e.tags.contains(tag));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+
+    use crate::memory::MemoryFileSystem;
+    use crate::state::Changes;
+
+    fn state() -> AppState<MemoryFileSystem> {
+        let fs = MemoryFileSystem::new();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs.write_file(&Path::new("/work").join(name), "").unwrap();
+        }
+        fs.create_dir_all(Path::new("/work/dir")).unwrap();
+        fs.create_symlink(Path::new("a.txt"), Path::new("/work/link"))
+            .unwrap();
+        tags::write(&fs, Path::new("/work/b.txt"), &["draft".to_string()]).unwrap();
+
+        let entries = fs.read_directory(Path::new("/work")).unwrap();
+        AppState::new(PathBuf::from("/work"), entries, fs)
+    }
+
+    fn names<F: FileSystem>(state: &AppState<F>) -> Vec<String> {
+        state
+            .entries()
+            .iter()
+            .map(|e| e.name.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn select(state: &mut AppState<MemoryFileSystem>, name: &str) {
+        while state.cursor().unwrap().name != name {
+            state.select_next();
+        }
+    }
+
+    #[test]
+    fn tags_are_set_on_the_entry_a_link_resolves_to() {
+        let mut state = state();
+        select(&mut state, "link");
+
+        state
+            .set_tags_selected(&[" final ".to_string(), "x".to_string(), "final".to_string()])
+            .unwrap();
+
+        let a = state.entries().iter().find(|e| e.name == "a.txt").unwrap();
+        assert_eq!(a.tags, ["final", "x"]);
+        assert_eq!(state.cursor().unwrap().name, "link");
+
+        assert!(matches!(
+            state.set_tags_selected(&["a,b".to_string()]),
+            Err(FilesError::InvalidTag(_))
+        ));
+
+        state.set_tags_selected(&[]).unwrap();
+        assert!(
+            state
+                .fs
+                .get_xattr(Path::new("/work/a.txt"), OsStr::new(tags::TAGS))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn the_filter_keeps_tagged_entries_and_directories() {
+        let mut state = state();
+
+        state.filter_by_tag(Some("draft".to_string())).unwrap();
+        assert_eq!(state.tag_filter(), Some("draft"));
+        assert_eq!(names(&state), ["dir", "b.txt"]);
+
+        // Entries that lose the tag drop out of the listing.
+        select(&mut state, "b.txt");
+        state.set_tags_selected(&[]).unwrap();
+        assert_eq!(names(&state), ["dir"]);
+
+        state.filter_by_tag(None).unwrap();
+        assert_eq!(names(&state), ["dir", "a.txt", "b.txt", "c.txt", "link"]);
+    }
+
+    #[test]
+    fn the_filter_applies_to_changes_and_loads() {
+        let mut state = state();
+        state.filter_by_tag(Some("draft".to_string())).unwrap();
+
+        let fs = &state.fs;
+        tags::write(fs, Path::new("/work/c.txt"), &["draft".to_string()]).unwrap();
+        tags::write(fs, Path::new("/work/b.txt"), &[]).unwrap();
+        let updated = ["b.txt", "c.txt"]
+            .map(|name| fs.read_entry(&Path::new("/work").join(name)).unwrap())
+            .to_vec();
+        state.apply_changes(Changes {
+            directory: PathBuf::from("/work"),
+            updated,
+            ..Changes::default()
+        });
+        assert_eq!(names(&state), ["dir", "c.txt"]);
+
+        state
+            .fs
+            .write_file(Path::new("/work/dir/d.txt"), "")
+            .unwrap();
+        tags::write(
+            &state.fs,
+            Path::new("/work/dir/d.txt"),
+            &["draft".to_string()],
+        )
+        .unwrap();
+        state
+            .fs
+            .write_file(Path::new("/work/dir/e.txt"), "")
+            .unwrap();
+        select(&mut state, "dir");
+        state.handle_command(Command::Enter).unwrap();
+        assert_eq!(names(&state), ["d.txt"]);
+    }
+}
@@ -1,7 +1,11 @@
-use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::{errors::FilesError, filesystem::FileSystem, models::FileEntry};
+use crate::{
+    errors::FilesError,
+    filesystem::FileSystem,
+    models::{EntryKind, EntryMetadata, FileEntry},
+};
 
 #[derive(Clone)]
 pub struct MockFileSystem {
@@ -16,7 +20,10 @@ impl FileSystem for MockFileSystem {
     fn rename(&self, _from: &Path, _to: &Path) -> Result<(), FilesError> {
         Ok(())
     }
-    fn delete(&self, _path: &Path) -> io::Result<()> {
+    fn copy(&self, _from: &Path, _to: &Path) -> Result<(), FilesError> {
+        Ok(())
+    }
+    fn delete(&self, _path: &Path) -> Result<(), FilesError> {
         Ok(())
     }
     fn create_file(&self, _path: &Path) -> Result<(), FilesError> {
@@ -29,13 +36,38 @@ impl FileSystem for MockFileSystem {
 }
 
 pub fn mock_entries(count: usize) -> Vec<FileEntry> {
-    use std::path::PathBuf;
+    use std::time::{Duration, UNIX_EPOCH};
 
     (0..count)
-        .map(|i| FileEntry {
-            name: format!("file{}", i),
-            path: PathBuf::from(format!("/tmp/file{}", i)),
-            is_dir: false,
+        .map(|i| {
+            FileEntry::new(
+                format!("file{}", i),
+                PathBuf::from(format!("/tmp/file{}", i)),
+                EntryKind::File,
+            )
+            .with_metadata(EntryMetadata {
+                size: i as u64 * 1024,
+                modified: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000 + i as u64)),
+                mode: 0o644,
+                uid: 1000,
+                gid: 1000,
+                ..EntryMetadata::default()
+            })
         })
         .collect()
 }
+
+/// Creates a fresh, empty directory under the system temp dir.
+pub fn temp_dir(label: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let dir = std::env::temp_dir().join(format!(
+        "files-core-{}-{}-{}",
+        label,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
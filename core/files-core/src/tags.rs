@@ -0,0 +1,84 @@
+//! User tags, kept the way desktop tools such as Dolphin keep them: as a
+//! comma-separated list in the `user.xdg.tags` extended attribute.
+
+use std::ffi::OsStr;
+use std::path::Path;
+
+use crate::{errors::FilesError, filesystem::FileSystem};
+
+/// The extended attribute holding an entry's tags.
+pub const TAGS: &str = "user.xdg.tags";
+
+/// The extended attribute holding a free-text comment.
+pub const COMMENT: &str = "user.comment";
+
+/// The tags in an attribute value: trimmed, without empty ones or
+/// repeats, in the order they were given.
+pub fn parse(value: &[u8]) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+
+    for tag in String::from_utf8_lossy(value).split(',') {
+        let tag = tag.trim();
+        if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+
+    tags
+}
+
+/// The attribute value holding `tags`.
+pub fn format(tags: &[String]) -> Vec<u8> {
+    tags.join(",").into_bytes()
+}
+
+/// Checks that `tag` can be stored: it must not be blank or contain a
+/// comma. Returns it trimmed.
+pub fn validate(tag: &str) -> Result<&str, FilesError> {
+    let trimmed = tag.trim();
+    if trimmed.is_empty() || trimmed.contains(',') {
+        return Err(FilesError::InvalidTag(tag.to_string()));
+    }
+    Ok(trimmed)
+}
+
+/// Reads the tags of `path`, without following a final link.
+pub fn read<F: FileSystem + ?Sized>(fs: &F, path: &Path) -> Result<Vec<String>, FilesError> {
+    Ok(fs
+        .get_xattr(path, OsStr::new(TAGS))?
+        .map(|value| parse(&value))
+        .unwrap_or_default())
+}
+
+/// Replaces the tags of `path`, without following a final link. No tags
+/// removes the attribute.
+pub fn write<F: FileSystem + ?Sized>(
+    fs: &F,
+    path: &Path,
+    tags: &[String],
+) -> Result<(), FilesError> {
+    if tags.is_empty() {
+        fs.remove_xattr(path, OsStr::new(TAGS))
+    } else {
+        fs.set_xattr(path, OsStr::new(TAGS), &format(tags))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_split_on_commas() {
+        assert_eq!(parse(b"draft, final,,draft ,x"), ["draft", "final", "x"]);
+        assert!(parse(b"").is_empty());
+        assert_eq!(format(&parse(b" a ,b")), b"a,b");
+    }
+
+    #[test]
+    fn tags_must_be_storable() {
+        assert_eq!(validate(" draft ").unwrap(), "draft");
+        assert!(validate("  ").is_err());
+        assert!(validate("a,b").is_err());
+    }
+}
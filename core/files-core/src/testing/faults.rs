@@ -0,0 +1,400 @@
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use crate::{
+    errors::FilesError,
+    filesystem::{Chunks, FileSystem, TRASH, rename_or_copy},
+    models::FileEntry,
+};
+
+/// A [`FileSystem`] method, as seen by [`Faulty`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Call {
+    ReadDirectory,
+    ReadEntry,
+    Rename,
+    Copy,
+    CopyMetadata,
+    SetPermissions,
+    SetOwner,
+    SetTimes,
+    Move,
+    Delete,
+    CreateFile,
+    CreateDir,
+    CreateSymlink,
+    CreateHardlink,
+    ListXattrs,
+    GetXattr,
+    SetXattr,
+    RemoveXattr,
+    Canonicalize,
+    Extract,
+    Trash,
+    Restore,
+    EmptyTrash,
+}
+
+/// Which calls [`Faulty`] fails, and with what error.
+///
+/// A fault matches every call until narrowed down with [`on`](Self::on),
+/// [`on_path`](Self::on_path) and [`nth`](Self::nth).
+#[derive(Debug, Clone)]
+pub struct Fault {
+    call: Option<Call>,
+    pattern: Option<String>,
+    nth: Option<usize>,
+    kind: io::ErrorKind,
+}
+
+impl Fault {
+    /// Fails matching calls with `FilesError::Io` of the given kind.
+    pub fn new(kind: io::ErrorKind) -> Self {
+        Self {
+            call: None,
+            pattern: None,
+            nth: None,
+            kind,
+        }
+    }
+
+    /// Only matches calls to `call`.
+    pub fn on(mut self, call: Call) -> Self {
+        self.call = Some(call);
+        self
+    }
+
+    /// Only matches calls with a path matching `pattern`, where `*` stands
+    /// for any run of characters (including `/`) and `?` for one.
+    ///
+    /// For two-path calls such as `rename`, either path may match.
+    pub fn on_path(mut self, pattern: &str) -> Self {
+        self.pattern = Some(pattern.to_string());
+        self
+    }
+
+    /// Only fails the `n`th matching call, counting from 1.
+    pub fn nth(mut self, n: usize) -> Self {
+        self.nth = Some(n);
+        self
+    }
+
+    fn matches(&self, call: Call, paths: &[&Path]) -> bool {
+        self.call.is_none_or(|c| c == call)
+            && self.pattern.as_deref().is_none_or(|pattern| {
+                paths
+                    .iter()
+                    .any(|path| glob_match(pattern, &path.to_string_lossy()))
+            })
+    }
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it was tried at.
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+            }
+            Some(&c) if c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    /// Matching calls seen so far, per fault.
+    seen: Vec<usize>,
+    injected: Vec<(Call, PathBuf)>,
+}
+
+/// Wraps a filesystem and fails chosen calls before they reach it.
+///
+/// Faults are checked in the order they were added; the first one that
+/// fires wins. Calls that are not failed pass through unchanged.
+#[derive(Debug)]
+pub struct Faulty<F: FileSystem> {
+    inner: F,
+    faults: Vec<Fault>,
+    counters: Mutex<Counters>,
+}
+
+impl<F: FileSystem> Faulty<F> {
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            faults: Vec::new(),
+            counters: Mutex::new(Counters::default()),
+        }
+    }
+
+    pub fn with_fault(mut self, fault: Fault) -> Self {
+        self.faults.push(fault);
+        self.counters().seen.push(0);
+        self
+    }
+
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    /// The calls that were failed so far, with their first path.
+    pub fn injected(&self) -> Vec<(Call, PathBuf)> {
+        self.counters().injected.clone()
+    }
+
+    fn counters(&self) -> MutexGuard<'_, Counters> {
+        self.counters.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    fn check(&self, call: Call, paths: &[&Path]) -> Result<(), FilesError> {
+        let mut counters = self.counters();
+
+        for (i, fault) in self.faults.iter().enumerate() {
+            if !fault.matches(call, paths) {
+                continue;
+            }
+
+            counters.seen[i] += 1;
+
+            if fault.nth.is_none_or(|n| n == counters.seen[i]) {
+                let path = paths.first().map(|p| p.to_path_buf()).unwrap_or_default();
+                counters.injected.push((call, path));
+
+                return Err(FilesError::Io(io::Error::new(fault.kind, "injected fault")));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: FileSystem> FileSystem for Faulty<F> {
+    fn read_directory(&self, path: &Path) -> Result<Vec<FileEntry>, FilesError> {
+        self.check(Call::ReadDirectory, &[path])?;
+        self.inner.read_directory(path)
+    }
+
+    fn read_directory_chunks(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<Chunks<'_>, FilesError> {
+        self.check(Call::ReadDirectory, &[path])?;
+        self.inner.read_directory_chunks(path, chunk_size)
+    }
+
+    fn read_entry(&self, path: &Path) -> Result<FileEntry, FilesError> {
+        self.check(Call::ReadEntry, &[path])?;
+        self.inner.read_entry(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        self.check(Call::Rename, &[from, to])?;
+        self.inner.rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        self.check(Call::Copy, &[from, to])?;
+        self.inner.copy(from, to)
+    }
+
+    fn copy_metadata(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        self.check(Call::CopyMetadata, &[from, to])?;
+        self.inner.copy_metadata(from, to)
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> Result<(), FilesError> {
+        self.check(Call::SetPermissions, &[path])?;
+        self.inner.set_permissions(path, mode)
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<(), FilesError> {
+        self.check(Call::SetOwner, &[path])?;
+        self.inner.set_owner(path, uid, gid)
+    }
+
+    fn set_times(
+        &self,
+        path: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> Result<(), FilesError> {
+        self.check(Call::SetTimes, &[path])?;
+        self.inner.set_times(path, accessed, modified)
+    }
+
+    fn move_to(&self, from: &Path, to: &Path) -> Result<(), FilesError> {
+        self.check(Call::Move, &[from, to])?;
+        // Through this wrapper, so faults in each step apply.
+        rename_or_copy(self, from, to)
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), FilesError> {
+        self.check(Call::Delete, &[path])?;
+        self.inner.delete(path)
+    }
+
+    fn create_file(&self, path: &Path) -> Result<(), FilesError> {
+        self.check(Call::CreateFile, &[path])?;
+        self.inner.create_file(path)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), FilesError> {
+        self.check(Call::CreateDir, &[path])?;
+        self.inner.create_dir(path)
+    }
+
+    fn create_symlink(&self, target: &Path, link: &Path) -> Result<(), FilesError> {
+        self.check(Call::CreateSymlink, &[target, link])?;
+        self.inner.create_symlink(target, link)
+    }
+
+    fn create_hardlink(&self, original: &Path, link: &Path) -> Result<(), FilesError> {
+        self.check(Call::CreateHardlink, &[original, link])?;
+        self.inner.create_hardlink(original, link)
+    }
+
+    fn list_xattrs(&self, path: &Path) -> Result<Vec<OsString>, FilesError> {
+        self.check(Call::ListXattrs, &[path])?;
+        self.inner.list_xattrs(path)
+    }
+
+    fn get_xattr(&self, path: &Path, name: &OsStr) -> Result<Option<Vec<u8>>, FilesError> {
+        self.check(Call::GetXattr, &[path])?;
+        self.inner.get_xattr(path, name)
+    }
+
+    fn set_xattr(&self, path: &Path, name: &OsStr, value: &[u8]) -> Result<(), FilesError> {
+        self.check(Call::SetXattr, &[path])?;
+        self.inner.set_xattr(path, name, value)
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &OsStr) -> Result<(), FilesError> {
+        self.check(Call::RemoveXattr, &[path])?;
+        self.inner.remove_xattr(path, name)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        self.check(Call::Canonicalize, &[path])?;
+        self.inner.canonicalize(path)
+    }
+
+    fn root(&self) -> Option<&Path> {
+        self.inner.root()
+    }
+
+    fn can_enter(&self, entry: &FileEntry) -> bool {
+        self.inner.can_enter(entry)
+    }
+
+    fn extract(&self, path: &Path, destination: &Path) -> Result<(), FilesError> {
+        self.check(Call::Extract, &[path, destination])?;
+        self.inner.extract(path, destination)
+    }
+
+    fn trash(&self, path: &Path) -> Result<(), FilesError> {
+        self.check(Call::Trash, &[path])?;
+        self.inner.trash(path)
+    }
+
+    fn restore(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        self.check(Call::Restore, &[path])?;
+        self.inner.restore(path)
+    }
+
+    fn empty_trash(&self) -> Result<(), FilesError> {
+        self.check(Call::EmptyTrash, &[Path::new(TRASH)])?;
+        self.inner.empty_trash()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::memory::MemoryFileSystem;
+
+    fn memory() -> MemoryFileSystem {
+        let fs = MemoryFileSystem::new();
+        fs.write_file(Path::new("/work/a.txt"), "a").unwrap();
+        fs.write_file(Path::new("/work/b.log"), "b").unwrap();
+        fs
+    }
+
+    fn kind<T>(result: Result<T, FilesError>) -> Option<io::ErrorKind> {
+        match result {
+            Err(FilesError::Io(err)) => Some(err.kind()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn fails_only_the_nth_matching_call() {
+        let fs = Faulty::new(memory()).with_fault(
+            Fault::new(io::ErrorKind::TimedOut)
+                .on(Call::ReadDirectory)
+                .nth(2),
+        );
+        let work = Path::new("/work");
+
+        assert!(fs.read_directory(work).is_ok());
+        assert_eq!(kind(fs.read_directory(work)), Some(io::ErrorKind::TimedOut));
+        assert!(fs.read_directory(work).is_ok());
+        assert_eq!(fs.injected(), [(Call::ReadDirectory, work.to_path_buf())]);
+    }
+
+    #[test]
+    fn fails_by_path_pattern() {
+        let fs = Faulty::new(memory())
+            .with_fault(Fault::new(io::ErrorKind::PermissionDenied).on_path("/work/*.log"));
+
+        assert_eq!(
+            kind(fs.delete(Path::new("/work/b.log"))),
+            Some(io::ErrorKind::PermissionDenied)
+        );
+        assert_eq!(
+            kind(fs.rename(Path::new("/work/a.txt"), Path::new("/work/a.log"))),
+            Some(io::ErrorKind::PermissionDenied)
+        );
+        fs.delete(Path::new("/work/a.txt")).unwrap();
+
+        assert!(fs.inner().exists(Path::new("/work/b.log")));
+    }
+
+    #[test]
+    fn glob_matches_wildcards() {
+        assert!(glob_match("*", "/any/path"));
+        assert!(glob_match("/work/*.txt", "/work/sub/a.txt"));
+        assert!(glob_match("/work/?.txt", "/work/a.txt"));
+        assert!(!glob_match("/work/?.txt", "/work/ab.txt"));
+        assert!(!glob_match("/work/*.txt", "/work/a.log"));
+    }
+}
@@ -0,0 +1,973 @@
+//! Conformance checks and fault injection for [`FileSystem`] implementations.
+//!
+//! Every backend should behave like [`RealFileSystem`] on Linux: the same
+//! results, the same error variants, the same edge cases. Run the whole
+//! suite from a test in your own crate:
+//!
+//! ```ignore
+//! #[test]
+//! fn my_backend_conforms() {
+//!     let root = files_core::testing::TempRoot::new("my-backend");
+//!     files_core::testing::run(&MyFileSystem::new(), root.path());
+//! }
+//! ```
+//!
+//! Each check works in a fresh subdirectory of `root`, which must be an
+//! existing, empty, writable directory in the backend's namespace.
+//!
+//! [`Faulty`] wraps a backend to make chosen calls fail, for testing error
+//! paths.
+//!
+//! [`RealFileSystem`]: crate::filesystem::RealFileSystem
+
+use std::ffi::OsStr;
+use std::fmt::Debug;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::{errors::FilesError, filesystem::FileSystem, models::EntryKind, tags};
+
+pub use faults::{Call, Fault, Faulty};
+
+mod faults;
+
+/// A single named conformance check.
+pub struct Check {
+    pub name: &'static str,
+    pub run: fn(&dyn FileSystem, &Path) -> Result<(), String>,
+}
+
+/// Every check in the suite.
+pub const CHECKS: &[Check] = &[
+    Check {
+        name: "read_directory_lists_entries",
+        run: read_directory_lists_entries,
+    },
+    Check {
+        name: "read_directory_empty",
+        run: read_directory_empty,
+    },
+    Check {
+        name: "read_directory_missing_is_invalid_path",
+        run: read_directory_missing_is_invalid_path,
+    },
+    Check {
+        name: "read_directory_on_file_is_invalid_path",
+        run: read_directory_on_file_is_invalid_path,
+    },
+    Check {
+        name: "read_entry_matches_listing",
+        run: read_entry_matches_listing,
+    },
+    Check {
+        name: "read_entry_missing_fails",
+        run: read_entry_missing_fails,
+    },
+    Check {
+        name: "create_file_creates_empty_file",
+        run: create_file_creates_empty_file,
+    },
+    Check {
+        name: "create_file_on_existing_file_fails",
+        run: create_file_on_existing_file_fails,
+    },
+    Check {
+        name: "create_file_on_directory_fails",
+        run: create_file_on_directory_fails,
+    },
+    Check {
+        name: "create_file_in_missing_directory_fails",
+        run: create_file_in_missing_directory_fails,
+    },
+    Check {
+        name: "create_dir_creates_directory",
+        run: create_dir_creates_directory,
+    },
+    Check {
+        name: "create_dir_on_existing_path_fails",
+        run: create_dir_on_existing_path_fails,
+    },
+    Check {
+        name: "create_dir_in_missing_directory_fails",
+        run: create_dir_in_missing_directory_fails,
+    },
+    Check {
+        name: "rename_moves_file",
+        run: rename_moves_file,
+    },
+    Check {
+        name: "rename_moves_directory_with_contents",
+        run: rename_moves_directory_with_contents,
+    },
+    Check {
+        name: "rename_missing_source_fails",
+        run: rename_missing_source_fails,
+    },
+    Check {
+        name: "rename_into_missing_directory_fails",
+        run: rename_into_missing_directory_fails,
+    },
+    Check {
+        name: "rename_onto_existing_file_replaces_it",
+        run: rename_onto_existing_file_replaces_it,
+    },
+    Check {
+        name: "rename_file_onto_directory_fails",
+        run: rename_file_onto_directory_fails,
+    },
+    Check {
+        name: "rename_directory_onto_non_empty_directory_fails",
+        run: rename_directory_onto_non_empty_directory_fails,
+    },
+    Check {
+        name: "rename_directory_into_itself_fails",
+        run: rename_directory_into_itself_fails,
+    },
+    Check {
+        name: "copy_duplicates_file",
+        run: copy_duplicates_file,
+    },
+    Check {
+        name: "copy_duplicates_directory_tree",
+        run: copy_duplicates_directory_tree,
+    },
+    Check {
+        name: "copy_missing_source_fails",
+        run: copy_missing_source_fails,
+    },
+    Check {
+        name: "copy_onto_itself_fails",
+        run: copy_onto_itself_fails,
+    },
+    Check {
+        name: "copy_file_onto_directory_fails",
+        run: copy_file_onto_directory_fails,
+    },
+    Check {
+        name: "copy_directory_onto_non_empty_directory_fails",
+        run: copy_directory_onto_non_empty_directory_fails,
+    },
+    Check {
+        name: "move_moves_directory_with_contents",
+        run: move_moves_directory_with_contents,
+    },
+    Check {
+        name: "delete_removes_file",
+        run: delete_removes_file,
+    },
+    Check {
+        name: "delete_removes_directory_tree",
+        run: delete_removes_directory_tree,
+    },
+    Check {
+        name: "delete_missing_path_fails",
+        run: delete_missing_path_fails,
+    },
+    Check {
+        name: "set_permissions_changes_mode",
+        run: set_permissions_changes_mode,
+    },
+    Check {
+        name: "set_permissions_missing_path_fails",
+        run: set_permissions_missing_path_fails,
+    },
+    Check {
+        name: "set_times_changes_modification_time",
+        run: set_times_changes_modification_time,
+    },
+    Check {
+        name: "create_symlink_stores_target",
+        run: create_symlink_stores_target,
+    },
+    Check {
+        name: "create_symlink_on_existing_path_fails",
+        run: create_symlink_on_existing_path_fails,
+    },
+    Check {
+        name: "create_hardlink_shares_file",
+        run: create_hardlink_shares_file,
+    },
+    Check {
+        name: "create_hardlink_on_directory_fails",
+        run: create_hardlink_on_directory_fails,
+    },
+    Check {
+        name: "xattrs_round_trip",
+        run: xattrs_round_trip,
+    },
+    Check {
+        name: "xattrs_of_missing_path_fail",
+        run: xattrs_of_missing_path_fail,
+    },
+    Check {
+        name: "tags_show_up_in_entries",
+        run: tags_show_up_in_entries,
+    },
+];
+
+/// Runs every check against `fs`, panicking with a report of all failures.
+pub fn run<F: FileSystem>(fs: &F, root: &Path) {
+    let failures = run_checks(fs, root, CHECKS);
+
+    if !failures.is_empty() {
+        let report: Vec<String> = failures
+            .iter()
+            .map(|(name, message)| format!("  {}: {}", name, message))
+            .collect();
+
+        panic!(
+            "{} of {} conformance checks failed:\n{}",
+            failures.len(),
+            CHECKS.len(),
+            report.join("\n")
+        );
+    }
+}
+
+/// Runs the given checks and returns `(name, message)` for each failure.
+pub fn run_checks<F: FileSystem>(
+    fs: &F,
+    root: &Path,
+    checks: &[Check],
+) -> Vec<(&'static str, String)> {
+    let mut failures = Vec::new();
+
+    for check in checks {
+        let dir = root.join(check.name);
+
+        let outcome = fs
+            .create_dir(&dir)
+            .map_err(|err| format!("could not create check directory: {}", err))
+            .and_then(|()| (check.run)(fs, &dir));
+
+        if let Err(message) = outcome {
+            failures.push((check.name, message));
+        }
+    }
+
+    failures
+}
+
+/// A unique directory under the system temp dir, removed on drop.
+pub struct TempRoot {
+    path: PathBuf,
+}
+
+impl TempRoot {
+    pub fn new(label: &str) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "files-conformance-{}-{}-{}",
+            label,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("temp root can be created");
+
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempRoot {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+// ========================
+// ASSERTION HELPERS
+// ========================
+
+fn ok<T, E: Debug>(result: Result<T, E>, what: &str) -> Result<T, String> {
+    result.map_err(|err| format!("{} failed: {:?}", what, err))
+}
+
+fn expect_io_error<T: Debug>(
+    result: Result<T, FilesError>,
+    expected: io::ErrorKind,
+    what: &str,
+) -> Result<(), String> {
+    match result {
+        Err(FilesError::Io(err)) if err.kind() == expected => Ok(()),
+        other => Err(format!(
+            "{}: expected Io({:?}), got {:?}",
+            what, expected, other
+        )),
+    }
+}
+
+fn names(fs: &dyn FileSystem, dir: &Path) -> Result<Vec<String>, String> {
+    let mut names: Vec<String> = ok(fs.read_directory(dir), "read_directory")?
+        .into_iter()
+        .map(|e| e.name.to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+fn expect_names(fs: &dyn FileSystem, dir: &Path, expected: &[&str]) -> Result<(), String> {
+    let actual = names(fs, dir)?;
+
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!(
+            "listing of {}: expected {:?}, got {:?}",
+            dir.display(),
+            expected,
+            actual
+        ))
+    }
+}
+
+fn entry_size(fs: &dyn FileSystem, dir: &Path, name: &str) -> Result<u64, String> {
+    ok(fs.read_directory(dir), "read_directory")?
+        .into_iter()
+        .find(|e| e.name == name)
+        .map(|e| e.metadata.size)
+        .ok_or_else(|| format!("{} missing from {}", name, dir.display()))
+}
+
+// ========================
+// READ DIRECTORY
+// ========================
+
+fn read_directory_lists_entries(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    ok(fs.create_file(&dir.join("file.txt")), "create_file")?;
+    ok(fs.create_dir(&dir.join("sub")), "create_dir")?;
+
+    let entries = ok(fs.read_directory(dir), "read_directory")?;
+    if entries.len() != 2 {
+        return Err(format!("expected 2 entries, got {:?}", entries));
+    }
+
+    for (name, kind) in [("file.txt", EntryKind::File), ("sub", EntryKind::Directory)] {
+        let entry = entries
+            .iter()
+            .find(|e| e.name == name)
+            .ok_or_else(|| format!("{} missing from listing", name))?;
+
+        if entry.kind != kind {
+            return Err(format!(
+                "{}: expected {:?}, got {:?}",
+                name, kind, entry.kind
+            ));
+        }
+        if entry.path != dir.join(name) {
+            return Err(format!(
+                "{}: expected path {}, got {}",
+                name,
+                dir.join(name).display(),
+                entry.path.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn read_directory_empty(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    expect_names(fs, dir, &[])
+}
+
+fn read_directory_missing_is_invalid_path(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    match fs.read_directory(&dir.join("missing")) {
+        Err(FilesError::InvalidPath) => Ok(()),
+        other => Err(format!("expected InvalidPath, got {:?}", other)),
+    }
+}
+
+fn read_directory_on_file_is_invalid_path(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    let file = dir.join("file.txt");
+    ok(fs.create_file(&file), "create_file")?;
+
+    match fs.read_directory(&file) {
+        Err(FilesError::InvalidPath) => Ok(()),
+        other => Err(format!("expected InvalidPath, got {:?}", other)),
+    }
+}
+
+fn read_entry_matches_listing(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    ok(fs.create_dir(&dir.join("sub")), "create_dir")?;
+    let path = dir.join("sub");
+
+    let entry = ok(fs.read_entry(&path), "read_entry")?;
+    if entry.name != "sub" || entry.path != path || entry.kind != EntryKind::Directory {
+        return Err(format!("expected the directory sub, got {:?}", entry));
+    }
+
+    Ok(())
+}
+
+fn read_entry_missing_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    expect_io_error(
+        fs.read_entry(&dir.join("missing")),
+        io::ErrorKind::NotFound,
+        "read_entry",
+    )
+}
+
+// ========================
+// CREATE FILE
+// ========================
+
+fn create_file_creates_empty_file(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    ok(fs.create_file(&dir.join("new.txt")), "create_file")?;
+
+    expect_names(fs, dir, &["new.txt"])?;
+
+    match entry_size(fs, dir, "new.txt")? {
+        0 => Ok(()),
+        size => Err(format!("expected an empty file, got {} bytes", size)),
+    }
+}
+
+fn create_file_on_existing_file_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    let file = dir.join("file.txt");
+    ok(fs.create_file(&file), "create_file")?;
+
+    expect_io_error(
+        fs.create_file(&file),
+        io::ErrorKind::AlreadyExists,
+        "create_file on an existing file",
+    )?;
+    expect_names(fs, dir, &["file.txt"])
+}
+
+fn create_file_on_directory_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    let sub = dir.join("sub");
+    ok(fs.create_dir(&sub), "create_dir")?;
+
+    expect_io_error(
+        fs.create_file(&sub),
+        io::ErrorKind::IsADirectory,
+        "create_file on a directory",
+    )
+}
+
+fn create_file_in_missing_directory_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    expect_io_error(
+        fs.create_file(&dir.join("missing").join("new.txt")),
+        io::ErrorKind::NotFound,
+        "create_file in a missing directory",
+    )
+}
+
+// ========================
+// CREATE DIR
+// ========================
+
+fn create_dir_creates_directory(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    let sub = dir.join("sub");
+    ok(fs.create_dir(&sub), "create_dir")?;
+
+    expect_names(fs, dir, &["sub"])?;
+    expect_names(fs, &sub, &[])
+}
+
+fn create_dir_on_existing_path_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    let sub = dir.join("sub");
+    let file = dir.join("file.txt");
+    ok(fs.create_dir(&sub), "create_dir")?;
+    ok(fs.create_file(&file), "create_file")?;
+
+    expect_io_error(
+        fs.create_dir(&sub),
+        io::ErrorKind::AlreadyExists,
+        "create_dir on an existing directory",
+    )?;
+    expect_io_error(
+        fs.create_dir(&file),
+        io::ErrorKind::AlreadyExists,
+        "create_dir on an existing file",
+    )
+}
+
+fn create_dir_in_missing_directory_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    expect_io_error(
+        fs.create_dir(&dir.join("missing").join("sub")),
+        io::ErrorKind::NotFound,
+        "create_dir in a missing directory",
+    )
+}
+
+// ========================
+// RENAME
+// ========================
+
+fn rename_moves_file(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    ok(fs.create_file(&dir.join("old.txt")), "create_file")?;
+    ok(
+        fs.rename(&dir.join("old.txt"), &dir.join("new.txt")),
+        "rename",
+    )?;
+
+    expect_names(fs, dir, &["new.txt"])
+}
+
+fn rename_moves_directory_with_contents(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    ok(fs.create_dir(&dir.join("a")), "create_dir")?;
+    ok(
+        fs.create_file(&dir.join("a").join("inner.txt")),
+        "create_file",
+    )?;
+    ok(fs.create_dir(&dir.join("b")), "create_dir")?;
+
+    ok(
+        fs.rename(&dir.join("a"), &dir.join("b").join("moved")),
+        "rename",
+    )?;
+
+    expect_names(fs, dir, &["b"])?;
+    expect_names(fs, &dir.join("b").join("moved"), &["inner.txt"])
+}
+
+fn rename_missing_source_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    expect_io_error(
+        fs.rename(&dir.join("missing"), &dir.join("new")),
+        io::ErrorKind::NotFound,
+        "rename of a missing source",
+    )
+}
+
+fn rename_into_missing_directory_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    ok(fs.create_file(&dir.join("file.txt")), "create_file")?;
+
+    expect_io_error(
+        fs.rename(&dir.join("file.txt"), &dir.join("missing").join("file.txt")),
+        io::ErrorKind::NotFound,
+        "rename into a missing directory",
+    )?;
+    expect_names(fs, dir, &["file.txt"])
+}
+
+fn rename_onto_existing_file_replaces_it(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    ok(fs.create_file(&dir.join("a.txt")), "create_file")?;
+    ok(fs.create_file(&dir.join("b.txt")), "create_file")?;
+
+    ok(fs.rename(&dir.join("a.txt"), &dir.join("b.txt")), "rename")?;
+
+    expect_names(fs, dir, &["b.txt"])
+}
+
+fn rename_file_onto_directory_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    ok(fs.create_file(&dir.join("file.txt")), "create_file")?;
+    ok(fs.create_dir(&dir.join("sub")), "create_dir")?;
+
+    expect_io_error(
+        fs.rename(&dir.join("file.txt"), &dir.join("sub")),
+        io::ErrorKind::IsADirectory,
+        "rename of a file onto a directory",
+    )
+}
+
+fn rename_directory_onto_non_empty_directory_fails(
+    fs: &dyn FileSystem,
+    dir: &Path,
+) -> Result<(), String> {
+    ok(fs.create_dir(&dir.join("a")), "create_dir")?;
+    ok(fs.create_dir(&dir.join("b")), "create_dir")?;
+    ok(
+        fs.create_file(&dir.join("b").join("inner.txt")),
+        "create_file",
+    )?;
+
+    expect_io_error(
+        fs.rename(&dir.join("a"), &dir.join("b")),
+        io::ErrorKind::DirectoryNotEmpty,
+        "rename of a directory onto a non-empty directory",
+    )
+}
+
+fn rename_directory_into_itself_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    ok(fs.create_dir(&dir.join("a")), "create_dir")?;
+
+    expect_io_error(
+        fs.rename(&dir.join("a"), &dir.join("a").join("inner")),
+        io::ErrorKind::InvalidInput,
+        "rename of a directory into itself",
+    )
+}
+
+// ========================
+// COPY AND MOVE
+// ========================
+
+fn copy_duplicates_file(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    ok(fs.create_file(&dir.join("a.txt")), "create_file")?;
+    ok(fs.copy(&dir.join("a.txt"), &dir.join("b.txt")), "copy")?;
+
+    expect_names(fs, dir, &["a.txt", "b.txt"])
+}
+
+fn copy_duplicates_directory_tree(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    let a = dir.join("a");
+    ok(fs.create_dir(&a), "create_dir")?;
+    ok(fs.create_dir(&a.join("nested")), "create_dir")?;
+    ok(
+        fs.create_file(&a.join("nested").join("file.txt")),
+        "create_file",
+    )?;
+
+    ok(fs.copy(&a, &dir.join("b")), "copy")?;
+
+    expect_names(fs, dir, &["a", "b"])?;
+    expect_names(fs, &a.join("nested"), &["file.txt"])?;
+    expect_names(fs, &dir.join("b").join("nested"), &["file.txt"])
+}
+
+fn copy_missing_source_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    expect_io_error(
+        fs.copy(&dir.join("missing"), &dir.join("new")),
+        io::ErrorKind::NotFound,
+        "copy of a missing source",
+    )
+}
+
+fn copy_onto_itself_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    ok(fs.create_file(&dir.join("file.txt")), "create_file")?;
+    ok(fs.create_dir(&dir.join("a")), "create_dir")?;
+
+    expect_io_error(
+        fs.copy(&dir.join("file.txt"), &dir.join("file.txt")),
+        io::ErrorKind::InvalidInput,
+        "copy of a file onto itself",
+    )?;
+    expect_io_error(
+        fs.copy(&dir.join("a"), &dir.join("a").join("inner")),
+        io::ErrorKind::InvalidInput,
+        "copy of a directory into itself",
+    )?;
+    expect_names(fs, &dir.join("a"), &[])
+}
+
+fn copy_file_onto_directory_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    ok(fs.create_file(&dir.join("file.txt")), "create_file")?;
+    ok(fs.create_dir(&dir.join("sub")), "create_dir")?;
+
+    expect_io_error(
+        fs.copy(&dir.join("file.txt"), &dir.join("sub")),
+        io::ErrorKind::IsADirectory,
+        "copy of a file onto a directory",
+    )
+}
+
+fn copy_directory_onto_non_empty_directory_fails(
+    fs: &dyn FileSystem,
+    dir: &Path,
+) -> Result<(), String> {
+    ok(fs.create_dir(&dir.join("a")), "create_dir")?;
+    ok(fs.create_dir(&dir.join("b")), "create_dir")?;
+    ok(
+        fs.create_file(&dir.join("b").join("inner.txt")),
+        "create_file",
+    )?;
+
+    expect_io_error(
+        fs.copy(&dir.join("a"), &dir.join("b")),
+        io::ErrorKind::DirectoryNotEmpty,
+        "copy of a directory onto a non-empty directory",
+    )
+}
+
+fn move_moves_directory_with_contents(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    ok(fs.create_dir(&dir.join("a")), "create_dir")?;
+    ok(
+        fs.create_file(&dir.join("a").join("inner.txt")),
+        "create_file",
+    )?;
+    ok(fs.create_dir(&dir.join("b")), "create_dir")?;
+
+    ok(
+        fs.move_to(&dir.join("a"), &dir.join("b").join("moved")),
+        "move_to",
+    )?;
+
+    expect_names(fs, dir, &["b"])?;
+    expect_names(fs, &dir.join("b").join("moved"), &["inner.txt"])
+}
+
+// ========================
+// DELETE
+// ========================
+
+fn delete_removes_file(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    ok(fs.create_file(&dir.join("file.txt")), "create_file")?;
+    ok(fs.delete(&dir.join("file.txt")), "delete")?;
+
+    expect_names(fs, dir, &[])
+}
+
+fn delete_removes_directory_tree(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    let sub = dir.join("sub");
+    ok(fs.create_dir(&sub), "create_dir")?;
+    ok(fs.create_dir(&sub.join("nested")), "create_dir")?;
+    ok(
+        fs.create_file(&sub.join("nested").join("file.txt")),
+        "create_file",
+    )?;
+
+    ok(fs.delete(&sub), "delete")?;
+
+    expect_names(fs, dir, &[])
+}
+
+fn delete_missing_path_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    expect_io_error(
+        fs.delete(&dir.join("missing")),
+        io::ErrorKind::NotFound,
+        "delete of a missing path",
+    )
+}
+
+// ========================
+// PERMISSIONS
+// ========================
+
+/// Backends that keep no modes may return `Unsupported` instead.
+fn set_permissions_changes_mode(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    let path = dir.join("file.txt");
+    ok(fs.create_file(&path), "create_file")?;
+
+    match fs.set_permissions(&path, 0o640) {
+        Err(FilesError::Unsupported) => return Ok(()),
+        result => ok(result, "set_permissions")?,
+    }
+
+    let mode = ok(fs.read_entry(&path), "read_entry")?.metadata.mode;
+    if mode == 0o640 {
+        Ok(())
+    } else {
+        Err(format!("expected mode 0640, got {:04o}", mode))
+    }
+}
+
+fn set_permissions_missing_path_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    match fs.set_permissions(&dir.join("missing"), 0o640) {
+        Err(FilesError::Unsupported) => Ok(()),
+        result => expect_io_error(result, io::ErrorKind::NotFound, "set_permissions"),
+    }
+}
+
+// ========================
+// TIMES
+// ========================
+
+/// Backends that keep no times may return `Unsupported` instead. Times
+/// are compared to the second, the precision SFTP keeps.
+fn set_times_changes_modification_time(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    let path = dir.join("file.txt");
+    ok(fs.create_file(&path), "create_file")?;
+    let time = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+
+    match fs.set_times(&path, None, Some(time)) {
+        Err(FilesError::Unsupported) => return Ok(()),
+        result => ok(result, "set_times")?,
+    }
+
+    let modified = ok(fs.read_entry(&path), "read_entry")?.metadata.modified;
+    if modified == Some(time) {
+        Ok(())
+    } else {
+        Err(format!("expected modified {:?}, got {:?}", time, modified))
+    }
+}
+
+// ========================
+// LINKS
+// ========================
+
+/// Backends without links may return `Unsupported` instead, here and in
+/// the checks below.
+fn create_symlink_stores_target(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    ok(fs.create_file(&dir.join("file.txt")), "create_file")?;
+    let path = dir.join("link");
+
+    match fs.create_symlink(Path::new("file.txt"), &path) {
+        Err(FilesError::Unsupported) => return Ok(()),
+        result => ok(result, "create_symlink")?,
+    }
+
+    let entry = ok(fs.read_entry(&path), "read_entry")?;
+    match &entry.link {
+        Some(link)
+            if entry.kind == EntryKind::Symlink
+                && link.target == Path::new("file.txt")
+                && link.target_kind == Some(EntryKind::File) =>
+        {
+            Ok(())
+        }
+        _ => Err(format!("expected a link to file.txt, got {:?}", entry)),
+    }
+}
+
+fn create_symlink_on_existing_path_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    let path = dir.join("file.txt");
+    ok(fs.create_file(&path), "create_file")?;
+
+    match fs.create_symlink(Path::new("missing"), &path) {
+        Err(FilesError::Unsupported) => Ok(()),
+        result => expect_io_error(result, io::ErrorKind::AlreadyExists, "create_symlink"),
+    }
+}
+
+fn create_hardlink_shares_file(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    let original = dir.join("file.txt");
+    ok(fs.create_file(&original), "create_file")?;
+
+    match fs.create_hardlink(&original, &dir.join("link.txt")) {
+        Err(FilesError::Unsupported) => return Ok(()),
+        result => ok(result, "create_hardlink")?,
+    }
+    expect_names(fs, dir, &["file.txt", "link.txt"])?;
+
+    // The other name keeps the file alive.
+    ok(fs.delete(&original), "delete")?;
+    let entry = ok(fs.read_entry(&dir.join("link.txt")), "read_entry")?;
+    if entry.kind == EntryKind::File {
+        Ok(())
+    } else {
+        Err(format!("expected a file, got {:?}", entry.kind))
+    }
+}
+
+fn create_hardlink_on_directory_fails(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    let original = dir.join("sub");
+    ok(fs.create_dir(&original), "create_dir")?;
+
+    match fs.create_hardlink(&original, &dir.join("link")) {
+        Err(FilesError::Unsupported) => Ok(()),
+        result => expect_io_error(result, io::ErrorKind::PermissionDenied, "create_hardlink"),
+    }
+}
+
+// ========================
+// EXTENDED ATTRIBUTES
+// ========================
+
+/// Whether `result` says the backend, or the filesystem under it, keeps no
+/// extended attributes. Such backends pass the checks below.
+fn no_xattrs<T>(result: &Result<T, FilesError>) -> bool {
+    match result {
+        Err(FilesError::Unsupported) => true,
+        Err(FilesError::Io(err)) => err.kind() == io::ErrorKind::Unsupported,
+        _ => false,
+    }
+}
+
+fn xattrs_round_trip(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    let path = dir.join("file.txt");
+    ok(fs.create_file(&path), "create_file")?;
+    let name = OsStr::new("user.conformance");
+
+    let set = fs.set_xattr(&path, name, b"value");
+    if no_xattrs(&set) {
+        return Ok(());
+    }
+    ok(set, "set_xattr")?;
+
+    let value = ok(fs.get_xattr(&path, name), "get_xattr")?;
+    if value.as_deref() != Some(&b"value"[..]) {
+        return Err(format!("expected Some(b\"value\"), got {:?}", value));
+    }
+    let names = ok(fs.list_xattrs(&path), "list_xattrs")?;
+    if !names.iter().any(|n| n == name) {
+        return Err(format!("{:?} missing from {:?}", name, names));
+    }
+
+    ok(fs.remove_xattr(&path, name), "remove_xattr")?;
+    ok(
+        fs.remove_xattr(&path, name),
+        "remove_xattr of a removed attribute",
+    )?;
+    match ok(fs.get_xattr(&path, name), "get_xattr")? {
+        None => Ok(()),
+        value => Err(format!("expected None after removal, got {:?}", value)),
+    }
+}
+
+fn xattrs_of_missing_path_fail(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    let path = dir.join("missing");
+    let name = OsStr::new("user.conformance");
+
+    let get = fs.get_xattr(&path, name);
+    if no_xattrs(&get) {
+        return Ok(());
+    }
+    expect_io_error(get, io::ErrorKind::NotFound, "get_xattr")?;
+    expect_io_error(
+        fs.set_xattr(&path, name, b"value"),
+        io::ErrorKind::NotFound,
+        "set_xattr",
+    )
+}
+
+fn tags_show_up_in_entries(fs: &dyn FileSystem, dir: &Path) -> Result<(), String> {
+    let path = dir.join("file.txt");
+    ok(fs.create_file(&path), "create_file")?;
+
+    let tagged = tags::write(fs, &path, &["draft".to_string(), "final".to_string()]);
+    if no_xattrs(&tagged) {
+        return Ok(());
+    }
+    ok(tagged, "set_xattr")?;
+
+    let entry = ok(fs.read_entry(&path), "read_entry")?;
+    let listed = ok(fs.read_directory(dir), "read_directory")?;
+    for tags in [&entry.tags, &listed[0].tags] {
+        if tags != &["draft", "final"] {
+            return Err(format!("expected tags [draft, final], got {:?}", tags));
+        }
+    }
+
+    ok(tags::write(fs, &path, &[]), "remove_xattr")?;
+    let entry = ok(fs.read_entry(&path), "read_entry")?;
+    if entry.tags.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("expected no tags, got {:?}", entry.tags))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::filesystem::RealFileSystem;
+    use crate::memory::MemoryFileSystem;
+
+    #[test]
+    fn real_filesystem_conforms() {
+        let root = TempRoot::new("real");
+        run(&RealFileSystem, root.path());
+    }
+
+    #[test]
+    fn memory_filesystem_conforms() {
+        let fs = MemoryFileSystem::new();
+        let root = Path::new("/conformance");
+        fs.create_dir_all(root).unwrap();
+
+        run(&fs, root);
+    }
+
+    #[test]
+    fn failures_are_reported_by_name() {
+        use crate::state::test_utils::MockFileSystem;
+
+        let fs = MockFileSystem { entries: vec![] };
+
+        let failures = run_checks(&fs, Path::new("/mock"), &CHECKS[..1]);
+
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, "read_directory_lists_entries");
+    }
+}
@@ -0,0 +1,600 @@
+//! The freedesktop.org trash, as described by the XDG Trash specification.
+//!
+//! Files on the same device as the home trash (`$XDG_DATA_HOME/Trash`,
+//! usually `~/.local/share/Trash`) go there. Files on other mounts go to a
+//! trash at the top of their mount: `$topdir/.Trash/$uid` if the
+//! administrator set up a sticky `$topdir/.Trash`, or `$topdir/.Trash-$uid`
+//! otherwise. Nothing is ever copied across devices.
+//!
+//! Each trashed item is moved to `files/` in its trash directory, next to
+//! an `info/<name>.trashinfo` file recording where it came from and when
+//! it was deleted.
+
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+use crate::{errors::FilesError, filesystem::normalize, models::FileEntry};
+
+const INFO_SUFFIX: &str = ".trashinfo";
+
+/// The trash directories of the current user.
+#[derive(Debug, Clone)]
+pub struct Trash {
+    home: PathBuf,
+    /// Whether trash directories at the top of other mounts are used.
+    mounts: bool,
+}
+
+impl Trash {
+    /// The user's home trash, plus one per mount as the spec lays out.
+    ///
+    /// Fails with [`FilesError::Unsupported`] when neither `XDG_DATA_HOME`
+    /// nor `HOME` says where the home trash is.
+    pub fn new() -> Result<Self, FilesError> {
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .filter(|dir| Path::new(dir).is_absolute())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+            .ok_or(FilesError::Unsupported)?;
+
+        Ok(Self {
+            home: data_home.join("Trash"),
+            mounts: true,
+        })
+    }
+
+    /// Only the trash directory at `home`, ignoring other mounts; trashing
+    /// a file on another device fails.
+    pub fn at(home: impl Into<PathBuf>) -> Self {
+        Self {
+            home: home.into(),
+            mounts: false,
+        }
+    }
+
+    /// Moves `path` to the trash. Links are trashed, not their targets.
+    pub fn trash(&self, path: &Path) -> Result<(), FilesError> {
+        let path = normalize(&std::path::absolute(path)?);
+        let metadata = fs::symlink_metadata(&path)?;
+        let name = path.file_name().ok_or(FilesError::InvalidPath)?;
+
+        if self.directories().iter().any(|dir| path.starts_with(dir)) {
+            return Err(FilesError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "already in the trash",
+            )));
+        }
+
+        let (dir, topdir) = self.directory_for(&path, &metadata)?;
+        for sub in ["files", "info"] {
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir.join(sub))?;
+        }
+
+        // Per-mount trashes record paths relative to the mount, so they
+        // stay valid when it is mounted elsewhere.
+        let original = match &topdir {
+            Some(topdir) => path.strip_prefix(topdir).unwrap_or(&path),
+            None => &path,
+        };
+
+        let (stored, mut info) = reserve(&dir, name)?;
+        let info_path = info_path(&dir, &stored);
+        let written = write!(
+            info,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode(original.as_os_str()),
+            chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+        )
+        .and_then(|()| info.sync_all())
+        .and_then(|()| fs::rename(&path, dir.join("files").join(&stored)));
+
+        if let Err(err) = written {
+            let _ = fs::remove_file(&info_path);
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+
+    /// Lists every trashed item.
+    ///
+    /// Entries are named after the original file and point at the item's
+    /// place inside the trash, where it can be browsed, restored with
+    /// [`restore`](Self::restore) or deleted for good.
+    pub fn list(&self) -> Result<Vec<FileEntry>, FilesError> {
+        let mut entries = Vec::new();
+
+        for dir in self.directories() {
+            let Ok(infos) = fs::read_dir(dir.join("info")) else {
+                continue;
+            };
+
+            for info in infos {
+                let info = info?.path();
+                let Some(stored) = stored_name(&info) else {
+                    continue;
+                };
+
+                // Info files without their item are leftovers; skip them.
+                let Ok(mut entry) = crate::fs::read_entry(&dir.join("files").join(&stored)) else {
+                    continue;
+                };
+                if let Some(name) =
+                    original_path(&info).and_then(|p| p.file_name().map(OsStr::to_os_string))
+                {
+                    entry.name = name;
+                }
+                entries.push(entry);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Moves a trashed item, as listed by [`list`](Self::list), back to
+    /// where it was deleted from and returns that path.
+    ///
+    /// Missing parent directories are recreated; an existing file at the
+    /// original path is never overwritten.
+    pub fn restore(&self, path: &Path) -> Result<PathBuf, FilesError> {
+        let (dir, stored) = self.locate(path).ok_or(FilesError::InvalidPath)?;
+        let info = info_path(&dir, &stored);
+
+        let original = original_path(&info).ok_or(FilesError::InvalidPath)?;
+        let original = if original.is_absolute() {
+            original
+        } else {
+            topdir(&dir).ok_or(FilesError::InvalidPath)?.join(original)
+        };
+
+        if fs::symlink_metadata(&original).is_ok() {
+            return Err(FilesError::Io(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", original.display()),
+            )));
+        }
+
+        if let Some(parent) = original.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(path, &original)?;
+        fs::remove_file(&info)?;
+
+        Ok(original)
+    }
+
+    /// Permanently deletes everything in every trash directory.
+    pub fn empty(&self) -> Result<(), FilesError> {
+        for dir in self.directories() {
+            if let Ok(files) = fs::read_dir(dir.join("files")) {
+                for file in files {
+                    crate::fs::operations::delete(&file?.path())?;
+                }
+            }
+            if let Ok(infos) = fs::read_dir(dir.join("info")) {
+                for info in infos {
+                    fs::remove_file(info?.path())?;
+                }
+            }
+            match fs::remove_file(dir.join("directorysizes")) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drops the record of a trashed item that was deleted for good.
+    ///
+    /// Does nothing for paths that are not items in the trash.
+    pub fn forget(&self, path: &Path) -> Result<(), FilesError> {
+        if let Some((dir, stored)) = self.locate(path) {
+            match fs::remove_file(info_path(&dir, &stored)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The trash directory and stored name of the trashed item at `path`.
+    fn locate(&self, path: &Path) -> Option<(PathBuf, OsString)> {
+        let path = normalize(path);
+        let files = path.parent()?;
+        let dir = files.parent()?;
+
+        if files.file_name()? != "files" || !self.directories().iter().any(|d| d == dir) {
+            return None;
+        }
+
+        Some((dir.to_path_buf(), path.file_name()?.to_os_string()))
+    }
+
+    /// The trash directories that exist, the home trash first.
+    fn directories(&self) -> Vec<PathBuf> {
+        let mut dirs = vec![self.home.clone()];
+
+        if self.mounts {
+            let uid = current_uid();
+            for mount in mount_points() {
+                for dir in [
+                    mount.join(".Trash").join(uid.to_string()),
+                    mount.join(format!(".Trash-{}", uid)),
+                ] {
+                    if dir.is_dir() && !dirs.contains(&dir) {
+                        dirs.push(dir);
+                    }
+                }
+            }
+        }
+
+        dirs
+    }
+
+    /// Picks the trash for `path`, with the mount it is relative to for
+    /// per-mount trashes.
+    fn directory_for(
+        &self,
+        path: &Path,
+        metadata: &fs::Metadata,
+    ) -> Result<(PathBuf, Option<PathBuf>), FilesError> {
+        let device = metadata.dev();
+
+        if existing_ancestor_device(&self.home) == Some(device) {
+            return Ok((self.home.clone(), None));
+        }
+        if !self.mounts {
+            return Err(FilesError::Io(io::Error::new(
+                io::ErrorKind::CrossesDevices,
+                "no trash on this device",
+            )));
+        }
+
+        let uid = current_uid();
+        let mut topdir = path;
+        while let Some(parent) = topdir.parent() {
+            match fs::metadata(parent) {
+                Ok(parent_metadata) if parent_metadata.dev() == device => topdir = parent,
+                _ => break,
+            }
+        }
+
+        // An administrator-created `.Trash` must be a real, sticky
+        // directory, or anyone could read what others throw away.
+        let admin = topdir.join(".Trash");
+        if let Ok(admin_metadata) = fs::symlink_metadata(&admin)
+            && admin_metadata.is_dir()
+            && admin_metadata.mode() & 0o1000 != 0
+        {
+            let dir = admin.join(uid.to_string());
+            if DirBuilder::new().mode(0o700).create(&dir).is_ok() || owned_dir(&dir, uid) {
+                return Ok((dir, Some(topdir.to_path_buf())));
+            }
+        }
+
+        let dir = topdir.join(format!(".Trash-{}", uid));
+        let _ = DirBuilder::new().mode(0o700).create(&dir);
+        if owned_dir(&dir, uid) {
+            return Ok((dir, Some(topdir.to_path_buf())));
+        }
+
+        Err(FilesError::Io(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("cannot use a trash directory in {}", topdir.display()),
+        )))
+    }
+}
+
+/// Claims a name in `dir` for `name`, numbering it on collisions, by
+/// creating its info file.
+/// Whether `path` is laid out like a trashed item: an entry of a `files`
+/// directory with an `info` sibling. Cheap enough to ask of every deletion,
+/// unlike finding the trash directories, which stats every mount.
+pub(crate) fn may_be_trashed(path: &Path) -> bool {
+    let path = normalize(path);
+    path.parent()
+        .filter(|files| files.file_name() == Some(OsStr::new("files")))
+        .and_then(Path::parent)
+        .is_some_and(|dir| dir.join("info").is_dir())
+}
+
+fn reserve(dir: &Path, name: &OsStr) -> Result<(OsString, fs::File), FilesError> {
+    for n in 1.. {
+        let candidate = if n == 1 {
+            name.to_os_string()
+        } else {
+            numbered(name, n)
+        };
+
+        if fs::symlink_metadata(dir.join("files").join(&candidate)).is_ok() {
+            continue;
+        }
+
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(info_path(dir, &candidate))
+        {
+            Ok(file) => return Ok((candidate, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    unreachable!("some numbered name is free")
+}
+
+/// `report.pdf` becomes `report.2.pdf`; `notes` becomes `notes.2`.
+fn numbered(name: &OsStr, n: usize) -> OsString {
+    let path = Path::new(name);
+    let mut numbered = path.file_stem().unwrap_or(name).to_os_string();
+    numbered.push(format!(".{}", n));
+    if let Some(extension) = path.extension() {
+        numbered.push(".");
+        numbered.push(extension);
+    }
+    numbered
+}
+
+fn info_path(dir: &Path, stored: &OsStr) -> PathBuf {
+    let mut name = stored.to_os_string();
+    name.push(INFO_SUFFIX);
+    dir.join("info").join(name)
+}
+
+fn stored_name(info: &Path) -> Option<OsString> {
+    let name = info.file_name()?.as_bytes();
+    let stored = name.strip_suffix(INFO_SUFFIX.as_bytes())?;
+    Some(OsString::from_vec(stored.to_vec()))
+}
+
+/// The `Path=` recorded in a `.trashinfo` file.
+fn original_path(info: &Path) -> Option<PathBuf> {
+    let text = fs::read_to_string(info).ok()?;
+    let mut in_section = false;
+
+    for line in text.lines() {
+        if line.starts_with('[') {
+            in_section = line == "[Trash Info]";
+        } else if in_section && let Some(value) = line.strip_prefix("Path=") {
+            return decode(value).map(PathBuf::from);
+        }
+    }
+
+    None
+}
+
+/// The mount a per-mount trash directory belongs to.
+fn topdir(dir: &Path) -> Option<&Path> {
+    let parent = dir.parent()?;
+    if parent.file_name()? == ".Trash" {
+        parent.parent()
+    } else {
+        Some(parent)
+    }
+}
+
+/// Percent-encodes a path for a `.trashinfo` file, keeping `/`.
+fn encode(path: &OsStr) -> String {
+    let mut encoded = String::new();
+
+    for &byte in path.as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
+fn decode(text: &str) -> Option<OsString> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = text.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    Some(OsString::from_vec(decoded))
+}
+
+fn existing_ancestor_device(path: &Path) -> Option<u64> {
+    path.ancestors()
+        .find_map(|ancestor| fs::metadata(ancestor).ok())
+        .map(|metadata| metadata.dev())
+}
+
+fn owned_dir(dir: &Path, uid: u32) -> bool {
+    fs::symlink_metadata(dir).is_ok_and(|metadata| metadata.is_dir() && metadata.uid() == uid)
+}
+
+fn current_uid() -> u32 {
+    // SAFETY: getuid(2) takes no arguments and always succeeds.
+    unsafe { libc::getuid() }
+}
+
+/// Mount points from `/proc/self/mounts`, if available.
+fn mount_points() -> Vec<PathBuf> {
+    let Ok(mounts) = fs::read_to_string("/proc/self/mounts") else {
+        return Vec::new();
+    };
+
+    mounts
+        .lines()
+        .filter_map(|line| line.split(' ').nth(1))
+        .map(|field| {
+            // Spaces and the like are written as octal escapes, e.g. `\040`.
+            let mut bytes = Vec::new();
+            let raw = field.as_bytes();
+            let mut i = 0;
+            while i < raw.len() {
+                let octal = field
+                    .get(i + 1..i + 4)
+                    .filter(|_| raw[i] == b'\\')
+                    .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+                match octal {
+                    Some(byte) => {
+                        bytes.push(byte);
+                        i += 4;
+                    }
+                    None => {
+                        bytes.push(raw[i]);
+                        i += 1;
+                    }
+                }
+            }
+            PathBuf::from(OsString::from_vec(bytes))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::state::test_utils::temp_dir;
+
+    fn setup(label: &str) -> (Trash, PathBuf) {
+        let dir = temp_dir(label);
+        fs::create_dir(dir.join("work")).unwrap();
+        (Trash::at(dir.join("Trash")), dir)
+    }
+
+    fn names(trash: &Trash) -> Vec<String> {
+        let mut names: Vec<_> = trash
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name.to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn trashes_and_restores() {
+        let (trash, dir) = setup("trash-round-trip");
+        let file = dir.join("work/my notes.txt");
+        fs::write(&file, "keep me").unwrap();
+
+        trash.trash(&file).unwrap();
+
+        assert!(!file.exists());
+        let info = fs::read_to_string(dir.join("Trash/info/my notes.txt.trashinfo")).unwrap();
+        assert!(info.starts_with("[Trash Info]\nPath="));
+        assert!(info.contains("/work/my%20notes.txt\nDeletionDate="));
+
+        let listed = trash.list().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, "my notes.txt");
+        assert_eq!(listed[0].path, dir.join("Trash/files/my notes.txt"));
+
+        assert_eq!(trash.restore(&listed[0].path).unwrap(), file);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "keep me");
+        assert!(trash.list().unwrap().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn numbers_clashing_names() {
+        let (trash, dir) = setup("trash-clash");
+        fs::create_dir(dir.join("work/sub")).unwrap();
+        fs::write(dir.join("work/a.txt"), "1").unwrap();
+        fs::write(dir.join("work/sub/a.txt"), "2").unwrap();
+
+        trash.trash(&dir.join("work/a.txt")).unwrap();
+        trash.trash(&dir.join("work/sub/a.txt")).unwrap();
+
+        assert!(dir.join("Trash/files/a.2.txt").exists());
+        assert_eq!(names(&trash), ["a.txt", "a.txt"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn restore_never_overwrites() {
+        let (trash, dir) = setup("trash-restore-clash");
+        let file = dir.join("work/a.txt");
+        fs::write(&file, "old").unwrap();
+        trash.trash(&file).unwrap();
+        fs::write(&file, "new").unwrap();
+
+        let stored = dir.join("Trash/files/a.txt");
+        assert!(matches!(
+            trash.restore(&stored),
+            Err(FilesError::Io(err)) if err.kind() == io::ErrorKind::AlreadyExists
+        ));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+        assert!(stored.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn empty_and_forget_drop_items() {
+        let (trash, dir) = setup("trash-empty");
+        fs::create_dir_all(dir.join("work/tree/nested")).unwrap();
+        fs::write(dir.join("work/a.txt"), "").unwrap();
+        trash.trash(&dir.join("work/tree")).unwrap();
+        trash.trash(&dir.join("work/a.txt")).unwrap();
+
+        let stored = dir.join("Trash/files/a.txt");
+        fs::remove_file(&stored).unwrap();
+        trash.forget(&stored).unwrap();
+        assert_eq!(names(&trash), ["tree"]);
+
+        trash.empty().unwrap();
+        assert!(trash.list().unwrap().is_empty());
+        assert_eq!(fs::read_dir(dir.join("Trash/files")).unwrap().count(), 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tells_trashed_items_by_their_layout() {
+        let (trash, dir) = setup("trash-layout");
+        fs::write(dir.join("work/a.txt"), "").unwrap();
+        trash.trash(&dir.join("work/a.txt")).unwrap();
+
+        assert!(may_be_trashed(&dir.join("Trash/files/a.txt")));
+        assert!(!may_be_trashed(&dir.join("Trash/info/a.txt.trashinfo")));
+        assert!(!may_be_trashed(&dir.join("work/a.txt")));
+
+        fs::create_dir(dir.join("work/files")).unwrap();
+        assert!(!may_be_trashed(&dir.join("work/files/b.txt")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_to_trash_the_trash() {
+        let (trash, dir) = setup("trash-itself");
+        fs::write(dir.join("work/a.txt"), "").unwrap();
+        trash.trash(&dir.join("work/a.txt")).unwrap();
+
+        assert!(trash.trash(&dir.join("Trash/files/a.txt")).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
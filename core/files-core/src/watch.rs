@@ -0,0 +1,498 @@
+//! Watching a directory for changes made outside the application.
+//!
+//! A [`Watcher`] follows one directory on a background thread and reports
+//! what changed in it as [`Changes`], ready for
+//! [`AppState::apply_changes`]. On Linux it is woken by inotify; on other
+//! systems, on network and FUSE mounts where inotify misses changes made
+//! elsewhere, and wherever inotify cannot be set up, it reads the
+//! directory again at an interval and compares.
+//!
+//! Bursts of events, such as a build writing many files, are debounced
+//! into a single batch.
+//!
+//! [`AppState::apply_changes`]: crate::state::AppState::apply_changes
+
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{errors::FilesError, filesystem::FileSystem, models::FileEntry, state::Changes};
+
+/// How often a polling watcher reads the directory by default.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long the directory has to stay quiet before a batch is sent.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// The longest a batch is held back while events keep coming.
+const MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// How often the thread checks whether its `Watcher` is gone.
+const TICK: Duration = Duration::from_millis(100);
+
+/// Watches one directory. The thread stops when the `Watcher` is dropped.
+pub struct Watcher {
+    directory: PathBuf,
+    changes: Receiver<Changes>,
+    stop: Arc<AtomicBool>,
+    polling: bool,
+}
+
+impl Watcher {
+    /// Watches `directory` with inotify where it can, and otherwise by
+    /// polling through `fs` every [`POLL_INTERVAL`].
+    ///
+    /// Either way, changed entries are read through `fs`, so wrappers such
+    /// as `Jailed` or `DryRun` see them like any other read. Only use this
+    /// for backends whose paths are local paths.
+    pub fn spawn<F>(fs: Arc<F>, directory: PathBuf) -> Self
+    where
+        F: FileSystem + Send + Sync + ?Sized + 'static,
+    {
+        #[cfg(target_os = "linux")]
+        if let Ok(inotify) = inotify::Inotify::watch(&directory) {
+            return Self::start(directory, false, move |directory, stop, changes| {
+                inotify::run(&*fs, inotify, directory, stop, changes)
+            });
+        }
+
+        Self::polling(fs, directory, POLL_INTERVAL)
+    }
+
+    /// Watches `directory` by listing it through `fs` every `interval`, for
+    /// backends that inotify cannot see into, such as remote ones.
+    pub fn polling<F>(fs: Arc<F>, directory: PathBuf, interval: Duration) -> Self
+    where
+        F: FileSystem + Send + Sync + ?Sized + 'static,
+    {
+        Self::start(directory, true, move |directory, stop, changes| {
+            poll(&*fs, directory, interval, stop, changes)
+        })
+    }
+
+    fn start(
+        directory: PathBuf,
+        polling: bool,
+        run: impl FnOnce(PathBuf, &AtomicBool, &Sender<Changes>) + Send + 'static,
+    ) -> Self {
+        let (outgoing, changes) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let flag = Arc::clone(&stop);
+        let watched = directory.clone();
+        thread::spawn(move || run(watched, &flag, &outgoing));
+
+        Self {
+            directory,
+            changes,
+            stop,
+            polling,
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Whether this watcher fell back to polling.
+    pub fn is_polling(&self) -> bool {
+        self.polling
+    }
+
+    /// Returns a batch of changes, if there is one, without blocking.
+    pub fn try_changes(&self) -> Option<Changes> {
+        self.changes.try_recv().ok()
+    }
+
+    /// Waits up to `timeout` for a batch of changes.
+    pub fn wait(&self, timeout: Duration) -> Option<Changes> {
+        self.changes.recv_timeout(timeout).ok()
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Reads the entries named in a batch of events, sorting them into
+/// updated and removed ones.
+fn read_changes<F: FileSystem + ?Sized>(
+    fs: &F,
+    directory: &Path,
+    names: BTreeSet<OsString>,
+) -> Changes {
+    let mut changes = Changes {
+        directory: directory.to_path_buf(),
+        ..Changes::default()
+    };
+
+    for name in names {
+        match fs.read_entry(&directory.join(&name)) {
+            Ok(entry) => changes.updated.push(entry),
+            Err(FilesError::Io(err)) if err.kind() == io::ErrorKind::NotFound => {
+                changes.removed.push(name)
+            }
+            // Still there, but unreadable for now; a later event will tell.
+            Err(_) => {}
+        }
+    }
+
+    changes
+}
+
+/// Lists `directory` every `interval` and sends what differs from the
+/// previous listing. Rounds where the directory cannot be read are
+/// skipped.
+fn poll<F: FileSystem + ?Sized>(
+    fs: &F,
+    directory: PathBuf,
+    interval: Duration,
+    stop: &AtomicBool,
+    changes: &Sender<Changes>,
+) {
+    let snapshot = |fs: &F| -> Option<HashMap<OsString, FileEntry>> {
+        let entries = fs.read_directory(&directory).ok()?;
+        Some(entries.into_iter().map(|e| (e.name.clone(), e)).collect())
+    };
+    let mut previous = snapshot(fs);
+
+    loop {
+        let started = Instant::now();
+        while started.elapsed() < interval {
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            thread::sleep(TICK.min(interval));
+        }
+
+        let Some(current) = snapshot(fs) else {
+            continue;
+        };
+        let Some(before) = previous.replace(current) else {
+            continue;
+        };
+        let current = previous.as_ref().expect("just replaced");
+
+        let batch = Changes {
+            directory: directory.clone(),
+            updated: current
+                .values()
+                .filter(|entry| {
+                    before
+                        .get(&entry.name)
+                        .is_none_or(|old| changed(old, entry))
+                })
+                .cloned()
+                .collect(),
+            removed: before
+                .into_keys()
+                .filter(|name| !current.contains_key(name))
+                .collect(),
+            complete: false,
+        };
+
+        if !batch.is_empty() && changes.send(batch).is_err() {
+            return;
+        }
+    }
+}
+
+/// Whether a polled entry differs from how it was listed before.
+fn changed(old: &FileEntry, new: &FileEntry) -> bool {
+    old.kind != new.kind
+        || old.metadata != new.metadata
+        || old.link.as_ref().map(|link| &link.target) != new.link.as_ref().map(|link| &link.target)
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::collections::BTreeSet;
+    use std::ffi::{CString, OsStr, OsString};
+    use std::fs::File;
+    use std::io::{self, Read};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::Sender;
+    use std::time::Instant;
+
+    use super::{DEBOUNCE, MAX_DELAY, TICK, read_changes};
+    use crate::{filesystem::FileSystem, state::Changes};
+
+    /// Filesystems where changes can come from other machines, which
+    /// inotify never hears about: NFS, SMB, CIFS, FUSE (sshfs among
+    /// others), Ceph, 9P and AFS.
+    const REMOTE: &[i64] = &[
+        0x6969,
+        0x517b,
+        0xff53_4d42,
+        0xfe53_4d42,
+        0x6573_5546,
+        0x00c3_6400,
+        0x0102_1997,
+        0x5346_414f,
+    ];
+
+    const EVENTS: u32 = libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MODIFY
+        | libc::IN_ATTRIB
+        | libc::IN_CLOSE_WRITE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO
+        | libc::IN_DELETE_SELF
+        | libc::IN_MOVE_SELF
+        | libc::IN_ONLYDIR
+        | libc::IN_EXCL_UNLINK;
+
+    /// The directory itself went away, or events were dropped.
+    const LOST: u32 =
+        libc::IN_DELETE_SELF | libc::IN_MOVE_SELF | libc::IN_IGNORED | libc::IN_Q_OVERFLOW;
+
+    /// An inotify instance watching one directory.
+    pub(super) struct Inotify(File);
+
+    impl Inotify {
+        /// Fails on remote filesystems as well as when inotify is
+        /// unavailable or out of watches.
+        pub(super) fn watch(directory: &Path) -> io::Result<Self> {
+            let path = CString::new(directory.as_os_str().as_bytes())?;
+
+            // SAFETY: statfs is plain data, and `path` is NUL-terminated.
+            let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+            if unsafe { libc::statfs(path.as_ptr(), &mut stats) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            #[allow(clippy::unnecessary_cast)]
+            if REMOTE.contains(&(stats.f_type as i64)) {
+                return Err(io::ErrorKind::Unsupported.into());
+            }
+
+            // SAFETY: a successful call returns a descriptor we now own.
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+            // SAFETY: `fd` is open and `path` is NUL-terminated.
+            if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), path.as_ptr(), EVENTS) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(Self(File::from(fd)))
+        }
+
+        /// Waits up to one tick for events to read.
+        fn ready(&self) -> io::Result<bool> {
+            let mut poll = libc::pollfd {
+                fd: self.0.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+
+            // SAFETY: `poll` points to exactly one pollfd.
+            match unsafe { libc::poll(&mut poll, 1, TICK.as_millis() as libc::c_int) } {
+                -1 => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        Ok(false)
+                    } else {
+                        Err(err)
+                    }
+                }
+                ready => Ok(ready > 0),
+            }
+        }
+
+        /// Reads the pending events, adding the names they concern to
+        /// `names`. Returns whether track of the directory was lost.
+        fn read(&mut self, names: &mut BTreeSet<OsString>) -> io::Result<bool> {
+            let mut buffer = [0; 16 * 1024];
+            let mut lost = false;
+
+            loop {
+                let len = match self.0.read(&mut buffer) {
+                    Ok(len) => len,
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(lost),
+                    Err(err) => return Err(err),
+                };
+
+                let mut offset = 0;
+                // Each event is a header of four 32-bit fields (wd, mask,
+                // cookie, len), then `len` bytes of NUL-padded name.
+                while offset + 16 <= len {
+                    let field = |i: usize| {
+                        u32::from_ne_bytes(buffer[offset + i..offset + i + 4].try_into().unwrap())
+                    };
+                    let (mask, name_len) = (field(4), field(12) as usize);
+                    let name = &buffer[offset + 16..(offset + 16 + name_len).min(len)];
+                    let name = name.split(|&byte| byte == 0).next().unwrap_or_default();
+
+                    lost |= mask & LOST != 0;
+                    if !name.is_empty() {
+                        names.insert(OsStr::from_bytes(name).to_os_string());
+                    }
+                    offset += 16 + name_len;
+                }
+            }
+        }
+    }
+
+    /// Collects events into batches, sending each once the directory has
+    /// been quiet for [`DEBOUNCE`], or [`MAX_DELAY`] after its first event.
+    ///
+    /// Once track of the directory is lost, the whole listing is sent, or
+    /// an empty one if the directory is gone, and the watch ends.
+    pub(super) fn run<F: FileSystem + ?Sized>(
+        fs: &F,
+        mut inotify: Inotify,
+        directory: PathBuf,
+        stop: &AtomicBool,
+        changes: &Sender<Changes>,
+    ) {
+        let mut names = BTreeSet::new();
+        let mut first: Option<Instant> = None;
+        let mut last = Instant::now();
+
+        while !stop.load(Ordering::Relaxed) {
+            let Ok(ready) = inotify.ready() else {
+                return;
+            };
+
+            if ready {
+                match inotify.read(&mut names) {
+                    Ok(false) => {}
+                    Ok(true) => {
+                        let listing = Changes {
+                            directory: directory.clone(),
+                            updated: fs.read_directory(&directory).unwrap_or_default(),
+                            complete: true,
+                            ..Changes::default()
+                        };
+                        let _ = changes.send(listing);
+                        return;
+                    }
+                    Err(_) => return,
+                }
+                last = Instant::now();
+                first.get_or_insert(last);
+            }
+
+            let due = first
+                .is_some_and(|first| last.elapsed() >= DEBOUNCE || first.elapsed() >= MAX_DELAY);
+            if due {
+                first = None;
+                let batch = read_changes(fs, &directory, std::mem::take(&mut names));
+                if !batch.is_empty() && changes.send(batch).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::filesystem::RealFileSystem;
+    use crate::memory::MemoryFileSystem;
+    use crate::state::test_utils::temp_dir;
+
+    /// Collects batches until `done` holds for everything seen so far.
+    fn collect(watcher: &Watcher, done: impl Fn(&[Changes]) -> bool) -> Vec<Changes> {
+        let mut seen = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        while !done(&seen) && Instant::now() < deadline {
+            seen.extend(watcher.wait(Duration::from_millis(100)));
+        }
+        seen
+    }
+
+    fn updated(seen: &[Changes]) -> Vec<String> {
+        seen.iter()
+            .flat_map(|changes| &changes.updated)
+            .map(|entry| entry.name.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn removed(seen: &[Changes]) -> Vec<String> {
+        seen.iter()
+            .flat_map(|changes| &changes.removed)
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn polling_reports_created_changed_and_removed_entries() {
+        let fs = Arc::new(MemoryFileSystem::new());
+        fs.write_file(Path::new("/work/kept"), "").unwrap();
+        fs.write_file(Path::new("/work/gone"), "").unwrap();
+        let watcher = Watcher::polling(
+            Arc::clone(&fs),
+            PathBuf::from("/work"),
+            Duration::from_millis(20),
+        );
+        assert!(watcher.is_polling());
+
+        // Let the first listing be taken before changing anything.
+        thread::sleep(Duration::from_millis(10));
+        fs.write_file(Path::new("/work/new"), "").unwrap();
+        fs.write_file(Path::new("/work/kept"), "grown").unwrap();
+        fs.delete(Path::new("/work/gone")).unwrap();
+
+        let seen = collect(&watcher, |seen| {
+            updated(seen).len() >= 2 && !removed(seen).is_empty()
+        });
+
+        let mut names = updated(&seen);
+        names.sort();
+        assert_eq!(names, ["kept", "new"]);
+        assert_eq!(removed(&seen), ["gone"]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn inotify_batches_a_burst_of_changes() {
+        let dir = temp_dir("watch");
+        std::fs::write(dir.join("old.txt"), "").unwrap();
+        let watcher = Watcher::spawn(Arc::new(RealFileSystem), dir.clone());
+        assert!(!watcher.is_polling());
+
+        for i in 0..20 {
+            std::fs::write(dir.join(format!("{}.txt", i)), "data").unwrap();
+        }
+        std::fs::remove_file(dir.join("old.txt")).unwrap();
+
+        let seen = collect(&watcher, |seen| {
+            updated(seen).len() >= 20 && !removed(seen).is_empty()
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(seen.len() < 20, "events were not batched: {}", seen.len());
+        assert_eq!(updated(&seen).len(), 20);
+        assert_eq!(removed(&seen), ["old.txt"]);
+        assert!(seen.iter().all(|changes| changes.directory == dir));
+    }
+
+    #[test]
+    fn missing_directories_fall_back_to_polling() {
+        let watcher = Watcher::spawn(
+            Arc::new(MemoryFileSystem::new()),
+            PathBuf::from("/no/such/directory"),
+        );
+
+        assert!(watcher.is_polling());
+    }
+}
@@ -1,19 +1,154 @@
-use crossterm::event::{KeyCode, KeyEvent};
-use files_core::filesystem::FileSystem;
-use files_core::state::{AppState, Command};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use files_core::archive::split_archive_path;
+use files_core::conflict::{Conflict, Policy};
+use files_core::errors::FilesError;
+use files_core::filesystem::{DryRun, FileSystem, TRASH};
+use files_core::jobs::{Job, Outcome, Task};
+use files_core::models::{FileEntry, escape_name, unescape_name};
+use files_core::permissions::ModeChange;
+use files_core::state::{AppState, Command, Loader, TouchTime, TouchTimes};
+use files_core::tags;
+use files_core::watch::Watcher;
+
+use crate::format;
+
+/// Handle on a dry-run session's recorded plan.
+pub type Plan = Arc<DryRun<Box<dyn FileSystem + Send + Sync>>>;
+
+/// Starts a job, resolving its conflicts with the given policy or by asking.
+pub type SpawnJob = Box<dyn Fn(Task, Option<Policy>) -> Job>;
+
+/// Starts watching a directory for outside changes.
+pub type SpawnWatcher = Box<dyn Fn(PathBuf) -> Watcher>;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InputKind {
     Rename,
     CreateFile,
     CreateDirectory,
+    /// Destination directory for extracting the selected archive entry.
+    Extract,
+    /// Destination path or directory for copying the selected entry.
+    Copy,
+    /// Destination path or directory for moving the selected entry.
+    Move,
+    /// Destination path or directory for a link to the selected entry; see
+    /// [`TuiApp::link_kind`].
+    Link,
+    /// When to touch the selected entry: now, a date and time, or the
+    /// times of a reference file.
+    Touch,
+    /// Comma-separated tags for the selected entry.
+    Tags,
+    /// The tag to narrow the listing to; empty lists everything.
+    TagFilter,
+}
+
+/// What the link prompt creates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    /// A symbolic link storing the path from its own directory.
+    Relative,
+    /// A symbolic link storing the absolute path.
+    Absolute,
+    Hard,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     Normal,
     Input(InputKind),
+    ConfirmTrash,
+    /// Permanent deletion, bypassing the trash.
     ConfirmDelete,
+    ConfirmEmptyTrash,
+    /// A destination already exists; see [`TuiApp::asking`].
+    ConfirmConflict,
+    /// Editing the mode of the selected entry; see [`TuiApp::permissions`].
+    Permissions,
+}
+
+/// The permissions dialog: a grid of mode bits to toggle, or a typed mode.
+#[derive(Debug, Clone)]
+pub struct PermissionsDialog {
+    /// The entry whose mode is edited: the selection, or its link target.
+    pub entry: FileEntry,
+    /// The mode as toggled on the grid.
+    pub mode: u32,
+    /// The grid cell under the cursor, as `(row, column)`.
+    pub cursor: (usize, usize),
+    /// Whether to change everything under a directory too.
+    pub recursive: bool,
+    /// An octal or symbolic mode, which takes over from the grid once typed.
+    pub expression: String,
+}
+
+impl PermissionsDialog {
+    /// Characters that make up octal and symbolic modes.
+    const EXPRESSION: &'static str = "01234567ugoa+-=,rwxXst";
+
+    pub fn new(entry: FileEntry) -> Self {
+        Self {
+            mode: entry.metadata.mode & 0o7777,
+            entry,
+            cursor: (0, 0),
+            recursive: false,
+            expression: String::new(),
+        }
+    }
+
+    /// The bit of a grid cell: read, write and execute for the user, group
+    /// and other rows, then setuid, setgid and sticky on the last row.
+    pub fn bit(row: usize, column: usize) -> u32 {
+        if row < 3 {
+            0o400 >> (row * 3 + column)
+        } else {
+            0o4000 >> column
+        }
+    }
+
+    /// The change to apply: the typed mode if any, otherwise the bits
+    /// toggled on the grid, so a recursive change leaves the other bits of
+    /// each entry alone.
+    pub fn change(&self) -> Result<ModeChange, FilesError> {
+        if self.expression.is_empty() {
+            Ok(ModeChange::between(self.entry.metadata.mode, self.mode))
+        } else {
+            self.expression.parse()
+        }
+    }
+
+    /// The mode the entry ends up with.
+    pub fn preview(&self) -> Result<u32, FilesError> {
+        let mode = self.entry.metadata.mode;
+        Ok(self.change()?.apply(mode, self.entry.is_dir()))
+    }
+
+    /// Toggles the bit under the cursor, starting from the typed mode if
+    /// there is a valid one.
+    fn toggle(&mut self) {
+        if let Ok(mode) = self.preview() {
+            self.mode = mode;
+        }
+        self.expression.clear();
+
+        let (row, column) = self.cursor;
+        self.mode ^= Self::bit(row, column);
+    }
+}
+
+/// What a "file exists" prompt is about.
+#[derive(Debug, Clone)]
+pub enum Asking {
+    /// A command that stopped at an existing path, to run again with the
+    /// answer.
+    Command(Command, PathBuf),
+    /// A conflict the running job is waiting on.
+    Job(Box<Conflict>),
 }
 
 pub struct TuiApp<F: FileSystem> {
@@ -21,6 +156,46 @@ pub struct TuiApp<F: FileSystem> {
     pub mode: Mode,
     pub input_buffer: String,
     pub cursor_position: usize,
+    /// Short labels for the session, e.g. `SANDBOX`, shown in the title.
+    pub indicators: Vec<&'static str>,
+    /// Disables the keys that would mutate the filesystem.
+    pub read_only: bool,
+    /// Feedback for the last key press, e.g. an error; cleared on the next.
+    pub message: Option<String>,
+    /// The plan being recorded, in dry-run sessions.
+    pub dry_run: Option<Plan>,
+    /// Shows the plan panel next to the file list.
+    pub show_plan: bool,
+    /// Reads directories in the background; navigation blocks without it.
+    pub loader: Option<Loader>,
+    /// Starts copies, moves and deletions in the background; they block
+    /// without it.
+    pub spawn_job: Option<SpawnJob>,
+    /// The copy, move or deletion running in the background, if any.
+    pub job: Option<Job>,
+    /// Watches each directory shown for outside changes; the listing only
+    /// changes on a refresh without it.
+    pub spawn_watcher: Option<SpawnWatcher>,
+    /// The watcher on the current directory.
+    pub watcher: Option<Watcher>,
+    /// How to resolve conflicts for the rest of the session, once the user
+    /// has chosen "apply to all"; until then they are asked about.
+    pub conflict_policy: Option<Policy>,
+    /// The conflict being asked about in [`Mode::ConfirmConflict`].
+    pub asking: Option<Asking>,
+    /// Whether the answer to the prompt applies to all later conflicts.
+    pub apply_to_all: bool,
+    /// The dialog open in [`Mode::Permissions`].
+    pub permissions: Option<PermissionsDialog>,
+    /// Which times the touch prompt sets.
+    pub touch_times: TouchTimes,
+    /// Whether the touch prompt applies to everything under a directory.
+    pub touch_recursive: bool,
+    /// What the link prompt creates.
+    pub link_kind: LinkKind,
+    /// Set when the input buffer holds an escaped non-UTF-8 name that must
+    /// be unescaped back to raw bytes on submit.
+    input_escaped: bool,
 }
 
 impl<F: FileSystem> TuiApp<F> {
@@ -30,6 +205,24 @@ impl<F: FileSystem> TuiApp<F> {
             mode: Mode::Normal,
             input_buffer: String::new(),
             cursor_position: 0,
+            indicators: Vec::new(),
+            read_only: false,
+            message: None,
+            dry_run: None,
+            show_plan: false,
+            loader: None,
+            spawn_job: None,
+            job: None,
+            spawn_watcher: None,
+            watcher: None,
+            conflict_policy: None,
+            asking: None,
+            apply_to_all: false,
+            permissions: None,
+            touch_times: TouchTimes::Both,
+            touch_recursive: false,
+            link_kind: LinkKind::Relative,
+            input_escaped: false,
         }
     }
 
@@ -39,26 +232,60 @@ impl<F: FileSystem> TuiApp<F> {
             // NORMAL MODE
             // ========================
             Mode::Normal => match key.code {
+                KeyCode::Char(
+                    'r' | 'n' | 'N' | 'd' | 'D' | 'u' | 'E' | 'x' | 'c' | 'm' | 'l' | 'P' | 'T'
+                    | '#',
+                ) if self.read_only => {
+                    self.message = Some("Read-only session: changes are disabled".into());
+                }
+
+                KeyCode::Char(
+                    'r' | 'n' | 'N' | 'd' | 'D' | 'u' | 'E' | 'x' | 'c' | 'm' | 'l' | 'P' | 'T'
+                    | '#' | 'C' | 'X',
+                ) if self.job.is_some() => {
+                    self.message = Some("Wait for the running job, or Esc to abort it".into());
+                }
+
                 KeyCode::Char('n') => {
                     self.input_buffer.clear();
+                    self.input_escaped = false;
                     self.cursor_position = 0;
                     self.mode = Mode::Input(InputKind::CreateFile)
                 }
 
                 KeyCode::Char('N') => {
                     self.input_buffer.clear();
+                    self.input_escaped = false;
                     self.cursor_position = 0;
                     self.mode = Mode::Input(InputKind::CreateDirectory);
                 }
                 KeyCode::Char('d') => {
+                    self.mode = Mode::ConfirmTrash;
+                }
+                KeyCode::Char('D') => {
                     self.mode = Mode::ConfirmDelete;
                 }
+                KeyCode::Char('E') => {
+                    self.mode = Mode::ConfirmEmptyTrash;
+                }
+                KeyCode::Char('u') if !self.in_trash() => {
+                    self.message = Some("Open the trash (t) to restore items".into());
+                }
+                KeyCode::Char('u') => {
+                    if let Some(original) = self.state.restore_selected()? {
+                        self.message = Some(match &self.dry_run {
+                            Some(_) => "Restore planned".into(),
+                            None => format!("Restored to {}", escape_name(original.as_os_str())),
+                        });
+                    }
+                }
                 KeyCode::Char('r') => {
                     if let Some(entry) = self.state.cursor() {
-                        self.input_buffer = entry.name.clone();
+                        self.input_buffer = entry.display_name().into_owned();
+                        self.input_escaped = entry.name.to_str().is_none();
 
                         // Place cursor before extension (if file)
-                        if entry.is_dir {
+                        if entry.is_dir() {
                             self.cursor_position = self.input_buffer.len();
                         } else {
                             self.cursor_position = match self.input_buffer.rfind('.') {
@@ -71,6 +298,101 @@ impl<F: FileSystem> TuiApp<F> {
                     }
                 }
 
+                KeyCode::Char('x') if self.state.cursor().is_some() => {
+                    // Default to the directory holding the archive.
+                    let current = self.state.current_directory();
+                    let destination = match split_archive_path(current) {
+                        Some((archive, _)) => archive.parent().unwrap_or(current).to_path_buf(),
+                        None => current.to_path_buf(),
+                    };
+
+                    self.input_buffer = escape_name(destination.as_os_str()).into_owned();
+                    self.input_escaped = destination.to_str().is_none();
+                    self.cursor_position = self.input_buffer.len();
+                    self.mode = Mode::Input(InputKind::Extract);
+                }
+
+                KeyCode::Char(key @ ('c' | 'm' | 'l')) if self.state.cursor().is_some() => {
+                    let current = self.state.current_directory();
+                    self.input_buffer = format!("{}/", escape_name(current.as_os_str()));
+                    self.input_escaped = current.to_str().is_none();
+                    self.cursor_position = self.input_buffer.len();
+                    self.link_kind = LinkKind::Relative;
+                    self.mode = Mode::Input(match key {
+                        'c' => InputKind::Copy,
+                        'm' => InputKind::Move,
+                        _ => InputKind::Link,
+                    });
+                }
+
+                KeyCode::Char('P') => {
+                    if let Some(entry) = self.state.selected_target()? {
+                        self.permissions = Some(PermissionsDialog::new(entry));
+                        self.mode = Mode::Permissions;
+                    }
+                }
+
+                KeyCode::Char('T') if self.state.cursor().is_some() => {
+                    self.input_buffer.clear();
+                    self.input_escaped = false;
+                    self.cursor_position = 0;
+                    self.touch_times = TouchTimes::Both;
+                    self.touch_recursive = false;
+                    self.mode = Mode::Input(InputKind::Touch);
+                }
+
+                KeyCode::Char('#') => {
+                    // Links are tagged through the entry they resolve to.
+                    if let Some(entry) = self.state.selected_target()? {
+                        self.input_buffer = entry.tags.join(", ");
+                        self.input_escaped = false;
+                        self.cursor_position = self.input_buffer.len();
+                        self.mode = Mode::Input(InputKind::Tags);
+                    }
+                }
+
+                KeyCode::Char('f') => {
+                    self.input_buffer = self.state.tag_filter().unwrap_or_default().to_string();
+                    self.input_escaped = false;
+                    self.cursor_position = self.input_buffer.len();
+                    self.mode = Mode::Input(InputKind::TagFilter);
+                }
+
+                KeyCode::Char('p' | 'C' | 'X') if self.dry_run.is_none() => {
+                    self.message = Some("Not a dry-run session (start with --dry-run)".into());
+                }
+
+                KeyCode::Char('p') => {
+                    self.show_plan = !self.show_plan;
+                }
+
+                KeyCode::Char('C') => {
+                    if let Some(plan) = &self.dry_run {
+                        let count = plan.plan().len();
+                        let outcome = plan.commit();
+                        // Show whatever did get applied, even on failure.
+                        self.state.handle_command(Command::Refresh)?;
+                        outcome?;
+                        self.message = Some(format!("Committed {} operation(s)", count));
+                    }
+                }
+
+                KeyCode::Char('X') => {
+                    if let Some(plan) = &self.dry_run {
+                        plan.discard();
+                        self.state.handle_command(Command::Refresh)?;
+                        self.message = Some("Discarded the plan".into());
+                    }
+                }
+
+                KeyCode::Esc => match &self.job {
+                    Some(job) => {
+                        job.cancel();
+                        self.message = Some("Aborting after the current entry...".into());
+                    }
+                    None => self.state.cancel_load(),
+                },
+
                 KeyCode::Down => {
                     self.state.handle_command(Command::MoveCursorDown)?;
                 }
@@ -80,27 +402,67 @@ impl<F: FileSystem> TuiApp<F> {
                 }
 
                 KeyCode::Enter => {
-                    self.state.handle_command(Command::Enter)?;
+                    self.navigate(Command::Enter)?;
                 }
 
                 KeyCode::Backspace => {
-                    self.state.handle_command(Command::GoUp)?;
+                    self.navigate(Command::GoUp)?;
+                }
+
+                KeyCode::Char('g') => {
+                    self.navigate(Command::FollowLink)?;
+                }
+
+                KeyCode::Char('t') => {
+                    self.navigate(Command::OpenTrash)?;
                 }
 
                 KeyCode::Char('R') => {
-                    self.state.handle_command(Command::Refresh)?;
+                    self.navigate(Command::Refresh)?;
                 }
 
                 _ => {}
             },
 
+            // ========================
+            // TRASH MODE
+            // ========================
+            Mode::ConfirmTrash => match key.code {
+                KeyCode::Char('y') => {
+                    self.mode = Mode::Normal;
+                    match self.state.handle_command(Command::Trash) {
+                        Err(FilesError::Unsupported) => {
+                            self.message =
+                                Some("No trash here; use D to delete permanently".into());
+                        }
+                        result => result?,
+                    }
+                }
+                KeyCode::Char('n') | KeyCode::Esc => {
+                    self.mode = Mode::Normal;
+                }
+                _ => {}
+            },
+
+            Mode::ConfirmEmptyTrash => match key.code {
+                KeyCode::Char('y') => {
+                    self.state.handle_command(Command::EmptyTrash)?;
+                    self.mode = Mode::Normal;
+                    self.message = Some("Emptied the trash".into());
+                }
+                KeyCode::Char('n') | KeyCode::Esc => {
+                    self.mode = Mode::Normal;
+                }
+                _ => {}
+            },
+
             // ========================
             // DELETE MODE
             // ========================
             Mode::ConfirmDelete => match key.code {
                 KeyCode::Char('y') => {
-                    self.state.handle_command(Command::Delete)?;
                     self.mode = Mode::Normal;
+                    self.start(Command::Delete)?;
                 }
                 KeyCode::Char('n') | KeyCode::Esc => {
                     self.mode = Mode::Normal;
@@ -108,10 +470,73 @@ impl<F: FileSystem> TuiApp<F> {
                 _ => {}
             },
 
+            // ========================
+            // CONFLICT MODE
+            // ========================
+            Mode::ConfirmConflict => match key.code {
+                KeyCode::Char('a') => {
+                    self.apply_to_all = !self.apply_to_all;
+                }
+                KeyCode::Char(key @ ('o' | 's' | 'k' | 'u')) => {
+                    let policy = match key {
+                        'o' => Policy::Overwrite,
+                        's' => Policy::Skip,
+                        'k' => Policy::KeepBoth,
+                        _ => Policy::OverwriteIfNewer,
+                    };
+                    self.mode = Mode::Normal;
+                    self.answer(policy)?;
+                }
+                KeyCode::Char('n') | KeyCode::Esc => {
+                    self.mode = Mode::Normal;
+                    self.apply_to_all = false;
+                    // A waiting job fails with the conflict, like a command.
+                    if let (Some(Asking::Job(_)), Some(job)) = (self.asking.take(), &self.job) {
+                        job.resolve(Policy::Fail, false);
+                    }
+                }
+                _ => {}
+            },
+
+            // ========================
+            // PERMISSIONS MODE
+            // ========================
+            Mode::Permissions => {
+                let Some(dialog) = &mut self.permissions else {
+                    self.mode = Mode::Normal;
+                    return Ok(());
+                };
+                let (row, column) = &mut dialog.cursor;
+
+                match key.code {
+                    KeyCode::Esc => {
+                        self.permissions = None;
+                        self.mode = Mode::Normal;
+                    }
+                    KeyCode::Enter => self.submit_permissions()?,
+                    KeyCode::Up | KeyCode::Char('k') => *row = row.saturating_sub(1),
+                    KeyCode::Down | KeyCode::Char('j') => *row = (*row + 1).min(3),
+                    KeyCode::Left | KeyCode::Char('h') => *column = column.saturating_sub(1),
+                    KeyCode::Right | KeyCode::Char('l') => *column = (*column + 1).min(2),
+                    KeyCode::Char(' ') => dialog.toggle(),
+                    KeyCode::Tab if dialog.entry.is_dir() => {
+                        dialog.recursive = !dialog.recursive;
+                    }
+                    KeyCode::Backspace => {
+                        dialog.expression.pop();
+                    }
+                    KeyCode::Char(c) if PermissionsDialog::EXPRESSION.contains(c) => {
+                        dialog.expression.push(c);
+                    }
+                    _ => {}
+                }
+            }
+
             Mode::Input(kind) => match key.code {
                 KeyCode::Esc => {
                     self.mode = Mode::Normal;
                     self.input_buffer.clear();
+                    self.input_escaped = false;
                     self.cursor_position = 0;
                 }
 
@@ -119,23 +544,40 @@ impl<F: FileSystem> TuiApp<F> {
                     self.submit_input(kind)?;
                 }
 
-                KeyCode::Left => {
-                    if self.cursor_position > 0 {
-                        self.cursor_position -= 1;
-                    }
+                KeyCode::Tab if kind == InputKind::Touch => {
+                    self.touch_times = match self.touch_times {
+                        TouchTimes::Both => TouchTimes::AccessOnly,
+                        TouchTimes::AccessOnly => TouchTimes::ModificationOnly,
+                        TouchTimes::ModificationOnly => TouchTimes::Both,
+                    };
                 }
 
-                KeyCode::Right => {
-                    if self.cursor_position < self.input_buffer.len() {
-                        self.cursor_position += 1;
-                    }
+                KeyCode::Tab if kind == InputKind::Link => {
+                    self.link_kind = match self.link_kind {
+                        LinkKind::Relative => LinkKind::Absolute,
+                        LinkKind::Absolute => LinkKind::Hard,
+                        LinkKind::Hard => LinkKind::Relative,
+                    };
                 }
 
-                KeyCode::Backspace => {
-                    if self.cursor_position > 0 {
-                        self.cursor_position -= 1;
-                        self.input_buffer.remove(self.cursor_position);
-                    }
+                KeyCode::Char('r')
+                    if kind == InputKind::Touch
+                        && key.modifiers.contains(KeyModifiers::CONTROL) =>
+                {
+                    self.touch_recursive = !self.touch_recursive;
+                }
+
+                KeyCode::Left if self.cursor_position > 0 => {
+                    self.cursor_position -= 1;
+                }
+
+                KeyCode::Right if self.cursor_position < self.input_buffer.len() => {
+                    self.cursor_position += 1;
+                }
+
+                KeyCode::Backspace if self.cursor_position > 0 => {
+                    self.cursor_position -= 1;
+                    self.input_buffer.remove(self.cursor_position);
                 }
 
                 KeyCode::Char(c) => {
@@ -150,31 +592,300 @@ impl<F: FileSystem> TuiApp<F> {
         Ok(())
     }
 
+    fn in_trash(&self) -> bool {
+        self.state.current_directory() == std::path::Path::new(TRASH)
+    }
+
+    /// Runs a navigation command, in the background when a loader is
+    /// attached.
+    fn navigate(&mut self, command: Command) -> Result<(), Box<dyn std::error::Error>> {
+        match &self.loader {
+            Some(loader) => {
+                if let Some(request) = self.state.request_load(&command) {
+                    loader.request(request);
+                }
+            }
+            None => self.state.handle_command(command)?,
+        }
+
+        Ok(())
+    }
+
+    /// Applies directory loads that finished in the background.
+    pub fn poll_loader(&mut self) {
+        let Some(loader) = &self.loader else {
+            return;
+        };
+
+        while let Some(result) = loader.try_result() {
+            if let Err(err) = self.state.apply_load(result) {
+                self.fail(err.into());
+                return;
+            }
+        }
+    }
+
+    /// Applies what the watcher saw change in the current directory, and
+    /// moves it along once navigation has settled on another directory.
+    pub fn poll_watcher(&mut self) {
+        let Some(spawn) = &self.spawn_watcher else {
+            return;
+        };
+        if self.state.loading().is_some() {
+            return;
+        }
+
+        let directory = self.state.current_directory();
+        if self
+            .watcher
+            .as_ref()
+            .is_none_or(|watcher| watcher.directory() != directory)
+        {
+            self.watcher = Some(spawn(directory.to_path_buf()));
+        }
+
+        if let Some(watcher) = &self.watcher {
+            while let Some(changes) = watcher.try_changes() {
+                self.state.apply_changes(changes);
+            }
+        }
+    }
+
+    /// Runs a command that may find its destination taken, with the
+    /// session's conflict policy or, without one, asking the user on a
+    /// conflict. Copies, moves and deletions run as background jobs when
+    /// jobs are enabled. Returns whether the command ran to completion in
+    /// place.
+    fn start(&mut self, command: Command) -> Result<bool, Box<dyn std::error::Error>> {
+        if let Some(spawn) = &self.spawn_job
+            && let Some(task) = self.state.job_for(&command)
+        {
+            self.job = Some(spawn(task, self.conflict_policy));
+            return Ok(false);
+        }
+
+        let policy = self.conflict_policy.unwrap_or_default();
+        match self.state.handle_command_with(command.clone(), policy) {
+            Err(FilesError::AlreadyExists(path)) if self.conflict_policy.is_none() => {
+                self.asking = Some(Asking::Command(command, path));
+                self.mode = Mode::ConfirmConflict;
+                Ok(false)
+            }
+            result => {
+                result?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Resolves the conflict being asked about with `policy`.
+    fn answer(&mut self, policy: Policy) -> Result<(), Box<dyn std::error::Error>> {
+        let for_all = std::mem::take(&mut self.apply_to_all);
+        if for_all {
+            self.conflict_policy = Some(policy);
+        }
+
+        match self.asking.take() {
+            Some(Asking::Command(command, _)) => {
+                self.state.handle_command_with(command, policy)?;
+            }
+            Some(Asking::Job(_)) => {
+                if let Some(job) = &self.job {
+                    job.resolve(policy, for_all);
+                }
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    /// Reports a job that has ended, with how far it got, and reloads the
+    /// listing.
+    ///
+    /// A conflict the job is waiting on is asked about once no other
+    /// prompt is open.
+    pub fn poll_job(&mut self) {
+        if self.mode == Mode::Normal
+            && let Some(conflict) = self.job.as_ref().and_then(Job::conflict)
+        {
+            self.asking = Some(Asking::Job(Box::new(conflict)));
+            self.mode = Mode::ConfirmConflict;
+            return;
+        }
+
+        let Some(outcome) = self.job.as_ref().and_then(Job::try_finish) else {
+            return;
+        };
+        let job = self.job.take().expect("polled a running job");
+        let progress = job.progress();
+        let (_, done) = format::job_verbs(job.task());
+
+        let finished = matches!(outcome, Ok(Outcome::Finished));
+        let partial = format!(
+            "{} {} of {} file(s)",
+            done.to_lowercase(),
+            progress.files_done,
+            progress.files_total
+        );
+        let mut report = match outcome {
+            Ok(Outcome::Finished) if progress.bytes_done > 0 => format!(
+                "{} {} file(s) ({})",
+                done,
+                progress.files_done - progress.files_skipped,
+                format::human_size(progress.bytes_done)
+            ),
+            Ok(Outcome::Finished) => format!(
+                "{} {} file(s)",
+                done,
+                progress.files_done - progress.files_skipped
+            ),
+            Ok(Outcome::Cancelled) => format!("Aborted: {}", partial),
+            Err(err) => format!("{} ({})", err, partial),
+        };
+        if progress.files_skipped > 0 {
+            report.push_str(&format!(", skipped {}", progress.files_skipped));
+        }
+
+        match self.state.apply_job(job.task(), &progress, finished) {
+            Ok(()) => self.message = Some(report),
+            Err(err) => self.message = Some(format!("{}; {}", report, err)),
+        }
+    }
+
+    /// Reports a failed action and returns to normal mode.
+    pub fn fail(&mut self, err: Box<dyn std::error::Error>) {
+        self.message = Some(err.to_string());
+        self.mode = Mode::Normal;
+        self.input_buffer.clear();
+        self.input_escaped = false;
+        self.cursor_position = 0;
+    }
+
+    fn submit_permissions(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(dialog) = &self.permissions else {
+            return Ok(());
+        };
+        let change = dialog.change()?;
+        let (mode, recursive) = (dialog.preview()?, dialog.recursive);
+
+        self.permissions = None;
+        self.mode = Mode::Normal;
+        if change.is_empty() {
+            return Ok(());
+        }
+
+        self.state.handle_command(Command::SetPermissions {
+            mode: change,
+            recursive,
+        })?;
+        self.message = Some(match (&self.dry_run, recursive) {
+            (Some(_), _) => "Permission change planned".into(),
+            (None, true) => "Changed permissions recursively".into(),
+            (None, false) => format!("Mode set to {:04o}", mode),
+        });
+        Ok(())
+    }
+
     fn submit_input(&mut self, kind: InputKind) -> Result<(), Box<dyn std::error::Error>> {
-        if self.input_buffer.trim().is_empty() {
+        // An empty touch means now; empty tags or filter clear them.
+        if self.input_buffer.trim().is_empty()
+            && !matches!(
+                kind,
+                InputKind::Touch | InputKind::Tags | InputKind::TagFilter
+            )
+        {
             return Ok(());
         }
 
+        let name = if self.input_escaped {
+            unescape_name(&self.input_buffer)
+        } else {
+            OsString::from(&self.input_buffer)
+        };
+
+        // Back to normal mode first: the command may open a prompt.
+        self.input_buffer.clear();
+        self.input_escaped = false;
+        self.cursor_position = 0;
+        self.mode = Mode::Normal;
+
         match kind {
             InputKind::Rename => {
-                self.state
-                    .handle_command(Command::Rename(self.input_buffer.clone()))?;
+                self.start(Command::Rename(name))?;
             }
 
             InputKind::CreateFile => {
-                self.state
-                    .handle_command(Command::CreateFile(self.input_buffer.clone()))?;
+                self.start(Command::CreateFile(name))?;
             }
 
             InputKind::CreateDirectory => {
+                self.start(Command::CreateDirectory(name))?;
+            }
+
+            InputKind::Extract => {
                 self.state
-                    .handle_command(Command::CreateDirectory(self.input_buffer.clone()))?;
+                    .handle_command(Command::Extract(PathBuf::from(name)))?;
+                self.message = Some("Extracted".into());
             }
-        }
 
-        self.input_buffer.clear();
-        self.cursor_position = 0;
-        self.mode = Mode::Normal;
+            InputKind::Copy => {
+                if self.start(Command::Copy(PathBuf::from(name)))? {
+                    self.message = Some("Copied".into());
+                }
+            }
+
+            InputKind::Move => {
+                if self.start(Command::Move(PathBuf::from(name)))? {
+                    self.message = Some("Moved".into());
+                }
+            }
+
+            InputKind::Link => {
+                let destination = PathBuf::from(name);
+                let command = match self.link_kind {
+                    LinkKind::Hard => Command::CreateHardlink(destination),
+                    kind => Command::CreateSymlink {
+                        destination,
+                        relative: kind == LinkKind::Relative,
+                    },
+                };
+                if self.start(command)? {
+                    self.message = Some("Linked".into());
+                }
+            }
+
+            InputKind::Touch => {
+                let text = name.to_string_lossy();
+                let time = if text.trim().is_empty() {
+                    TouchTime::Now
+                } else if let Some(time) = format::parse_timestamp(&text) {
+                    TouchTime::At(time)
+                } else {
+                    TouchTime::Reference(PathBuf::from(name))
+                };
+
+                self.state.handle_command(Command::Touch {
+                    time,
+                    times: self.touch_times,
+                    recursive: self.touch_recursive,
+                })?;
+                self.message = Some("Touched".into());
+            }
+
+            InputKind::Tags => {
+                let tags = tags::parse(name.as_encoded_bytes());
+                self.state.handle_command(Command::SetTags(tags))?;
+                self.message = Some("Tagged".into());
+            }
+
+            InputKind::TagFilter => {
+                let text = name.to_string_lossy();
+                let tag = Some(text.trim()).filter(|tag| !tag.is_empty());
+                self.state
+                    .handle_command(Command::FilterTag(tag.map(str::to_string)))?;
+            }
+        }
 
         Ok(())
     }
@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+
+use files_core::{s3::S3Url, sftp::SftpUrl};
+
+pub const USAGE: &str = "\
+Usage: files-tui [OPTIONS] [DIRECTORY]
+       files-tui [OPTIONS] sftp://[USER@]HOST[:PORT][/DIRECTORY]
+       files-tui [OPTIONS] s3://BUCKET[/PREFIX]
+
+Options:
+  --sandbox    Browse a throwaway in-memory demo tree
+  --read-only  Refuse every change to the filesystem
+  --jail       Confine browsing and changes to DIRECTORY
+  --dry-run    Record changes as a plan to review, commit or discard
+  --audit-log FILE
+               Append a JSON line to FILE for every change made
+  -h, --help   Print this help";
+
+/// A remote location given instead of a local directory.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Remote {
+    Sftp(SftpUrl),
+    S3(S3Url),
+}
+
+/// Command-line options.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    pub start: Option<PathBuf>,
+    /// Set when the directory argument is an `sftp://` or `s3://` URL.
+    pub remote: Option<Remote>,
+    pub sandbox: bool,
+    pub read_only: bool,
+    pub jail: bool,
+    pub dry_run: bool,
+    pub audit_log: Option<PathBuf>,
+    pub help: bool,
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--sandbox" => options.sandbox = true,
+                "--read-only" => options.read_only = true,
+                "--jail" => options.jail = true,
+                "--dry-run" => options.dry_run = true,
+                "--audit-log" => match args.next() {
+                    Some(path) => options.audit_log = Some(PathBuf::from(path)),
+                    None => return Err("--audit-log needs a file path".to_string()),
+                },
+                "-h" | "--help" => options.help = true,
+                flag if flag.starts_with('-') => {
+                    return Err(format!("unknown option: {}", flag));
+                }
+                _ if options.start.is_some() || options.remote.is_some() => {
+                    return Err(format!("unexpected argument: {}", arg));
+                }
+                url if url.starts_with("sftp://") => match SftpUrl::parse(url) {
+                    Some(url) => options.remote = Some(Remote::Sftp(url)),
+                    None => return Err(format!("invalid sftp URL: {}", url)),
+                },
+                url if url.starts_with("s3://") => match S3Url::parse(url) {
+                    Some(url) => options.remote = Some(Remote::S3(url)),
+                    None => return Err(format!("invalid s3 URL: {}", url)),
+                },
+                _ => options.start = Some(PathBuf::from(arg)),
+            }
+        }
+
+        if options.sandbox && options.remote.is_some() {
+            return Err("--sandbox cannot be used with a remote URL".to_string());
+        }
+
+        Ok(options)
+    }
+}
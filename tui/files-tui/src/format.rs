@@ -0,0 +1,187 @@
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use files_core::conflict::Conflict;
+use files_core::jobs::{Progress, Task};
+use files_core::models::{EntryKind, FileEntry, escape_name};
+
+/// Renders one line of the file list: mode, owner, size, mtime, icon and
+/// name, followed by `-> target` for symbolic links.
+pub fn entry_line(entry: &FileEntry) -> String {
+    let meta = &entry.metadata;
+
+    let mut line = format!(
+        "{} {:>5}:{:<5} {:>7}  {}  {} {}",
+        mode_string(entry.kind, meta.mode),
+        meta.uid,
+        meta.gid,
+        human_size(meta.size),
+        timestamp(meta.modified),
+        icon(entry),
+        entry.display_name()
+    );
+
+    if let Some(link) = &entry.link {
+        line.push_str(" -> ");
+        line.push_str(&escape_name(link.target.as_os_str()));
+    }
+
+    line
+}
+
+fn icon(entry: &FileEntry) -> &'static str {
+    match entry.kind {
+        EntryKind::Directory => "📁",
+        EntryKind::File => "📄",
+        EntryKind::Symlink if entry.is_dir() => "📂",
+        EntryKind::Symlink => "🔗",
+        EntryKind::Fifo | EntryKind::Socket => "🔌",
+        EntryKind::BlockDevice | EntryKind::CharDevice => "💽",
+    }
+}
+
+/// Formats permission bits the way `ls -l` does, e.g. `drwxr-xr-x`.
+pub fn mode_string(kind: EntryKind, mode: u32) -> String {
+    let type_char = match kind {
+        EntryKind::File => '-',
+        EntryKind::Directory => 'd',
+        EntryKind::Symlink => 'l',
+        EntryKind::Fifo => 'p',
+        EntryKind::Socket => 's',
+        EntryKind::BlockDevice => 'b',
+        EntryKind::CharDevice => 'c',
+    };
+
+    let mut out = String::with_capacity(10);
+    out.push(type_char);
+
+    for (shift, special, special_char) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = (mode >> shift) & 0o7;
+        out.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        out.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => special_char,
+            (false, true) => special_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+
+    out
+}
+
+pub fn human_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["K", "M", "G", "T", "P"];
+
+    if size < 1024 {
+        return size.to_string();
+    }
+
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+/// The verbs for a job in progress and a finished one, e.g. `Copying` and
+/// `Copied`.
+pub fn job_verbs(task: &Task) -> (&'static str, &'static str) {
+    match task {
+        Task::Copy { .. } => ("Copying", "Copied"),
+        Task::Move { .. } => ("Moving", "Moved"),
+        Task::Delete(_) => ("Deleting", "Deleted"),
+    }
+}
+
+/// The text on a job's progress bar: files, bytes, rate and time left.
+pub fn job_progress(progress: &Progress) -> String {
+    if progress.scanning {
+        return format!(
+            "Counting... {} file(s), {}",
+            progress.files_total,
+            human_size(progress.bytes_total)
+        );
+    }
+
+    let mut label = format!(
+        "{:.0}%  {}/{} file(s)  {}/{}  {}/s",
+        progress.fraction() * 100.0,
+        progress.files_done,
+        progress.files_total,
+        human_size(progress.bytes_done),
+        human_size(progress.bytes_total),
+        human_size(progress.rate() as u64)
+    );
+
+    if let Some(eta) = progress.eta() {
+        label.push_str(&format!("  ETA {}", duration(eta)));
+    }
+
+    label
+}
+
+/// Describes a conflict: the existing entry against the incoming one.
+pub fn conflict(conflict: &Conflict) -> String {
+    let describe = |entry: &FileEntry| {
+        format!(
+            "{}, {}",
+            human_size(entry.metadata.size),
+            timestamp(entry.metadata.modified)
+        )
+    };
+
+    format!(
+        "{} exists ({}); incoming: {}",
+        escape_name(conflict.existing.path.as_os_str()),
+        describe(&conflict.existing),
+        describe(&conflict.source)
+    )
+}
+
+/// Formats a duration as `m:ss`, or `h:mm:ss` from an hour on.
+fn duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+fn timestamp(time: Option<SystemTime>) -> String {
+    match time {
+        Some(time) => DateTime::<Local>::from(time)
+            .format("%Y-%m-%d %H:%M")
+            .to_string(),
+        None => "-".repeat(16),
+    }
+}
+
+/// Reads a local date and time the way the file list shows them
+/// (`2026-01-02 03:04`), with or without seconds, a date alone for its
+/// midnight, or an RFC 3339 timestamp with its own offset.
+pub fn parse_timestamp(text: &str) -> Option<SystemTime> {
+    let text = text.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.into());
+    }
+
+    let local = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })?;
+    Local
+        .from_local_datetime(&local)
+        .earliest()
+        .map(SystemTime::from)
+}
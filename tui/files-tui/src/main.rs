@@ -1,4 +1,6 @@
 use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 
 use crossterm::{
@@ -10,20 +12,176 @@ use crossterm::{
 use ratatui::{
     Terminal,
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph},
 };
 
 use files_core::{
-    filesystem::{FileSystem, RealFileSystem},
-    state::AppState,
+    archive::ArchiveFileSystem,
+    filesystem::{Audited, DryRun, FileSystem, Jailed, ReadOnly, Sidecar},
+    jobs::Job,
+    models::escape_name,
+    s3::{S3Config, S3FileSystem},
+    sftp::SftpFileSystem,
+    state::{AppState, Loader, TouchTimes},
+    watch::Watcher,
 };
 
 mod app;
-use app::{InputKind, Mode, TuiApp};
+mod cli;
+mod format;
+mod sandbox;
+use app::{Asking, InputKind, LinkKind, Mode, PermissionsDialog, Plan, TuiApp};
+use cli::{Options, Remote};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if options.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    let (mut fs, start, mut indicators): (SharedFileSystem, PathBuf, Vec<&'static str>) =
+        if options.sandbox {
+            let start = options
+                .start
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(sandbox::HOME));
+            (Box::new(sandbox::demo_filesystem()), start, vec!["SANDBOX"])
+        } else if let Some(Remote::Sftp(url)) = &options.remote {
+            let sftp = SftpFileSystem::connect(url)?;
+            let start = url
+                .path
+                .clone()
+                .unwrap_or_else(|| sftp.home().to_path_buf());
+            (Box::new(sftp), start, vec!["SFTP"])
+        } else if let Some(Remote::S3(url)) = &options.remote {
+            let s3 = S3FileSystem::new(S3Config::from_env(&url.bucket)?);
+            (Box::new(s3), url.path.clone(), vec!["S3"])
+        } else {
+            let start = match options.start.clone() {
+                Some(path) => path,
+                None => std::env::current_dir()?,
+            };
+            (Box::new(ArchiveFileSystem::new()), start, Vec::new())
+        };
+
+    // Backends and mounts without extended attributes keep tags on the
+    // side; the in-memory sandbox has them. Without a data directory, or
+    // with a database that cannot be read, tags last for the session.
+    let database = match &options.remote {
+        _ if options.sandbox => None,
+        Some(Remote::Sftp(url)) => Some(format!("sftp-{}-{}", url.host, url.port)),
+        Some(Remote::S3(url)) => Some(format!("s3-{}", url.bucket)),
+        None => Some("local".to_string()),
+    };
+    let mut notice = None;
+    if let Some(name) = database {
+        let mut sidecar = Sidecar::new(fs);
+        if let Some(home) = data_home()
+            && let Err(err) = sidecar.load(&home.join("files/attributes").join(name))
+        {
+            notice = Some(format!("Tags are kept for this session only: {}", err));
+        }
+        fs = Box::new(sidecar);
+    }
+
+    let start = if options.jail {
+        let jailed = Jailed::new(&start, fs)?;
+        let root = jailed.canonicalize(&start)?;
+        fs = Box::new(jailed);
+        indicators.push("JAILED");
+        root
+    } else {
+        start
+    };
+
+    if let Some(path) = &options.audit_log {
+        fs = Box::new(Audited::open(fs, path)?);
+        indicators.push("AUDIT");
+    }
+
+    let dry_run = if options.dry_run {
+        let dry_run = Arc::new(DryRun::new(fs));
+        fs = Box::new(Arc::clone(&dry_run));
+        indicators.push("DRY-RUN");
+        Some(dry_run)
+    } else {
+        None
+    };
+
+    if options.read_only {
+        fs = Box::new(ReadOnly::new(fs));
+        indicators.push("READ-ONLY");
+    }
+
+    // Remote and in-memory paths are not local paths for inotify to watch.
+    let poll_interval =
+        (options.sandbox || options.remote.is_some()).then_some(REMOTE_POLL_INTERVAL);
+
+    run(
+        fs,
+        start,
+        indicators,
+        options.read_only,
+        dry_run,
+        poll_interval,
+        notice,
+    )
+}
+
+/// The filesystem stack, shareable with the background loader.
+type SharedFileSystem = Box<dyn FileSystem + Send + Sync>;
+
+/// Where per-user data goes: `$XDG_DATA_HOME`, or `~/.local/share`.
+fn data_home() -> Option<PathBuf> {
+    std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| Path::new(dir).is_absolute())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+}
+
+/// How often remote directories are listed again to spot outside changes.
+const REMOTE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+fn run<F: FileSystem + Send + Sync + 'static>(
+    fs: F,
+    start: PathBuf,
+    indicators: Vec<&'static str>,
+    read_only: bool,
+    dry_run: Option<Plan>,
+    poll_interval: Option<Duration>,
+    notice: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let fs = Arc::new(fs);
+    let entries = fs.read_directory(&start)?;
+    let state = AppState::new(start, entries, Arc::clone(&fs));
+
+    let mut app = TuiApp::new(state);
+    let job_fs = Arc::clone(&fs);
+    app.spawn_job = Some(Box::new(move |task, policy| {
+        Job::spawn(Arc::clone(&job_fs), task, policy)
+    }));
+    let watch_fs = Arc::clone(&fs);
+    app.spawn_watcher = Some(Box::new(move |directory| match poll_interval {
+        Some(interval) => Watcher::polling(Arc::clone(&watch_fs), directory, interval),
+        None => Watcher::spawn(Arc::clone(&watch_fs), directory),
+    }));
+    app.loader = Some(Loader::spawn(fs));
+    app.indicators = indicators;
+    app.read_only = read_only;
+    app.dry_run = dry_run;
+    app.message = notice;
+
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen)?;
@@ -31,14 +189,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut terminal = Terminal::new(backend)?;
     terminal.hide_cursor()?;
 
-    let fs = RealFileSystem;
-    let cwd = std::env::current_dir()?;
-    let entries = fs.read_directory(&cwd)?;
-    let state = AppState::new(cwd, entries, fs);
-
-    let mut app = TuiApp::new(state);
+    let mut tick: usize = 0;
 
     loop {
+        app.poll_loader();
+        app.poll_job();
+        app.poll_watcher();
+        tick = tick.wrapping_add(1);
+
         terminal.draw(|f| {
             let size = f.size();
 
@@ -62,12 +220,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .entries()
                 .iter()
                 .map(|e| {
-                    let name = if e.is_dir {
-                        format!("📁 {}", e.name)
+                    let mut spans = vec![Span::raw(format::entry_line(e))];
+                    for tag in &e.tags {
+                        spans.push(Span::raw(" "));
+                        spans.push(Span::styled(
+                            format!(" {} ", tag),
+                            Style::default().fg(Color::Black).bg(Color::Cyan),
+                        ));
+                    }
+
+                    let item = ListItem::new(Line::from(spans));
+                    if e.is_broken_link() {
+                        item.style(Style::default().fg(Color::Red))
                     } else {
-                        format!("📄 {}", e.name)
-                    };
-                    ListItem::new(name)
+                        item
+                    }
                 })
                 .collect();
 
@@ -77,32 +244,76 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             let list = List::new(items)
                 .block(
                     Block::default()
-                        .title(app.state.current_directory().to_string_lossy().to_string())
+                        .title(title(&app, tick))
                         .borders(Borders::ALL),
                 )
                 .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
 
-            f.render_stateful_widget(list, chunks[0], &mut list_state);
+            // ========================
+            // PLAN PANEL
+            // ========================
+            let list_area = match &app.dry_run {
+                Some(plan) if app.show_plan => {
+                    let columns = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+                        .split(chunks[0]);
+
+                    let operations = plan.plan();
+                    let items: Vec<ListItem> = operations
+                        .iter()
+                        .enumerate()
+                        .map(|(i, op)| ListItem::new(format!("{:>3}. {}", i + 1, op)))
+                        .collect();
+
+                    let panel = List::new(items).block(
+                        Block::default()
+                            .title(format!("Plan ({})", operations.len()))
+                            .borders(Borders::ALL),
+                    );
+
+                    f.render_widget(panel, columns[1]);
+                    columns[0]
+                }
+                _ => chunks[0],
+            };
+
+            f.render_stateful_widget(list, list_area, &mut list_state);
 
             // ========================
             // RENAME INPUT
             // ========================
             if let Mode::Input(kind) = app.mode {
                 let label = match kind {
-    InputKind::Rename => "Rename",
-    InputKind::CreateFile => "New file",
-    InputKind::CreateDirectory => "New directory",
-};
+                    InputKind::Rename => "Rename".to_string(),
+                    InputKind::CreateFile => "New file".to_string(),
+                    InputKind::CreateDirectory => "New directory".to_string(),
+                    InputKind::Extract => "Extract to".to_string(),
+                    InputKind::Copy => "Copy to".to_string(),
+                    InputKind::Move => "Move to".to_string(),
+                    InputKind::Link => match app.link_kind {
+                        LinkKind::Relative => "Link (relative) to".to_string(),
+                        LinkKind::Absolute => "Link (absolute) to".to_string(),
+                        LinkKind::Hard => "Hard link to".to_string(),
+                    },
+                    InputKind::Tags => "Tags".to_string(),
+                    InputKind::TagFilter => "Filter by tag".to_string(),
+                    InputKind::Touch => format!(
+                        "Touch {} [{}] recursive",
+                        match app.touch_times {
+                            TouchTimes::Both => "access+modification",
+                            TouchTimes::AccessOnly => "access",
+                            TouchTimes::ModificationOnly => "modification",
+                        },
+                        if app.touch_recursive { 'x' } else { ' ' }
+                    ),
+                };
 
                 let input = Paragraph::new(format!("{}: {}", label, app.input_buffer))
                     .block(Block::default().borders(Borders::ALL));
 
                 f.render_widget(input, chunks[1]);
-                let label_len = match kind {
-    InputKind::Rename => 8,
-    InputKind::CreateFile => 10,
-    InputKind::CreateDirectory => 15,
-};
+                let label_len = label.chars().count() as u16 + 2;
 
                 // Cursor position
                 let x = chunks[1].x + 1 + label_len + app.cursor_position as u16;
@@ -110,58 +321,96 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                 f.set_cursor(x, y);
             }
+            // ========================
+            // JOB PROGRESS
+            // ========================
+            if let (Mode::Normal, Some(job)) = (app.mode, &app.job) {
+                let progress = job.progress();
+                let (doing, _) = format::job_verbs(job.task());
+                let current = progress
+                    .current
+                    .as_ref()
+                    .map(|path| escape_name(path.as_os_str()).into_owned())
+                    .unwrap_or_default();
+
+                let gauge = Gauge::default()
+                    .block(
+                        Block::default()
+                            .title(format!("{} {} (Esc to abort)", doing, current))
+                            .borders(Borders::ALL),
+                    )
+                    .gauge_style(Style::default().fg(Color::Green))
+                    .ratio(progress.fraction().clamp(0.0, 1.0))
+                    .label(format::job_progress(&progress));
+
+                f.render_widget(gauge, chunks[1]);
+            }
+
             // ========================
             // DELETE CONFIRMATION
             // ========================
-            if app.mode == Mode::ConfirmDelete
-                && let Some(entry) = app.state.cursor()
-            {
-                let kind = if entry.is_dir { "directory" } else { "file" };
-                let text = format!("Delete {} \"{}\"? (y/n)", kind, entry.name);
+            let confirmation = match (app.mode, app.state.cursor()) {
+                (Mode::ConfirmTrash, Some(entry)) => Some((
+                    "Confirm Trash".to_string(),
+                    format!(
+                        "Move {} \"{}\" to the trash? (y/n)",
+                        kind(entry.is_dir()),
+                        entry.display_name()
+                    ),
+                )),
+                (Mode::ConfirmDelete, Some(entry)) => Some((
+                    "Confirm Delete".to_string(),
+                    format!(
+                        "Permanently delete {} \"{}\"? (y/n)",
+                        kind(entry.is_dir()),
+                        entry.display_name()
+                    ),
+                )),
+                (Mode::ConfirmEmptyTrash, _) => Some((
+                    "Confirm Empty Trash".to_string(),
+                    "Permanently delete everything in the trash? (y/n)".to_string(),
+                )),
+                (Mode::ConfirmConflict, _) => {
+                    let text = match &app.asking {
+                        Some(Asking::Job(conflict)) => format::conflict(conflict),
+                        Some(Asking::Command(_, path)) => {
+                            format!("{} already exists", escape_name(path.as_os_str()))
+                        }
+                        None => String::new(),
+                    };
+                    let check = if app.apply_to_all { 'x' } else { ' ' };
+                    Some((format!("File Exists [{}] apply to all", check), text))
+                }
+                _ => None,
+            };
+
+            if let Some((title, text)) = confirmation {
+                let popup =
+                    Paragraph::new(text).block(Block::default().borders(Borders::ALL).title(title));
+
+                f.render_widget(popup, chunks[1]);
+            }
 
-                let popup = Paragraph::new(text).block(
+            // ========================
+            // PERMISSIONS DIALOG
+            // ========================
+            if let (Mode::Permissions, Some(dialog)) = (app.mode, &app.permissions) {
+                let lines = permissions_lines(dialog);
+                let area = centered(list_area, 54, lines.len() as u16 + 2);
+                let popup = Paragraph::new(lines).block(
                     Block::default()
                         .borders(Borders::ALL)
-                        .title("Confirm Delete"),
+                        .title(format!("Permissions of {}", dialog.entry.display_name())),
                 );
 
-                f.render_widget(popup, chunks[1]);
+                f.render_widget(Clear, area);
+                f.render_widget(popup, area);
             }
 
             // ========================
             // STATUS BAR
             // ========================
-            let total = app.state.entries().len();
-            let current = app.state.cursor_index().map(|i| i + 1).unwrap_or(0);
-
-            let status_text = match app.mode {
-    Mode::Normal => format!(
-        " NORMAL | {}/{} | r:rename n:new-file N:new-dir d:delete ↑↓:move Enter:open Backspace:up q:quit ",
-        current, total
-    ),
-
-    Mode::Input(InputKind::Rename) => format!(
-        " RENAME | {}/{} | type new name • Enter:confirm • Esc:cancel ",
-        current, total
-    ),
-
-    Mode::Input(InputKind::CreateFile) => format!(
-        " CREATE FILE | {}/{} | type file name • Enter:create • Esc:cancel ",
-        current, total
-    ),
-
-    Mode::Input(InputKind::CreateDirectory) => format!(
-        " CREATE DIR | {}/{} | type directory name • Enter:create • Esc:cancel ",
-        current, total
-    ),
-
-    Mode::ConfirmDelete => format!(
-        " DELETE | {}/{} | y:confirm • n/Esc:cancel ",
-        current, total
-    ),
-};
-
-            let status = Paragraph::new(status_text).style(
+            let status = Paragraph::new(status_line(&app)).style(
                 Style::default()
                     .bg(Color::DarkGray)
                     .fg(Color::White)
@@ -171,21 +420,40 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             f.render_widget(status, chunks[2]);
         })?;
 
-        if event::poll(Duration::from_millis(200))?
+        // Redraw more often while the spinner or a progress bar runs.
+        let timeout = if app.state.loading().is_some() || app.job.is_some() {
+            80
+        } else {
+            200
+        };
+
+        if event::poll(Duration::from_millis(timeout))?
             && let Event::Key(key) = event::read()?
         {
             if key.code == KeyCode::Char('q') && app.mode == Mode::Normal {
-                break;
+                if app.job.is_none() {
+                    break;
+                }
+                app.message = Some("A job is running; Esc aborts it".into());
+                continue;
             }
 
             let previous_mode = app.mode;
-            app.handle_key(key)?;
+            app.message = None;
+
+            if let Err(err) = app.handle_key(key) {
+                app.fail(err);
+            }
 
             if previous_mode != app.mode {
                 match app.mode {
                     Mode::Input(_) => terminal.show_cursor()?,
                     Mode::Normal => terminal.hide_cursor()?,
-                    Mode::ConfirmDelete => terminal.hide_cursor()?,
+                    Mode::ConfirmTrash
+                    | Mode::ConfirmDelete
+                    | Mode::ConfirmEmptyTrash
+                    | Mode::ConfirmConflict
+                    | Mode::Permissions => terminal.hide_cursor()?,
                 }
             }
         }
@@ -197,3 +465,247 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+fn kind(is_dir: bool) -> &'static str {
+    if is_dir { "directory" } else { "file" }
+}
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// The file list title: active session indicators followed by the directory,
+/// the tag filter, and a spinner with the directory being loaded, if any.
+fn title<F: FileSystem>(app: &TuiApp<F>, tick: usize) -> String {
+    let directory = escape_name(app.state.current_directory().as_os_str());
+
+    let mut title = if app.indicators.is_empty() {
+        directory.into_owned()
+    } else {
+        format!("[{}] {}", app.indicators.join("|"), directory)
+    };
+
+    if let Some(tag) = app.state.tag_filter() {
+        title.push_str(&format!(" (tag: {})", tag));
+    }
+
+    if let Some(loading) = app.state.loading() {
+        title.push_str(&format!(
+            " {} loading {}",
+            SPINNER[tick % SPINNER.len()],
+            escape_name(loading.as_os_str())
+        ));
+    }
+
+    title
+}
+
+/// A `width` by `height` area in the middle of `area`, clipped to it.
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let (width, height) = (width.min(area.width), height.min(area.height));
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// The permissions dialog: the bit grid with the cursor cell highlighted,
+/// the typed mode, and a preview of the result.
+fn permissions_lines(dialog: &PermissionsDialog) -> Vec<Line<'static>> {
+    const ROWS: [&str; 4] = ["user", "group", "other", "special"];
+    let header = |columns: [&str; 3]| {
+        Line::from(format!(
+            "{:9}{:<9}{:<9}{}",
+            "", columns[0], columns[1], columns[2]
+        ))
+    };
+
+    // Show the result of a typed mode on the grid as well.
+    let shown = dialog.preview().unwrap_or(dialog.mode);
+    let mut lines = vec![header(["read", "write", "execute"])];
+
+    for (row, label) in ROWS.iter().enumerate() {
+        if row == 3 {
+            lines.push(header(["setuid", "setgid", "sticky"]));
+        }
+
+        let mut spans = vec![Span::raw(format!("{:9}", label))];
+        for column in 0..3 {
+            let set = shown & PermissionsDialog::bit(row, column) != 0;
+            let style = if dialog.cursor == (row, column) {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            spans.push(Span::styled(if set { "[x]" } else { "[ ]" }, style));
+            spans.push(Span::raw("      "));
+        }
+        lines.push(Line::from(spans));
+    }
+
+    let original = dialog.entry.metadata.mode & 0o7777;
+    let kind = dialog.entry.kind;
+    let preview = match dialog.change() {
+        Ok(change) => {
+            let mode = change.apply(original, dialog.entry.is_dir());
+            let change = if change.is_empty() {
+                "none".to_string()
+            } else {
+                change.to_string()
+            };
+            Line::from(format!(
+                "{} {:04o} → {} {:04o}  change: {}",
+                format::mode_string(kind, original),
+                original,
+                format::mode_string(kind, mode),
+                mode,
+                change
+            ))
+        }
+        Err(err) => Line::styled(err.to_string(), Style::default().fg(Color::Red)),
+    };
+
+    lines.push(Line::from(""));
+    lines.push(preview);
+
+    let mut mode = format!("Mode: {}", dialog.expression);
+    if dialog.entry.is_dir() {
+        let check = if dialog.recursive { 'x' } else { ' ' };
+        mode = format!("{:<32}[{}] recursive", mode, check);
+    }
+    lines.push(Line::from(mode));
+
+    lines
+}
+
+/// Normal-mode key hints; the flag marks keys that mutate the filesystem.
+const NORMAL_HINTS: &[(&str, bool)] = &[
+    ("r:rename", true),
+    ("n:new-file", true),
+    ("N:new-dir", true),
+    ("d:trash", true),
+    ("D:delete", true),
+    ("c:copy", true),
+    ("m:move", true),
+    ("l:link", true),
+    ("x:extract", true),
+    ("P:permissions", true),
+    ("T:touch", true),
+    ("#:tags", true),
+    ("f:filter-tag", false),
+    ("g:follow-link", false),
+    ("t:trash-dir", false),
+    ("u:restore", true),
+    ("E:empty-trash", true),
+    ("p:plan", false),
+    ("↑↓:move", false),
+    ("Enter:open", false),
+    ("Backspace:up", false),
+    ("q:quit", false),
+];
+
+/// Key hints while the plan panel is open.
+const PLAN_HINTS: &str = "p:hide-plan C:commit X:discard";
+
+/// The status bar: mode, position, then key hints or the last message.
+///
+/// In read-only sessions the mutating key hints are greyed out.
+fn status_line<F: FileSystem>(app: &TuiApp<F>) -> Line<'static> {
+    let total = app.state.entries().len();
+    let current = app.state.cursor_index().map(|i| i + 1).unwrap_or(0);
+
+    let (mode, hints) = match app.mode {
+        Mode::Normal => ("NORMAL", None),
+        Mode::Input(InputKind::Rename) => {
+            ("RENAME", Some("type new name • Enter:confirm • Esc:cancel"))
+        }
+        Mode::Input(InputKind::CreateFile) => (
+            "CREATE FILE",
+            Some("type file name • Enter:create • Esc:cancel"),
+        ),
+        Mode::Input(InputKind::CreateDirectory) => (
+            "CREATE DIR",
+            Some("type directory name • Enter:create • Esc:cancel"),
+        ),
+        Mode::Input(InputKind::Extract) => (
+            "EXTRACT",
+            Some("type destination directory • Enter:extract • Esc:cancel"),
+        ),
+        Mode::Input(InputKind::Copy) => (
+            "COPY",
+            Some("type destination path or directory • Enter:copy • Esc:cancel"),
+        ),
+        Mode::Input(InputKind::Move) => (
+            "MOVE",
+            Some("type destination path or directory • Enter:move • Esc:cancel"),
+        ),
+        Mode::Input(InputKind::Link) => (
+            "LINK",
+            Some(
+                "type destination path or directory • Tab:relative/absolute/hard • Enter:link • Esc:cancel",
+            ),
+        ),
+        Mode::Input(InputKind::Touch) => (
+            "TOUCH",
+            Some(
+                "empty:now • type date/time or reference file • Tab:times • Ctrl-R:recursive • Enter:touch • Esc:cancel",
+            ),
+        ),
+        Mode::Input(InputKind::Tags) => (
+            "TAGS",
+            Some("type tags separated by commas • empty:untag • Enter:confirm • Esc:cancel"),
+        ),
+        Mode::Input(InputKind::TagFilter) => (
+            "FILTER",
+            Some("type a tag • empty:show all • Enter:filter • Esc:cancel"),
+        ),
+        Mode::ConfirmTrash => ("TRASH", Some("y:confirm • n/Esc:cancel")),
+        Mode::ConfirmDelete => ("DELETE", Some("y:confirm • n/Esc:cancel")),
+        Mode::ConfirmEmptyTrash => ("EMPTY TRASH", Some("y:confirm • n/Esc:cancel")),
+        Mode::Permissions => (
+            "PERMISSIONS",
+            Some(
+                "arrows:move • Space:toggle • type octal/symbolic mode • Tab:recursive • Enter:apply • Esc:cancel",
+            ),
+        ),
+        Mode::ConfirmConflict => (
+            "EXISTS",
+            Some(
+                "o:overwrite • s:skip • k:keep-both • u:overwrite-if-newer • a:apply-to-all • Esc:cancel",
+            ),
+        ),
+    };
+
+    let mut spans = vec![Span::raw(format!(" {} | {}/{} | ", mode, current, total))];
+
+    if let Some(message) = &app.message {
+        spans.push(Span::styled(
+            message.clone(),
+            Style::default().fg(Color::Yellow),
+        ));
+        return Line::from(spans);
+    }
+
+    match hints {
+        Some(hints) => spans.push(Span::raw(hints)),
+        None if app.dry_run.is_some() && app.show_plan => {
+            spans.push(Span::raw(PLAN_HINTS));
+        }
+        None => {
+            for (hint, mutating) in NORMAL_HINTS {
+                let style = if *mutating && app.read_only {
+                    Style::default()
+                        .fg(Color::Gray)
+                        .remove_modifier(Modifier::BOLD)
+                        .add_modifier(Modifier::DIM | Modifier::CROSSED_OUT)
+                } else {
+                    Style::default()
+                };
+                spans.push(Span::styled(*hint, style));
+                spans.push(Span::raw(" "));
+            }
+        }
+    }
+
+    Line::from(spans)
+}
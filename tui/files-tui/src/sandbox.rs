@@ -0,0 +1,40 @@
+use std::path::Path;
+
+use files_core::memory::MemoryFileSystem;
+
+/// Where a sandbox session starts.
+pub const HOME: &str = "/home/sandbox";
+
+/// Builds the demo tree backing a `--sandbox` session.
+///
+/// Everything lives in memory, so changes are discarded on exit.
+pub fn demo_filesystem() -> MemoryFileSystem {
+    let fs = MemoryFileSystem::new();
+    let home = Path::new(HOME);
+
+    let files: [(&str, &str); 6] = [
+        (
+            "README.md",
+            "# Sandbox\n\nNothing here touches your disk.\n",
+        ),
+        ("notes.txt", "rename me, delete me, break things\n"),
+        ("docs/guide.md", "# Guide\n"),
+        ("docs/changelog.md", "# Changelog\n"),
+        ("src/main.rs", "fn main() {\n    println!(\"hello\");\n}\n"),
+        ("src/lib.rs", ""),
+    ];
+
+    for (path, contents) in files {
+        fs.write_file(&home.join(path), contents)
+            .expect("sandbox paths are valid");
+    }
+
+    fs.create_dir_all(&home.join("photos"))
+        .expect("sandbox paths are valid");
+    fs.symlink("docs", &home.join("docs-link"))
+        .expect("sandbox paths are valid");
+    fs.symlink("missing.txt", &home.join("broken-link"))
+        .expect("sandbox paths are valid");
+
+    fs
+}